@@ -0,0 +1,316 @@
+/// Benchmarks of linking synthetic inputs: many objects with many
+/// symbols each, large archives and SDK sized sets of TBDs.
+///
+/// The inputs are generated rather than checked in so their size can be
+/// scaled. Each benchmark of a whole link runs the machop binary, the
+/// others call into the parts of the library doing the work.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use goblin::{archive::Archive, mach::MachO};
+use machop::{
+    archive,
+    layout::Layout,
+    linker_args::{Architecture, Platform},
+    tbd::TbdDylib,
+    threads::ThreadPool,
+};
+
+const SYMBOLS_PER_OBJECT: usize = 1000;
+
+/// A directory for the generated inputs and outputs of `benchmark`.
+fn scratch(benchmark: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(benchmark);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An MH_OBJECT defining each of `defined` as a function that calls one
+/// of `undefined` and returns.
+fn object(defined: &[String], undefined: &[String]) -> Vec<u8> {
+    const HEADER_SIZE: usize = 32;
+    const SEGMENT_SIZE: usize = 72 + 80;
+    const SYMTAB_SIZE: usize = 24;
+    let text_offset = HEADER_SIZE + SEGMENT_SIZE + SYMTAB_SIZE;
+    let text_size = defined.len() * 8;
+    let relocations = if undefined.is_empty() { 0 } else { defined.len() };
+    let reloc_offset = text_offset + text_size;
+    let symoff = reloc_offset + relocations * 8;
+    let nsyms = defined.len() + undefined.len();
+    let stroff = symoff + nsyms * 16;
+
+    let mut strtab = vec![0];
+    let mut symtab = vec![];
+    for (index, name) in defined.iter().chain(undefined).enumerate() {
+        let is_defined = index < defined.len();
+        symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+        // N_SECT | N_EXT in section 1, or N_UNDF | N_EXT.
+        symtab.extend_from_slice(if is_defined { &[0x0f, 1] } else { &[0x01, 0] });
+        symtab.extend_from_slice(&0u16.to_le_bytes());
+        let value = if is_defined { index as u64 * 8 } else { 0 };
+        symtab.extend_from_slice(&value.to_le_bytes());
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let mut out = vec![];
+    let u32s = |out: &mut Vec<u8>, words: &[u32]| {
+        for word in words {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+    };
+    let name = |out: &mut Vec<u8>, name: &str| {
+        let mut field = [0u8; 16];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        out.extend_from_slice(&field);
+    };
+    // MH_MAGIC_64, CPU_TYPE_ARM64, MH_OBJECT, MH_SUBSECTIONS_VIA_SYMBOLS.
+    u32s(
+        &mut out,
+        &[
+            0xfeed_facf,
+            0x0100_000c,
+            0,
+            1,
+            2,
+            (SEGMENT_SIZE + SYMTAB_SIZE) as u32,
+            0x2000,
+            0,
+        ],
+    );
+    u32s(&mut out, &[0x19, SEGMENT_SIZE as u32]);
+    name(&mut out, "");
+    for value in [0, text_size, text_offset, text_size] {
+        out.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+    u32s(&mut out, &[7, 7, 1, 0]);
+    name(&mut out, "__text");
+    name(&mut out, "__TEXT");
+    for value in [0, text_size] {
+        out.extend_from_slice(&(value as u64).to_le_bytes());
+    }
+    u32s(
+        &mut out,
+        &[
+            text_offset as u32,
+            2,
+            reloc_offset as u32,
+            relocations as u32,
+            0x8000_0400,
+            0,
+            0,
+            0,
+        ],
+    );
+    u32s(
+        &mut out,
+        &[
+            0x2,
+            SYMTAB_SIZE as u32,
+            symoff as u32,
+            nsyms as u32,
+            stroff as u32,
+            strtab.len() as u32,
+        ],
+    );
+    for _ in defined {
+        // bl (relocated), ret
+        u32s(&mut out, &[0x9400_0000, 0xd65f_03c0]);
+    }
+    for index in 0..relocations {
+        let symbol = defined.len() + index % undefined.len();
+        // ARM64_RELOC_BRANCH26, extern, 4 bytes long, pc relative.
+        let info = symbol as u32 | 1 << 24 | 2 << 25 | 1 << 27 | 2 << 28;
+        u32s(&mut out, &[index as u32 * 8, info]);
+    }
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab);
+    out
+}
+
+/// `count` objects, each defining SYMBOLS_PER_OBJECT functions calling
+/// the functions of the next.
+fn objects(count: usize) -> Vec<Vec<u8>> {
+    let names = |object: usize| -> Vec<String> {
+        (0..SYMBOLS_PER_OBJECT)
+            .map(|symbol| format!("_f{object}_{symbol}"))
+            .collect()
+    };
+    (0..count)
+        .map(|index| object(&names(index), &names((index + 1) % count)))
+        .collect()
+}
+
+/// An archive of `members`, with GNU style names.
+fn archive(members: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = b"!<arch>\n".to_vec();
+    for (index, member) in members.iter().enumerate() {
+        let header = format!(
+            "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            format!("m{index}.o/"),
+            0,
+            0,
+            0,
+            644,
+            member.len()
+        );
+        out.extend_from_slice(header.as_bytes());
+        out.extend_from_slice(member);
+        if !out.len().is_multiple_of(2) {
+            out.push(b'\n');
+        }
+    }
+    out
+}
+
+/// A TBD for a library exporting `symbols`.
+fn tbd(index: usize, symbols: &[String]) -> String {
+    format!(
+        "--- !tapi-tbd\n\
+         tbd-version: 4\n\
+         targets: [ arm64-macos ]\n\
+         install-name: '/usr/lib/libbench{index}.dylib'\n\
+         current-version: 1\n\
+         exports:\n  \
+           - targets: [ arm64-macos ]\n    \
+             symbols: [ {} ]\n\
+         ...\n",
+        symbols.join(", ")
+    )
+}
+
+/// `count` TBDs each exporting SYMBOLS_PER_OBJECT symbols.
+fn tbds(count: usize) -> Vec<(Vec<String>, String)> {
+    (0..count)
+        .map(|index| {
+            let symbols: Vec<String> = (0..SYMBOLS_PER_OBJECT)
+                .map(|symbol| format!("_lib{index}_{symbol}"))
+                .collect();
+            let tbd = tbd(index, &symbols);
+            (symbols, tbd)
+        })
+        .collect()
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// Link `inputs` into `dir` with the machop binary.
+fn link(dir: &Path, inputs: &[PathBuf]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args(["-arch", "arm64", "-o"])
+        .arg(dir.join("a.out"))
+        .args(inputs)
+        .env("RUST_LOG", "error")
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+/// An object with `_main`, calling `callee`.
+fn main_object(callee: &str) -> Vec<u8> {
+    object(&["_main".to_string()], &[callee.to_string()])
+}
+
+fn symbol_resolution(c: &mut Criterion) {
+    let mut group = c.benchmark_group("symbol resolution");
+    group.sample_size(10);
+    for count in [10, 100] {
+        let dir = scratch(&format!("objects-{count}"));
+        let mut inputs = vec![write(&dir, "main.o", &main_object("_f0_0"))];
+        for (index, object) in objects(count).iter().enumerate() {
+            inputs.push(write(&dir, &format!("{index}.o"), object));
+        }
+        group.throughput(Throughput::Elements((count * SYMBOLS_PER_OBJECT) as u64));
+        group.bench_with_input(BenchmarkId::new("link", count), &inputs, |b, inputs| {
+            b.iter(|| link(&dir, inputs))
+        });
+    }
+    group.finish();
+}
+
+fn object_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("object parsing");
+    let objects = objects(100);
+    let machos: Vec<MachO> = objects
+        .iter()
+        .map(|object| MachO::parse(object, 0).unwrap())
+        .collect();
+    let inputs: Vec<&MachO> = machos.iter().collect();
+    for threads in [1, 0] {
+        let pool = ThreadPool::new(threads);
+        group.bench_with_input(BenchmarkId::new("layout", threads), &inputs, |b, inputs| {
+            b.iter(|| Layout::new(inputs, &pool).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn archive_loading(c: &mut Criterion) {
+    let mut group = c.benchmark_group("archive loading");
+    group.sample_size(10);
+    let count = 1000;
+    let members = objects(count);
+    let bytes = archive(&members);
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("members", |b| {
+        b.iter(|| {
+            let parsed = Archive::parse(&bytes).unwrap();
+            archive::members(&parsed, &bytes).unwrap().len()
+        })
+    });
+    let dir = scratch("archive");
+    let inputs = vec![
+        write(&dir, "main.o", &main_object("_f0_0")),
+        write(&dir, "libbench.a", &bytes),
+    ];
+    group.bench_function("link", |b| b.iter(|| link(&dir, &inputs)));
+    group.finish();
+}
+
+fn tbd_sets(c: &mut Criterion) {
+    let mut group = c.benchmark_group("TBD sets");
+    group.sample_size(10);
+    let count = 200;
+    let tbds = tbds(count);
+    group.throughput(Throughput::Elements((count * SYMBOLS_PER_OBJECT) as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            for (_, tbd) in &tbds {
+                TbdDylib::parse(Architecture::ARM64, &[Platform::MacOS], tbd.as_bytes()).unwrap();
+            }
+        })
+    });
+    // Every library has its last symbol called, so each is searched.
+    let dir = scratch("tbds");
+    let callees: Vec<String> = tbds
+        .iter()
+        .map(|(symbols, _)| symbols.last().unwrap().clone())
+        .collect();
+    let mut inputs = vec![write(
+        &dir,
+        "main.o",
+        &object(&["_main".to_string()], &callees),
+    )];
+    for (index, (_, tbd)) in tbds.iter().enumerate() {
+        inputs.push(write(&dir, &format!("libbench{index}.tbd"), tbd.as_bytes()));
+    }
+    group.bench_function("link", |b| b.iter(|| link(&dir, &inputs)));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    symbol_resolution,
+    object_parsing,
+    archive_loading,
+    tbd_sets
+);
+criterion_main!(benches);
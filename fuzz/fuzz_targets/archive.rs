@@ -0,0 +1,11 @@
+#![no_main]
+
+use goblin::archive::Archive;
+use libfuzzer_sys::fuzz_target;
+use machop::archive;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(parsed) = Archive::parse(data) {
+        let _ = archive::members(&parsed, data);
+    }
+});
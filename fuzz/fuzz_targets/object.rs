@@ -0,0 +1,19 @@
+#![no_main]
+
+use goblin::{mach::Mach, Object};
+use libfuzzer_sys::fuzz_target;
+use machop::{input_kind, layout::Layout, threads::ThreadPool};
+
+// Objects are parsed by goblin, then split into atoms and have their
+// relocations decoded and Objective-C metadata rewritten by machop.
+fuzz_target!(|data: &[u8]| {
+    let _ = input_kind::sniff(data);
+    let Ok(Object::Mach(Mach::Binary(macho))) = Object::parse(data) else {
+        return;
+    };
+    let threads = ThreadPool::new(1);
+    if let Ok(mut layout) = Layout::new(&[&macho], &threads) {
+        layout.merge_objc_categories(&threads);
+        let _ = layout.merge_objc_image_info(&["fuzz.o".to_string()]);
+    }
+});
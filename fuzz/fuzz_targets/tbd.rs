@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use machop::{
+    linker_args::{Architecture, Platform},
+    tbd::{self, TbdDylib},
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = TbdDylib::parse(Architecture::ARM64, &[Platform::MacOS], data);
+    let _ = tbd::foreign_exports(&Architecture::ARM64, data);
+});
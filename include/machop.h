@@ -0,0 +1,26 @@
+/* Linking in-process with machop, from libmachop built as a cdylib. */
+#ifndef MACHOP_H
+#define MACHOP_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Link with the command line argv, of argc arguments, as if it were run.
+ * argv[0] is the program name, which picks the options accepted as for
+ * the executable, so "ld" takes ld64's.
+ *
+ * Returns 0 if the link succeeded, 1 if it failed and 101 if machop hit
+ * an internal error. If out_diagnostics isn't NULL it's set to the
+ * warnings and errors reported, one per line, or the usage for -help,
+ * to be freed with machop_free_diagnostics. */
+int machop_link(int argc, const char *const *argv, char **out_diagnostics);
+
+/* Free diagnostics returned by machop_link. */
+void machop_free_diagnostics(char *diagnostics);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,31 @@
+/// Reading the members of static archives.
+use goblin::archive::Archive;
+
+/// The names and contents of the members of `archive`, read from
+/// `bytes`, in the order they're stored. Unlike [Archive::members],
+/// members sharing a name are all kept.
+///
+/// goblin has already resolved BSD `#1/<length>` names and the GNU
+/// `//` name table, only the padding and GNU's trailing `/` are left
+/// to trim.
+pub fn members<'a>(
+    archive: &Archive<'a>,
+    bytes: &'a [u8],
+) -> Result<Vec<(String, &'a [u8])>, String> {
+    (0..archive.len())
+        .filter_map(|index| archive.get_at(index))
+        .map(|member| {
+            let name = member
+                .extended_name()
+                .trim_end_matches(['\0', ' '])
+                .trim_end_matches('/')
+                .to_string();
+            let start = member.offset as usize;
+            let contents = start
+                .checked_add(member.size())
+                .and_then(|end| bytes.get(start..end))
+                .ok_or_else(|| format!("member {name} is truncated"))?;
+            Ok((name, contents))
+        })
+        .collect()
+}
@@ -0,0 +1,240 @@
+/// Table driven command line parsing.
+///
+/// Options are described by [OptionSpec]s, in the same spirit as LLVM's
+/// OptTable, so the set of accepted options is data rather than code. An
+/// option can take its value joined to the name (`-lfoo`), as the next
+/// argument (`-o out`), either of the two, as a comma separated list
+/// (`-sectalign,a,b`) or as a fixed number of following arguments.
+use std::ffi::OsString;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `-flag`, without a value.
+    Flag,
+    /// `-Lvalue`
+    Joined,
+    /// `-o value`
+    Separate,
+    /// `-Lvalue` or `-L value`
+    JoinedOrSeparate,
+    /// `-name,a,b,c`, giving the values a, b and c.
+    CommaJoined,
+    /// `-name a b`, with a fixed number of values.
+    MultiArg(usize),
+}
+
+/// Which of an option's values is a path, so tools like --reproduce can
+/// find the files an argument list reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathValue {
+    /// The value at this index.
+    At(usize),
+    /// What follows the first colon of the value, as in
+    /// `-dylib_file INSTALL_NAME:FILE`.
+    AfterColon,
+}
+
+#[derive(Debug)]
+pub struct OptionSpec {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// The option this one is another spelling of. Parsed arguments are
+    /// always reported under the name of the option aliased.
+    pub alias: Option<&'static str>,
+    /// Used to categorise options, e.g. all the -<platform>_version_min
+    /// flags.
+    pub group: Option<&'static str>,
+    pub path: Option<PathValue>,
+}
+
+impl OptionSpec {
+    pub const fn new(name: &'static str, kind: Kind) -> Self {
+        Self {
+            name,
+            kind,
+            alias: None,
+            group: None,
+            path: None,
+        }
+    }
+
+    pub const fn alias(self, alias: &'static str) -> Self {
+        Self {
+            alias: Some(alias),
+            ..self
+        }
+    }
+
+    pub const fn group(self, group: &'static str) -> Self {
+        Self {
+            group: Some(group),
+            ..self
+        }
+    }
+
+    pub const fn path(self, path: PathValue) -> Self {
+        Self {
+            path: Some(path),
+            ..self
+        }
+    }
+
+    /// How many of the arguments after a spelling of this option, with
+    /// `joined` after the name, are its values.
+    pub fn separate_values(&self, joined: Option<&str>) -> usize {
+        match (self.kind, joined) {
+            (Kind::Flag | Kind::Joined | Kind::CommaJoined, _) => 0,
+            (Kind::JoinedOrSeparate, Some(value)) if !value.is_empty() => 0,
+            (Kind::JoinedOrSeparate | Kind::Separate, _) => 1,
+            (Kind::MultiArg(count), _) => count,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    MissingValue { option: String, expected: usize },
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MissingValue { option, expected } => {
+                write!(f, "{option} expects {expected} value(s)")
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Arg {
+    /// An argument that isn't an option, usually an input file.
+    Positional(OsString),
+    /// Something that looks like an option but isn't in the table.
+    Unknown(OsString),
+    Option {
+        /// The name of the option, after resolving aliases.
+        name: &'static str,
+        group: Option<&'static str>,
+        values: Vec<OsString>,
+    },
+}
+
+/// A set of option lists. Earlier lists take precedence, which lets a
+/// dialect override or extend a base list.
+#[derive(Debug)]
+pub struct OptTable {
+    lists: &'static [&'static [OptionSpec]],
+    /// Spellings this table doesn't implement that start with the name
+    /// of a joined option, like -ld_classic with -l. They're unknown
+    /// rather than that option with the rest as its value.
+    unknown: &'static [&'static str],
+}
+
+impl OptTable {
+    pub const fn new(lists: &'static [&'static [OptionSpec]]) -> Self {
+        Self {
+            lists,
+            unknown: &[],
+        }
+    }
+
+    pub const fn unknown(self, unknown: &'static [&'static str]) -> Self {
+        Self { unknown, ..self }
+    }
+
+    pub fn options(&self) -> impl Iterator<Item = &'static OptionSpec> {
+        self.lists.iter().flat_map(|list| list.iter())
+    }
+
+    pub fn find(&self, name: &str) -> Option<&'static OptionSpec> {
+        self.options().find(|option| option.name == name)
+    }
+
+    /// Whether `arg` is an option this table knows.
+    pub fn recognises(&self, arg: &str) -> bool {
+        self.lookup(arg).is_some()
+    }
+
+    /// Find the option `arg` is a spelling of, with the joined value if
+    /// there is one. An exact match wins, otherwise the longest option
+    /// name that takes a joined value and prefixes `arg`, unless `arg`
+    /// is one of the spellings known not to be an option here.
+    pub fn lookup<'arg>(&self, arg: &'arg str) -> Option<(&'static OptionSpec, Option<&'arg str>)> {
+        if let Some(option) = self
+            .options()
+            .find(|option| option.name == arg && option.kind != Kind::CommaJoined)
+        {
+            return Some((option, None));
+        }
+        if self.unknown.contains(&arg) {
+            return None;
+        }
+        self.options()
+            .filter(|option| {
+                matches!(
+                    option.kind,
+                    Kind::Joined | Kind::JoinedOrSeparate | Kind::CommaJoined
+                )
+            })
+            .filter_map(|option| Some((option, arg.strip_prefix(option.name)?)))
+            .max_by_key(|(option, _)| option.name.len())
+            .map(|(option, value)| (option, Some(value)))
+    }
+
+    /// Resolve an alias to the option it's an alias of.
+    pub fn canonical(&self, option: &'static OptionSpec) -> &'static OptionSpec {
+        option
+            .alias
+            .and_then(|alias| self.find(alias))
+            .unwrap_or(option)
+    }
+
+    pub fn parse(&self, args: impl IntoIterator<Item = OsString>) -> Result<Vec<Arg>, Error> {
+        let mut args = args.into_iter();
+        let mut parsed = vec![];
+        while let Some(arg) = args.next() {
+            let Some(text) = arg.to_str().filter(|s| s.starts_with('-') && s.len() > 1) else {
+                parsed.push(Arg::Positional(arg));
+                continue;
+            };
+            let Some((option, joined)) = self.lookup(text) else {
+                parsed.push(Arg::Unknown(arg));
+                continue;
+            };
+            let mut next = |count: usize| -> Result<Vec<OsString>, Error> {
+                let values: Vec<OsString> = args.by_ref().take(count).collect();
+                if values.len() == count {
+                    Ok(values)
+                } else {
+                    Err(Error::MissingValue {
+                        option: option.name.to_string(),
+                        expected: count,
+                    })
+                }
+            };
+            let values = match (option.kind, joined) {
+                (Kind::Flag, _) => vec![],
+                (Kind::Joined, value) => vec![value.unwrap_or_default().into()],
+                (Kind::JoinedOrSeparate, Some(value)) if !value.is_empty() => vec![value.into()],
+                (Kind::JoinedOrSeparate | Kind::Separate, _) => next(1)?,
+                (Kind::CommaJoined, value) => value
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|value| !value.is_empty())
+                    .map(OsString::from)
+                    .collect(),
+                (Kind::MultiArg(count), _) => next(count)?,
+            };
+            let option = self.canonical(option);
+            parsed.push(Arg::Option {
+                name: option.name,
+                group: option.group,
+                values,
+            });
+        }
+        Ok(parsed)
+    }
+}
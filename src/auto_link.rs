@@ -0,0 +1,87 @@
+/// Libraries and frameworks objects ask to be linked against with
+/// LC_LINKER_OPTION, which clang records for the modules they import
+/// (or `#pragma comment(lib, ...)`).
+///
+/// Like ld64, only `-l` and `-framework` are understood, and they're
+/// looked for once every input has been read, following the inputs in
+/// the link order.
+use std::fmt::Display;
+
+use goblin::mach::{constants::cputype::CpuType, load_command::CommandVariant, MachO};
+
+use crate::warning;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AutoLink {
+    Library(String),
+    Framework(String),
+}
+
+impl Display for AutoLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AutoLink::Library(name) => write!(f, "library {name}"),
+            AutoLink::Framework(name) => write!(f, "framework {name}"),
+        }
+    }
+}
+
+impl AutoLink {
+    /// The library or framework an LC_LINKER_OPTION's options name, if
+    /// it's one that's understood.
+    pub fn parse(options: &[String]) -> Option<Self> {
+        match options {
+            [option] => option
+                .strip_prefix("-l")
+                .filter(|name| !name.is_empty())
+                .map(|name| AutoLink::Library(name.to_string())),
+            [option, name] if option == "-framework" => Some(AutoLink::Framework(name.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The options of each LC_LINKER_OPTION in `macho`, parsed from
+/// `bytes`.
+pub fn linker_options(macho: &MachO, bytes: &[u8]) -> Vec<Vec<String>> {
+    macho
+        .load_commands
+        .iter()
+        .filter_map(|command| {
+            // goblin reads the command as a linkedit_data_command, so
+            // its count of strings is in dataoff.
+            let CommandVariant::LinkerOption(option) = command.command else {
+                return None;
+            };
+            let start = command.offset + 12;
+            let end = command.offset + option.cmdsize as usize;
+            let strings = bytes.get(start..end.min(bytes.len()))?;
+            Some(
+                strings
+                    .split(|byte| *byte == 0)
+                    .take(option.dataoff as usize)
+                    .map(|option| String::from_utf8_lossy(option).into_owned())
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// What the object `macho`, parsed from `bytes`, asks to be linked
+/// against if it's built for `cputype`, warning about the options that
+/// aren't understood.
+pub fn auto_links(macho: &MachO, bytes: &[u8], cputype: CpuType) -> Vec<AutoLink> {
+    if !macho.is_object_file() || macho.header.cputype() != cputype {
+        return vec![];
+    }
+    linker_options(macho, bytes)
+        .into_iter()
+        .filter_map(|options| {
+            let auto_link = AutoLink::parse(&options);
+            if auto_link.is_none() {
+                warning!("Ignoring linker option {}", options.join(" "));
+            }
+            auto_link
+        })
+        .collect()
+}
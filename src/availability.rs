@@ -0,0 +1,15 @@
+/// Symbol availability, from the `$ld$` markers Apple's libraries export.
+///
+/// A library exporting `$ld$weak$os<VERSION>$<SYMBOL>` says SYMBOL is
+/// missing from the library on the OS release VERSION, so an image built
+/// for it as the minimum has to import SYMBOL weakly. That's what lets
+/// code guarded by `if (@available(...))` load on older systems.
+use crate::linker_args::Version;
+
+/// The symbol `marker` says has to be weakly imported when building for
+/// `min_version`, if it's a `$ld$weak$os` marker for that version.
+pub fn weak_import(marker: &str, min_version: Version) -> Option<&str> {
+    let (version, symbol) = marker.strip_prefix("$ld$weak$os")?.split_once('$')?;
+    let version: Version = version.parse().ok()?;
+    (version == min_version && !symbol.is_empty()).then_some(symbol)
+}
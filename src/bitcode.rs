@@ -0,0 +1,232 @@
+/// The bitcode bundle of -bitcode_bundle, which carries the inputs'
+/// embedded bitcode in __LLVM,__bundle so the output can be rebuilt
+/// from it.
+///
+/// Objects compiled with -fembed-bitcode have their bitcode in
+/// __LLVM,__bitcode and the compiler's options in __LLVM,__cmdline.
+/// The bundle is a xar archive of the bitcode, one file per object,
+/// with a table of contents describing the link. Objects compiled with
+/// -fembed-bitcode-marker only have a placeholder, and give a marker
+/// bundle of a single zero byte.
+///
+/// The archive has no checksums and its table of contents is zlib
+/// stored rather than deflated, which xar readers accept. machop can't
+/// compile bitcode, so it doesn't check that the bundle rebuilds.
+use std::fmt::Write;
+
+pub const SEGMENT: &str = "__LLVM";
+pub const BITCODE_SECTION: &str = "__bitcode";
+pub const CMDLINE_SECTION: &str = "__cmdline";
+pub const BUNDLE_SECTION: &str = "__bundle";
+
+const XAR_MAGIC: u32 = 0x7861_7221;
+const XAR_HEADER_SIZE: u16 = 28;
+const XAR_VERSION: u16 = 1;
+const XAR_CKSUM_NONE: u32 = 0;
+
+/// The bitcode embedded in an object.
+#[derive(Debug, Clone, Default)]
+pub struct Member {
+    pub bitcode: Vec<u8>,
+    /// The options the object was compiled with, from __cmdline.
+    pub cmdline: Vec<String>,
+}
+
+impl Member {
+    /// Whether the object only has -fembed-bitcode-marker's
+    /// placeholder.
+    pub fn is_marker(&self) -> bool {
+        self.bitcode.len() <= 1
+    }
+}
+
+/// What the table of contents records about the link.
+#[derive(Debug, Clone, Default)]
+pub struct Link {
+    pub architecture: String,
+    pub platform: String,
+    pub sdk_version: String,
+    /// The install names of the dylibs linked against.
+    pub dylibs: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// An input without embedded bitcode.
+    Missing { input: String },
+    /// An input with only a marker, linked with inputs that have their
+    /// bitcode.
+    Marker { input: String },
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Missing { input } => write!(
+                f,
+                "{input} has no embedded bitcode for -bitcode_bundle, rebuild it with -fembed-bitcode"
+            ),
+            Error::Marker { input } => write!(
+                f,
+                "{input} only has a bitcode marker but other inputs have their bitcode, rebuild it with -fembed-bitcode"
+            ),
+        }
+    }
+}
+
+/// The marker bundle, for when the inputs only have markers.
+pub fn marker() -> Vec<u8> {
+    vec![0]
+}
+
+/// The bundle of `members`, each paired with the name of its input for
+/// errors. It's a marker bundle if they're all markers.
+pub fn bundle(link: &Link, members: &[(&str, Option<Member>)]) -> Result<Vec<u8>, Error> {
+    let mut present = vec![];
+    for (input, member) in members {
+        match member {
+            Some(member) => present.push((*input, member)),
+            None => {
+                return Err(Error::Missing {
+                    input: input.to_string(),
+                })
+            }
+        }
+    }
+    if present.iter().all(|(_, member)| member.is_marker()) {
+        return Ok(marker());
+    }
+    if let Some((input, _)) = present.iter().find(|(_, member)| member.is_marker()) {
+        return Err(Error::Marker {
+            input: input.to_string(),
+        });
+    }
+    let members: Vec<&Member> = present.into_iter().map(|(_, member)| member).collect();
+    Ok(xar(link, &members))
+}
+
+fn xar(link: &Link, members: &[&Member]) -> Vec<u8> {
+    let toc = toc(link, members);
+    let compressed = zlib_stored(toc.as_bytes());
+    let mut archive = vec![];
+    archive.extend_from_slice(&XAR_MAGIC.to_be_bytes());
+    archive.extend_from_slice(&XAR_HEADER_SIZE.to_be_bytes());
+    archive.extend_from_slice(&XAR_VERSION.to_be_bytes());
+    archive.extend_from_slice(&(compressed.len() as u64).to_be_bytes());
+    archive.extend_from_slice(&(toc.len() as u64).to_be_bytes());
+    archive.extend_from_slice(&XAR_CKSUM_NONE.to_be_bytes());
+    archive.extend_from_slice(&compressed);
+    // The heap, which the table of contents' offsets are relative to.
+    for member in members {
+        archive.extend_from_slice(&member.bitcode);
+    }
+    archive
+}
+
+/// The table of contents, laid out like the one ld64 writes.
+fn toc(link: &Link, members: &[&Member]) -> String {
+    let mut toc = String::new();
+    writeln!(toc, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(toc, "<xar>").unwrap();
+    writeln!(toc, r#" <subdoc subdoc_name="Ld">"#).unwrap();
+    writeln!(toc, "  <version>1.0</version>").unwrap();
+    writeln!(
+        toc,
+        "  <architecture>{}</architecture>",
+        escape(&link.architecture)
+    )
+    .unwrap();
+    writeln!(toc, "  <platform>{}</platform>", escape(&link.platform)).unwrap();
+    writeln!(
+        toc,
+        "  <sdkversion>{}</sdkversion>",
+        escape(&link.sdk_version)
+    )
+    .unwrap();
+    writeln!(toc, "  <dylibs>").unwrap();
+    for dylib in &link.dylibs {
+        writeln!(toc, "   <lib>{}</lib>", escape(dylib)).unwrap();
+    }
+    writeln!(toc, "  </dylibs>").unwrap();
+    writeln!(toc, " </subdoc>").unwrap();
+    writeln!(toc, " <toc>").unwrap();
+    let mut offset = 0;
+    for (index, member) in members.iter().enumerate() {
+        let id = index + 1;
+        let size = member.bitcode.len();
+        writeln!(toc, r#"  <file id="{id}">"#).unwrap();
+        writeln!(toc, "   <name>{id}</name>").unwrap();
+        writeln!(toc, "   <type>file</type>").unwrap();
+        writeln!(toc, "   <data>").unwrap();
+        writeln!(toc, "    <length>{size}</length>").unwrap();
+        writeln!(toc, r#"    <encoding style="application/octet-stream"/>"#).unwrap();
+        writeln!(toc, "    <offset>{offset}</offset>").unwrap();
+        writeln!(toc, "    <size>{size}</size>").unwrap();
+        writeln!(toc, "   </data>").unwrap();
+        writeln!(toc, "   <file-type>Bitcode</file-type>").unwrap();
+        writeln!(toc, "   <clang>").unwrap();
+        for option in &member.cmdline {
+            writeln!(toc, "    <cmd>{}</cmd>", escape(option)).unwrap();
+        }
+        writeln!(toc, "   </clang>").unwrap();
+        writeln!(toc, "  </file>").unwrap();
+        offset += size;
+    }
+    writeln!(toc, " </toc>").unwrap();
+    writeln!(toc, "</xar>").unwrap();
+    toc
+}
+
+/// The options in a __cmdline section, which are NUL terminated.
+pub fn parse_cmdline(data: &[u8]) -> Vec<String> {
+    data.split(|byte| *byte == 0)
+        .filter(|option| !option.is_empty())
+        .map(|option| String::from_utf8_lossy(option).into_owned())
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `data` as a zlib stream of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % MOD;
+        b = (b + a) % MOD;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,103 @@
+/// Restrictions on which images can link directly against a library.
+///
+/// A library inside an umbrella framework names it with
+/// LC_SUB_FRAMEWORK, and it can list the other images allowed to link
+/// against it with LC_SUB_CLIENT. Everything else has to link against
+/// the umbrella. Like ld64, the output is matched by the name of its
+/// install path, which for an executable is where it's finally written
+/// (-final_output, falling back to -o).
+use goblin::mach::{load_command::CommandVariant, MachO};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Restrictions {
+    /// The umbrella framework the library is part of.
+    pub umbrella: Option<String>,
+    pub allowable_clients: Vec<String>,
+}
+
+impl Restrictions {
+    /// The restrictions in the load commands of `macho`, parsed from
+    /// `bytes`.
+    pub fn from_macho(macho: &MachO, bytes: &[u8]) -> Self {
+        let mut restrictions = Self::default();
+        for command in &macho.load_commands {
+            match command.command {
+                CommandVariant::SubFramework(sub) => {
+                    let end = command.offset + sub.cmdsize as usize;
+                    restrictions.umbrella =
+                        lc_str(bytes, command.offset + sub.umbrella as usize, end);
+                }
+                CommandVariant::SubClient(sub) => {
+                    let end = command.offset + sub.cmdsize as usize;
+                    restrictions.allowable_clients.extend(lc_str(
+                        bytes,
+                        command.offset + sub.client as usize,
+                        end,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        restrictions
+    }
+
+    /// Check that the output, which will be installed at
+    /// `install_path`, can link against the library at `path` directly.
+    /// `umbrella` is the one the output is part of, from -umbrella, and
+    /// `client_name` its name from -client_name.
+    pub fn check(
+        &self,
+        path: &str,
+        install_path: &str,
+        umbrella: Option<&str>,
+        client_name: Option<&str>,
+    ) -> Result<(), String> {
+        if self.umbrella.is_none() && self.allowable_clients.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = &self.umbrella {
+            // The umbrella itself, or another library in it.
+            if image_name(install_path) == parent || umbrella == Some(parent) {
+                return Ok(());
+            }
+        }
+        let client_name = client_name.unwrap_or_else(|| image_name(install_path));
+        if self
+            .allowable_clients
+            .iter()
+            .any(|client| client == client_name)
+        {
+            return Ok(());
+        }
+        match &self.umbrella {
+            Some(parent) => Err(format!(
+                "Can't link directly with {path}, link against the umbrella framework {parent}.framework instead"
+            )),
+            None => Err(format!(
+                "Can't link directly with {path}, {} isn't one of its allowable clients",
+                client_name
+            )),
+        }
+    }
+}
+
+/// The name an image is matched against umbrellas and clients by: the
+/// last component of its install path without any `lib` prefix, and
+/// up to the first dot or underscore (so `libfoo_debug.A.dylib` is
+/// `foo`).
+pub fn image_name(install_path: &str) -> &str {
+    let name = install_path.rsplit('/').next().unwrap_or(install_path);
+    let name = name.strip_prefix("lib").unwrap_or(name);
+    name.split(['.', '_']).next().unwrap_or(name)
+}
+
+/// The NUL terminated string at `start` in a load command ending at
+/// `end`.
+fn lc_str(bytes: &[u8], start: usize, end: usize) -> Option<String> {
+    let bytes = bytes.get(start..end.min(bytes.len()))?;
+    let len = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(bytes.len());
+    Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+}
@@ -0,0 +1,196 @@
+/// Shell completion scripts for machop, generated from an option table
+/// so they can't fall behind the options that are accepted.
+///
+/// Values are completed as paths, apart from those of -arch and the
+/// platform of -platform_version, which have a fixed set.
+use std::{collections::HashSet, fmt::Write, str::FromStr};
+
+use crate::{
+    arg_parser::{Kind, OptTable, OptionSpec},
+    linker_args::{Architecture, Platform},
+};
+
+/// The subcommands main understands before the link arguments.
+pub const SUBCOMMANDS: &[&str] = &["link", "version", "options", "daemon", "completions"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            _ => Err(format!("Unknown shell {s}, expected bash, zsh or fish")),
+        }
+    }
+}
+
+/// The values an option has a fixed set of, for its first value.
+fn choices(option: &OptionSpec) -> Option<Vec<String>> {
+    match option.alias.unwrap_or(option.name) {
+        "-arch" => Some(Architecture::ALL.iter().map(ToString::to_string).collect()),
+        "-platform_version" => Some(Platform::ALL.iter().map(ToString::to_string).collect()),
+        _ => None,
+    }
+}
+
+/// The options of `table`, without the spellings shadowed by an earlier
+/// list.
+fn options(table: &OptTable) -> Vec<&'static OptionSpec> {
+    let mut seen = HashSet::new();
+    table
+        .options()
+        .filter(|option| seen.insert(option.name))
+        .collect()
+}
+
+/// The script completing machop's arguments in `shell`.
+pub fn generate(shell: Shell, table: &OptTable) -> String {
+    let options = options(table);
+    match shell {
+        Shell::Bash => bash(&options),
+        Shell::Zsh => zsh(&options),
+        Shell::Fish => fish(&options),
+    }
+}
+
+fn bash(options: &[&OptionSpec]) -> String {
+    let mut script = String::new();
+    writeln!(script, "_machop() {{").unwrap();
+    writeln!(script, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(script, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(script, "    case \"$prev\" in").unwrap();
+    for option in options {
+        if let Some(choices) = choices(option) {
+            writeln!(
+                script,
+                "        {})\n            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n            return\n            ;;",
+                option.name,
+                choices.join(" ")
+            )
+            .unwrap();
+        }
+    }
+    let separate: Vec<&str> = options
+        .iter()
+        .filter(|option| choices(option).is_none())
+        .filter(|option| {
+            matches!(
+                option.kind,
+                Kind::Separate | Kind::JoinedOrSeparate | Kind::MultiArg(_)
+            )
+        })
+        .map(|option| option.name)
+        .collect();
+    writeln!(
+        script,
+        "        {})\n            COMPREPLY=($(compgen -f -- \"$cur\"))\n            return\n            ;;",
+        separate.join("|")
+    )
+    .unwrap();
+    writeln!(script, "    esac").unwrap();
+    writeln!(
+        script,
+        "    if [[ $COMP_CWORD -eq 1 && \"$cur\" != -* ]]; then"
+    )
+    .unwrap();
+    writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"{}\" -f -- \"$cur\"))",
+        SUBCOMMANDS.join(" ")
+    )
+    .unwrap();
+    writeln!(script, "    elif [[ \"$cur\" == -* ]]; then").unwrap();
+    let names: Vec<&str> = options.iter().map(|option| option.name).collect();
+    writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))",
+        names.join(" ")
+    )
+    .unwrap();
+    writeln!(script, "    else").unwrap();
+    writeln!(script, "        COMPREPLY=($(compgen -f -- \"$cur\"))").unwrap();
+    writeln!(script, "    fi").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script, "complete -o filenames -F _machop machop").unwrap();
+    script
+}
+
+fn zsh(options: &[&OptionSpec]) -> String {
+    let mut script = String::new();
+    writeln!(script, "#compdef machop").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "_machop() {{").unwrap();
+    writeln!(script, "    _arguments -s \\").unwrap();
+    for option in options {
+        let (value, rest) = match choices(option) {
+            Some(choices) => (format!(":value:({})", choices.join(" ")), ": : "),
+            None => (":value:_files".to_string(), ": :_files"),
+        };
+        let spec = match option.kind {
+            Kind::Flag => option.name.to_string(),
+            // For _arguments, a trailing = takes the value after it or
+            // as the next word, so it has to be told it's only joined.
+            Kind::Joined => match option.name.strip_suffix('=') {
+                Some(name) => format!("{name}=-{value}"),
+                None => format!("{}-{value}", option.name),
+            },
+            Kind::CommaJoined => format!("{}-{value}", option.name),
+            Kind::Separate => format!("{}{value}", option.name),
+            Kind::JoinedOrSeparate => format!("{}+{value}", option.name),
+            Kind::MultiArg(count) => {
+                let rest = rest.repeat(count.saturating_sub(1));
+                format!("{}{value}{rest}", option.name)
+            }
+        };
+        writeln!(script, "        '{spec}' \\").unwrap();
+    }
+    writeln!(
+        script,
+        "        '1::subcommand:({})' \\",
+        SUBCOMMANDS.join(" ")
+    )
+    .unwrap();
+    writeln!(script, "        '*:input:_files'").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script).unwrap();
+    writeln!(script, "_machop \"$@\"").unwrap();
+    script
+}
+
+fn fish(options: &[&OptionSpec]) -> String {
+    let mut script = String::new();
+    writeln!(
+        script,
+        "complete -c machop -n __fish_use_subcommand -a \"{}\"",
+        SUBCOMMANDS.join(" ")
+    )
+    .unwrap();
+    let mut seen = HashSet::new();
+    for option in options {
+        // fish tells -old style options from --long ones.
+        let name = option.name.trim_end_matches('=');
+        if !seen.insert(name) {
+            continue;
+        }
+        let switch = match name.strip_prefix("--") {
+            Some(long) => format!("-l '{long}'"),
+            None => format!("-o '{}'", name.trim_start_matches('-')),
+        };
+        let value = match (option.kind, choices(option)) {
+            (Kind::Flag, _) => String::new(),
+            (_, Some(choices)) => format!(" -x -a \"{}\"", choices.join(" ")),
+            _ => " -r -F".to_string(),
+        };
+        writeln!(script, "complete -c machop {switch}{value}").unwrap();
+    }
+    script
+}
@@ -0,0 +1,284 @@
+/// Defaults for the command line from machop.toml files, so a project
+/// can keep its search paths and diagnostics settings in one place
+/// rather than in a wrapper script.
+///
+/// The user's file, ~/.config/machop/machop.toml (or the same under
+/// XDG_CONFIG_HOME), is read first, then the machop.toml in the current
+/// directory or the closest of its parents, whose settings win. Flags
+/// given on the command line win over both. For example:
+///
+/// ```toml
+/// # Searched after the -L and -F directories.
+/// library-paths = ["vendor/lib"]
+/// framework-paths = ["vendor/Frameworks"]
+/// # Used when neither -platform_version nor a deployment target
+/// # variable is given.
+/// platform-version = "macos 12.0 13.0"
+/// threads = 4
+/// # Overridden by RUST_LOG.
+/// log-level = "info"
+/// fatal-warnings = true
+/// ```
+///
+/// Only this flat subset of TOML is understood: strings, integers,
+/// booleans and arrays of them, without tables. Relative paths are
+/// relative to the file they're in.
+use std::path::{Path, PathBuf};
+
+use crate::{environment::Environment, linker_args::PlatformVersion};
+
+pub const FILE_NAME: &str = "machop.toml";
+
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub library_paths: Vec<PathBuf>,
+    pub framework_paths: Vec<PathBuf>,
+    pub platform_version: Option<PlatformVersion>,
+    pub threads: Option<usize>,
+    /// The log level when RUST_LOG isn't set.
+    pub log_level: Option<String>,
+    pub fatal_warnings: Option<bool>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+impl Config {
+    /// Parse the contents of a config file in `dir`.
+    pub fn parse(content: &str, dir: &Path) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut lines = content.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let number = index + 1;
+            let line = without_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(format!("Tables aren't supported, on line {number}"));
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("Expected KEY = VALUE on line {number}"));
+            };
+            let key = key.trim();
+            // Arrays can be spread over several lines.
+            let mut value = value.trim().to_string();
+            if value.starts_with('[') {
+                while parse_value(&value).is_err() {
+                    let Some((_, next)) = lines.next() else {
+                        break;
+                    };
+                    value.push(' ');
+                    value.push_str(without_comment(next).trim());
+                }
+            }
+            let value = parse_value(&value).map_err(|e| format!("{e} on line {number}"))?;
+            config
+                .set(key, value, dir)
+                .map_err(|e| format!("{e} on line {number}"))?;
+        }
+        Ok(config)
+    }
+
+    fn set(&mut self, key: &str, value: Value, dir: &Path) -> Result<(), String> {
+        let expected = |what: &str, value: &Value| {
+            format!("Expected {what} for {key}, found {}", value.type_name())
+        };
+        let paths = |value: Value| -> Result<Vec<PathBuf>, String> {
+            let Value::Array(values) = value else {
+                return Err(expected("an array of paths", &value));
+            };
+            values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(path) => Ok(dir.join(path)),
+                    other => Err(expected("an array of paths", &other)),
+                })
+                .collect()
+        };
+        match (key, value) {
+            ("library-paths", value) => self.library_paths = paths(value)?,
+            ("framework-paths", value) => self.framework_paths = paths(value)?,
+            ("platform-version", Value::String(version)) => {
+                self.platform_version = Some(
+                    version
+                        .parse()
+                        .map_err(|e| format!("Invalid platform-version {version}: {e}"))?,
+                )
+            }
+            ("threads", Value::Integer(threads)) => {
+                self.threads = Some(
+                    threads
+                        .try_into()
+                        .map_err(|_| format!("Invalid thread count {threads}"))?,
+                )
+            }
+            ("log-level", Value::String(level)) => self.log_level = Some(level),
+            ("fatal-warnings", Value::Boolean(fatal)) => self.fatal_warnings = Some(fatal),
+            ("platform-version" | "log-level", value) => return Err(expected("a string", &value)),
+            ("threads", value) => return Err(expected("an integer", &value)),
+            ("fatal-warnings", value) => return Err(expected("a boolean", &value)),
+            (key, _) => return Err(format!("Unknown key {key}")),
+        }
+        Ok(())
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&content, dir).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// The settings of `self`, overridden by those in `other`. Search
+    /// paths from both are kept, `other`'s first.
+    pub fn merge(self, other: Config) -> Self {
+        Self {
+            library_paths: [other.library_paths, self.library_paths].concat(),
+            framework_paths: [other.framework_paths, self.framework_paths].concat(),
+            platform_version: other.platform_version.or(self.platform_version),
+            threads: other.threads.or(self.threads),
+            log_level: other.log_level.or(self.log_level),
+            fatal_warnings: other.fatal_warnings.or(self.fatal_warnings),
+        }
+    }
+
+    /// The user's config, found from `env`'s HOME or XDG_CONFIG_HOME,
+    /// merged with the project's for `dir`, either of which may be
+    /// missing.
+    pub fn find(dir: &Path, env: &Environment) -> Result<Self, String> {
+        let mut config = Self::default();
+        if let Some(path) = user_config(env).filter(|path| path.is_file()) {
+            config = config.merge(Self::from_file(&path)?);
+        }
+        if let Some(path) = dir
+            .ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            config = config.merge(Self::from_file(&path)?);
+        }
+        Ok(config)
+    }
+}
+
+fn user_config(env: &Environment) -> Option<PathBuf> {
+    let dir = match env.var("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env.var("HOME")?).join(".config"),
+    };
+    Some(dir.join("machop").join(FILE_NAME))
+}
+
+/// `line` up to the `#` starting a comment, if there's one outside a
+/// string.
+fn without_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(open), c) if c == open && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// Parse a whole value.
+fn parse_value(s: &str) -> Result<Value, String> {
+    let (value, rest) = parse_prefix(s)?;
+    let rest = rest.trim_start();
+    if !rest.is_empty() {
+        return Err(format!("Unexpected {rest} after the value"));
+    }
+    Ok(value)
+}
+
+/// Parse the value at the start of `s`, returning what follows it.
+fn parse_prefix(s: &str) -> Result<(Value, &str), String> {
+    let s = s.trim_start();
+    if let Some(rest) = s.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((index, c)) = chars.next() {
+            match c {
+                '"' => return Ok((Value::String(string), &rest[index + 1..])),
+                '\\' => match chars.next() {
+                    Some((_, '"')) => string.push('"'),
+                    Some((_, '\\')) => string.push('\\'),
+                    Some((_, 'n')) => string.push('\n'),
+                    Some((_, 't')) => string.push('\t'),
+                    other => {
+                        return Err(format!(
+                            "Unsupported escape \\{}",
+                            other.map(|(_, c)| c).unwrap_or_default()
+                        ))
+                    }
+                },
+                c => string.push(c),
+            }
+        }
+        return Err("Unterminated string".to_string());
+    }
+    if let Some(rest) = s.strip_prefix('\'') {
+        // Literal strings have no escapes.
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| "Unterminated string".to_string())?;
+        return Ok((Value::String(rest[..end].to_string()), &rest[end + 1..]));
+    }
+    if let Some(mut rest) = s.strip_prefix('[') {
+        let mut values = vec![];
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Ok((Value::Array(values), after));
+            }
+            let (value, after) = parse_prefix(rest)?;
+            values.push(value);
+            rest = after.trim_start();
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None if rest.starts_with(']') => {}
+                None => return Err("Expected , or ] in the array".to_string()),
+            }
+        }
+    }
+    let end = s
+        .find(|c: char| c.is_whitespace() || c == ',' || c == ']')
+        .unwrap_or(s.len());
+    let (word, rest) = s.split_at(end);
+    match word {
+        "true" => Ok((Value::Boolean(true), rest)),
+        "false" => Ok((Value::Boolean(false), rest)),
+        "" => Err("Expected a value".to_string()),
+        word => word
+            .replace('_', "")
+            .parse()
+            .map(|integer| (Value::Integer(integer), rest))
+            .map_err(|_| format!("Invalid value {word}")),
+    }
+}
@@ -0,0 +1,94 @@
+/// The cpusubtype of the output, merged from the objects'.
+///
+/// arm64 objects are either for any arm64 CPU or specifically ARMv8,
+/// and the output is as specific as the most specific of them. arm64e
+/// objects use pointer authentication, so can only be linked with other
+/// arm64e objects, and the ABI version in their capability bits has to
+/// agree. -force_cpusubtype_ALL skips all of this and records
+/// CPU_SUBTYPE_ARM64_ALL.
+use goblin::mach::cputype::{
+    CPU_SUBTYPE_ARM64_ALL, CPU_SUBTYPE_ARM64_E, CPU_SUBTYPE_ARM64_V8, CPU_SUBTYPE_MASK,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// An arm64e object linked with an arm64 one.
+    MixedArm64e { arm64e: String, arm64: String },
+    /// Objects with different capability bits, like arm64e objects
+    /// for different pointer authentication ABIs.
+    Capabilities {
+        first: (String, u32),
+        second: (String, u32),
+    },
+    /// A subtype machop doesn't know how to merge.
+    Unknown { input: String, subtype: u32 },
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MixedArm64e { arm64e, arm64 } => write!(
+                f,
+                "{arm64e} is built for arm64e but {arm64} is built for arm64, they can't be linked together"
+            ),
+            Error::Capabilities {
+                first: (first, first_bits),
+                second: (second, second_bits),
+            } => write!(
+                f,
+                "{first} has cpusubtype capability bits {first_bits:#x} but {second} has {second_bits:#x}"
+            ),
+            Error::Unknown { input, subtype } => {
+                write!(f, "{input} has unknown arm64 cpusubtype {subtype:#x}")
+            }
+        }
+    }
+}
+
+/// The cpusubtype of an output linked from objects with `subtypes`,
+/// each paired with the name of its input for errors. Without any
+/// objects it's CPU_SUBTYPE_ARM64_ALL.
+pub fn merge(subtypes: &[(&str, u32)]) -> Result<u32, Error> {
+    let mut merged: Option<(&str, u32)> = None;
+    for &(input, subtype) in subtypes {
+        let base = subtype & !CPU_SUBTYPE_MASK;
+        if !matches!(
+            base,
+            CPU_SUBTYPE_ARM64_ALL | CPU_SUBTYPE_ARM64_V8 | CPU_SUBTYPE_ARM64_E
+        ) {
+            return Err(Error::Unknown {
+                input: input.to_string(),
+                subtype,
+            });
+        }
+        let Some((first, first_subtype)) = merged else {
+            merged = Some((input, subtype));
+            continue;
+        };
+        let first_base = first_subtype & !CPU_SUBTYPE_MASK;
+        if (first_base == CPU_SUBTYPE_ARM64_E) != (base == CPU_SUBTYPE_ARM64_E) {
+            let (arm64e, arm64) = if base == CPU_SUBTYPE_ARM64_E {
+                (input, first)
+            } else {
+                (first, input)
+            };
+            return Err(Error::MixedArm64e {
+                arm64e: arm64e.to_string(),
+                arm64: arm64.to_string(),
+            });
+        }
+        if first_subtype & CPU_SUBTYPE_MASK != subtype & CPU_SUBTYPE_MASK {
+            return Err(Error::Capabilities {
+                first: (first.to_string(), first_subtype & CPU_SUBTYPE_MASK),
+                second: (input.to_string(), subtype & CPU_SUBTYPE_MASK),
+            });
+        }
+        // ALL and V8 are the only ones left to choose between.
+        if base > first_base {
+            merged = Some((input, subtype));
+        }
+    }
+    Ok(merged.map_or(CPU_SUBTYPE_ARM64_ALL, |(_, subtype)| subtype))
+}
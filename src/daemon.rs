@@ -0,0 +1,165 @@
+/// Linking through a long running machop process.
+///
+/// `machop daemon <SOCKET>` listens on a Unix socket and links on behalf
+/// of clients, sharing one [InputCache](crate::input_cache::InputCache)
+/// between links so system TBDs and SDK dylibs are read and parsed once
+/// rather than on every invocation. A client is any machop or ld
+/// invocation with MACHOP_DAEMON set to the socket.
+///
+/// A request is a sequence of NUL terminated strings: the working
+/// directory, the client's environment entries that affect a link as
+/// `KEY=VALUE`, an empty string, then the arguments. The reply is a
+/// single byte, the exit status of the link.
+use std::{
+    ffi::OsString,
+    io::{self, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        ffi::{OsStrExt, OsStringExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+};
+
+use crate::environment::Environment;
+
+/// The environment variable naming the socket of the daemon to link
+/// with.
+pub const SOCKET_ENV: &str = "MACHOP_DAEMON";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub cwd: PathBuf,
+    pub env: Vec<(OsString, OsString)>,
+    pub args: Vec<OsString>,
+}
+
+impl Request {
+    /// A request for linking `args` the way this process would.
+    pub fn from_current(args: Vec<OsString>) -> io::Result<Self> {
+        let env = std::env::vars_os()
+            .filter(|(key, _)| is_forwarded(key))
+            .collect();
+        Ok(Self {
+            cwd: std::env::current_dir()?,
+            env,
+            args,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut push = |s: &[u8]| {
+            bytes.extend_from_slice(s);
+            bytes.push(0);
+        };
+        push(self.cwd.as_os_str().as_bytes());
+        for (key, value) in &self.env {
+            push(&[key.as_bytes(), b"=", value.as_bytes()].concat());
+        }
+        push(b"");
+        for arg in &self.args {
+            push(arg.as_bytes());
+        }
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let Some(bytes) = bytes.strip_suffix(b"\0") else {
+            return Err(invalid("request isn't NUL terminated"));
+        };
+        let mut fields = bytes
+            .split(|b| *b == 0)
+            .map(|field| OsString::from_vec(field.to_vec()));
+        let cwd = fields
+            .next()
+            .ok_or_else(|| invalid("request has no working directory"))?
+            .into();
+        let mut env = vec![];
+        loop {
+            let entry = fields
+                .next()
+                .ok_or_else(|| invalid("request environment isn't terminated"))?;
+            if entry.is_empty() {
+                break;
+            }
+            let entry = entry.into_vec();
+            let Some(split) = entry.iter().position(|b| *b == b'=') else {
+                return Err(invalid("environment entry has no ="));
+            };
+            env.push((
+                OsString::from_vec(entry[..split].to_vec()),
+                OsString::from_vec(entry[split + 1..].to_vec()),
+            ));
+        }
+        Ok(Self {
+            cwd,
+            env,
+            args: fields.collect(),
+        })
+    }
+
+    /// The client's working directory and the environment it
+    /// forwarded, for the link to run in.
+    pub fn environment(&self) -> Environment {
+        Environment::client(self.cwd.clone(), self.env.clone())
+    }
+}
+
+/// Whether an environment variable changes the result of a link and so
+/// is sent with a request.
+fn is_forwarded(key: &std::ffi::OsStr) -> bool {
+    key.to_str().is_some_and(|key| {
+        key.ends_with("_DEPLOYMENT_TARGET")
+            || key == "SOURCE_DATE_EPOCH"
+            // Where the user's config is found.
+            || key == "HOME"
+            || key == "XDG_CONFIG_HOME"
+    })
+}
+
+/// Have the daemon listening on `socket` link `request`, returning the
+/// link's exit status.
+pub fn forward(socket: &Path, request: &Request) -> io::Result<u8> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(&request.encode())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut status = [0];
+    stream.read_exact(&mut status)?;
+    Ok(status[0])
+}
+
+/// Listen on `socket` and pass each request to `link`, one at a time.
+/// A stale socket left by an earlier daemon is replaced.
+pub fn serve(socket: &Path, mut link: impl FnMut(Request) -> u8) -> io::Result<()> {
+    if socket.exists() && UnixStream::connect(socket).is_err() {
+        std::fs::remove_file(socket)?;
+    }
+    let listener = UnixListener::bind(socket)?;
+    log::info!("Listening on {}", socket.display());
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Unable to accept a connection: {e}");
+                continue;
+            }
+        };
+        let mut bytes = vec![];
+        let request = stream
+            .read_to_end(&mut bytes)
+            .and_then(|_| Request::decode(&bytes));
+        let status = match request {
+            Ok(request) => link(request),
+            Err(e) => {
+                log::warn!("Ignoring malformed request: {e}");
+                1
+            }
+        };
+        if let Err(e) = stream.write_all(&[status]) {
+            log::warn!("Unable to reply to a client: {e}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,84 @@
+/// Listings of the directories libraries and frameworks are searched for
+/// in, so finding one doesn't stat every candidate name in every search
+/// path.
+///
+/// Each directory is read once per link. Most candidates aren't there,
+/// and those are answered from the listing without touching the
+/// filesystem. A listed name is still checked with a stat, so a dangling
+/// link is passed over as before, and so is a name that's only listed
+/// with a different case, which on a case-insensitive filesystem is
+/// the same file.
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Default)]
+struct Listing {
+    names: HashSet<OsString>,
+    /// The names that are UTF-8, lowercased.
+    folded: HashSet<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct DirCache {
+    listings: Mutex<HashMap<PathBuf, Arc<Listing>>>,
+}
+
+impl DirCache {
+    fn listing(&self, dir: &Path) -> Arc<Listing> {
+        let mut listings = self.listings.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(listing) = listings.get(dir) {
+            return listing.clone();
+        }
+        // A directory that can't be read has nothing to find in it.
+        let names: HashSet<OsString> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .collect();
+        let folded = names
+            .iter()
+            .filter_map(|name| name.to_str())
+            .map(str::to_lowercase)
+            .collect();
+        let listing = Arc::new(Listing { names, folded });
+        listings.insert(dir.to_path_buf(), listing.clone());
+        listing
+    }
+
+    /// The UTF-8 names of the entries in `dir`, sorted, none if it
+    /// can't be read.
+    pub fn entries(&self, dir: &Path) -> Vec<String> {
+        let mut entries: Vec<String> = self
+            .listing(dir)
+            .names
+            .iter()
+            .filter_map(|name| name.to_str())
+            .map(str::to_string)
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Whether `path` exists, like [Path::exists].
+    pub fn exists(&self, path: &Path) -> bool {
+        let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+            return path.exists();
+        };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let listing = self.listing(dir);
+        let listed = listing.names.contains(name)
+            || name
+                .to_str()
+                .is_some_and(|name| listing.folded.contains(&name.to_lowercase()));
+        listed && path.exists()
+    }
+}
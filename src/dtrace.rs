@@ -0,0 +1,22 @@
+/// DTrace USDT probes, as compiled from the macros `dtrace -h`
+/// generates.
+///
+/// A probe site is a call to `___dtrace_probe$<provider>$<probe>$...`
+/// and an is-enabled check a call to `___dtrace_isenabled$...`, neither
+/// of which is defined anywhere. ld64 replaces the calls with nops and
+/// describes the probes in a `__DATA,__dof` section for dtrace to find
+/// them. machop doesn't generate DOF, so a link with probes fails
+/// rather than produce a binary whose probes can't be enabled.
+const PREFIXES: &[&str] = &[
+    "___dtrace_probe$",
+    "___dtrace_isenabled$",
+    // Only describe the provider.
+    "___dtrace_stability$",
+    "___dtrace_typedefs$",
+];
+
+/// Whether `name` is one of the undefined symbols DTrace probes refer
+/// to.
+pub fn is_probe_symbol(name: &str) -> bool {
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
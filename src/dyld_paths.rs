@@ -0,0 +1,58 @@
+/// Paths dyld resolves: install names and run path search directories.
+///
+/// Both can start with one of dyld's macros, `@rpath`, `@loader_path`
+/// or `@executable_path`, standing for a directory only known at run
+/// time. Anything else has to be absolute.
+const MACROS: [&str; 3] = ["@rpath", "@loader_path", "@executable_path"];
+
+/// Where a path is recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathUse {
+    /// A dylib's install name, in LC_ID_DYLIB or LC_LOAD_DYLIB.
+    InstallName,
+    /// A run path search directory, in LC_RPATH.
+    Rpath,
+}
+
+/// Check `path` can be resolved by dyld when recorded for `usage`, and
+/// tidy it up: repeated and trailing slashes are dropped.
+pub fn normalize(path: &str, usage: PathUse) -> Result<String, String> {
+    let (start, rest) = match path.split_once('/') {
+        Some((start, rest)) => (start, rest),
+        None => (path, ""),
+    };
+    if let Some(macro_name) = start.strip_prefix('@') {
+        if !MACROS.contains(&start) {
+            return Err(format!(
+                "{path} uses @{macro_name}, which dyld doesn't know"
+            ));
+        }
+        // dyld doesn't expand a run path in terms of run paths.
+        if usage == PathUse::Rpath && start == "@rpath" {
+            return Err(format!("{path} is a run path relative to the run paths"));
+        }
+        if usage == PathUse::InstallName && rest.is_empty() {
+            return Err(format!("{path} doesn't name a file after {start}"));
+        }
+    } else if !start.is_empty() {
+        return Err(format!(
+            "{path} is relative, dyld needs an absolute path or one starting with {}",
+            MACROS.join(", ")
+        ));
+    }
+    let components: Vec<&str> = rest.split('/').filter(|part| !part.is_empty()).collect();
+    let mut normalized = start.to_string();
+    for component in components {
+        normalized.push('/');
+        normalized.push_str(component);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+/// Whether dyld needs a run path to find `install_name`.
+pub fn needs_rpath(install_name: &str) -> bool {
+    install_name.starts_with("@rpath/")
+}
@@ -0,0 +1,55 @@
+/// The working directory and environment variables a link runs with.
+///
+/// Usually they're the process's own, but a daemon links for clients in
+/// other directories, with their own environment. Rather than take each
+/// client's on for the whole process, the link is given them: relative
+/// paths are resolved against the client's directory and variables read
+/// from what the client sent.
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    /// The client's working directory, or the process's if there's none.
+    cwd: Option<PathBuf>,
+    /// The client's variables, or the process's if there are none.
+    vars: Option<Vec<(OsString, OsString)>>,
+}
+
+impl Environment {
+    /// The process's own.
+    pub fn current() -> Self {
+        Self::default()
+    }
+
+    /// A client's, whose variables are only `vars`.
+    pub fn client(cwd: PathBuf, vars: Vec<(OsString, OsString)>) -> Self {
+        Self {
+            cwd: Some(cwd),
+            vars: Some(vars),
+        }
+    }
+
+    /// The value of the variable `key`, if it's set.
+    pub fn var(&self, key: &str) -> Option<OsString> {
+        match &self.vars {
+            Some(vars) => vars
+                .iter()
+                .find(|(name, _)| name == OsStr::new(key))
+                .map(|(_, value)| value.clone()),
+            None => std::env::var_os(key),
+        }
+    }
+
+    /// `path` as the link should open it. Relative paths are relative
+    /// to the client's directory, the process's are left for the OS to
+    /// resolve so diagnostics show them as they were given.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.join(path),
+            None => path.as_ref().to_path_buf(),
+        }
+    }
+}
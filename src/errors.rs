@@ -0,0 +1,54 @@
+/// Errors from the link in progress, for -error-limit.
+///
+/// The link reports errors with [error!](crate::error), which counts
+/// them and only hands the first -error-limit of them to the logger, so
+/// a link with thousands of undefined or duplicate symbols doesn't bury
+/// the first ones. The rest are summarised when the link is done. Like
+/// [warnings](crate::warnings), this assumes one link at a time.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static ERRORS: AtomicUsize = AtomicUsize::new(0);
+static LIMIT: AtomicUsize = AtomicUsize::new(0);
+
+/// Log an error for the link in progress, like `log::error!`, unless
+/// -error-limit errors have been already.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => {{
+        if $crate::errors::count() {
+            log::error!($($arg)+)
+        }
+    }};
+}
+
+/// Start counting afresh, for a new link reporting at most `limit`
+/// errors, or all of them when it's 0.
+pub fn start(limit: usize) {
+    ERRORS.store(0, Ordering::Relaxed);
+    LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Count an error, done by [error!](crate::error), and whether it's
+/// within the limit so should be reported.
+pub fn count() -> bool {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let before = ERRORS.fetch_add(1, Ordering::Relaxed);
+    limit == 0 || before < limit
+}
+
+/// The number of errors since [start], reported or not.
+pub fn counted() -> usize {
+    ERRORS.load(Ordering::Relaxed)
+}
+
+/// Say how many errors went unreported, if any did.
+pub fn summarize() {
+    let limit = LIMIT.load(Ordering::Relaxed);
+    let errors = counted();
+    if limit != 0 && errors > limit {
+        log::error!(
+            "... and {} more errors, use -error-limit 0 to see all",
+            errors - limit
+        );
+    }
+}
@@ -0,0 +1,140 @@
+/// The export trie dyld looks up an image's symbols in, for dlsym and
+/// for binding to them.
+///
+/// Each node holds the export whose name is the path to it, if any, and
+/// edges labelled with the bytes that follow. Nodes refer to each other
+/// by ULEB128 offsets, so their offsets are worked out again until no
+/// node grows.
+use std::iter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Export<'a> {
+    pub name: &'a str,
+    /// EXPORT_SYMBOL_FLAGS_*.
+    pub flags: u64,
+    /// The offset from the Mach-O header, or the value of an absolute
+    /// symbol.
+    pub address: u64,
+}
+
+pub const EXPORT_SYMBOL_FLAGS_KIND_REGULAR: u64 = 0x00;
+pub const EXPORT_SYMBOL_FLAGS_KIND_THREAD_LOCAL: u64 = 0x01;
+pub const EXPORT_SYMBOL_FLAGS_KIND_ABSOLUTE: u64 = 0x02;
+pub const EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION: u64 = 0x04;
+
+#[derive(Debug, Default)]
+struct Node {
+    /// The flags and address of the export ending here.
+    terminal: Option<(u64, u64)>,
+    children: Vec<(Vec<u8>, usize)>,
+}
+
+/// The trie of `exports`, padded to a multiple of the pointer size like
+/// ld64's. It's empty if there are no exports.
+pub fn build(exports: &[Export]) -> Vec<u8> {
+    if exports.is_empty() {
+        return vec![];
+    }
+    let mut nodes = vec![Node::default()];
+    for export in exports {
+        insert(
+            &mut nodes,
+            export.name.as_bytes(),
+            (export.flags, export.address),
+        );
+    }
+    let mut offsets = vec![0; nodes.len()];
+    loop {
+        let mut offset = 0;
+        let mut changed = false;
+        for (index, node) in nodes.iter().enumerate() {
+            if offsets[index] != offset {
+                offsets[index] = offset;
+                changed = true;
+            }
+            offset += encode(node, &offsets).len() as u64;
+        }
+        if !changed {
+            break;
+        }
+    }
+    let mut trie: Vec<u8> = nodes
+        .iter()
+        .flat_map(|node| encode(node, &offsets))
+        .collect();
+    trie.resize(trie.len().next_multiple_of(8), 0);
+    trie
+}
+
+fn insert(nodes: &mut Vec<Node>, name: &[u8], terminal: (u64, u64)) {
+    let mut node = 0;
+    let mut rest = name;
+    loop {
+        if rest.is_empty() {
+            nodes[node].terminal = Some(terminal);
+            return;
+        }
+        let found = nodes[node]
+            .children
+            .iter()
+            .position(|(edge, _)| edge[0] == rest[0]);
+        let Some(index) = found else {
+            nodes.push(Node {
+                terminal: Some(terminal),
+                children: vec![],
+            });
+            let child = nodes.len() - 1;
+            nodes[node].children.push((rest.to_vec(), child));
+            return;
+        };
+        let (edge, child) = nodes[node].children[index].clone();
+        let common = iter::zip(&edge, rest).take_while(|(a, b)| a == b).count();
+        if common < edge.len() {
+            // Split the edge where the names part.
+            nodes.push(Node {
+                terminal: None,
+                children: vec![(edge[common..].to_vec(), child)],
+            });
+            let middle = nodes.len() - 1;
+            nodes[node].children[index] = (edge[..common].to_vec(), middle);
+            node = middle;
+        } else {
+            node = child;
+        }
+        rest = &rest[common..];
+    }
+}
+
+/// A node, with its children at `offsets`.
+fn encode(node: &Node, offsets: &[u64]) -> Vec<u8> {
+    let mut terminal = vec![];
+    if let Some((flags, address)) = node.terminal {
+        push_uleb128(&mut terminal, flags);
+        push_uleb128(&mut terminal, address);
+    }
+    let mut buf = vec![];
+    push_uleb128(&mut buf, terminal.len() as u64);
+    buf.extend_from_slice(&terminal);
+    // Each child starts with a different byte and names are UTF-8, so
+    // there are never more than fit.
+    let children = u8::try_from(node.children.len()).expect("a node has at most 255 children");
+    buf.push(children);
+    for (edge, child) in &node.children {
+        buf.extend_from_slice(edge);
+        buf.push(0);
+        push_uleb128(&mut buf, offsets[*child]);
+    }
+    buf
+}
+
+fn push_uleb128(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,77 @@
+/// Universal files with 64-bit headers, which lipo writes when a slice is
+/// bigger than 4 GiB or starts past 4 GiB into the file.
+///
+/// goblin only reads the original fat header, whose fat_arch entries have
+/// 32-bit offsets and sizes. An input with fat_arch_64 entries instead is
+/// cut down to the slice being linked as it's read, so the rest of the
+/// link sees the thin file that slice is.
+pub const FAT_MAGIC_64: u32 = 0xcafe_babf;
+
+/// The fat_header, then nfat_arch fat_arch_64 entries of cputype,
+/// cpusubtype, offset, size, align and a reserved word.
+const HEADER_SIZE: usize = 8;
+const ARCH_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arch {
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    pub offset: u64,
+    pub size: u64,
+}
+
+impl Arch {
+    /// The contents of this slice of `bytes`.
+    pub fn slice<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.offset as usize..(self.offset + self.size) as usize]
+    }
+}
+
+/// Whether `bytes` start with a fat header with 64-bit entries.
+pub fn is_fat64(bytes: &[u8]) -> bool {
+    bytes
+        .get(..4)
+        .is_some_and(|word| word == FAT_MAGIC_64.to_be_bytes())
+}
+
+/// The slices listed by the fat_arch_64 header of `bytes`.
+pub fn arches(bytes: &[u8]) -> Result<Vec<Arch>, String> {
+    // Fat headers are big endian.
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|word| u32::from_be_bytes(word.try_into().unwrap()))
+            .ok_or("fat header is truncated")
+    };
+    let u64_at = |at: usize| {
+        bytes
+            .get(at..at + 8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+            .ok_or("fat header is truncated")
+    };
+    if !is_fat64(bytes) {
+        return Err("not a universal file with a 64-bit header".to_string());
+    }
+    let count = u32_at(4)? as usize;
+    let mut arches = Vec::with_capacity(count.min(bytes.len() / ARCH_SIZE));
+    for i in 0..count {
+        let at = HEADER_SIZE + i * ARCH_SIZE;
+        let arch = Arch {
+            cputype: u32_at(at)?,
+            cpusubtype: u32_at(at + 4)?,
+            offset: u64_at(at + 8)?,
+            size: u64_at(at + 16)?,
+        };
+        if arch
+            .offset
+            .checked_add(arch.size)
+            .is_none_or(|end| end > bytes.len() as u64)
+        {
+            return Err(format!(
+                "slice {i} is truncated, it ends past the end of the file"
+            ));
+        }
+        arches.push(arch);
+    }
+    Ok(arches)
+}
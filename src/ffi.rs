@@ -0,0 +1,142 @@
+/// A C interface for linking in-process, declared in include/machop.h,
+/// for toolchains that aren't written in Rust and would otherwise spawn
+/// machop.
+///
+/// Diagnostics are captured by installing a logger on the first link,
+/// unless the host has already installed one, which then receives them
+/// instead. Links through this interface run one at a time, so their
+/// diagnostics don't mix.
+use std::{
+    ffi::{c_char, c_int, CStr, CString, OsStr, OsString},
+    fmt::Write,
+    os::unix::ffi::OsStrExt,
+    panic::{self, AssertUnwindSafe},
+    sync::{Mutex, MutexGuard, Once},
+};
+
+use crate::{
+    input_cache::InputCache,
+    link::link,
+    linker_args::{Args, Persona, Query, USAGE},
+};
+
+/// Warnings and errors logged by the link in progress.
+static DIAGNOSTICS: Mutex<Option<String>> = Mutex::new(None);
+static LINK: Mutex<()> = Mutex::new(());
+static INSTALL: Once = Once::new();
+
+struct Capture;
+
+static CAPTURE: Capture = Capture;
+
+impl log::Log for Capture {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Some(diagnostics) = lock(&DIAGNOSTICS).as_mut() {
+            let level = match record.level() {
+                log::Level::Error => "error",
+                _ => "warning",
+            };
+            writeln!(diagnostics, "{level}: {}", record.args()).unwrap();
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Link with the command line `argv`, of `argc` arguments, as if it were
+/// run. `argv[0]` is the program name, which picks the options accepted
+/// as for the executable, so "ld" takes ld64's.
+///
+/// Returns 0 if the link succeeded, 1 if it failed and 101 if machop hit
+/// an internal error. If `out_diagnostics` isn't null it's set to the
+/// warnings and errors reported, one per line, or the usage for -help,
+/// to be freed with [machop_free_diagnostics].
+///
+/// # Safety
+///
+/// `argv` must point to `argc` NUL terminated strings and
+/// `out_diagnostics` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn machop_link(
+    argc: c_int,
+    argv: *const *const c_char,
+    out_diagnostics: *mut *mut c_char,
+) -> c_int {
+    let _link = lock(&LINK);
+    INSTALL.call_once(|| {
+        if log::set_logger(&CAPTURE).is_ok() {
+            log::set_max_level(log::LevelFilter::Warn);
+        }
+    });
+    *lock(&DIAGNOSTICS) = Some(String::new());
+
+    let argv: Vec<OsString> = (0..argc.max(0) as usize)
+        .map(|index| {
+            let arg = CStr::from_ptr(*argv.add(index));
+            OsStr::from_bytes(arg.to_bytes()).to_os_string()
+        })
+        .collect();
+    let persona = argv
+        .first()
+        .map_or(Persona::Machop, |argv0| Persona::from_argv0(argv0));
+    let args = argv.into_iter().skip(1).collect();
+    let status = match Args::parse(args, persona.default_dialect()) {
+        // Like the command line, -help is answered on stderr, which
+        // for the host is the diagnostics.
+        Ok(args) if args.query == Some(Query::Help) => {
+            if let Some(diagnostics) = lock(&DIAGNOSTICS).as_mut() {
+                diagnostics.push_str(USAGE);
+            }
+            1
+        }
+        Ok(args) => {
+            let cache = InputCache::default();
+            match panic::catch_unwind(AssertUnwindSafe(|| link(args, &cache, &mut ()))) {
+                Ok(Ok(())) => 0,
+                Ok(Err(())) => 1,
+                Err(_) => {
+                    log::error!("machop hit an internal error");
+                    101
+                }
+            }
+        }
+        Err(e) => {
+            log::error!("{e}");
+            1
+        }
+    };
+
+    let diagnostics = lock(&DIAGNOSTICS).take().unwrap_or_default();
+    if !out_diagnostics.is_null() {
+        // Log messages don't contain NULs, but paths in them could.
+        let diagnostics = CString::new(diagnostics.replace('\0', "?")).unwrap_or_default();
+        *out_diagnostics = diagnostics.into_raw();
+    }
+    status
+}
+
+/// Free diagnostics returned by [machop_link].
+///
+/// # Safety
+///
+/// `diagnostics` must be null or have come from [machop_link], and not
+/// have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn machop_free_diagnostics(diagnostics: *mut c_char) {
+    if !diagnostics.is_null() {
+        drop(CString::from_raw(diagnostics));
+    }
+}
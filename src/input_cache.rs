@@ -0,0 +1,132 @@
+/// Reading input files once.
+///
+/// The same archive or TBD often appears more than once on a command
+/// line, e.g. through both -l and a path. Files are keyed by their
+/// canonical path and modification time, so different spellings of a
+/// path share one read and a file that changed is read again.
+///
+/// Parsed TBDs are kept too, since parsing the SDK's stubs is the most
+/// expensive part of reading the inputs. A cache that outlives one link,
+/// as in daemon mode, should be [pruned](InputCache::prune) between
+/// links.
+///
+/// Inputs can also be [supplied in memory](InputCache::insert_buffer),
+/// by callers that already hold their contents, under the name they're
+/// given as in the arguments. They're used in place of any file of that
+/// name.
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::SystemTime,
+};
+
+use crate::{linker_args::Platform, tbd::TbdDylib};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputKey {
+    pub path: PathBuf,
+    pub modified: Option<SystemTime>,
+}
+
+impl InputKey {
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let path = path.canonicalize()?;
+        let modified = path.metadata()?.modified().ok();
+        Ok(Self { path, modified })
+    }
+}
+
+/// A TBD file and the platforms it was matched against.
+type TbdKey = (InputKey, Vec<Platform>);
+
+#[derive(Debug, Default)]
+pub struct InputCache {
+    contents: Mutex<HashMap<InputKey, Arc<Vec<u8>>>>,
+    buffers: Mutex<HashMap<PathBuf, Arc<Vec<u8>>>>,
+    tbds: Mutex<HashMap<TbdKey, Arc<TbdDylib>>>,
+}
+
+impl InputCache {
+    /// Use `contents` as the input named `name`, replacing any buffer
+    /// already given that name.
+    pub fn insert_buffer(&self, name: impl Into<PathBuf>, contents: Vec<u8>) {
+        lock(&self.buffers).insert(name.into(), Arc::new(contents));
+    }
+
+    /// Forget the buffer named `name`.
+    pub fn remove_buffer(&self, name: &Path) {
+        lock(&self.buffers).remove(name);
+    }
+
+    /// The key `path` is cached under. Buffers are keyed by their name,
+    /// with no modification time.
+    pub fn key(&self, path: &Path) -> io::Result<InputKey> {
+        if lock(&self.buffers).contains_key(path) {
+            return Ok(InputKey {
+                path: path.to_path_buf(),
+                modified: None,
+            });
+        }
+        InputKey::new(path)
+    }
+
+    /// Read `path`, or return the contents from an earlier read if the
+    /// file hasn't changed since.
+    pub fn read(&self, path: &Path) -> io::Result<(InputKey, Arc<Vec<u8>>)> {
+        if let Some(contents) = lock(&self.buffers).get(path) {
+            let key = InputKey {
+                path: path.to_path_buf(),
+                modified: None,
+            };
+            return Ok((key, contents.clone()));
+        }
+        let key = InputKey::new(path)?;
+        if let Some(contents) = lock(&self.contents).get(&key) {
+            log::trace!("Using cached contents of {}", path.display());
+            return Ok((key, contents.clone()));
+        }
+        let contents = Arc::new(std::fs::read(path)?);
+        lock(&self.contents).insert(key.clone(), contents.clone());
+        Ok((key, contents))
+    }
+
+    /// The TBD read from `key` for `platforms`, parsing it with `parse`
+    /// if it hasn't been already.
+    pub fn tbd<E>(
+        &self,
+        key: &InputKey,
+        platforms: &[Platform],
+        parse: impl FnOnce() -> Result<TbdDylib, E>,
+    ) -> Result<Arc<TbdDylib>, E> {
+        let key = (key.clone(), platforms.to_vec());
+        if let Some(tbd) = lock(&self.tbds).get(&key) {
+            log::trace!("Using cached TBD {}", key.0.path.display());
+            return Ok(tbd.clone());
+        }
+        let tbd = Arc::new(parse()?);
+        lock(&self.tbds).insert(key, tbd.clone());
+        Ok(tbd)
+    }
+
+    /// Forget the files that have changed or gone since they were read.
+    /// Buffers are kept until they're removed.
+    pub fn prune(&self) {
+        let buffers = lock(&self.buffers);
+        let is_current = |key: &InputKey| {
+            (key.modified.is_none() && buffers.contains_key(&key.path))
+                || InputKey::new(&key.path).is_ok_and(|now| now == *key)
+        };
+        lock(&self.contents).retain(|key, _| is_current(key));
+        lock(&self.tbds).retain(|(key, _), _| is_current(key));
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Nothing can leave the maps inconsistent, so a panic while one was
+    // held doesn't matter.
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,153 @@
+/// Telling inputs apart by their contents.
+///
+/// Inputs are classified by what they contain rather than their
+/// extension, so that a library given by path links like one found with
+/// -l and anything that can't be linked is reported as what it is.
+use goblin::mach::{
+    fat::FAT_MAGIC,
+    header::{
+        filetype_to_str, MH_BUNDLE, MH_CIGAM, MH_CIGAM_64, MH_DYLIB, MH_DYLIB_STUB, MH_EXECUTE,
+        MH_MAGIC, MH_MAGIC_64, MH_OBJECT,
+    },
+};
+
+use crate::fat::FAT_MAGIC_64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputKind {
+    Object,
+    Archive,
+    Dylib,
+    /// An executable, which can be linked against like a dylib.
+    Executable,
+    /// A bundle, which is loaded at runtime rather than linked against.
+    Bundle,
+    /// A universal file, holding one of the others per architecture.
+    Fat,
+    Tbd,
+    Bitcode,
+    Elf,
+    Pe,
+    /// A Mach-O file of a type that can't be an input, like a core
+    /// dump, with its filetype.
+    OtherMachO(u32),
+    /// A 32-bit Mach-O file, with its magic.
+    MachO32(u32),
+    /// A big-endian Mach-O file, with its magic as read little-endian.
+    ByteSwappedMachO(u32),
+    Unknown,
+}
+
+impl InputKind {
+    /// Whether machop can link with inputs of this kind.
+    pub fn is_supported(self) -> bool {
+        !matches!(
+            self,
+            InputKind::Bitcode
+                | InputKind::Elf
+                | InputKind::Pe
+                | InputKind::Bundle
+                | InputKind::OtherMachO(_)
+                | InputKind::MachO32(_)
+                | InputKind::ByteSwappedMachO(_)
+                | InputKind::Unknown
+        )
+    }
+}
+
+/// Classify the contents of an input.
+pub fn sniff(bytes: &[u8]) -> InputKind {
+    let magic = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    if bytes.starts_with(b"!<arch>\n") {
+        return InputKind::Archive;
+    }
+    if bytes.starts_with(b"\x7fELF") {
+        return InputKind::Elf;
+    }
+    if bytes.starts_with(b"MZ") {
+        return InputKind::Pe;
+    }
+    // Raw bitcode, or bitcode in the wrapper Apple's toolchains use.
+    if bytes.starts_with(b"BC\xc0\xde") || bytes.starts_with(&[0xde, 0xc0, 0x17, 0x0b]) {
+        return InputKind::Bitcode;
+    }
+    // Fat headers are big endian.
+    if bytes
+        .get(..4)
+        .is_some_and(|word| word == FAT_MAGIC.to_be_bytes() || word == FAT_MAGIC_64.to_be_bytes())
+    {
+        return InputKind::Fat;
+    }
+    if magic(0) == Some(MH_MAGIC_64) {
+        return match magic(12) {
+            Some(MH_OBJECT) => InputKind::Object,
+            Some(MH_DYLIB | MH_DYLIB_STUB) => InputKind::Dylib,
+            Some(MH_EXECUTE) => InputKind::Executable,
+            Some(MH_BUNDLE) => InputKind::Bundle,
+            filetype => InputKind::OtherMachO(filetype.unwrap_or_default()),
+        };
+    }
+    match magic(0) {
+        Some(magic @ MH_MAGIC) => return InputKind::MachO32(magic),
+        Some(magic @ (MH_CIGAM | MH_CIGAM_64)) => return InputKind::ByteSwappedMachO(magic),
+        _ => {}
+    }
+    // TBDs are YAML, or JSON from v5.
+    let text = bytes.trim_ascii_start();
+    if text.starts_with(b"---") || text.starts_with(b"{") {
+        return InputKind::Tbd;
+    }
+    InputKind::Unknown
+}
+
+/// The start of `bytes` in hex, to show what an unknown input is.
+pub fn first_bytes(bytes: &[u8]) -> String {
+    let shown: Vec<String> = bytes
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    shown.join(" ")
+}
+
+impl std::fmt::Display for InputKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            InputKind::Object => "an object file",
+            InputKind::Archive => "an archive",
+            InputKind::Dylib => "a dylib",
+            InputKind::Executable => "an executable",
+            InputKind::Bundle => "a bundle, which can only be loaded at runtime, not linked against",
+            InputKind::Fat => "a universal file",
+            InputKind::Tbd => "a TBD",
+            InputKind::Bitcode => "LLVM bitcode, which needs LTO",
+            InputKind::Elf => "an ELF file, not Mach-O",
+            InputKind::Pe => "a PE file, not Mach-O",
+            InputKind::Unknown => "not an object file, archive, dylib or TBD",
+            InputKind::OtherMachO(filetype) => {
+                return write!(
+                    f,
+                    "a Mach-O file of type MH_{} ({filetype:#x}), which can't be linked",
+                    filetype_to_str(*filetype)
+                )
+            }
+            InputKind::MachO32(magic) => {
+                return write!(
+                    f,
+                    "a 32-bit Mach-O file (magic {magic:#010x}), only 64-bit ones are supported"
+                )
+            }
+            InputKind::ByteSwappedMachO(magic) => {
+                return write!(
+                    f,
+                    "a big-endian Mach-O file (magic {magic:#010x}), only little-endian ones are supported"
+                )
+            }
+        };
+        f.write_str(description)
+    }
+}
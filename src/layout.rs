@@ -0,0 +1,1071 @@
+/// Placement of input sections in the output image.
+///
+/// Sections of objects built with MH_SUBSECTIONS_VIA_SYMBOLS are split
+/// into atoms at each symbol so they can be placed independently,
+/// otherwise the whole section is a single atom.
+use std::{
+    collections::HashMap,
+    mem,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use goblin::mach::{
+    constants::{S_ATTR_DEBUG, S_NON_LAZY_SYMBOL_POINTERS, S_ZEROFILL},
+    constants::{VM_PROT_EXECUTE, VM_PROT_READ, VM_PROT_WRITE},
+    header::MH_SUBSECTIONS_VIA_SYMBOLS,
+    relocation::RelocationInfo,
+    symbols::{Nlist, N_ABS, N_EXT, N_PEXT, N_SECT, N_TYPE, N_UNDF},
+    MachO,
+};
+
+use crate::{
+    bitcode,
+    objc::{self, ImageInfo},
+    output::{MachOWriter, OutputSymbol},
+    profile::{Placement, Profile},
+    reloc::{self, Kind, Relocation, Target},
+    symbol_list::SymbolList,
+    threads::ThreadPool,
+    time_trace, warning,
+    wrap::{self, Wrap},
+};
+
+/// Size of a GOT slot.
+const POINTER_SIZE: u64 = 8;
+
+/// Sections that only need to be writable while dyld applies fixups.
+/// With __DATA_CONST enabled these are moved out of __DATA so dyld can
+/// mprotect them read-only afterwards.
+const DATA_CONST_SECTIONS: &[&str] = &[
+    "__got",
+    "__const",
+    "__cfstring",
+    "__mod_init_func",
+    "__mod_term_func",
+    "__objc_classlist",
+    "__objc_nlclslist",
+    "__objc_catlist",
+    "__objc_nlcatlist",
+    "__objc_protolist",
+    "__objc_imageinfo",
+];
+
+/// Sections of Swift metadata. The runtime finds the metadata by
+/// walking these as arrays rather than through symbols, so they're
+/// placed whole, each input's contiguous with the next, and never split
+/// into atoms that could be dropped or padded.
+const SWIFT_METADATA_SECTIONS: &[&str] = &[
+    "__swift5_typeref",
+    "__swift5_reflstr",
+    "__swift5_fieldmd",
+    "__swift5_assocty",
+    "__swift5_builtin",
+    "__swift5_capture",
+    "__swift5_proto",
+    "__swift5_protos",
+    "__swift5_types",
+    "__swift5_types2",
+    "__swift5_replace",
+    "__swift5_replac2",
+    "__swift5_acfuncs",
+    "__swift5_mpenum",
+];
+
+#[derive(Debug)]
+pub enum Error {
+    ParseError(goblin::error::Error),
+    RelocationError {
+        segname: String,
+        sectname: String,
+        error: reloc::Error,
+    },
+}
+
+impl std::error::Error for Error {}
+
+impl From<goblin::error::Error> for Error {
+    fn from(e: goblin::error::Error) -> Self {
+        Error::ParseError(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::ParseError(e) => write!(f, "{}", e),
+            Error::RelocationError {
+                segname,
+                sectname,
+                error,
+            } => write!(f, "{segname},{sectname}: {error}"),
+        }
+    }
+}
+
+/// The smallest unit of an input section that gets placed.
+#[derive(Debug)]
+pub struct Atom {
+    /// Offset of the atom in its input section.
+    pub offset: u64,
+    pub size: u64,
+    /// Alignment as a power of two.
+    pub align: u32,
+    /// Symbol table index of the symbol the atom starts at, if any.
+    pub symbol: Option<usize>,
+    pub data: Vec<u8>,
+    /// Relocations with addresses relative to the start of the atom.
+    pub relocations: Vec<Relocation>,
+    /// The output section (segment and section index in the writer)
+    /// and offset in it, once placed.
+    pub output: Option<((usize, usize), u64)>,
+}
+
+#[derive(Debug)]
+pub struct InputSection {
+    pub segname: String,
+    pub sectname: String,
+    pub addr: u64,
+    pub size: u64,
+    pub align: u32,
+    pub flags: u32,
+    /// Sorted by offset.
+    pub atoms: Vec<Atom>,
+}
+
+impl InputSection {
+    /// The atom containing `offset`.
+    fn atom_at(&self, offset: u64) -> Option<&Atom> {
+        self.atoms.get(self.atom_index(offset)?)
+    }
+
+    /// Index in `atoms` of the atom containing `offset`.
+    pub(crate) fn atom_index(&self, offset: u64) -> Option<usize> {
+        self.atoms
+            .partition_point(|atom| atom.offset <= offset)
+            .checked_sub(1)
+    }
+}
+
+#[derive(Debug)]
+pub struct InputObject<'a> {
+    /// Indexed by section ordinal - 1, use [InputObject::section] to
+    /// look one up by ordinal.
+    pub sections: Vec<InputSection>,
+    /// Indexed by symbol table index.
+    pub symbols: Vec<(&'a str, Nlist)>,
+}
+
+impl<'a> InputObject<'a> {
+    fn new(macho: &MachO<'a>) -> Result<Self, Error> {
+        let subsections = macho.header.flags & MH_SUBSECTIONS_VIA_SYMBOLS != 0;
+        let symbols = macho.symbols().collect::<Result<Vec<_>, _>>()?;
+        // The relocations of the sections that have any, in the same
+        // order as the sections, so that sections with the same name
+        // don't share them.
+        let mut relocations = macho.relocations()?.into_iter();
+        let mut sections = vec![];
+        for segment in macho.segments.iter() {
+            for section in segment {
+                let (section, data) = section?;
+                let segname = section.segname()?.to_string();
+                let sectname = section.name()?.to_string();
+                let ordinal = sections.len() + 1;
+                let whole = SWIFT_METADATA_SECTIONS.contains(&&sectname[..]);
+                let mut atoms = atomize(&section, ordinal, data, &symbols, subsections && !whole);
+                let infos = match section.nreloc {
+                    0 => vec![],
+                    _ => match relocations.next() {
+                        Some((_, infos, _)) => infos.collect::<Result<Vec<RelocationInfo>, _>>()?,
+                        None => vec![],
+                    },
+                };
+                if !infos.is_empty() {
+                    let decoded =
+                        reloc::decode(&infos).map_err(|error| Error::RelocationError {
+                            segname: segname.clone(),
+                            sectname: sectname.clone(),
+                            error,
+                        })?;
+                    for mut relocation in decoded {
+                        let address = relocation.address as u64;
+                        let index = atoms
+                            .partition_point(|atom| atom.offset <= address)
+                            .saturating_sub(1);
+                        let atom = &mut atoms[index];
+                        relocation.address -= atom.offset as i32;
+                        atom.relocations.push(relocation);
+                    }
+                }
+                sections.push(InputSection {
+                    segname,
+                    sectname,
+                    addr: section.addr,
+                    size: section.size,
+                    align: section.align,
+                    flags: section.flags,
+                    atoms,
+                });
+            }
+        }
+        Ok(InputObject { sections, symbols })
+    }
+
+    /// The section with the 1-based `ordinal` a symbol's n_sect or a
+    /// section relocation gives, counting across all the segments.
+    pub fn section(&self, ordinal: usize) -> Option<&InputSection> {
+        self.sections.get(ordinal.checked_sub(1)?)
+    }
+
+    /// The section a symbol is defined in, if it's defined in one.
+    pub fn symbol_section(&self, nlist: &Nlist) -> Option<&InputSection> {
+        if nlist.get_type() != N_SECT {
+            return None;
+        }
+        self.section(nlist.n_sect)
+    }
+}
+
+/// Whether `nlist` is a common symbol, a tentative definition like C's
+/// `int x;`. Commons are undefined with their size as the value.
+pub fn is_common(nlist: &Nlist) -> bool {
+    nlist.n_type & N_TYPE == N_UNDF && nlist.n_type & N_EXT != 0 && nlist.n_value != 0
+}
+
+/// A common symbol that no object defined for real, allocated in
+/// __DATA,__common.
+#[derive(Debug)]
+pub struct Common<'a> {
+    pub name: &'a str,
+    pub size: u64,
+    /// Alignment as a power of two.
+    pub align: u32,
+}
+
+impl<'a> Common<'a> {
+    pub fn new(name: &'a str, nlist: &Nlist) -> Self {
+        let size = nlist.n_value;
+        // The alignment is in bits 8-11 of n_desc (GET_COMM_ALIGN). If
+        // it isn't given, commons are naturally aligned up to 16 bytes.
+        let align = match (nlist.n_desc >> 8) & 0xf {
+            0 => size.trailing_zeros().min(4),
+            align => align as u32,
+        };
+        Self { name, size, align }
+    }
+
+    /// The largest of the tentative definitions, with the strictest
+    /// alignment, wins.
+    pub fn merge(&mut self, nlist: &Nlist) {
+        let other = Common::new(self.name, nlist);
+        self.size = self.size.max(other.size);
+        self.align = self.align.max(other.align);
+    }
+}
+
+/// Symbols from a -move_to_ro_segment/-move_to_rw_segment list that
+/// are placed in their own segment.
+#[derive(Debug)]
+pub struct SegmentMove {
+    pub segment: String,
+    pub symbols: SymbolList,
+    pub writable: bool,
+}
+
+/// An atom once placed, for reports on the output.
+#[derive(Debug)]
+pub struct PlacedAtom<'l> {
+    /// Index of the object the atom is from.
+    pub object: usize,
+    /// The symbol the atom starts at, if any.
+    pub symbol: Option<&'l str>,
+    pub section: (usize, usize),
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub struct Layout<'a> {
+    pub objects: Vec<InputObject<'a>>,
+    /// Put write-once data in __DATA_CONST rather than __DATA.
+    pub data_const: bool,
+    pub segment_moves: Vec<SegmentMove>,
+    /// The order to lay out __text in, from --profile-order.
+    pub profile: Option<Profile>,
+    pub commons: Vec<Common<'a>>,
+    /// Offset of each of `commons` in __DATA,__common, once placed.
+    common_offsets: HashMap<&'a str, u64>,
+    common_section: Option<(usize, usize)>,
+    got: Vec<&'a str>,
+    got_slots: HashMap<&'a str, usize>,
+    got_section: Option<(usize, usize)>,
+    /// The output's __objc_imageinfo, merged from the inputs'.
+    image_info: Option<ImageInfo>,
+    /// The contents of __LLVM,__bundle, from -bitcode_bundle.
+    pub bitcode_bundle: Option<Vec<u8>>,
+}
+
+/// Make a global definition private extern, so it's only visible
+/// within the output, as for the members of -load_hidden archives.
+pub fn hide(nlist: &mut Nlist) {
+    if nlist.n_type & N_EXT != 0 && !nlist.is_undefined() && !nlist.is_stab() {
+        nlist.n_type |= N_PEXT;
+    }
+}
+
+/// Split a section into atoms at each symbol defined in it.
+fn atomize(
+    section: &goblin::mach::segment::Section,
+    ordinal: usize,
+    data: &[u8],
+    symbols: &[(&str, Nlist)],
+    subsections: bool,
+) -> Vec<Atom> {
+    let mut starts: Vec<(u64, Option<usize>)> = vec![];
+    if subsections {
+        starts.extend(
+            symbols
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, nlist))| {
+                    !nlist.is_stab() && nlist.get_type() == N_SECT && nlist.n_sect == ordinal
+                })
+                .filter_map(|(index, (_, nlist))| {
+                    let offset = nlist.n_value.checked_sub(section.addr)?;
+                    (offset < section.size).then_some((offset, Some(index)))
+                }),
+        );
+        // Prefer global symbols as the name of an atom when several
+        // start at the same offset.
+        starts.sort_by_key(|(offset, index)| {
+            let local = index.is_none_or(|index| symbols[index].1.n_type & N_EXT == 0);
+            (*offset, local)
+        });
+        starts.dedup_by_key(|(offset, _)| *offset);
+    }
+    if starts.first().is_none_or(|(offset, _)| *offset != 0) {
+        starts.insert(0, (0, None));
+    }
+
+    let mut atoms = vec![];
+    for (i, (offset, symbol)) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).map_or(section.size, |(next, _)| *next);
+        // An atom can't be more aligned than its position in the
+        // section guarantees.
+        let align = if *offset == 0 {
+            section.align
+        } else {
+            section.align.min(offset.trailing_zeros())
+        };
+        let atom_data = data
+            .get(*offset as usize..end as usize)
+            .map(<[u8]>::to_vec)
+            .unwrap_or_default();
+        atoms.push(Atom {
+            offset: *offset,
+            size: end - offset,
+            align,
+            symbol: *symbol,
+            data: atom_data,
+            relocations: vec![],
+            output: None,
+        });
+    }
+    atoms
+}
+
+impl<'a> Layout<'a> {
+    /// Decode the inputs, spreading the objects across `threads`.
+    pub fn new(inputs: &[&MachO<'a>], threads: &ThreadPool) -> Result<Self, Error> {
+        let objects = threads
+            .map(inputs, |macho| InputObject::new(macho))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            objects,
+            data_const: true,
+            segment_moves: vec![],
+            profile: None,
+            commons: vec![],
+            common_offsets: HashMap::new(),
+            common_section: None,
+            got: vec![],
+            got_slots: HashMap::new(),
+            got_section: None,
+            image_info: None,
+            bitcode_bundle: None,
+        })
+    }
+
+    /// Rename the undefined symbols of every object that are redirected
+    /// by `wraps`, so relocations against them resolve to the wrapper.
+    pub fn wrap_references(&mut self, wraps: &'a [Wrap]) {
+        if wraps.is_empty() {
+            return;
+        }
+        for object in &mut self.objects {
+            for (name, nlist) in &mut object.symbols {
+                *name = wrap::resolved_name(wraps, name, nlist);
+            }
+        }
+    }
+
+    /// Make the global definitions of the objects `hidden` picks private
+    /// extern (see [hide]).
+    pub fn hide_definitions(&mut self, hidden: impl Fn(usize) -> bool) {
+        for (index, object) in self.objects.iter_mut().enumerate() {
+            if hidden(index) {
+                for (_, nlist) in &mut object.symbols {
+                    hide(nlist);
+                }
+            }
+        }
+    }
+
+    /// Where an atom ends up in the output, or None if it isn't copied
+    /// into the image.
+    fn output_section_name(
+        &self,
+        object: &InputObject,
+        section: &InputSection,
+        atom: &Atom,
+    ) -> Option<(String, String)> {
+        if section.flags & S_ATTR_DEBUG != 0 || section.segname == "__LD" {
+            return None;
+        }
+        if let Some(symbol) = atom.symbol {
+            let name = object.symbols[symbol].0;
+            let writable = section.segname != "__TEXT";
+            if let Some(segment_move) = self
+                .segment_moves
+                .iter()
+                .find(|segment_move| segment_move.symbols.contains(name))
+            {
+                if segment_move.writable == writable {
+                    return Some((segment_move.segment.clone(), section.sectname.clone()));
+                }
+                warning!(
+                    "{name} is in {}, which is not {}, so can't be moved to {}",
+                    section.segname,
+                    if segment_move.writable {
+                        "writable"
+                    } else {
+                        "read-only"
+                    },
+                    segment_move.segment
+                );
+            }
+        }
+        let segname = match &section.segname[..] {
+            "__DATA" if self.data_const && DATA_CONST_SECTIONS.contains(&&section.sectname[..]) => {
+                "__DATA_CONST"
+            }
+            "__DATA_CONST" if !self.data_const => "__DATA",
+            other => other,
+        };
+        Some((segname.to_string(), section.sectname.clone()))
+    }
+
+    /// Relax GOT loads of symbols defined in the image (see
+    /// [reloc::relax_got_loads]). `is_defined` says whether a global
+    /// symbol was resolved to a definition in one of the objects.
+    pub fn relax_got_loads(
+        &mut self,
+        threads: &ThreadPool,
+        is_defined: impl Fn(&str) -> bool + Sync,
+    ) -> usize {
+        let relaxed = AtomicUsize::new(0);
+        threads.for_each_mut(&mut self.objects, |object| {
+            let InputObject { sections, symbols } = object;
+            for atom in sections.iter_mut().flat_map(|section| &mut section.atoms) {
+                let count =
+                    reloc::relax_got_loads(&mut atom.relocations, &mut atom.data, |target| {
+                        match target {
+                            Target::Symbol(index) => {
+                                symbols.get(index).is_some_and(|(name, nlist)| {
+                                    !nlist.is_undefined() || is_defined(name)
+                                })
+                            }
+                            Target::Section(_) => true,
+                        }
+                    });
+                relaxed.fetch_add(count, Ordering::Relaxed);
+            }
+        });
+        relaxed.into_inner()
+    }
+
+    /// Fold Objective-C categories into classes defined in the same
+    /// object (see [objc::merge_categories]), returning how many were
+    /// merged.
+    pub fn merge_objc_categories(&mut self, threads: &ThreadPool) -> usize {
+        let merged = AtomicUsize::new(0);
+        threads.for_each_mut(&mut self.objects, |object| {
+            merged.fetch_add(objc::merge_categories(object), Ordering::Relaxed);
+        });
+        merged.into_inner()
+    }
+
+    /// Take the bitcode embedded in each object out of it, so none of
+    /// __LLVM is copied into the output. Objects without bitcode give
+    /// None.
+    pub fn take_bitcode(&mut self) -> Vec<Option<bitcode::Member>> {
+        self.objects
+            .iter_mut()
+            .map(|object| {
+                let mut bitcode = None;
+                let mut cmdline = vec![];
+                for section in &mut object.sections {
+                    if section.segname != bitcode::SEGMENT {
+                        continue;
+                    }
+                    let data: Vec<u8> = mem::take(&mut section.atoms)
+                        .into_iter()
+                        .flat_map(|atom| atom.data)
+                        .collect();
+                    match &section.sectname[..] {
+                        bitcode::BITCODE_SECTION => bitcode = Some(data),
+                        bitcode::CMDLINE_SECTION => cmdline = bitcode::parse_cmdline(&data),
+                        _ => {}
+                    }
+                }
+                bitcode.map(|bitcode| bitcode::Member { bitcode, cmdline })
+            })
+            .collect()
+    }
+
+    /// Merge the inputs' __objc_imageinfo sections into the single one
+    /// the output gets when placed. `names` are the names of the
+    /// inputs, for errors.
+    pub fn merge_objc_image_info(&mut self, names: &[String]) -> Result<(), objc::Error> {
+        let mut infos = vec![];
+        for (object, name) in self.objects.iter().zip(names) {
+            let data = object
+                .sections
+                .iter()
+                .filter(|section| section.sectname == objc::IMAGE_INFO_SECTION)
+                .flat_map(|section| section.atoms.iter().flat_map(|atom| &atom.data))
+                .copied()
+                .collect::<Vec<u8>>();
+            if let Some(info) = ImageInfo::parse(&data) {
+                infos.push((&name[..], info));
+            }
+        }
+        self.image_info = ImageInfo::merge(&infos)?;
+        Ok(())
+    }
+
+    /// Copy every atom into its output section and allocate the GOT and
+    /// commons.
+    pub fn place(&mut self, writer: &mut MachOWriter, threads: &ThreadPool) {
+        for object in &self.objects {
+            for atom in object.sections.iter().flat_map(|section| &section.atoms) {
+                for relocation in &atom.relocations {
+                    if !matches!(
+                        relocation.kind,
+                        Kind::GotLoadPage21 | Kind::GotLoadPageOff12 | Kind::PointerToGot
+                    ) {
+                        continue;
+                    }
+                    if let Target::Symbol(index) = relocation.target {
+                        let name = object.symbols[index].0;
+                        if !self.got_slots.contains_key(name) {
+                            self.got_slots.insert(name, self.got.len());
+                            self.got.push(name);
+                        }
+                    }
+                }
+            }
+        }
+
+        for segment_move in &self.segment_moves {
+            let prot = if segment_move.writable {
+                VM_PROT_READ | VM_PROT_WRITE
+            } else {
+                VM_PROT_READ | VM_PROT_EXECUTE
+            };
+            writer.add_segment(&segment_move.segment, prot);
+        }
+
+        // Every output section is created before any is filled, since
+        // creating one can move the zerofill sections.
+        let got_segname = if self.data_const {
+            "__DATA_CONST"
+        } else {
+            "__DATA"
+        };
+        if !self.got.is_empty() {
+            writer.section_index(got_segname, "__got", S_NON_LAZY_SYMBOL_POINTERS);
+        }
+        let mut placements = vec![];
+        let mut image_info_section = None;
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (section_index, section) in object.sections.iter().enumerate() {
+                for (atom_index, atom) in section.atoms.iter().enumerate() {
+                    let Some((segname, sectname)) = self.output_section_name(object, section, atom)
+                    else {
+                        continue;
+                    };
+                    writer.section_index(&segname, &sectname, section.flags);
+                    if sectname == objc::IMAGE_INFO_SECTION {
+                        image_info_section.get_or_insert((segname, sectname, section.flags));
+                        continue;
+                    }
+                    placements.push((object_index, section_index, atom_index, segname, sectname));
+                }
+            }
+        }
+        if !self.commons.is_empty() {
+            writer.section_index("__DATA", "__common", S_ZEROFILL);
+        }
+        if self.bitcode_bundle.is_some() {
+            writer.section_index(bitcode::SEGMENT, bitcode::BUNDLE_SECTION, 0);
+        }
+
+        // Each output section is filled separately, so only the order of
+        // the __text atoms relative to each other changes.
+        if let Some(profile) = &self.profile {
+            placements.sort_by_key(|(object, section, atom, segname, sectname)| {
+                if segname != "__TEXT" || sectname != "__text" {
+                    return Placement::Unlisted;
+                }
+                let object = &self.objects[*object];
+                match object.sections[*section].atoms[*atom].symbol {
+                    Some(symbol) => profile.placement(object.symbols[symbol].0),
+                    None => Placement::Unlisted,
+                }
+            });
+        }
+
+        // The atoms' contents are copied in once every offset is known.
+        for (object, section, atom, segname, sectname) in placements {
+            let section = &mut self.objects[object].sections[section];
+            let index = writer.section_index(&segname, &sectname, section.flags);
+            let atom = &mut section.atoms[atom];
+            let offset = writer.section_at_mut(index).reserve(atom.size, atom.align);
+            atom.output = Some((index, offset));
+        }
+
+        if let (Some(info), Some((segname, sectname, flags))) =
+            (self.image_info, image_info_section)
+        {
+            let index = writer.section_index(&segname, &sectname, flags);
+            writer
+                .section_at_mut(index)
+                .append(&info.to_bytes(), ImageInfo::SIZE, 2);
+        }
+
+        if !self.got.is_empty() {
+            let index = writer.section_index(got_segname, "__got", S_NON_LAZY_SYMBOL_POINTERS);
+            let size = self.got.len() as u64 * POINTER_SIZE;
+            writer
+                .section_at_mut(index)
+                .append(&vec![0; size as usize], size, 3);
+            self.got_section = Some(index);
+        }
+
+        if !self.commons.is_empty() {
+            let index = writer.section_index("__DATA", "__common", S_ZEROFILL);
+            let section = writer.section_at_mut(index);
+            for common in &self.commons {
+                let offset = section.append(&[], common.size, common.align);
+                self.common_offsets.insert(common.name, offset);
+            }
+            self.common_section = Some(index);
+        }
+
+        if let Some(bundle) = &self.bitcode_bundle {
+            let index = writer.section_index(bitcode::SEGMENT, bitcode::BUNDLE_SECTION, 0);
+            writer
+                .section_at_mut(index)
+                .append(bundle, bundle.len() as u64, 0);
+        }
+
+        self.copy_atoms(writer, threads);
+    }
+
+    /// Copy the contents of the placed atoms into the output sections,
+    /// in parallel since they don't overlap.
+    fn copy_atoms(&self, writer: &mut MachOWriter, threads: &ThreadPool) {
+        for section in writer
+            .segments
+            .iter_mut()
+            .flat_map(|segment| &mut segment.sections)
+        {
+            if !section.is_zerofill() {
+                section.data.resize(section.size as usize, 0);
+            }
+        }
+        let mut ranges = vec![];
+        for atom in self
+            .objects
+            .iter()
+            .flat_map(|object| &object.sections)
+            .flat_map(|section| &section.atoms)
+        {
+            let Some((index, offset)) = atom.output else {
+                continue;
+            };
+            if writer.section(index).is_zerofill() {
+                continue;
+            }
+            // Anything past the atom's size is left out, as appending it
+            // would have.
+            let data = &atom.data[..atom.data.len().min(atom.size as usize)];
+            let start = offset as usize;
+            ranges.push((index, start, start + data.len(), (start, data)));
+        }
+        let mut chunks = split_sections(writer, ranges);
+        threads.for_each_mut(&mut chunks, |chunk| {
+            for (_, (start, data)) in &chunk.items {
+                let start = start - chunk.start;
+                chunk.data[start..start + data.len()].copy_from_slice(data);
+            }
+        });
+    }
+
+    /// Every atom copied into the output, once placed.
+    pub fn placed_atoms(&self) -> impl Iterator<Item = PlacedAtom<'_>> {
+        self.objects.iter().enumerate().flat_map(|(index, object)| {
+            object
+                .sections
+                .iter()
+                .flat_map(|section| &section.atoms)
+                .filter_map(move |atom| {
+                    let (section, _) = atom.output?;
+                    Some(PlacedAtom {
+                        object: index,
+                        symbol: atom.symbol.map(|symbol| object.symbols[symbol].0),
+                        section,
+                        size: atom.size,
+                    })
+                })
+        })
+    }
+
+    /// Final address of each of the commons. Only valid once the writer
+    /// has been laid out.
+    pub fn common_addresses<'l>(
+        &'l self,
+        writer: &'l MachOWriter,
+    ) -> impl Iterator<Item = (&'a str, u64)> + 'l {
+        self.common_section.into_iter().flat_map(move |index| {
+            let addr = writer.section(index).addr;
+            self.common_offsets
+                .iter()
+                .map(move |(name, offset)| (*name, addr + offset))
+        })
+    }
+
+    /// The output section for each of the commons, once placed.
+    pub fn common_section(&self) -> Option<(usize, usize)> {
+        self.common_section
+    }
+
+    /// The output section a symbol defined in `object` was placed in.
+    pub fn symbol_section(&self, object: usize, nlist: &Nlist) -> Option<(usize, usize)> {
+        let section = self.objects.get(object)?.symbol_section(nlist)?;
+        let (index, _) = section
+            .atom_at(nlist.n_value.checked_sub(section.addr)?)?
+            .output?;
+        Some(index)
+    }
+
+    /// The output symbol table entries for the symbols local to each
+    /// object, in input order. Private externs become locals. Only valid
+    /// once the writer has been laid out.
+    pub fn local_symbols(&self, writer: &MachOWriter) -> Vec<OutputSymbol> {
+        let mut locals = vec![];
+        for (object_index, object) in self.objects.iter().enumerate() {
+            for (name, nlist) in &object.symbols {
+                let local = nlist.n_type & N_EXT == 0 || nlist.n_type & N_PEXT != 0;
+                // Like ld64, assembler temporaries (ltmp0 and the like)
+                // are left out.
+                if nlist.is_stab() || !local || name.is_empty() || name.starts_with(['l', 'L']) {
+                    continue;
+                }
+                let Some(section) = self.symbol_section(object_index, nlist) else {
+                    continue;
+                };
+                let Some(n_value) = self.symbol_address(writer, object_index, nlist) else {
+                    continue;
+                };
+                locals.push(OutputSymbol {
+                    name: name.to_string(),
+                    n_type: (nlist.n_type & !N_EXT) | N_SECT,
+                    section: Some(section),
+                    n_desc: OutputSymbol::defined_desc(nlist.n_desc),
+                    n_value,
+                });
+            }
+        }
+        locals
+    }
+
+    /// Final address of the atom containing `offset` in `section`.
+    fn address_in_section(
+        &self,
+        writer: &MachOWriter,
+        section: &InputSection,
+        offset: u64,
+    ) -> Option<u64> {
+        let atom = section.atom_at(offset)?;
+        let (index, output_offset) = atom.output?;
+        Some(writer.section(index).addr + output_offset + (offset - atom.offset))
+    }
+
+    /// Final address of a symbol defined in `object`. Only valid once
+    /// the writer has been laid out.
+    pub fn symbol_address(
+        &self,
+        writer: &MachOWriter,
+        object: usize,
+        nlist: &Nlist,
+    ) -> Option<u64> {
+        if nlist.n_type & N_TYPE == N_ABS {
+            return Some(nlist.n_value);
+        }
+        let section = self.objects.get(object)?.section(nlist.n_sect)?;
+        self.address_in_section(writer, section, nlist.n_value.checked_sub(section.addr)?)
+    }
+
+    fn got_slot_address(&self, writer: &MachOWriter, name: &str) -> Option<u64> {
+        let slot = *self.got_slots.get(name)? as u64;
+        Some(writer.section(self.got_section?).addr + slot * POINTER_SIZE)
+    }
+
+    /// Address a relocation target refers to. External symbols are
+    /// looked up with `resolve`, so that the definition the resolver
+    /// picked is used.
+    ///
+    /// Section relative relocations hold the target's input address
+    /// inline (`inline`), the returned value is the adjustment that
+    /// moves it to the output address.
+    fn target_address(
+        &self,
+        writer: &MachOWriter,
+        object: usize,
+        target: Target,
+        inline: u64,
+        resolve: &impl Fn(&str) -> Option<u64>,
+    ) -> Option<u64> {
+        let input = &self.objects[object];
+        match target {
+            Target::Symbol(index) => {
+                let (name, nlist) = input.symbols.get(index)?;
+                if nlist.n_type & N_EXT != 0 || nlist.is_undefined() {
+                    resolve(name)
+                } else {
+                    self.symbol_address(writer, object, nlist)
+                }
+            }
+            Target::Section(ordinal) => {
+                let section = input.section(ordinal)?;
+                let offset = inline.wrapping_sub(section.addr).min(section.size);
+                let address = self.address_in_section(writer, section, offset)?;
+                Some(address.wrapping_sub(section.addr + offset))
+            }
+        }
+    }
+
+    /// The target, and subtrahend for SUBTRACTOR pairs, of each of an
+    /// atom's relocations, keyed by address.
+    fn resolve_targets(
+        &self,
+        writer: &MachOWriter,
+        object_index: usize,
+        section: &InputSection,
+        atom: &Atom,
+        resolve: &impl Fn(&str) -> Option<u64>,
+    ) -> HashMap<i32, (Option<u64>, Option<u64>)> {
+        let object = &self.objects[object_index];
+        let mut targets = HashMap::new();
+        for relocation in &atom.relocations {
+            let inline = match relocation.kind {
+                Kind::Unsigned | Kind::Subtractor { .. } => {
+                    reloc::read_field(&atom.data, relocation.address as usize, relocation.length)
+                }
+                _ => section.addr,
+            };
+            let target = match (relocation.kind, relocation.target) {
+                (
+                    Kind::GotLoadPage21 | Kind::GotLoadPageOff12 | Kind::PointerToGot,
+                    Target::Symbol(symbol),
+                ) => self.got_slot_address(writer, object.symbols[symbol].0),
+                (_, target) => self.target_address(writer, object_index, target, inline, resolve),
+            };
+            let subtrahend = match relocation.kind {
+                Kind::Subtractor { subtrahend } => {
+                    self.target_address(writer, object_index, subtrahend, inline, resolve)
+                }
+                _ => None,
+            };
+            targets.insert(relocation.address, (target, subtrahend));
+        }
+        targets
+    }
+
+    /// Fill in the GOT and apply every relocation to the copied atom
+    /// contents. References to symbols that can't be resolved (those
+    /// bound by dyld) are left untouched.
+    pub fn apply_relocations(
+        &self,
+        writer: &mut MachOWriter,
+        threads: &ThreadPool,
+        input_names: &[String],
+        resolve: impl Fn(&str) -> Option<u64> + Sync,
+    ) -> Result<(), Error> {
+        if let Some(got_section) = self.got_section {
+            let slots: Vec<u64> = self
+                .got
+                .iter()
+                .map(|name| resolve(name).unwrap_or(0))
+                .collect();
+            let data = &mut writer.section_at_mut(got_section).data;
+            for (i, value) in slots.into_iter().enumerate() {
+                let offset = i * POINTER_SIZE as usize;
+                data[offset..offset + POINTER_SIZE as usize].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        // Resolving targets only reads the layout, and each atom's
+        // relocations only patch its own contents, so both are done in
+        // parallel.
+        let object_indexes: Vec<usize> = (0..self.objects.len()).collect();
+        let resolved = threads.map(&object_indexes, |object_index| {
+            let _span =
+                time_trace::span("Resolve relocations", || input_names[*object_index].clone());
+            let mut resolved = vec![];
+            let object = &self.objects[*object_index];
+            for section in &object.sections {
+                for atom in &section.atoms {
+                    let Some((index, offset)) = atom.output else {
+                        continue;
+                    };
+                    if atom.relocations.is_empty() {
+                        continue;
+                    }
+                    let targets =
+                        self.resolve_targets(writer, *object_index, section, atom, &resolve);
+                    resolved.push((section, atom, index, offset, targets));
+                }
+            }
+            resolved
+        });
+
+        let ranges = resolved
+            .into_iter()
+            .flatten()
+            .map(|(section, atom, index, offset, targets)| {
+                let atom_addr = writer.section(index).addr + offset;
+                let start = offset as usize;
+                let end = start + atom.size as usize;
+                (
+                    index,
+                    start,
+                    end,
+                    (section, atom, start, atom_addr, targets),
+                )
+            })
+            .collect();
+        let chunks = split_sections(writer, ranges);
+        let mut jobs: Vec<_> = chunks.into_iter().map(|chunk| (chunk, None)).collect();
+        threads.for_each_mut(&mut jobs, |(chunk, failure)| {
+            for (order, (section, atom, start, atom_addr, targets)) in &chunk.items {
+                let start = start - chunk.start;
+                let data = &mut chunk.data[start..start + atom.size as usize];
+                for relocation in &atom.relocations {
+                    match targets[&relocation.address] {
+                        (Some(target), subtrahend) => {
+                            if let Err(error) =
+                                relocation.apply(data, *atom_addr, target, subtrahend)
+                            {
+                                *failure = Some((
+                                    *order,
+                                    Error::RelocationError {
+                                        segname: section.segname.clone(),
+                                        sectname: section.sectname.clone(),
+                                        error,
+                                    },
+                                ));
+                                return;
+                            }
+                        }
+                        (None, _) => log::trace!(
+                            "Leaving relocation at {:#x} in {},{} for dyld",
+                            atom.offset + relocation.address as u64,
+                            section.segname,
+                            section.sectname
+                        ),
+                    }
+                }
+                reloc::fold_adrp_add(&atom.relocations, data, *atom_addr, |relocation| {
+                    targets[&relocation.address].0
+                });
+            }
+        });
+        // The error reported is the one patching in order would have hit
+        // first.
+        match jobs
+            .into_iter()
+            .filter_map(|(_, failure)| failure)
+            .min_by_key(|(order, _)| *order)
+        {
+            Some((_, error)) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Part of the contents of an output section, to be filled in by one
+/// thread.
+struct Chunk<'w, T> {
+    data: &'w mut [u8],
+    /// The offset of `data` in its section.
+    start: usize,
+    /// The items in the chunk with their index in the ranges given to
+    /// [split_sections], in that order.
+    items: Vec<(usize, T)>,
+}
+
+/// Split the contents of the output sections into chunks covering
+/// `ranges`, given as output section, start and end offsets, and an
+/// item to fill the range with. Ranges that overlap share a chunk.
+fn split_sections<T>(
+    writer: &mut MachOWriter,
+    ranges: Vec<((usize, usize), usize, usize, T)>,
+) -> Vec<Chunk<'_, T>> {
+    let mut by_section: HashMap<(usize, usize), Vec<_>> = HashMap::new();
+    for (order, (index, start, end, item)) in ranges.into_iter().enumerate() {
+        by_section
+            .entry(index)
+            .or_default()
+            .push((start, end, order, item));
+    }
+    let mut chunks = vec![];
+    for (segment_index, segment) in writer.segments.iter_mut().enumerate() {
+        for (section_index, section) in segment.sections.iter_mut().enumerate() {
+            let Some(mut ranges) = by_section.remove(&(segment_index, section_index)) else {
+                continue;
+            };
+            ranges.sort_by_key(|(start, _, order, _)| (*start, *order));
+            let mut ranges = ranges.into_iter().peekable();
+            let mut rest = section.data.as_mut_slice();
+            let mut consumed = 0;
+            while let Some((start, mut end, order, item)) = ranges.next() {
+                let mut items = vec![(order, item)];
+                while let Some((_, next_end, order, item)) =
+                    ranges.next_if(|(next_start, ..)| *next_start < end)
+                {
+                    end = end.max(next_end);
+                    items.push((order, item));
+                }
+                items.sort_by_key(|(order, _)| *order);
+                let (_, after) = std::mem::take(&mut rest).split_at_mut(start - consumed);
+                let (data, after) = after.split_at_mut(end - start);
+                rest = after;
+                consumed = end;
+                chunks.push(Chunk { data, start, items });
+            }
+        }
+    }
+    chunks
+}
@@ -1,2 +1,44 @@
+pub mod archive;
+pub mod arg_parser;
+pub mod auto_link;
+pub mod availability;
+pub mod bitcode;
+pub mod clients;
+pub mod completions;
+pub mod config;
+pub mod cpu_subtype;
+pub mod daemon;
+pub mod dir_cache;
+pub mod dtrace;
+pub mod dyld_paths;
+pub mod environment;
+pub mod errors;
+pub mod export_trie;
+pub mod fat;
+pub mod ffi;
+pub mod input_cache;
+pub mod input_kind;
+pub mod layout;
+pub mod link;
 pub mod linker_args;
+pub mod lld_options;
+pub mod mangling;
+pub mod objc;
+pub mod observer;
+pub mod output;
+pub mod panic_context;
+pub mod profile;
+pub mod reexports;
+pub mod reloc;
+pub mod report;
+pub mod reproduce;
+pub mod spec;
+pub mod stats;
+pub mod strtab;
+pub mod symbol_list;
 pub mod tbd;
+pub mod threads;
+pub mod time_trace;
+pub mod verify;
+pub mod warnings;
+pub mod wrap;
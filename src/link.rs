@@ -0,0 +1,2253 @@
+/// Linking: finding and reading the inputs, resolving symbols against
+/// them and writing the output.
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::{Debug, Display},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use goblin::mach::{
+    cputype::get_arch_name_from_types,
+    fat::FatArch,
+    header::{MH_APP_EXTENSION_SAFE, MH_DYLIB, MH_DYLIB_STUB, MH_EXECUTE, MH_OBJECT, MH_PRELOAD},
+    load_command::{CommandVariant, LC_LOAD_DYLIB, LC_LOAD_UPWARD_DYLIB},
+    symbols::{Nlist, N_ABS, N_EXT, N_PEXT, N_SECT, N_UNDF, N_WEAK_REF},
+    MachO, MultiArch, SingleArch,
+};
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{
+    archive,
+    auto_link::{self, AutoLink},
+    availability,
+    bitcode::{self, Link as BitcodeLink},
+    clients::Restrictions,
+    cpu_subtype,
+    dir_cache::DirCache,
+    dtrace,
+    dyld_paths::{self, PathUse},
+    error, errors, fat,
+    input_cache::{InputCache, InputKey},
+    input_kind::{self, InputKind},
+    layout::{self, is_common, Common, Layout, SegmentMove},
+    linker_args::{
+        Architecture, Args, BitcodeMode, Platform, PlatformVersion, Query, UndefinedTreatment,
+        Version, USAGE,
+    },
+    mangling::LinkageIndex,
+    observer::{Definition, Observer},
+    output::{LoadCommand, MachOWriter, OutputSymbol},
+    panic_context,
+    profile::Profile,
+    reexports, report, reproduce, stats,
+    symbol_list::SymbolList,
+    tbd::{self, TbdDylib},
+    threads::ThreadPool,
+    time_trace, warning, warnings, wrap,
+};
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Object<'a> {
+    /// An ELF32/ELF64!
+    Elf(goblin::elf::Elf<'a>),
+    /// A PE32/PE32+!
+    PE(goblin::pe::PE<'a>),
+    /// A 32/64-bit Mach-o binary _OR_ it is a multi-architecture binary container!
+    Mach(goblin::mach::Mach<'a>),
+    /// A Unix archive
+    Archive(goblin::archive::Archive<'a>),
+    /// A text stub file, shared with the cache.
+    Tbd(Arc<tbd::TbdDylib>),
+}
+
+impl<'a> From<Arc<TbdDylib>> for Object<'a> {
+    fn from(tbd: Arc<TbdDylib>) -> Self {
+        Object::Tbd(tbd)
+    }
+}
+
+impl<'a> Object<'a> {
+    /// Parse `s`, the contents of the input `key`. TBDs are only parsed
+    /// if `cache` doesn't already have them.
+    pub fn parse(
+        s: &'a [u8],
+        platforms: &[Platform],
+        cache: &InputCache,
+        key: &InputKey,
+    ) -> Result<Self, Box<dyn Error>> {
+        match input_kind::sniff(s) {
+            InputKind::Tbd => Ok(cache
+                .tbd(key, platforms, || {
+                    tbd::TbdDylib::parse(Architecture::ARM64, platforms, s)
+                })?
+                .into()),
+            InputKind::Unknown if s.is_empty() => Err("it's empty".into()),
+            InputKind::Unknown => Err(format!(
+                "unknown file type (first bytes: {}), expected a Mach-O file, archive or TBD",
+                input_kind::first_bytes(s)
+            )
+            .into()),
+            kind if !kind.is_supported() => Err(format!("it's {kind}").into()),
+            _ => Ok(goblin::Object::parse(s)?.try_into()?),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ObjectConversionError(());
+impl Error for ObjectConversionError {}
+impl Display for ObjectConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to convert object")
+    }
+}
+
+impl<'a> TryFrom<goblin::Object<'a>> for Object<'a> {
+    type Error = ObjectConversionError;
+
+    fn try_from(o: goblin::Object<'a>) -> Result<Self, Self::Error> {
+        match o {
+            goblin::Object::Elf(elf) => Ok(Object::Elf(elf)),
+            goblin::Object::PE(pe) => Ok(Object::PE(pe)),
+            goblin::Object::Mach(mach) => Ok(Object::Mach(mach)),
+            goblin::Object::Archive(archive) => Ok(Object::Archive(archive)),
+            goblin::Object::Unknown(_) => Err(ObjectConversionError(())),
+        }
+    }
+}
+
+/// A library linked against, which `machop verify` also reads to check
+/// binaries against.
+pub(crate) enum Dylib<'a> {
+    MachO(&'a MachO<'a>),
+    Tbd(&'a tbd::TbdDylib),
+}
+
+impl<'a> Dylib<'a> {
+    pub(crate) fn install_name(&self) -> String {
+        match self {
+            Dylib::MachO(macho) => macho.name.unwrap_or_default().to_string(),
+            Dylib::Tbd(tbd) => tbd.install_name.display().to_string(),
+        }
+    }
+
+    /// The names of the symbols the library exports. Names are borrowed
+    /// from the library where possible.
+    pub(crate) fn exports(&self) -> Vec<Cow<'a, str>> {
+        match self {
+            Dylib::MachO(macho) => {
+                // Stubs only describe their exports in the symbol table.
+                let exports = if macho.header.filetype == MH_DYLIB_STUB {
+                    Ok(vec![])
+                } else {
+                    macho.exports()
+                };
+                match exports {
+                    Ok(exports) if !exports.is_empty() => {
+                        return exports
+                            .into_iter()
+                            .map(|export| Cow::Owned(export.name))
+                            .collect()
+                    }
+                    Ok(_) => {}
+                    Err(e) => warning!(
+                        "Unable to read the export trie of {}, using its symbol table: {e}",
+                        self.install_name()
+                    ),
+                }
+                // Old or hand built dylibs may not have an export trie
+                // (or LC_DYLD_INFO at all), in which case the external
+                // definitions in the symbol table are what it exports.
+                macho
+                    .symbols()
+                    .filter_map(Result::ok)
+                    .filter(|(_, nlist)| {
+                        !nlist.is_stab() && nlist.is_global() && !nlist.is_undefined()
+                    })
+                    .map(|(name, _)| Cow::Borrowed(name))
+                    .collect()
+            }
+            Dylib::Tbd(tbd) => tbd
+                .exports
+                .iter()
+                .map(|export| Cow::Borrowed(&export[..]))
+                .collect(),
+        }
+    }
+
+    /// The install names of the libraries this one re-exports that
+    /// aren't described along with it, so have to be read separately.
+    pub(crate) fn external_reexports(&self) -> Vec<String> {
+        match self {
+            Dylib::MachO(macho) => macho
+                .load_commands
+                .iter()
+                .filter(|command| {
+                    matches!(
+                        command.command,
+                        CommandVariant::LoadDylib(_)
+                            | CommandVariant::LoadUpwardDylib(_)
+                            | CommandVariant::ReexportDylib(_)
+                            | CommandVariant::LoadWeakDylib(_)
+                            | CommandVariant::LazyLoadDylib(_)
+                    )
+                })
+                // libs has the names of those commands, in order, after
+                // "self".
+                .zip(macho.libs.iter().skip(1))
+                .filter(|(command, _)| matches!(command.command, CommandVariant::ReexportDylib(_)))
+                .map(|(_, lib)| lib.to_string())
+                .collect(),
+            Dylib::Tbd(tbd) => tbd
+                .reexported_libraries
+                .iter()
+                .filter(|lib| {
+                    !tbd.reexports
+                        .iter()
+                        .any(|child| child.install_name == **lib)
+                })
+                .map(|lib| lib.display().to_string())
+                .collect(),
+        }
+    }
+
+    fn is_app_extension_safe(&self) -> bool {
+        match self {
+            Dylib::MachO(macho) => macho.header.flags & MH_APP_EXTENSION_SAFE != 0,
+            Dylib::Tbd(tbd) => tbd.app_extension_safe,
+        }
+    }
+
+    /// The current and compatibility versions.
+    pub(crate) fn versions(&self) -> (Version, Version) {
+        match self {
+            Dylib::MachO(macho) => macho
+                .load_commands
+                .iter()
+                .find_map(|command| match &command.command {
+                    CommandVariant::IdDylib(id) => Some((
+                        Version::from_u32(id.dylib.current_version),
+                        Version::from_u32(id.dylib.compatibility_version),
+                    )),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+            Dylib::Tbd(tbd) => (tbd.current_version, tbd.compatibility_version),
+        }
+    }
+
+    /// What's wrong with the library's [Dylib::versions], if anything.
+    fn version_problems(&self) -> Vec<String> {
+        let mut problems = match self {
+            Dylib::MachO(macho) => {
+                let has_id = macho
+                    .load_commands
+                    .iter()
+                    .any(|command| matches!(command.command, CommandVariant::IdDylib(_)));
+                if has_id || macho.header.filetype == MH_EXECUTE {
+                    vec![]
+                } else {
+                    vec!["has no LC_ID_DYLIB, recording version 0".to_string()]
+                }
+            }
+            Dylib::Tbd(tbd) => tbd
+                .malformed_versions
+                .iter()
+                .map(|problem| format!("{problem}, recording 1.0"))
+                .collect(),
+        };
+        let (current, compatibility) = self.versions();
+        if compatibility.to_u32() > current.to_u32() {
+            problems.push(format!(
+                "has compatibility version {compatibility}, newer than its current version {current}"
+            ));
+        }
+        problems
+    }
+}
+
+struct Symbol<'a> {
+    name: &'a str,
+    nlist: Nlist,
+    /// Index of the defining object in the layout's inputs.
+    input: usize,
+}
+
+impl<'a> Debug for Symbol<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Symbol")
+            // .field("name", &self.name)
+            .field("nlist", &self.nlist)
+            .field("type_str()", &self.nlist.type_str())
+            .field("is_global()", &self.nlist.is_global())
+            .field("is_weak()", &self.nlist.is_weak())
+            .field("is_undefined()", &self.nlist.is_undefined())
+            .field("is_stab()", &self.nlist.is_stab())
+            .finish()
+    }
+}
+
+/// An input, or a slice or member of one, that may define symbols for
+/// an architecture other than the one being linked.
+struct ForeignInput<'a> {
+    name: String,
+    /// The architecture of a Mach-O file. .tbd files can describe many.
+    arch: Option<String>,
+    bytes: &'a [u8],
+}
+
+fn arch_name(cputype: u32, cpusubtype: u32) -> String {
+    get_arch_name_from_types(cputype, cpusubtype)
+        .map_or_else(|| format!("cputype {cputype}"), str::to_string)
+}
+
+/// The external symbols defined by a Mach-O file, by the members of an
+/// archive, or by the `cputype` slice of a fat file.
+fn defined_symbols(bytes: &[u8], cputype: u32) -> Vec<String> {
+    let defined = |macho: &MachO| -> Vec<String> {
+        macho
+            .symbols()
+            .filter_map(Result::ok)
+            .filter(|(_, nlist)| !nlist.is_stab() && nlist.is_global() && !nlist.is_undefined())
+            .map(|(name, _)| name.to_string())
+            .collect()
+    };
+    match goblin::Object::parse(bytes) {
+        Ok(goblin::Object::Mach(goblin::mach::Mach::Binary(macho))) => defined(&macho),
+        Ok(goblin::Object::Mach(goblin::mach::Mach::Fat(fat))) => fat
+            .iter_arches()
+            .filter_map(Result::ok)
+            .zip(slices(bytes).unwrap_or_default())
+            .find(|(arch, _)| arch.cputype() == cputype)
+            .map(|(_, slice)| defined_symbols(slice, cputype))
+            .unwrap_or_default(),
+        Ok(goblin::Object::Archive(archive)) => archive::members(&archive, bytes)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(_, bytes)| defined_symbols(bytes, cputype))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// `contents`, or just its `arch` slice if it's a universal file with a
+/// 64-bit header, which goblin can't read.
+pub(crate) fn thin_fat64(
+    path: &Path,
+    contents: Arc<Vec<u8>>,
+    arch: &Architecture,
+) -> Result<Arc<Vec<u8>>, String> {
+    if !fat::is_fat64(&contents) {
+        return Ok(contents);
+    }
+    let arches =
+        fat::arches(&contents).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    let Some(slice) = arches.iter().find(|slice| slice.cputype == arch.cpu_type()) else {
+        let available: Vec<String> = arches
+            .iter()
+            .map(|slice| arch_name(slice.cputype, slice.cpusubtype))
+            .collect();
+        return Err(format!(
+            "{} does not contain {arch}, only {}",
+            path.display(),
+            available.join(", ")
+        ));
+    };
+    Ok(Arc::new(slice.slice(&contents).to_vec()))
+}
+
+/// The slices of a universal file, or just `bytes` if it isn't one.
+/// Some vendors ship archives whose members are universal objects.
+pub(crate) fn slices(bytes: &[u8]) -> Result<Vec<&[u8]>, goblin::error::Error> {
+    if input_kind::sniff(bytes) != InputKind::Fat {
+        return Ok(vec![bytes]);
+    }
+    if fat::is_fat64(bytes) {
+        return fat::arches(bytes)
+            .map(|arches| arches.iter().map(|arch| arch.slice(bytes)).collect())
+            .map_err(goblin::error::Error::Malformed);
+    }
+    MultiArch::new(bytes)?
+        .iter_arches()
+        .map(|arch| {
+            let arch = arch?;
+            let start = arch.offset as usize;
+            start
+                .checked_add(arch.size as usize)
+                .and_then(|end| bytes.get(start..end))
+                .ok_or_else(|| goblin::error::Error::Malformed("slice is truncated".into()))
+        })
+        .collect()
+}
+
+/// The platform each of the object files for `arch` was built for, for
+/// those recording one.
+fn build_versions<'a>(
+    arch: &Architecture,
+    paths: &'a [PathBuf],
+    contents: &[Arc<Vec<u8>>],
+) -> Vec<(&'a Path, PlatformVersion)> {
+    paths
+        .iter()
+        .zip(contents)
+        .filter_map(|(path, bytes)| {
+            let version = slices(bytes)
+                .ok()?
+                .into_iter()
+                .filter_map(|slice| MachO::parse(slice, 0).ok())
+                .filter(|macho| {
+                    macho.header.filetype == MH_OBJECT && macho.header.cputype() == arch.cpu_type()
+                })
+                .find_map(|macho| PlatformVersion::from_load_commands(&macho))?;
+            Some((path.as_path(), version))
+        })
+        .collect()
+}
+
+/// What the objects for `arch` ask to be linked against with
+/// LC_LINKER_OPTION, in the order they ask and without repeats. Every
+/// object member of an archive is linked, so all of them are asked.
+fn auto_links(arch: &Architecture, contents: &[Arc<Vec<u8>>]) -> Vec<AutoLink> {
+    // Anything that doesn't parse is reported once it's linked.
+    let object_auto_links = |bytes: &[u8]| {
+        MachO::parse(bytes, 0)
+            .map(|macho| auto_link::auto_links(&macho, bytes, arch.cpu_type()))
+            .unwrap_or_default()
+    };
+    let mut auto_links = vec![];
+    for bytes in contents {
+        for slice in slices(bytes).unwrap_or_default() {
+            let found = if input_kind::sniff(slice) == InputKind::Archive {
+                let members = goblin::archive::Archive::parse(slice)
+                    .ok()
+                    .and_then(|archive| archive::members(&archive, slice).ok())
+                    .unwrap_or_default();
+                members
+                    .into_iter()
+                    .flat_map(|(_, member)| slices(member).unwrap_or_default())
+                    .flat_map(object_auto_links)
+                    .collect()
+            } else {
+                object_auto_links(slice)
+            };
+            for auto_link in found {
+                if !auto_links.contains(&auto_link) {
+                    auto_links.push(auto_link);
+                }
+            }
+        }
+    }
+    auto_links
+}
+
+/// The platform the object files were built for, from the first with a
+/// build version, like ld64 does without -platform_version. It's an
+/// error for the others to be built for a different platform.
+fn infer_platform_version(
+    versions: &[(&Path, PlatformVersion)],
+) -> Result<Option<PlatformVersion>, ()> {
+    let mut inferred: Option<&(&Path, PlatformVersion)> = None;
+    for entry in versions {
+        let (path, version) = entry;
+        match inferred {
+            None => inferred = Some(entry),
+            Some((first_path, first)) if first.platform != version.platform => {
+                error!(
+                    "{} was built for {} but {} was built for {}, pass -platform_version \
+                     to choose one",
+                    first_path.display(),
+                    first.platform,
+                    path.display(),
+                    version.platform
+                );
+                return Err(());
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(inferred.map(|(path, version)| {
+        warning!(
+            "No -platform_version given, linking for {} {} like {} was built for",
+            version.platform,
+            version.min_version,
+            path.display()
+        );
+        version.clone()
+    }))
+}
+
+/// Report the object files built for a newer minimum OS than the output,
+/// which may use what older systems don't have and crash on them.
+fn check_deployment_target(versions: &[(&Path, PlatformVersion)], target: &PlatformVersion) {
+    let newer: Vec<String> = versions
+        .iter()
+        .filter(|(_, version)| {
+            version.platform == target.platform && version.min_version > target.min_version
+        })
+        .map(|(path, version)| format!("\n    {}: {}", path.display(), version.min_version))
+        .collect();
+    if newer.is_empty() {
+        return;
+    }
+    let message = format!(
+        "{} object files were built for a newer {} than the {} being linked for, \
+         so the output may crash on older systems:{}",
+        newer.len(),
+        target.platform,
+        target.min_version,
+        newer.concat()
+    );
+    warning!("{message}");
+}
+
+/// Fail the link if there were warnings and `fatal`, from
+/// -fatal_warnings, is set.
+fn check_warnings(fatal: bool) -> Result<(), ()> {
+    let warnings = warnings::counted();
+    if fatal && warnings > 0 {
+        error!("Failing the link, -fatal_warnings is set and there were {warnings} warnings");
+        return Err(());
+    }
+    Ok(())
+}
+
+/// The external symbols `input` defines for architectures other than
+/// `arch`, each with the architecture it's defined for.
+fn foreign_definitions(input: &ForeignInput, arch: &Architecture) -> Vec<(String, String)> {
+    match &input.arch {
+        Some(input_arch) => defined_symbols(input.bytes, arch.cpu_type())
+            .into_iter()
+            .map(|name| (name, input_arch.clone()))
+            .collect(),
+        None => tbd::foreign_exports(arch, input.bytes).unwrap_or_default(),
+    }
+}
+
+/// The libraries in the search paths that aren't in `linked`, each with
+/// the flag that would link it. As with -l and -framework, a library is
+/// found in the first search path that has it.
+fn unlinked_libraries(
+    dirs: &DirCache,
+    library_search_paths: &[PathBuf],
+    framework_search_paths: &[PathBuf],
+    linked: &[InputKey],
+) -> Vec<(String, PathBuf)> {
+    let mut flags = HashSet::new();
+    let mut libraries = vec![];
+    for dir in library_search_paths {
+        for file in dirs.entries(dir) {
+            // Versioned names like libz.1.dylib are usually links to
+            // an unversioned one, and -l can't name them anyway.
+            let Some((name, extension)) = file
+                .strip_prefix("lib")
+                .and_then(|file| file.split_once('.'))
+            else {
+                continue;
+            };
+            if !["tbd", "dylib", "a"].contains(&extension) || !flags.insert(format!("-l{name}")) {
+                continue;
+            }
+            if let Some(path) = discover_library_path(dirs, library_search_paths, name) {
+                libraries.push((format!("-l{name}"), path));
+            }
+        }
+    }
+    for dir in framework_search_paths {
+        for file in dirs.entries(dir) {
+            let Some(name) = file.strip_suffix(".framework") else {
+                continue;
+            };
+            if !flags.insert(format!("-framework {name}")) {
+                continue;
+            }
+            if let Some(path) = discover_framework_path(dirs, framework_search_paths, name) {
+                libraries.push((format!("-framework {name}"), path));
+            }
+        }
+    }
+    libraries.retain(|(_, path)| InputKey::new(path).map_or(true, |key| !linked.contains(&key)));
+    libraries
+}
+
+/// The library and framework directories searched after those from -L
+/// and -F, before they're moved into the -syslibroots. Only macOS links
+/// can use the host's, other platforms' libraries only exist in their
+/// SDK, so without -syslibroot there are none.
+fn default_search_paths(platform: Platform, sys_lib_root: bool) -> (Vec<PathBuf>, Vec<PathBuf>) {
+    match platform {
+        Platform::MacOS => (
+            vec!["/usr/lib".into(), "/usr/local/lib".into()],
+            vec!["/System/Library/Frameworks".into()],
+        ),
+        // Mac Catalyst apps link against the iOS flavoured libraries
+        // in the macOS SDK where there's one.
+        Platform::MacCatalyst => (
+            vec![
+                "/System/iOSSupport/usr/lib".into(),
+                "/usr/lib".into(),
+                "/usr/local/lib".into(),
+            ],
+            vec![
+                "/System/iOSSupport/System/Library/Frameworks".into(),
+                "/System/Library/Frameworks".into(),
+            ],
+        ),
+        _ if sys_lib_root => (
+            vec!["/usr/lib".into()],
+            vec!["/System/Library/Frameworks".into()],
+        ),
+        _ => {
+            log::debug!("Not searching the host's libraries without a -syslibroot for {platform}");
+            (vec![], vec![])
+        }
+    }
+}
+
+/// Maps keyed by symbol name, for resolution. FxHash is much faster
+/// than the default SipHash on short strings, and there's no need to
+/// guard against crafted collisions in a linker's own inputs.
+type SymbolMap<'a, V> = FxHashMap<&'a str, V>;
+type SymbolSet<'a> = FxHashSet<&'a str>;
+
+/// Upper bounds on the number of symbols `macho` defines and references,
+/// from its symbol table commands.
+fn symbol_counts(macho: &MachO) -> (usize, usize) {
+    let mut counts = (0, 0);
+    for command in &macho.load_commands {
+        match &command.command {
+            CommandVariant::Symtab(symtab) => counts.0 = symtab.nsyms as usize,
+            CommandVariant::Dysymtab(dysymtab) => counts.1 = dysymtab.nundefsym as usize,
+            _ => {}
+        }
+    }
+    counts
+}
+
+/// Link `args`, reading inputs through `cache` and telling `observer`
+/// how symbols are resolved. Errors are logged as they're found, up to
+/// -error-limit of them, so there's nothing more to report on failure.
+#[allow(clippy::result_unit_err)]
+pub fn link(args: Args, cache: &InputCache, observer: &mut dyn Observer) -> Result<(), ()> {
+    errors::start(args.error_limit);
+    let linked = link_reporting_errors(args, cache, observer);
+    errors::summarize();
+    linked
+}
+
+fn link_reporting_errors(
+    mut args: Args,
+    cache: &InputCache,
+    observer: &mut dyn Observer,
+) -> Result<(), ()> {
+    panic_context::start(&args.argv);
+    stats::start(args.print_statistics);
+    time_trace::start(args.time_trace.is_some());
+    panic_context::set_phase("finding inputs");
+    // Dedupe only removes consecutive duplicates so we need to sort
+    // it first. Maybe it'd be better to just use a set?
+    args.library_search_paths.sort();
+    args.library_search_paths.dedup();
+    // The platform picks the default search paths, so without
+    // -platform_version it's inferred before any library is looked for.
+    // Only objects record one, and those are all given by path. One
+    // that can't be read is reported when the inputs are.
+    if args.platform_version_defaulted {
+        let (paths, contents): (Vec<PathBuf>, Vec<Arc<Vec<u8>>>) = args
+            .object_files
+            .iter()
+            .filter_map(|path| Some((path.clone(), cache.read(path).ok()?.1)))
+            .unzip();
+        let build_versions = build_versions(&args.arch, &paths, &contents);
+        if let Some(platform_version) = infer_platform_version(&build_versions)? {
+            args.platform_version = platform_version;
+        }
+    }
+    let (default_library_paths, default_framework_paths) = default_search_paths(
+        args.platform_version.platform,
+        !args.sys_lib_roots.is_empty(),
+    );
+    log::debug!("Arg: {:#?}", args);
+    // args.object_files = vec![args.object_files.first().unwrap().to_owned()];
+    // args.libraries = vec![];
+    let mut object_files = vec![];
+    // let (cpu_type, cpu_subtype) = get_arch_from_flag(&args.arch.to_string())
+    //     .unwrap_or_else(|| panic!("no arch found for {}", args.arch));
+    object_files.append(&mut args.object_files.clone());
+    // Like ld64, each path is looked for in every -syslibroot, in order,
+    // so SDKs can be layered. Paths from -L and -F that are in none of
+    // them are used as given, but the defaults and install names are
+    // never taken from the host once there's a -syslibroot.
+    let in_sys_lib_root = |paths: &[PathBuf], as_given: bool| -> Vec<PathBuf> {
+        if args.sys_lib_roots.is_empty() {
+            return paths.to_vec();
+        }
+        paths
+            .iter()
+            .flat_map(|path| {
+                let relative = path.strip_prefix("/").unwrap_or(path);
+                let rooted: Vec<PathBuf> = args
+                    .sys_lib_roots
+                    .iter()
+                    .map(|root| root.join(relative))
+                    .collect();
+                let found: Vec<PathBuf> = rooted
+                    .iter()
+                    .filter(|path| path.exists())
+                    .cloned()
+                    .collect();
+                if !found.is_empty() {
+                    found
+                } else if as_given {
+                    vec![path.clone()]
+                } else {
+                    vec![rooted[0].clone()]
+                }
+            })
+            .collect()
+    };
+    let search_paths = |given: &[PathBuf], defaults: &[PathBuf]| -> Vec<PathBuf> {
+        let mut paths = in_sys_lib_root(given, true);
+        for path in in_sys_lib_root(defaults, false) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        paths
+    };
+    let library_search_paths = search_paths(&args.library_search_paths, &default_library_paths);
+    log::trace!("Using library search paths: {:?}", library_search_paths);
+    let framework_search_paths =
+        search_paths(&args.framework_search_paths, &default_framework_paths);
+    log::trace!("Using framework search paths: {:?}", framework_search_paths);
+    if let Some(query) = &args.query {
+        print!(
+            "{}",
+            answer(query, &library_search_paths, &framework_search_paths)
+        );
+        return Ok(());
+    }
+    // Better to find out now than after all the work of linking.
+    check_output_directory(&args.output_file, args.create_dirs).map_err(|e| error!("{e}"))?;
+    let dirs = DirCache::default();
+    // A missing library would otherwise only show up as a pile of
+    // undefined symbols.
+    let mut missing = false;
+    let mut not_found = |kind: &str, name: &str, searched: &[PathBuf]| {
+        let searched: String = searched
+            .iter()
+            .map(|path| format!("\n    {}", path.display()))
+            .collect();
+        if args.undefined == UndefinedTreatment::Error {
+            error!("Unable to find {kind} {name}, searched:{searched}");
+            missing = true;
+        } else {
+            warning!("Ignoring {kind} {name}, it wasn't found in:{searched}");
+        }
+    };
+    for library in &args.libraries {
+        match discover_library_path(&dirs, &library_search_paths, library) {
+            Some(path) => object_files.push(path),
+            None => not_found("library", library, &library_search_paths),
+        }
+    }
+    // Upward libraries are loaded with LC_LOAD_UPWARD_DYLIB, which lets
+    // them depend on the output in turn.
+    let mut upward_files: Vec<PathBuf> = args.upward_library_paths.clone();
+    for library in &args.upward_libraries {
+        match discover_library_path(&dirs, &library_search_paths, library) {
+            Some(path) => upward_files.push(path),
+            None => not_found("library", library, &library_search_paths),
+        }
+    }
+    for framework in &args.upward_frameworks {
+        match discover_framework_path(&dirs, &framework_search_paths, framework) {
+            Some(path) => upward_files.push(path),
+            None => not_found("framework", framework, &framework_search_paths),
+        }
+    }
+    // Hidden libraries can only be archives, their definitions are
+    // made private extern as they're loaded.
+    let mut hidden_files: Vec<PathBuf> = args.hidden_library_paths.clone();
+    for library in &args.hidden_libraries {
+        match discover_library_path(&dirs, &library_search_paths, &format!(":lib{library}.a")) {
+            Some(path) => hidden_files.push(path),
+            None => not_found("archive", library, &library_search_paths),
+        }
+    }
+    if missing {
+        return Err(());
+    }
+    object_files.extend(upward_files.iter().cloned());
+    object_files.extend(hidden_files.iter().cloned());
+    log::trace!("Object files: {:?}", object_files);
+    let input_key = |path: &PathBuf| {
+        cache
+            .key(path)
+            .map_err(|e| error!("Unable to read {}: {e}", path.display()))
+    };
+    let upward_keys = upward_files
+        .iter()
+        .map(input_key)
+        .collect::<Result<HashSet<InputKey>, ()>>()?;
+    let hidden_keys = hidden_files
+        .iter()
+        .map(input_key)
+        .collect::<Result<HashSet<InputKey>, ()>>()?;
+    // Each file is only loaded once, however many times and ways it's
+    // given. The first occurrence decides where it goes in the link
+    // order.
+    let mut unique_files = vec![];
+    let mut object_keys = vec![];
+    for path in object_files {
+        let key = input_key(&path)?;
+        if object_keys.contains(&key) {
+            log::debug!("Ignoring repeated input {}", path.display());
+        } else {
+            unique_files.push(path);
+            object_keys.push(key);
+        }
+    }
+    let mut object_files = unique_files;
+    let threads = ThreadPool::new(args.threads);
+    log::debug!("Using {} threads", threads.threads());
+    panic_context::set_phase("reading inputs");
+    let mut object_contents = threads
+        .map(&object_files, |object_file_path| {
+            let _span = time_trace::span("Read", || object_file_path.display().to_string());
+            cache
+                .read(object_file_path)
+                .map_err(|e| format!("Unable to read {}: {e}", object_file_path.display()))
+                .and_then(|(_, contents)| thin_fat64(object_file_path, contents, &args.arch))
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, String>>()
+        .map_err(|e| error!("{e}"))?;
+    // What the objects ask for with LC_LINKER_OPTION follows the other
+    // inputs, unless it's already one of them. Like ld64, it's only a
+    // warning if it can't be found. An auto-linked archive's members can
+    // ask for more in turn, so this goes on until nothing new is asked
+    // for.
+    let mut asked: Vec<AutoLink> = vec![];
+    let mut scanned = 0;
+    while args.auto_link && scanned < object_contents.len() {
+        let mut auto_linked = vec![];
+        let found = auto_links(&args.arch, &object_contents[scanned..]);
+        scanned = object_contents.len();
+        for auto_link in found {
+            if asked.contains(&auto_link) {
+                continue;
+            }
+            asked.push(auto_link.clone());
+            let path = match &auto_link {
+                AutoLink::Library(name) => {
+                    discover_library_path(&dirs, &library_search_paths, name)
+                }
+                AutoLink::Framework(name) => {
+                    discover_framework_path(&dirs, &framework_search_paths, name)
+                }
+            };
+            let Some(path) = path else {
+                warning!("Ignoring auto-linked {auto_link}, it wasn't found");
+                continue;
+            };
+            let key = input_key(&path)?;
+            if !object_keys.contains(&key) {
+                log::debug!("Auto-linking {auto_link} from {}", path.display());
+                object_keys.push(key);
+                auto_linked.push(path);
+            }
+        }
+        let contents = threads
+            .map(&auto_linked, |path| {
+                cache
+                    .read(path)
+                    .map_err(|e| format!("Unable to read {}: {e}", path.display()))
+                    .and_then(|(_, contents)| thin_fat64(path, contents, &args.arch))
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>, String>>()
+            .map_err(|e| error!("{e}"))?;
+        object_files.extend(auto_linked);
+        object_contents.extend(contents);
+    }
+    for path in &object_files {
+        observer.on_file_loaded(path);
+    }
+    if let Some(bundle) = &args.reproduce {
+        write_reproduce_bundle(bundle, &args, &object_files, &object_contents)
+            .map_err(|e| error!("Unable to write {}: {e}", bundle.display()))?;
+    }
+    let build_versions = build_versions(&args.arch, &object_files, &object_contents);
+    check_deployment_target(&build_versions, &args.platform_version);
+    let platforms = [args.platform_version.platform];
+    let indexes: Vec<usize> = (0..object_contents.len()).collect();
+    let objects = threads
+        .map(&indexes, |&i| {
+            log::debug!("Parsing {}", object_files[i].display());
+            let _span = time_trace::span("Parse", || object_files[i].display().to_string());
+            panic_context::set_input(Some(object_files[i].display().to_string()));
+            let object = Object::parse(
+                object_contents[i].as_slice(),
+                &platforms,
+                cache,
+                &object_keys[i],
+            )
+            .map_err(|e| error!("Unable to link {}: {e}", object_files[i].display()));
+            panic_context::set_input(None);
+            object
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, ()>>()?;
+    log::debug!("Linking {} objects", objects.len());
+    panic_context::set_phase("loading inputs");
+    // log::debug!("Objects: {objects:#?}");
+
+    let mut dylibs = vec![];
+    // Install names of the dylibs given with the -upward_* flags.
+    let mut upward_dylibs: HashSet<String> = HashSet::new();
+    let mut objs: Vec<MachO> = vec![];
+    let mut unowned_objs: Vec<&MachO> = vec![];
+    // Display names of objs and unowned_objs, for diagnostics.
+    let mut obj_names: Vec<String> = vec![];
+    let mut unowned_obj_names: Vec<String> = vec![];
+    let mut foreign: Vec<ForeignInput> = vec![];
+    // Indexes into objs of the members of hidden archives.
+    let mut hidden_objs: HashSet<usize> = HashSet::new();
+    for (i, path) in object_files.iter().enumerate() {
+        let archive = slices(&object_contents[i])
+            .unwrap_or_default()
+            .into_iter()
+            .all(|slice| input_kind::sniff(slice) == InputKind::Archive);
+        if hidden_keys.contains(&object_keys[i]) && !archive {
+            error!(
+                "{} can't be loaded hidden, only archives can",
+                path.display()
+            );
+            return Err(());
+        }
+        let kind = input_kind::sniff(&object_contents[i]);
+        let linkable = matches!(
+            kind,
+            InputKind::Dylib | InputKind::Executable | InputKind::Tbd | InputKind::Fat
+        );
+        if upward_keys.contains(&object_keys[i]) && !linkable {
+            error!(
+                "{} can't be linked upward, it's {kind} rather than a dylib",
+                path.display()
+            );
+            return Err(());
+        }
+    }
+
+    for (i, object) in objects.iter().enumerate() {
+        panic_context::set_input(Some(object_files[i].display().to_string()));
+        match object {
+            Object::Elf(_) => todo!(),
+            Object::PE(_) => todo!(),
+            Object::Mach(mach) => match mach {
+                goblin::mach::Mach::Fat(fat) => {
+                    let arches = fat
+                        .iter_arches()
+                        .collect::<Result<Vec<FatArch>, _>>()
+                        .map_err(|e| error!("Unable to read {}: {e}", object_files[i].display()))?;
+                    // Checked before goblin slices them, which it does
+                    // without checking.
+                    let arch_slices = slices(&object_contents[i])
+                        .map_err(|e| error!("Unable to read {}: {e}", object_files[i].display()))?;
+                    for (arch, &bytes) in arches.iter().zip(&arch_slices) {
+                        if arch.cputype() != args.arch.cpu_type() {
+                            foreign.push(ForeignInput {
+                                name: object_files[i].display().to_string(),
+                                arch: Some(arch_name(arch.cputype(), arch.cpusubtype())),
+                                bytes,
+                            });
+                        }
+                    }
+                    let Some(arch_position) = arches
+                        .iter()
+                        .position(|arch| arch.cputype() == args.arch.cpu_type())
+                    else {
+                        let available: Vec<String> = arches
+                            .iter()
+                            .map(|arch| arch_name(arch.cputype(), arch.cpusubtype()))
+                            .collect();
+                        error!(
+                            "{} does not contain {}, only {}",
+                            object_files[i].display(),
+                            args.arch,
+                            available.join(", ")
+                        );
+                        return Err(());
+                    };
+                    match fat.get(arch_position) {
+                        Ok(entry) => match entry {
+                            SingleArch::MachO(_)
+                                if !input_kind::sniff(arch_slices[arch_position])
+                                    .is_supported() =>
+                            {
+                                error!(
+                                    "Unable to link the {} slice of {}: it's {}",
+                                    args.arch,
+                                    object_files[i].display(),
+                                    input_kind::sniff(arch_slices[arch_position])
+                                );
+                                return Err(());
+                            }
+                            SingleArch::MachO(macho) => {
+                                if macho.is_object_file() {
+                                    objs.push(macho);
+                                    obj_names.push(object_files[i].display().to_string());
+                                }
+                            }
+                            SingleArch::Archive(archive) => {
+                                let bytes = arch_slices[arch_position];
+                                let members = archive::members(&archive, bytes).map_err(|e| {
+                                    error!("Unable to read {}: {e}", object_files[i].display())
+                                })?;
+                                for (member_name, member_bytes) in members {
+                                    let name =
+                                        format!("{}({member_name})", object_files[i].display());
+                                    let macho = MachO::parse(member_bytes, 0)
+                                        .map_err(|e| error!("Unable to parse {name}: {e}"))?;
+                                    if !macho.is_64 || !macho.little_endian {
+                                        error!(
+                                            "Unable to link {name}: it's {}",
+                                            input_kind::sniff(member_bytes)
+                                        );
+                                        return Err(());
+                                    }
+                                    if macho.is_object_file() {
+                                        observer.on_member_pulled(&object_files[i], &member_name);
+                                        if hidden_keys.contains(&object_keys[i]) {
+                                            hidden_objs.insert(objs.len());
+                                        }
+                                        objs.push(macho);
+                                        obj_names.push(name);
+                                    }
+                                }
+                            }
+                        },
+                        Err(e) => {
+                            error!("Unable to parse {}: {e}", object_files[i].display());
+                            return Err(());
+                        }
+                    }
+                }
+                goblin::mach::Mach::Binary(macho) => {
+                    if macho.header.cputype != args.arch.cpu_type() {
+                        let arch = arch_name(macho.header.cputype, macho.header.cpusubtype);
+                        warning!(
+                            "Ignoring {}, it's built for {arch} but linking {}",
+                            object_files[i].display(),
+                            args.arch
+                        );
+                        foreign.push(ForeignInput {
+                            name: object_files[i].display().to_string(),
+                            arch: Some(arch),
+                            bytes: &object_contents[i],
+                        });
+                    } else if macho.is_object_file() {
+                        unowned_objs.push(macho);
+                        unowned_obj_names.push(object_files[i].display().to_string());
+                    } else {
+                        match macho.header.filetype {
+                            // Stub dylibs from older SDKs have a symbol
+                            // table but no code.
+                            MH_EXECUTE | MH_DYLIB | MH_DYLIB_STUB => {
+                                // TBDs' allowable clients aren't readable
+                                // from text-stub-library, so only Mach-O
+                                // libraries are checked.
+                                let restrictions =
+                                    Restrictions::from_macho(macho, &object_contents[i]);
+                                restrictions
+                                    .check(
+                                        &object_files[i].display().to_string(),
+                                        &args.install_path().to_string_lossy(),
+                                        args.umbrella.as_deref(),
+                                        args.client_name.as_deref(),
+                                    )
+                                    .map_err(|e| error!("{e}"))?;
+                                if upward_keys.contains(&object_keys[i]) {
+                                    upward_dylibs.insert(Dylib::MachO(macho).install_name());
+                                }
+                                dylibs.push(Dylib::MachO(macho))
+                            }
+                            _ => {
+                                error!(
+                                    "Unable to link {}: it's {}",
+                                    object_files[i].display(),
+                                    input_kind::sniff(&object_contents[i])
+                                );
+                                return Err(());
+                            }
+                        }
+                    }
+                }
+            },
+            Object::Archive(archive) => {
+                let bytes = &object_contents[i];
+                let members = archive::members(archive, bytes)
+                    .map_err(|e| error!("Unable to read {}: {e}", object_files[i].display()))?;
+                for (member_name, member_bytes) in members {
+                    let name = format!("{}({member_name})", object_files[i].display());
+                    let slices =
+                        slices(member_bytes).map_err(|e| error!("Unable to parse {name}: {e}"))?;
+                    for slice in slices {
+                        let macho = MachO::parse(slice, 0)
+                            .map_err(|e| error!("Unable to parse {name}: {e}"))?;
+                        if macho.header.cputype != args.arch.cpu_type() {
+                            foreign.push(ForeignInput {
+                                name: name.clone(),
+                                arch: Some(arch_name(
+                                    macho.header.cputype,
+                                    macho.header.cpusubtype,
+                                )),
+                                bytes: slice,
+                            });
+                        } else if !macho.is_64 || !macho.little_endian {
+                            error!("Unable to link {name}: it's {}", input_kind::sniff(slice));
+                            return Err(());
+                        } else if macho.is_object_file() {
+                            observer.on_member_pulled(&object_files[i], &member_name);
+                            if hidden_keys.contains(&object_keys[i]) {
+                                hidden_objs.insert(objs.len());
+                            }
+                            objs.push(macho);
+                            obj_names.push(name.clone());
+                        }
+                    }
+                }
+            }
+            Object::Tbd(tbd) => {
+                if upward_keys.contains(&object_keys[i]) {
+                    upward_dylibs.insert(tbd.install_name.display().to_string());
+                }
+                // The exports for other architectures are only read if
+                // they're needed to explain an undefined symbol.
+                foreign.push(ForeignInput {
+                    name: object_files[i].display().to_string(),
+                    arch: None,
+                    bytes: &object_contents[i],
+                });
+                dylibs.push(Dylib::Tbd(tbd))
+            }
+        }
+    }
+    // Without dyld there's nothing to load dylibs, everything has to
+    // come from the objects and archives.
+    let uses_dyld = !args.static_link && !args.preload;
+    if !uses_dyld && !dylibs.is_empty() {
+        warning!(
+            "Ignoring {} dylibs, they can't be used without dyld",
+            dylibs.len()
+        );
+        dylibs.clear();
+    }
+    if !uses_dyld && args.dylinker.is_some() {
+        warning!("Ignoring --dynamic-linker, the output isn't started by dyld");
+    }
+
+    panic_context::set_input(None);
+    let inputs: Vec<&MachO> = objs.iter().chain(unowned_objs.iter().copied()).collect();
+    let input_names: Vec<String> = obj_names.into_iter().chain(unowned_obj_names).collect();
+    // -trace_symbol output goes to stdout regardless of the log level so
+    // it can be used without drowning in RUST_LOG=trace output.
+    let mut trace = |name: &str, message: std::fmt::Arguments| {
+        if args.trace_symbols.iter().any(|traced| traced == name) {
+            observer.on_symbol_traced(name, message);
+        }
+    };
+    // Relocations are decoded up front so that malformed ADDEND/SUBTRACTOR
+    // pairs are reported instead of silently producing corrupt output.
+    panic_context::set_phase("decoding relocations");
+    let mut layout = Layout::new(&inputs, &threads).map_err(|e| error!("{e}"))?;
+    layout.wrap_references(&args.wraps);
+    layout.hide_definitions(|object| hidden_objs.contains(&object));
+    layout.data_const = args.data_const;
+    // Dirty data is laid out like any other symbol moved into a
+    // writable segment.
+    let dirty_data = args
+        .dirty_data_list
+        .clone()
+        .map(|path| ("__DATA_DIRTY".to_string(), path));
+    let moves = args
+        .move_to_ro_segment
+        .iter()
+        .cloned()
+        .map(|moved| (moved, false))
+        .chain(
+            args.move_to_rw_segment
+                .iter()
+                .cloned()
+                .chain(dirty_data)
+                .map(|moved| (moved, true)),
+        );
+    for ((segment, path), writable) in moves {
+        let symbols = SymbolList::from_file(&path).map_err(|e| error!("{e}"))?;
+        layout.segment_moves.push(SegmentMove {
+            segment,
+            symbols,
+            writable,
+        });
+    }
+    if let Some(path) = &args.profile_order {
+        layout.profile = Some(Profile::from_file(path).map_err(|e| error!("{e}"))?);
+    }
+
+    // let mut executable = ArtifactBuilder::new(target_lexicon::Triple {
+    //     architecture: target_lexicon::Architecture::Arm(ArmArchitecture::Arm),
+    //     vendor: target_lexicon::Vendor::Unknown,
+    //     operating_system: target_lexicon::OperatingSystem::Unknown,
+    //     environment: target_lexicon::Environment::Unknown,
+    //     binary_format: target_lexicon::BinaryFormat::Macho,
+    // })
+    // .name(
+    //     args.output_file
+    //         .file_name()
+    //         .unwrap()
+    //         .to_str()
+    //         .unwrap()
+    //         .to_string(),
+    // )
+    // .finish();
+
+    // Names are borrowed from the inputs' string tables rather than
+    // copied, they live as long as object_contents.
+    // Sized up front, growing them is a large part of resolving a big
+    // link.
+    let (defined, undefined) = inputs
+        .iter()
+        .map(|input| symbol_counts(input))
+        .fold((0, 0), |(a, b), (c, d)| (a + c, b + d));
+    let mut symbols: SymbolMap<Symbol> =
+        SymbolMap::with_capacity_and_hasher(defined, Default::default());
+    let mut undefined_symbols: SymbolSet =
+        SymbolSet::with_capacity_and_hasher(undefined, Default::default());
+    let mut commons: SymbolMap<Common> = SymbolMap::default();
+    // Absolute symbols from --defsym, which take the place of any
+    // definition in the inputs.
+    let absolutes: SymbolMap<u64> = args
+        .defsyms
+        .iter()
+        .map(|(name, value)| (&name[..], *value))
+        .collect();
+    // Symbols referenced without N_WEAK_REF somewhere. The rest are only
+    // weakly imported.
+    let mut strong_references: SymbolSet = SymbolSet::default();
+    // References to DTrace probes, which need DOF.
+    let mut probes: Vec<(&str, usize)> = vec![];
+
+    panic_context::set_phase("resolving symbols");
+    for (input, obj) in objs.iter().enumerate() {
+        panic_context::set_input(Some(input_names[input].clone()));
+        for symbol in obj.symbols() {
+            let (name, mut nlist) = symbol.unwrap();
+            // Symbols without N_EXT are only the object's own, the
+            // layout resolves them within it.
+            if nlist.n_type & N_EXT == 0 {
+                continue;
+            }
+            if hidden_objs.contains(&input) {
+                layout::hide(&mut nlist);
+            }
+            let name = wrap::resolved_name(&args.wraps, name, &nlist);
+            // println!(
+            //     "{}:\t{:?}, type={}, global={}, weak={}, undefined={}, stab={}",
+            //     name,
+            //     Nlist64::from(nlist.clone()),
+            //     nlist.type_str(),
+            //     nlist.is_global(),
+            //     nlist.is_weak(),
+            //     nlist.is_undefined(),
+            //     nlist.is_stab(),
+            // );
+            let symbol = Symbol { nlist, input, name };
+
+            if is_common(&symbol.nlist) {
+                trace(name, format_args!("common in {}", input_names[input]));
+                commons
+                    .entry(name)
+                    .and_modify(|common| common.merge(&symbol.nlist))
+                    .or_insert_with(|| Common::new(name, &symbol.nlist));
+                undefined_symbols.remove(name);
+                continue;
+            }
+
+            // Keep track of undefined symbols so that we can check
+            // them at the end. If we encounter a definition of the
+            // symbol it'll be removed from the set.
+            if symbol.nlist.is_undefined() {
+                trace(name, format_args!("reference in {}", input_names[input]));
+                // These fail the link once every input is resolved.
+                if dtrace::is_probe_symbol(name) {
+                    probes.push((name, input));
+                    continue;
+                }
+                if symbol.nlist.n_desc & N_WEAK_REF == 0 {
+                    strong_references.insert(name);
+                }
+                if !symbols.contains_key(name)
+                    && !commons.contains_key(name)
+                    && !absolutes.contains_key(name)
+                {
+                    undefined_symbols.insert(name);
+                }
+                continue;
+            }
+            trace(name, format_args!("definition in {}", input_names[input]));
+            if absolutes.contains_key(name) {
+                trace(
+                    name,
+                    format_args!("definition ignored, it's set by --defsym"),
+                );
+                continue;
+            }
+
+            // Insert the symbol, whatever is, if we've never seen it
+            // before. Otherwise, only insert it if the new symbol is
+            // not weak. If there are only weak symbols then we just
+            // take the first one.
+            //
+            // Having two "strong" external symbols fails the link once
+            // the rest have been resolved, so every duplicate is
+            // reported along with what's undefined.
+            if let Some(existing_symbol) = symbols.get(name) {
+                if existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
+                    // The old symbol was weak but this one isn't - replace it.
+                    trace(
+                        name,
+                        format_args!(
+                            "{} overrides weak definition in {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    symbols.insert(name, symbol);
+                    undefined_symbols.remove(name);
+                } else if !existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
+                    trace(
+                        name,
+                        format_args!(
+                            "duplicate definition in {} ignored, keeping {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    error!(
+                        "duplicate symbol {name} in {} and {}",
+                        input_names[existing_symbol.input], input_names[symbol.input]
+                    );
+                } else {
+                    trace(
+                        name,
+                        format_args!(
+                            "weak definition in {} ignored, keeping {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    log::trace!("Weak symbol {} already seen, ignoring it", name)
+                }
+            } else {
+                symbols.insert(name, symbol);
+                undefined_symbols.remove(name);
+            }
+        }
+    }
+
+    for (i, obj) in unowned_objs.into_iter().enumerate() {
+        panic_context::set_input(Some(input_names[objs.len() + i].clone()));
+        for symbol in obj.symbols() {
+            let (name, nlist) = symbol.unwrap();
+            if nlist.n_type & N_EXT == 0 {
+                continue;
+            }
+            let name = wrap::resolved_name(&args.wraps, name, &nlist);
+            let symbol = Symbol {
+                name,
+                nlist,
+                input: objs.len() + i,
+            };
+            if is_common(&symbol.nlist) {
+                trace(
+                    name,
+                    format_args!("common in {}", input_names[symbol.input]),
+                );
+                commons
+                    .entry(name)
+                    .and_modify(|common| common.merge(&symbol.nlist))
+                    .or_insert_with(|| Common::new(name, &symbol.nlist));
+                undefined_symbols.remove(name);
+                continue;
+            }
+            if symbol.nlist.is_undefined() {
+                trace(
+                    name,
+                    format_args!("reference in {}", input_names[symbol.input]),
+                );
+                if dtrace::is_probe_symbol(name) {
+                    probes.push((name, symbol.input));
+                    continue;
+                }
+                if symbol.nlist.n_desc & N_WEAK_REF == 0 {
+                    strong_references.insert(name);
+                }
+                if !symbols.contains_key(name)
+                    && !commons.contains_key(name)
+                    && !absolutes.contains_key(name)
+                {
+                    undefined_symbols.insert(name);
+                }
+                continue;
+            }
+            trace(
+                name,
+                format_args!("definition in {}", input_names[symbol.input]),
+            );
+            if absolutes.contains_key(name) {
+                trace(
+                    name,
+                    format_args!("definition ignored, it's set by --defsym"),
+                );
+                continue;
+            }
+            if let Some(existing_symbol) = symbols.get(name) {
+                if existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
+                    // The old symbol was weak but this one isn't - replace it.
+                    trace(
+                        name,
+                        format_args!(
+                            "{} overrides weak definition in {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    symbols.insert(name, symbol);
+                    undefined_symbols.remove(name);
+                } else if !existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
+                    trace(
+                        name,
+                        format_args!(
+                            "duplicate definition in {} ignored, keeping {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    error!(
+                        "duplicate symbol {name} in {} and {}",
+                        input_names[existing_symbol.input], input_names[symbol.input]
+                    );
+                } else {
+                    trace(
+                        name,
+                        format_args!(
+                            "weak definition in {} ignored, keeping {}",
+                            input_names[symbol.input], input_names[existing_symbol.input]
+                        ),
+                    );
+                    log::trace!("Weak symbol {} already seen, ignoring it", name)
+                }
+            } else {
+                symbols.insert(name, symbol);
+                undefined_symbols.remove(name);
+            }
+        }
+    }
+
+    panic_context::set_input(None);
+
+    if let Some(&(name, input)) = probes.first() {
+        error!(
+            "USDT probes need DOF, which isn't supported: {name} is referenced from {}{}",
+            input_names[input],
+            match probes.len() - 1 {
+                0 => String::new(),
+                more => format!(" and {more} more"),
+            }
+        );
+        return Err(());
+    }
+
+    // Commons only need space of their own if nothing defined them for
+    // real.
+    let mut tentative: Vec<Common> = commons
+        .into_values()
+        .filter(|common| {
+            !absolutes.contains_key(common.name)
+                && symbols
+                    .get(common.name)
+                    .is_none_or(|symbol| symbol.nlist.is_undefined())
+        })
+        .collect();
+    tentative.sort_by_key(|common| common.name);
+    let common_names: SymbolSet = tentative.iter().map(|common| common.name).collect();
+    for common in &tentative {
+        trace(
+            common.name,
+            format_args!("allocated {} bytes in __DATA,__common", common.size),
+        );
+    }
+    layout.commons = tentative;
+
+    // Like ld64, symbols re-exported from a public library bind directly
+    // to it rather than the library re-exporting it. These implicit
+    // dylibs come after the ones given on the command line, and map
+    // each of their exports to (implicit dylib, re-exporting dylib).
+    let mut implicit_exports: HashMap<&str, (usize, usize)> = HashMap::new();
+    if args.implicit_dylibs {
+        let explicit = dylibs.len();
+        for parent in 0..explicit {
+            let Dylib::Tbd(tbd) = dylibs[parent] else {
+                continue;
+            };
+            for child in &tbd.reexports {
+                if !tbd::is_public_install_name(&child.install_name) {
+                    continue;
+                }
+                for export in &child.exports {
+                    implicit_exports
+                        .entry(export)
+                        .or_insert((dylibs.len(), parent));
+                }
+                dylibs.push(Dylib::Tbd(child));
+            }
+        }
+    }
+
+    // Libraries that are re-exported but not part of the link are read
+    // from the SDK, or where -dylib_file says, and their exports become
+    // the re-exporter's.
+    let find_reexport = |install_name: &str| {
+        if let Some((_, file)) = args
+            .dylib_files
+            .iter()
+            .find(|(name, _)| name == install_name)
+        {
+            return Some(file.clone());
+        }
+        let path = in_sys_lib_root(&[PathBuf::from(install_name)], false).remove(0);
+        [path.with_extension("tbd"), path]
+            .into_iter()
+            .find(|path| path.exists())
+    };
+    // Each library read is reported once, however many re-export it,
+    // once the symbols have been traced.
+    let mut loaded: Vec<PathBuf> = vec![];
+    let mut reexported_exports = |dylib: &Dylib| -> Vec<String> {
+        let mut exports = vec![];
+        let root = dylib.install_name();
+        reexports::walk(&root, |name| {
+            if name == root {
+                return dylib.external_reexports();
+            }
+            let Some(path) = find_reexport(name) else {
+                warning!("Unable to find {name}, re-exported by {root}");
+                return vec![];
+            };
+            let (key, contents) = match cache.read(&path) {
+                Ok(read) => read,
+                Err(e) => {
+                    warning!("Unable to read {}: {e}", path.display());
+                    return vec![];
+                }
+            };
+            if !loaded.contains(&path) {
+                loaded.push(path.clone());
+            }
+            let contents = match thin_fat64(&path, contents, &args.arch) {
+                Ok(contents) => contents,
+                Err(e) => {
+                    warning!("{e}");
+                    return vec![];
+                }
+            };
+            match goblin::Object::parse(&contents) {
+                Ok(goblin::Object::Unknown(_)) => {
+                    let tbd = cache.tbd(&key, &platforms, || {
+                        TbdDylib::parse(args.arch.clone(), &platforms, &contents)
+                    });
+                    match tbd {
+                        Ok(tbd) => {
+                            exports.extend(tbd.exports.iter().cloned());
+                            Dylib::Tbd(&tbd).external_reexports()
+                        }
+                        Err(e) => {
+                            warning!("Unable to parse {}: {e}", path.display());
+                            vec![]
+                        }
+                    }
+                }
+                Ok(goblin::Object::Mach(goblin::mach::Mach::Binary(macho))) => {
+                    let child = Dylib::MachO(&macho);
+                    exports.extend(child.exports().into_iter().map(Cow::into_owned));
+                    child.external_reexports()
+                }
+                _ => {
+                    warning!("Ignoring {}, it isn't a dylib or TBD", path.display());
+                    vec![]
+                }
+            }
+        });
+        exports
+    };
+
+    // The dylib that satisfied each undefined symbol, as an index into
+    // dylibs.
+    let mut providers: HashMap<&str, usize> = HashMap::new();
+    for (index, dylib) in dylibs.iter().enumerate() {
+        let reexported = reexported_exports(dylib).into_iter().map(Cow::Owned);
+        for export in dylib.exports().into_iter().chain(reexported) {
+            if let Some(name) = undefined_symbols.take(&export[..]) {
+                let provider = match implicit_exports.get(name) {
+                    Some((child, parent)) if *parent == index => *child,
+                    _ => index,
+                };
+                let install_name = dylibs[provider].install_name();
+                trace(name, format_args!("resolved to {install_name}"));
+                log::trace!("{name} will be defined by {install_name}");
+                providers.insert(name, provider);
+            }
+        }
+    }
+
+    for name in &args.trace_symbols {
+        match symbols.get(&name[..]) {
+            Some(symbol) => trace(
+                name,
+                format_args!("resolved to definition in {}", input_names[symbol.input]),
+            ),
+            None if undefined_symbols.contains(&name[..]) => trace(name, format_args!("undefined")),
+            None if absolutes.contains_key(&name[..]) => trace(
+                name,
+                format_args!("defined as {:#x} by --defsym", absolutes[&name[..]]),
+            ),
+            None => {}
+        }
+    }
+    for path in &loaded {
+        observer.on_file_loaded(path);
+    }
+    for symbol in symbols.values().filter(|symbol| symbol.nlist.is_global()) {
+        observer.on_symbol_resolved(symbol.name, Definition::Object(&input_names[symbol.input]));
+    }
+    for name in &common_names {
+        observer.on_symbol_resolved(name, Definition::Common);
+    }
+    for (name, value) in &absolutes {
+        observer.on_symbol_resolved(name, Definition::Absolute(*value));
+    }
+    for (name, provider) in &providers {
+        let install_name = dylibs[*provider].install_name();
+        observer.on_symbol_resolved(name, Definition::Dylib(&install_name));
+    }
+
+    if !undefined_symbols.is_empty() {
+        // The most likely reason for a symbol to be missing is that it's
+        // only been built for another architecture, so say if it has.
+        let mut elsewhere: HashMap<String, (String, &str)> = HashMap::new();
+        for input in &foreign {
+            for (name, arch) in foreign_definitions(input, &args.arch) {
+                if undefined_symbols.contains(&name[..]) {
+                    elsewhere.entry(name).or_insert((arch, &input.name));
+                }
+            }
+        }
+        // Next most likely is a mismatch between C and C++ linkage.
+        let mut linkage = LinkageIndex::default();
+        for symbol in symbols.values().filter(|symbol| symbol.nlist.is_global()) {
+            linkage.insert(symbol.name, &input_names[symbol.input]);
+        }
+        for dylib in &dylibs {
+            let install_name = dylib.install_name();
+            for export in dylib.exports() {
+                linkage.insert(&export, &install_name);
+            }
+        }
+        // Failing that, the symbol may be in a library that just wasn't
+        // asked for.
+        let unlinked = unlinked_libraries(
+            &dirs,
+            &library_search_paths,
+            &framework_search_paths,
+            &object_keys,
+        );
+        let found = threads.map(&unlinked, |(_, path)| {
+            let Ok((key, contents)) = cache.read(path) else {
+                return vec![];
+            };
+            let Ok(contents) = thin_fat64(path, contents, &args.arch) else {
+                return vec![];
+            };
+            let exports = match goblin::Object::parse(&contents) {
+                Ok(goblin::Object::Unknown(_)) => cache
+                    .tbd(&key, &platforms, || {
+                        TbdDylib::parse(args.arch.clone(), &platforms, &contents)
+                    })
+                    .map(|tbd| [&tbd.exports[..], &tbd.weak_exports[..]].concat())
+                    .unwrap_or_default(),
+                Ok(_) => defined_symbols(&contents, args.arch.cpu_type()),
+                Err(_) => vec![],
+            };
+            exports
+                .into_iter()
+                .filter(|export| undefined_symbols.contains(&export[..]))
+                .collect::<Vec<String>>()
+        });
+        let mut suggestions: HashMap<String, &(String, PathBuf)> = HashMap::new();
+        for (library, exports) in unlinked.iter().zip(found) {
+            for export in exports {
+                suggestions.entry(export).or_insert(library);
+            }
+        }
+        // Sorted so the ones reported under -error-limit don't change
+        // from one link to the next.
+        let mut undefined_symbols: Vec<&str> = undefined_symbols.into_iter().collect();
+        undefined_symbols.sort_unstable();
+        for &symbol in &undefined_symbols {
+            observer.on_symbol_undefined(symbol);
+            if let Some((arch, input)) = elsewhere.get(symbol) {
+                error!(
+                    "{symbol} is undefined, found {symbol} for {arch} in {input}, but linking {}",
+                    args.arch
+                );
+            } else if let Some((hint, input)) = linkage.hint(symbol) {
+                error!(
+                    "{symbol} is undefined, but {hint} in {input}. Is a declaration missing extern \"C\"?"
+                );
+            } else if let Some((flag, path)) = suggestions.get(symbol) {
+                error!(
+                    "{symbol} is undefined, perhaps add {flag} (found in {})",
+                    path.display()
+                );
+            } else {
+                error!("{symbol} is undefined");
+            }
+        }
+        return Err(());
+    }
+    // Duplicate definitions were reported as they were found.
+    if errors::counted() > 0 {
+        return Err(());
+    }
+
+    // Only libraries that provide at least one symbol are loaded. dyld
+    // ordinals are 1-based and follow the command line order.
+    let used: Vec<usize> = (0..dylibs.len())
+        .filter(|index| providers.values().any(|provider| provider == index))
+        .collect();
+    let used_dylibs: Vec<&Dylib> = used.iter().map(|index| &dylibs[*index]).collect();
+    for (ordinal, dylib) in used_dylibs.iter().enumerate() {
+        log::debug!("{} has ordinal {}", dylib.install_name(), ordinal + 1);
+    }
+    // Symbols the libraries say are newer than the deployment target, so
+    // may not be there at runtime.
+    let weak_imports: FxHashSet<String> = used_dylibs
+        .iter()
+        .flat_map(|dylib| dylib.exports())
+        .filter_map(|export| {
+            availability::weak_import(&export, args.platform_version.min_version)
+                .map(str::to_string)
+        })
+        .filter(|symbol| providers.contains_key(&symbol[..]))
+        .collect();
+    for symbol in &weak_imports {
+        log::debug!("Weakly importing {symbol}, which is newer than the deployment target");
+    }
+    if args.dry_run {
+        let install_names: Vec<String> = used_dylibs
+            .iter()
+            .map(|dylib| dylib.install_name())
+            .collect();
+        let mut imports: Vec<(&str, usize)> = providers
+            .iter()
+            .map(|(name, provider)| {
+                let ordinal = used.iter().position(|index| index == provider).unwrap();
+                (*name, ordinal)
+            })
+            .collect();
+        imports.sort_unstable();
+        print!(
+            "{}",
+            report::link_plan(&input_names, &install_names, &imports)
+        );
+        return check_warnings(args.fatal_warnings);
+    }
+
+    let filetype = if args.preload { MH_PRELOAD } else { MH_EXECUTE };
+    let mut writer = MachOWriter::new(&args.arch, filetype);
+    if !args.force_cpusubtype_all {
+        let subtypes: Vec<(&str, u32)> = input_names
+            .iter()
+            .zip(&inputs)
+            .map(|(name, macho)| (&name[..], macho.header.cpusubtype))
+            .collect();
+        writer.cpusubtype = cpu_subtype::merge(&subtypes).map_err(|e| error!("{e}"))?;
+    }
+    writer.segment_addresses = args.segment_addresses.clone();
+    writer.segment_order = args.segment_order.clone();
+    writer.section_order = args.section_order.clone();
+    writer.uuid = !args.no_uuid;
+    writer.reproducible = args.reproducible;
+    writer.export_trie = args.export_dynamic;
+    if args.application_extension {
+        writer.flags |= MH_APP_EXTENSION_SAFE;
+        // Like ld64, this is only a warning, the extension may never
+        // call into the library.
+        for dylib in used_dylibs
+            .iter()
+            .filter(|dylib| !dylib.is_app_extension_safe())
+        {
+            warning!(
+                "Linking against {}, which isn't safe for use in application extensions",
+                dylib.install_name()
+            );
+        }
+    }
+    if uses_dyld {
+        let path = args.dylinker.as_deref().unwrap_or("/usr/lib/dyld");
+        // The kernel loads the dynamic linker, it doesn't know dyld's
+        // path macros or a working directory to resolve against.
+        if !path.starts_with('/') {
+            error!("Invalid --dynamic-linker {path}: it must be an absolute path");
+            return Err(());
+        }
+        writer.push_load_command(LoadCommand::Dylinker {
+            path: path.to_string(),
+        });
+    }
+    writer.push_load_command(LoadCommand::for_platform_version(&args.platform_version));
+    for dylib in &used_dylibs {
+        let (current_version, compatibility_version) = dylib.versions();
+        let name = dylib.install_name();
+        // A bad install name is the library's problem, but dyld will
+        // fail to load it.
+        let name = dyld_paths::normalize(&name, PathUse::InstallName).unwrap_or_else(|e| {
+            warning!("{e}");
+            name
+        });
+        for problem in dylib.version_problems() {
+            warning!("{name} {problem}");
+        }
+        let cmd = if upward_dylibs.contains(&name) {
+            LC_LOAD_UPWARD_DYLIB
+        } else {
+            LC_LOAD_DYLIB
+        };
+        writer.push_load_command(LoadCommand::Dylib {
+            cmd,
+            name,
+            current_version,
+            compatibility_version,
+        });
+    }
+    for rpath in &args.rpaths {
+        let path = dyld_paths::normalize(rpath, PathUse::Rpath).map_err(|e| {
+            error!("Invalid -rpath: {e}");
+        })?;
+        writer.push_load_command(LoadCommand::Rpath { path });
+    }
+    if args.rpaths.is_empty() {
+        let rpath_relative = used_dylibs
+            .iter()
+            .map(|dylib| dylib.install_name())
+            .find(|name| dyld_paths::needs_rpath(name));
+        if let Some(name) = rpath_relative {
+            warning!("{name} can't be loaded without a run path, add one with -rpath");
+        }
+    }
+    if args.encryptable {
+        if !platforms.contains(&Platform::IOS) {
+            warning!("-encryptable is only meaningful for iOS targets");
+        }
+        writer.encryptable = true;
+    }
+
+    panic_context::set_phase("laying out the output");
+    if let Err(e) = layout.merge_objc_image_info(&input_names) {
+        error!("{e}");
+        return Err(());
+    }
+    if args.objc_category_merging {
+        let merged = layout.merge_objc_categories(&threads);
+        log::debug!("Merged {merged} Objective-C categories into their classes");
+    }
+    let relaxed = layout.relax_got_loads(&threads, |name| {
+        symbols.contains_key(name) || common_names.contains(name)
+    });
+    log::debug!("Relaxed {relaxed} GOT loads");
+    if let Some(mode) = args.bitcode_bundle {
+        let members = layout.take_bitcode();
+        layout.bitcode_bundle = match mode {
+            BitcodeMode::Strip => None,
+            BitcodeMode::Marker => Some(bitcode::marker()),
+            BitcodeMode::Data => {
+                let platform_version = &args.platform_version;
+                let link = BitcodeLink {
+                    architecture: args.arch.to_string(),
+                    platform: platform_version.platform.to_string(),
+                    sdk_version: platform_version.sdk_version.to_string(),
+                    dylibs: used_dylibs
+                        .iter()
+                        .map(|dylib| dylib.install_name())
+                        .collect(),
+                };
+                let members: Vec<_> = input_names
+                    .iter()
+                    .map(String::as_str)
+                    .zip(members)
+                    .collect();
+                let bundle = bitcode::bundle(&link, &members).map_err(|e| error!("{e}"))?;
+                Some(bundle)
+            }
+        };
+    }
+    layout.place(&mut writer, &threads);
+    if !uses_dyld {
+        // The entry is only known once laid out.
+        writer.unix_thread = Some(0);
+    }
+    writer.layout();
+    let addresses: SymbolMap<u64> = symbols
+        .values()
+        .filter_map(|symbol| {
+            layout
+                .symbol_address(&writer, symbol.input, &symbol.nlist)
+                .map(|address| (symbol.name, address))
+        })
+        .chain(layout.common_addresses(&writer))
+        .chain(absolutes.iter().map(|(name, value)| (*name, *value)))
+        .collect();
+    if !uses_dyld {
+        let Some(&entry) = addresses.get(&args.entry[..]) else {
+            error!("Entry point {} is undefined", args.entry);
+            return Err(());
+        };
+        writer.unix_thread = Some(entry);
+    }
+    panic_context::set_phase("applying relocations");
+    if let Err(e) = layout.apply_relocations(&mut writer, &threads, &input_names, |name| {
+        addresses.get(name).copied()
+    }) {
+        error!("{e}");
+        return Err(());
+    }
+
+    // The symbol table is only sized when writing and sits in
+    // __LINKEDIT, after everything else, so filling it in now doesn't
+    // move anything that has already been laid out.
+    writer.symbols = args
+        .ast_paths
+        .iter()
+        .map(|path| OutputSymbol::ast(&path.to_string_lossy()))
+        .collect();
+    // A list of locals either says which to strip or which to keep.
+    let local_list = match (
+        &args.non_global_symbols_strip_list,
+        &args.non_global_symbols_no_strip_list,
+    ) {
+        (Some(path), _) => Some((path, false)),
+        (_, Some(path)) => Some((path, true)),
+        (None, None) => None,
+    }
+    .map(|(path, keep)| SymbolList::from_file(path).map(|list| (list, keep)))
+    .transpose()
+    .map_err(|e| error!("{e}"))?;
+    writer
+        .symbols
+        .extend(layout.local_symbols(&writer).into_iter().filter(|symbol| {
+            local_list
+                .as_ref()
+                .is_none_or(|(list, keep)| list.contains(&symbol.name) == *keep)
+        }));
+    for symbol in symbols.values() {
+        if symbol.nlist.n_type & N_EXT == 0 || symbol.nlist.n_type & N_PEXT != 0 {
+            continue;
+        }
+        let (Some(section), Some(&n_value)) = (
+            layout.symbol_section(symbol.input, &symbol.nlist),
+            addresses.get(symbol.name),
+        ) else {
+            continue;
+        };
+        writer.symbols.push(OutputSymbol {
+            name: symbol.name.to_string(),
+            n_type: N_SECT | N_EXT,
+            section: Some(section),
+            n_desc: OutputSymbol::defined_desc(symbol.nlist.n_desc),
+            n_value,
+        });
+    }
+    let commons: Vec<_> = layout.common_addresses(&writer).collect();
+    for (name, n_value) in commons {
+        writer.symbols.push(OutputSymbol {
+            name: name.to_string(),
+            n_type: N_SECT | N_EXT,
+            section: layout.common_section(),
+            n_desc: 0,
+            n_value,
+        });
+    }
+    for (name, value) in &absolutes {
+        writer.symbols.push(OutputSymbol {
+            name: name.to_string(),
+            n_type: N_ABS | N_EXT,
+            section: None,
+            n_desc: 0,
+            n_value: *value,
+        });
+    }
+    for (name, provider) in &providers {
+        let ordinal = used
+            .iter()
+            .position(|index| index == provider)
+            .unwrap_or_default()
+            + 1;
+        writer.symbols.push(OutputSymbol {
+            name: name.to_string(),
+            n_type: N_UNDF | N_EXT,
+            section: None,
+            n_desc: OutputSymbol::undefined_desc(
+                ordinal as u16,
+                !strong_references.contains(name) || weak_imports.contains(*name),
+            ),
+            n_value: 0,
+        });
+    }
+
+    // The symbol table has been filled in since the last layout.
+    writer.layout();
+    let overflows = writer.offset_overflows();
+    if !overflows.is_empty() {
+        for overflow in &overflows {
+            error!("{overflow}");
+        }
+        error!(
+            "The output would be {} bytes, too large for Mach-O to describe",
+            writer.file_size()
+        );
+        return Err(());
+    }
+    if !args.max_sizes.is_empty() {
+        let overruns = report::budget_overruns(&writer, &args.max_sizes);
+        if !overruns.is_empty() {
+            for overrun in &overruns {
+                error!("{overrun}");
+            }
+            eprint!("{}", report::memory_usage(&writer));
+            return Err(());
+        }
+    }
+
+    check_warnings(args.fatal_warnings)?;
+    panic_context::set_phase("writing the output");
+    let executable = writer.filetype != MH_OBJECT;
+    write_output(&args.output_file, executable, |file| {
+        writer.write_to(file, &threads)
+    })
+    .map_err(|e| error!("Unable to write {}: {e}", args.output_file.display()))?;
+
+    if let Some(path) = &args.map {
+        let map = report::map(
+            &layout,
+            &writer,
+            &args.output_file,
+            &args.arch,
+            &input_names,
+            args.cross_reference,
+        );
+        std::fs::write(path, map).map_err(|e| error!("Unable to write {}: {e}", path.display()))?;
+    }
+    if args.print_memory_usage {
+        print!("{}", report::memory_usage(&writer));
+    }
+    if let Some(count) = args.size_report {
+        print!(
+            "{}",
+            report::largest_atoms(&layout, &writer, &input_names, count)
+        );
+    }
+    if let Some(report) = stats::finish() {
+        print!("{report}");
+    }
+    if let (Some(path), Some(trace)) = (&args.time_trace, time_trace::finish()) {
+        std::fs::write(path, trace)
+            .map_err(|e| error!("Unable to write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// The answer to `query`, in the format GCC prints it in, so configure
+/// scripts that parse it recognise machop.
+fn answer(query: &Query, library_paths: &[PathBuf], framework_paths: &[PathBuf]) -> String {
+    let exe = std::env::current_exe().ok();
+    let join = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(":")
+    };
+    match query {
+        Query::SearchDirs => {
+            let install = exe
+                .as_deref()
+                .and_then(Path::parent)
+                .map_or(String::new(), |dir| dir.display().to_string());
+            format!(
+                "install: {install}/\nprograms: ={install}\nlibraries: ={}\nframeworks: ={}\n",
+                join(library_paths),
+                join(framework_paths)
+            )
+        }
+        // Like GCC, a program it doesn't know is printed as it's named.
+        Query::ProgName(name) => match (&name[..], exe) {
+            ("ld" | "ld64" | "machop", Some(exe)) => format!("{}\n", exe.display()),
+            _ => format!("{name}\n"),
+        },
+        Query::Help => format!("{USAGE}\n"),
+    }
+}
+
+/// Bundle the arguments and every input for --reproduce. The inputs
+/// found with -l and friends are in `object_files` already, the files
+/// named by options are found by the bundle itself.
+fn write_reproduce_bundle(
+    bundle: &Path,
+    args: &Args,
+    object_files: &[PathBuf],
+    object_contents: &[Arc<Vec<u8>>],
+) -> std::io::Result<()> {
+    let inputs: Vec<(PathBuf, &[u8])> = object_files
+        .iter()
+        .cloned()
+        .zip(object_contents.iter().map(|contents| &contents[..]))
+        .collect();
+    reproduce::write(bundle, &args.argv, args.dialect, &args.environment, &inputs)?;
+    log::info!("Wrote a reproduction of the link to {}", bundle.display());
+    Ok(())
+}
+
+/// The directory the output is written to, `.` if `path` has none.
+fn output_directory(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Check the output can be written to `path`, creating its directory
+/// first if `create` is set.
+fn check_output_directory(path: &Path, create: bool) -> Result<(), String> {
+    let directory = output_directory(path);
+    if create {
+        std::fs::create_dir_all(directory)
+            .map_err(|e| format!("Unable to create {}: {e}", directory.display()))?;
+    }
+    match std::fs::metadata(directory) {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return Err(format!("{} isn't a directory", directory.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!(
+                "Output directory {} doesn't exist, pass --create-dirs to create it",
+                directory.display()
+            ))
+        }
+        Err(e) => return Err(format!("Unable to access {}: {e}", directory.display())),
+    }
+    // Permission bits don't tell the whole story (ACLs, read-only
+    // mounts), so actually try creating a file.
+    let probe = directory.join(format!(".machop-probe-{}", std::process::id()));
+    std::fs::File::create(&probe)
+        .and_then(|_| std::fs::remove_file(&probe))
+        .map_err(|e| format!("Unable to write to {}: {e}", directory.display()))
+}
+
+/// Write the output to a temporary file next to `path` and rename it
+/// into place, so an interrupted link never leaves a truncated output
+/// behind. The mode is filtered by the umask like any other new file.
+fn write_output(
+    path: &Path,
+    executable: bool,
+    contents: impl FnOnce(&std::fs::File) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let directory = output_directory(path);
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = directory.join(format!(".{file_name}.machop-{}", std::process::id()));
+    let mode = if executable { 0o755 } else { 0o644 };
+    let write = || {
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temporary)?;
+        contents(&file)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)
+    };
+    write().inspect_err(|_| {
+        let _ = std::fs::remove_file(&temporary);
+    })
+}
+
+fn discover_library_path(
+    dirs: &DirCache,
+    locations: &[PathBuf],
+    library_name: &str,
+) -> Option<PathBuf> {
+    log::trace!("Discovering library {library_name}");
+    // GNU style -l:libfoo.a names the file exactly.
+    if let Some(file_name) = library_name.strip_prefix(':') {
+        return locations
+            .iter()
+            .map(|prefix| prefix.join(file_name))
+            .find(|candidate| dirs.exists(candidate));
+    }
+    let extensions = ["tbd", "dylib", "a"];
+    for prefix in locations {
+        for extension in extensions {
+            log::trace!(
+                "Looking for library {library_name} with extension {extension} in {}",
+                prefix.display()
+            );
+            let candidate = prefix
+                .join(format!("lib{}", library_name))
+                .with_extension(extension);
+            log::trace!(
+                "Trying candidate {} for library {library_name}",
+                candidate.display()
+            );
+            if dirs.exists(&candidate) {
+                log::trace!(
+                    "Using candidate {} for library {library_name}",
+                    candidate.display()
+                );
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+fn discover_framework_path(
+    dirs: &DirCache,
+    locations: &[PathBuf],
+    framework_name: &str,
+) -> Option<PathBuf> {
+    log::trace!("Discovering framework {framework_name}");
+    for prefix in locations {
+        let framework = prefix.join(format!("{framework_name}.framework"));
+        for candidate in [
+            framework.join(framework_name).with_extension("tbd"),
+            framework.join(framework_name),
+        ] {
+            log::trace!(
+                "Trying candidate {} for framework {framework_name}",
+                candidate.display()
+            );
+            if dirs.exists(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
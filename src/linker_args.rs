@@ -1,16 +1,47 @@
+use std::fmt::Display;
 use std::{
-    ffi::{OsStr, OsString},
-    fmt::Display,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
-use std::{path::PathBuf, str::FromStr};
 
-use llvm_option_parser::ParsedArguments;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arg_parser::{Arg, OptTable},
+    config::Config,
+    environment::Environment,
+    lld_options, output, spec, warning, warnings,
+    wrap::Wrap,
+};
 
 #[derive(Debug, Clone)]
 pub enum Architecture {
     ARM64,
 }
 
+impl Architecture {
+    pub const ALL: &'static [Architecture] = &[Architecture::ARM64];
+
+    pub fn cpu_type(&self) -> u32 {
+        match self {
+            Architecture::ARM64 => goblin::mach::cputype::CPU_TYPE_ARM64,
+        }
+    }
+
+    pub fn cpu_subtype(&self) -> u32 {
+        match self {
+            Architecture::ARM64 => goblin::mach::cputype::CPU_SUBTYPE_ARM64_ALL,
+        }
+    }
+
+    pub fn page_size(&self) -> u64 {
+        match self {
+            Architecture::ARM64 => 0x4000,
+        }
+    }
+}
+
 impl Display for Architecture {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -19,15 +50,339 @@ impl Display for Architecture {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Platform {
+    MacOS,
+    IOS,
+    TvOS,
+    WatchOS,
+    BridgeOS,
+    /// iOS apps running on macOS (macabi).
+    MacCatalyst,
+    IOSSimulator,
+    TvOSSimulator,
+    WatchOSSimulator,
+    DriverKit,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 10] = [
+        Platform::MacOS,
+        Platform::IOS,
+        Platform::TvOS,
+        Platform::WatchOS,
+        Platform::BridgeOS,
+        Platform::MacCatalyst,
+        Platform::IOSSimulator,
+        Platform::TvOSSimulator,
+        Platform::WatchOSSimulator,
+        Platform::DriverKit,
+    ];
+
+    /// The `PLATFORM_*` constant used in LC_BUILD_VERSION.
+    pub fn to_u32(self) -> u32 {
+        use goblin::mach::load_command::{
+            PLATFORM_BRIDGEOS, PLATFORM_DRIVERKIT, PLATFORM_IOS, PLATFORM_IOSSIMULATOR,
+            PLATFORM_MACCATALYST, PLATFORM_MACOS, PLATFORM_TVOS, PLATFORM_TVOSSIMULATOR,
+            PLATFORM_WATCHOS, PLATFORM_WATCHOSSIMULATOR,
+        };
+        match self {
+            Platform::MacOS => PLATFORM_MACOS,
+            Platform::IOS => PLATFORM_IOS,
+            Platform::TvOS => PLATFORM_TVOS,
+            Platform::WatchOS => PLATFORM_WATCHOS,
+            Platform::BridgeOS => PLATFORM_BRIDGEOS,
+            Platform::MacCatalyst => PLATFORM_MACCATALYST,
+            Platform::IOSSimulator => PLATFORM_IOSSIMULATOR,
+            Platform::TvOSSimulator => PLATFORM_TVOSSIMULATOR,
+            Platform::WatchOSSimulator => PLATFORM_WATCHOSSIMULATOR,
+            Platform::DriverKit => PLATFORM_DRIVERKIT,
+        }
+    }
+
+    /// The platform a `PLATFORM_*` constant stands for.
+    pub fn from_u32(platform: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|candidate| candidate.to_u32() == platform)
+    }
+
+    pub fn is_simulator(self) -> bool {
+        matches!(
+            self,
+            Platform::IOSSimulator | Platform::TvOSSimulator | Platform::WatchOSSimulator
+        )
+    }
+
+    /// The device platform a simulator platform runs builds for.
+    pub fn device(self) -> Self {
+        match self {
+            Platform::IOSSimulator => Platform::IOS,
+            Platform::TvOSSimulator => Platform::TvOS,
+            Platform::WatchOSSimulator => Platform::WatchOS,
+            other => other,
+        }
+    }
+
+    /// Parse the platform part of a target triple, either in the TBD
+    /// style (`arm64-maccatalyst`) or the LLVM style
+    /// (`arm64-apple-ios13.1-macabi`). Versions in the OS name are
+    /// ignored.
+    pub fn from_target(target: &str) -> Option<Self> {
+        let target = target.strip_prefix("apple-").unwrap_or(target);
+        let (os, environment) = match target.split_once('-') {
+            Some((os, environment)) => (os, Some(environment)),
+            None => (target, None),
+        };
+        let os = os.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        match (os, environment) {
+            ("macos" | "macosx", None) => Some(Platform::MacOS),
+            ("maccatalyst", None) | ("ios", Some("macabi")) => Some(Platform::MacCatalyst),
+            ("ios", None) => Some(Platform::IOS),
+            ("tvos", None) => Some(Platform::TvOS),
+            ("watchos", None) => Some(Platform::WatchOS),
+            ("ios", Some("simulator")) => Some(Platform::IOSSimulator),
+            ("tvos", Some("simulator")) => Some(Platform::TvOSSimulator),
+            ("watchos", Some("simulator")) => Some(Platform::WatchOSSimulator),
+            ("bridgeos", None) => Some(Platform::BridgeOS),
+            ("driverkit", None) => Some(Platform::DriverKit),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Platform::MacOS => write!(f, "macos"),
+            Platform::IOS => write!(f, "ios"),
+            Platform::TvOS => write!(f, "tvos"),
+            Platform::WatchOS => write!(f, "watchos"),
+            Platform::BridgeOS => write!(f, "bridgeos"),
+            Platform::MacCatalyst => write!(f, "mac-catalyst"),
+            Platform::IOSSimulator => write!(f, "ios-simulator"),
+            Platform::TvOSSimulator => write!(f, "tvos-simulator"),
+            Platform::WatchOSSimulator => write!(f, "watchos-simulator"),
+            Platform::DriverKit => write!(f, "driverkit"),
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    /// Parse either one of the platform names accepted by ld64 or the
+    /// raw `PLATFORM_*` number.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Platform::*;
+        match &s.to_lowercase()[..] {
+            "macos" | "1" => Ok(MacOS),
+            "ios" | "2" => Ok(IOS),
+            "tvos" | "3" => Ok(TvOS),
+            "watchos" | "4" => Ok(WatchOS),
+            "bridgeos" | "5" => Ok(BridgeOS),
+            "mac-catalyst" | "maccatalyst" | "6" => Ok(MacCatalyst),
+            "ios-simulator" | "7" => Ok(IOSSimulator),
+            "tvos-simulator" | "8" => Ok(TvOSSimulator),
+            "watchos-simulator" | "9" => Ok(WatchOSSimulator),
+            "driverkit" | "10" => Ok(DriverKit),
+            _ => Err(format!("Unknown platform {s}")),
+        }
+    }
+}
+
+/// A limit on the size of the output, from --max-size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeBudget {
+    /// The segment limited, or the whole file if there isn't one.
+    pub segment: Option<String>,
+    pub bytes: u64,
+}
+
+impl FromStr for SizeBudget {
+    type Err = String;
+
+    /// `[SEGMENT=]SIZE`, where the size is in bytes, decimal or 0x
+    /// prefixed hex, or in KiB, MiB or GiB with a K, M or G suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("--max-size {s} should be [<SEGMENT>=]<SIZE>");
+        let (segment, size) = match s.split_once('=') {
+            Some((segment, size)) => (Some(segment.to_string()), size),
+            None => (None, s),
+        };
+        let (digits, unit) = match size.as_bytes().last() {
+            Some(b'K' | b'k') => (&size[..size.len() - 1], 1 << 10),
+            Some(b'M' | b'm') => (&size[..size.len() - 1], 1 << 20),
+            Some(b'G' | b'g') => (&size[..size.len() - 1], 1 << 30),
+            _ => (size, 1),
+        };
+        let number = match digits.strip_prefix("0x") {
+            Some(digits) => u64::from_str_radix(digits, 16),
+            None => digits.parse(),
+        }
+        .map_err(|_| invalid())?;
+        let bytes = number.checked_mul(unit).ok_or_else(invalid)?;
+        Ok(SizeBudget { segment, bytes })
+    }
+}
+
+/// A `major.minor[.patch]` version as used by the platform and
+/// dylib version fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+pub struct Version {
+    pub major: u16,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl Version {
+    pub fn new(major: u16, minor: u8, patch: u8) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Encode the version as xxxx.yy.zz nibbles, the format used in
+    /// load commands.
+    pub fn to_u32(self) -> u32 {
+        ((self.major as u32) << 16) | ((self.minor as u32) << 8) | self.patch as u32
+    }
+
+    pub fn from_u32(version: u32) -> Self {
+        Self::new((version >> 16) as u16, (version >> 8) as u8, version as u8)
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.patch == 0 {
+            write!(f, "{}.{}", self.major, self.minor)
+        } else {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split('.').collect();
+        if parts.is_empty() || parts.len() > 3 {
+            return Err(format!("Invalid version {s}"));
+        }
+        let major = parts[0]
+            .parse()
+            .map_err(|_| format!("Invalid major version in {s}"))?;
+        let minor = match parts.get(1) {
+            Some(minor) => minor
+                .parse()
+                .map_err(|_| format!("Invalid minor version in {s}"))?,
+            None => 0,
+        };
+        let patch = match parts.get(2) {
+            Some(patch) => patch
+                .parse()
+                .map_err(|_| format!("Invalid patch version in {s}"))?,
+            None => 0,
+        };
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct PlatformVersion {
-    // TODO: This would be better represented as a enum taking a
-    // number or one of the predefined strings.
-    pub platform: String,
-    // TODO: Thse should be parsed to some version representation
-    // (major.minor[.patch]).
-    pub min_version: String,
-    pub sdk_version: String,
+    pub platform: Platform,
+    pub min_version: Version,
+    pub sdk_version: Version,
+}
+
+impl PlatformVersion {
+    /// LC_BUILD_VERSION was introduced with macOS 10.14, iOS 12,
+    /// tvOS 12 and watchOS 5. Deployment targets older than that need
+    /// the legacy LC_VERSION_MIN_* command because their dyld doesn't
+    /// understand the newer one.
+    pub fn supports_build_version(&self) -> bool {
+        match self.platform.device() {
+            Platform::MacOS => self.min_version >= Version::new(10, 14, 0),
+            Platform::IOS | Platform::TvOS => self.min_version >= Version::new(12, 0, 0),
+            Platform::WatchOS => self.min_version >= Version::new(5, 0, 0),
+            _ => true,
+        }
+    }
+
+    /// Derive the platform from the *_DEPLOYMENT_TARGET environment
+    /// variables the way ld64 does when no version flags are given. The
+    /// SDK version defaults to the minimum version.
+    pub fn from_deployment_target_env(env: &Environment) -> Result<Option<Self>, String> {
+        const VARIABLES: &[(&str, Platform)] = &[
+            ("MACOSX_DEPLOYMENT_TARGET", Platform::MacOS),
+            ("IPHONEOS_DEPLOYMENT_TARGET", Platform::IOS),
+            ("TVOS_DEPLOYMENT_TARGET", Platform::TvOS),
+            ("WATCHOS_DEPLOYMENT_TARGET", Platform::WatchOS),
+        ];
+        for (variable, platform) in VARIABLES {
+            let Some(value) = env.var(variable).and_then(|value| value.into_string().ok()) else {
+                continue;
+            };
+            if value.is_empty() {
+                continue;
+            }
+            let min_version = value
+                .parse()
+                .map_err(|e| format!("Invalid {variable} {value}: {e}"))?;
+            return Ok(Some(Self {
+                platform: *platform,
+                min_version,
+                sdk_version: min_version,
+            }));
+        }
+        Ok(None)
+    }
+}
+
+impl PlatformVersion {
+    /// The platform an image was built for, from its LC_BUILD_VERSION
+    /// or LC_VERSION_MIN_* command.
+    pub fn from_load_commands(macho: &goblin::mach::MachO) -> Option<Self> {
+        use goblin::mach::load_command::CommandVariant;
+        macho
+            .load_commands
+            .iter()
+            .find_map(|command| match &command.command {
+                CommandVariant::BuildVersion(build) => Some(Self {
+                    platform: Platform::from_u32(build.platform)?,
+                    min_version: Version::from_u32(build.minos),
+                    sdk_version: Version::from_u32(build.sdk),
+                }),
+                CommandVariant::VersionMinMacosx(min) => {
+                    Some(Self::from_version_min(Platform::MacOS, min))
+                }
+                CommandVariant::VersionMinIphoneos(min) => {
+                    Some(Self::from_version_min(Platform::IOS, min))
+                }
+                CommandVariant::VersionMinTvos(min) => {
+                    Some(Self::from_version_min(Platform::TvOS, min))
+                }
+                CommandVariant::VersionMinWatchos(min) => {
+                    Some(Self::from_version_min(Platform::WatchOS, min))
+                }
+                _ => None,
+            })
+    }
+
+    fn from_version_min(
+        platform: Platform,
+        min: &goblin::mach::load_command::VersionMinCommand,
+    ) -> Self {
+        Self {
+            platform,
+            min_version: Version::from_u32(min.version),
+            sdk_version: Version::from_u32(min.sdk),
+        }
+    }
 }
 
 impl FromStr for PlatformVersion {
@@ -39,9 +394,9 @@ impl FromStr for PlatformVersion {
             return Err(format!("Expected 3 parts, found {}", parts.len()));
         }
         Ok(Self {
-            platform: parts[0].to_string(),
-            min_version: parts[1].to_string(),
-            sdk_version: parts[2].to_string(),
+            platform: parts[0].parse()?,
+            min_version: parts[1].parse()?,
+            sdk_version: parts[2].parse()?,
         })
     }
 }
@@ -53,16 +408,168 @@ pub struct Args {
     // TODO: Make this an enum so we're explicit about what libs are
     // handled.
     pub libraries: Vec<String>,
+    /// Libraries from -upward-l, searched for like -l.
+    pub upward_libraries: Vec<String>,
+    /// Libraries from -upward_library.
+    pub upward_library_paths: Vec<PathBuf>,
+    /// Archives from -hidden-l, searched for like -l, whose
+    /// definitions are made private extern so they aren't exported.
+    pub hidden_libraries: Vec<String>,
+    /// Archives from -load_hidden, like those from -hidden-l.
+    pub hidden_library_paths: Vec<PathBuf>,
+    /// Frameworks from -upward_framework.
+    pub upward_frameworks: Vec<String>,
+    pub framework_search_paths: Vec<PathBuf>,
     pub output_file: PathBuf,
+    /// Where the output ends up when `output_file` is a temporary path,
+    /// from -final_output.
+    pub final_output: Option<PathBuf>,
+    /// The umbrella framework the output is part of, from -umbrella.
+    pub umbrella: Option<String>,
+    /// The name the output is checked against libraries' allowable
+    /// clients by, from -client_name. Defaults to the name of
+    /// [Args::install_path].
+    pub client_name: Option<String>,
+    /// Create the output file's directory if it doesn't exist, from
+    /// --create-dirs.
+    pub create_dirs: bool,
     pub object_files: Vec<PathBuf>,
-    pub sys_lib_root: Option<PathBuf>,
+    /// SDKs to search for libraries and frameworks in, in order, from
+    /// -syslibroot.
+    pub sys_lib_roots: Vec<PathBuf>,
     pub demangle: bool,
     // Note: Defaults to true. I've inverted it from the flag
     // (-no_demangle) because I think that will make the code easier
     // to read. Lets see if this is the case.
     pub deduplicate: bool,
     pub dynamic: bool,
-    pub platform_version: Option<PlatformVersion>,
+    /// Link an executable that isn't loaded by dyld, so can't use
+    /// dylibs, from -static.
+    pub static_link: bool,
+    /// Output an MH_PRELOAD image, for firmware and other code loaded
+    /// by something other than the kernel and dyld, from -preload.
+    pub preload: bool,
+    /// The dynamic linker executables are started by, from
+    /// --dynamic-linker, rather than /usr/lib/dyld.
+    pub dylinker: Option<String>,
+    /// Segment names and the addresses they're placed at, from
+    /// -segaddr.
+    pub segment_addresses: Vec<(String, u64)>,
+    /// The order to lay out segments in, from -segment_order.
+    pub segment_order: Vec<String>,
+    /// The order to lay out the sections of a segment in, from
+    /// -section_order.
+    pub section_order: Vec<(String, Vec<String>)>,
+    /// Leave out anything that changes from one link of the same
+    /// inputs to the next, from -reproducible or SOURCE_DATE_EPOCH.
+    pub reproducible: bool,
+    /// Record no LC_UUID, from -no_uuid.
+    pub no_uuid: bool,
+    /// Record CPU_SUBTYPE_ARM64_ALL rather than the objects' merged
+    /// cpusubtype, from -force_cpusubtype_ALL.
+    pub force_cpusubtype_all: bool,
+    /// Run path search directories, from -rpath.
+    pub rpaths: Vec<String>,
+    /// The symbol the executable starts at, from -e.
+    pub entry: String,
+    /// The platform the output is built for.
+    pub platform_version: PlatformVersion,
+    /// No flag or environment variable gave the platform, so
+    /// platform_version holds the macOS 11.0 default, which the link
+    /// replaces with the platform the inputs were built for.
+    pub platform_version_defaulted: bool,
+    /// Fail the link, without writing the output, if there were any
+    /// warnings, from -fatal_warnings and undone by -no_fatal_warnings.
+    pub fatal_warnings: bool,
+    pub encryptable: bool,
+    /// Export the executable's external definitions, for the plugins it
+    /// loads to call into. From -export_dynamic.
+    pub export_dynamic: bool,
+    /// Mark the output as safe to use in app extensions, from
+    /// -application_extension, and warn about libraries that aren't.
+    pub application_extension: bool,
+    /// What to bundle of the inputs' embedded bitcode, if anything,
+    /// from -bitcode_bundle and -bitcode_process_mode.
+    pub bitcode_bundle: Option<BitcodeMode>,
+    /// Move write-once data into __DATA_CONST. Inverted from
+    /// -no_data_const.
+    pub data_const: bool,
+    /// Link directly against public libraries re-exported by another
+    /// library. Inverted from -no_implicit_dylibs.
+    pub implicit_dylibs: bool,
+    /// Link against the libraries and frameworks objects ask for with
+    /// LC_LINKER_OPTION. Inverted from -ignore_auto_link.
+    pub auto_link: bool,
+    /// Fold Objective-C categories into their classes. Inverted from
+    /// -no_objc_category_merging.
+    pub objc_category_merging: bool,
+    /// Segment names and symbol list files from -move_to_ro_segment.
+    pub move_to_ro_segment: Vec<(String, PathBuf)>,
+    /// Segment names and symbol list files from -move_to_rw_segment.
+    pub move_to_rw_segment: Vec<(String, PathBuf)>,
+    /// Where to write a map of the output, from -map.
+    pub map: Option<PathBuf>,
+    /// Whether the map lists the objects referencing each symbol, from
+    /// -cross_reference.
+    pub cross_reference: bool,
+    /// Data symbols to move into __DATA_DIRTY, from -dirty_data_list.
+    pub dirty_data_list: Option<PathBuf>,
+    /// Local symbols to leave out of the symbol table, from
+    /// -non_global_symbols_strip_list.
+    pub non_global_symbols_strip_list: Option<PathBuf>,
+    /// The only local symbols to keep in the symbol table, from
+    /// -non_global_symbols_no_strip_list.
+    pub non_global_symbols_no_strip_list: Option<PathBuf>,
+    /// Install names and the files to read for them instead of looking
+    /// in the SDK, from -dylib_file.
+    pub dylib_files: Vec<(String, PathBuf)>,
+    /// Swift module ASTs to record for the debugger, from
+    /// -add_ast_path.
+    pub ast_paths: Vec<PathBuf>,
+    /// Number of threads to use, 0 meaning one per CPU.
+    pub threads: usize,
+    /// How many errors to report before summarising the rest, 0
+    /// meaning all of them.
+    pub error_limit: usize,
+    /// Also decides whether libraries that can't be found are an error.
+    pub undefined: UndefinedTreatment,
+    /// Absolute symbols and their values, from --defsym.
+    pub defsyms: Vec<(String, u64)>,
+    /// Functions whose references are redirected, from --wrap.
+    pub wraps: Vec<Wrap>,
+    /// The order to lay out functions in, from --profile-order.
+    pub profile_order: Option<PathBuf>,
+    /// Print the size of each segment and section after linking, from
+    /// --print-memory-usage.
+    pub print_memory_usage: bool,
+    /// Stop once symbols are resolved and print what the link would
+    /// load, from --dry-run.
+    pub dry_run: bool,
+    /// Answer a query about the linker rather than linking. -arch and
+    /// -o aren't needed for one.
+    pub query: Option<Query>,
+    /// Print the time and memory each phase of the link took, from
+    /// -print_statistics.
+    pub print_statistics: bool,
+    /// Where to write a Chrome trace of the link, from -time_trace.
+    pub time_trace: Option<PathBuf>,
+    /// Print this many of the largest atoms after linking, from
+    /// --size-report[=N].
+    pub size_report: Option<usize>,
+    /// Budgets for the size of the output file, or of a segment of it,
+    /// from --max-size.
+    pub max_sizes: Vec<SizeBudget>,
+    /// Where to write a bundle of the inputs and arguments, from
+    /// --reproduce.
+    pub reproduce: Option<PathBuf>,
+    /// The arguments as given, for --reproduce.
+    pub argv: Vec<OsString>,
+    /// The dialect `argv` is in.
+    pub dialect: Dialect,
+    /// Where the link runs. The paths above are already resolved in it.
+    pub environment: Environment,
+    /// Symbols to report the resolution of, from -trace_symbol and -y.
+    pub trace_symbols: Vec<String>,
 }
 
 impl FromStr for Architecture {
@@ -77,113 +584,499 @@ impl FromStr for Architecture {
     }
 }
 
+/// Which linker's spelling of the options to accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Lld,
+    /// lld-macho's options plus the ld64 spellings Xcode uses.
+    Ld64,
+}
+
+impl Dialect {
+    pub fn table(self) -> &'static OptTable {
+        match self {
+            Dialect::Lld => &lld_options::TABLE,
+            Dialect::Ld64 => &lld_options::LD64_TABLE,
+        }
+    }
+
+    /// Use the ld64 dialect if any of the arguments is only spelt that
+    /// way by ld64.
+    pub fn detect(args: &[OsString]) -> Self {
+        let ld64_only = args.iter().filter_map(|arg| arg.to_str()).any(|arg| {
+            arg.starts_with('-')
+                && !lld_options::TABLE.recognises(arg)
+                && lld_options::LD64_TABLE.recognises(arg)
+        });
+        if ld64_only {
+            Dialect::Ld64
+        } else {
+            Dialect::Lld
+        }
+    }
+}
+
+impl Display for Dialect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dialect::Lld => write!(f, "lld"),
+            Dialect::Ld64 => write!(f, "ld64"),
+        }
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lld" | "lld-macho" => Ok(Dialect::Lld),
+            "ld64" => Ok(Dialect::Ld64),
+            _ => Err(format!("Unknown dialect {s}")),
+        }
+    }
+}
+
+/// What a build system probing the linker asks instead of linking, in
+/// GCC's spelling.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    /// The directories libraries and frameworks are looked for in, from
+    /// -print-search-dirs.
+    SearchDirs,
+    /// The program run for a name, from -print-prog-name=NAME.
+    ProgName(String),
+    /// The usage, from -help.
+    Help,
+}
+
+/// What to do about undefined symbols, from -undefined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UndefinedTreatment {
+    #[default]
+    Error,
+    Warning,
+    Suppress,
+    DynamicLookup,
+}
+
+impl FromStr for UndefinedTreatment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(UndefinedTreatment::Error),
+            "warning" => Ok(UndefinedTreatment::Warning),
+            "suppress" => Ok(UndefinedTreatment::Suppress),
+            "dynamic_lookup" => Ok(UndefinedTreatment::DynamicLookup),
+            _ => Err(format!("Unknown -undefined treatment {s}")),
+        }
+    }
+}
+
+/// What -bitcode_bundle puts in __LLVM,__bundle, from
+/// -bitcode_process_mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitcodeMode {
+    /// The inputs' bitcode.
+    #[default]
+    Data,
+    /// Only a marker, as if the inputs had been compiled with
+    /// -fembed-bitcode-marker.
+    Marker,
+    /// Nothing, dropping the inputs' bitcode.
+    Strip,
+}
+
+impl FromStr for BitcodeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "data" => Ok(BitcodeMode::Data),
+            "marker" => Ok(BitcodeMode::Marker),
+            "strip" => Ok(BitcodeMode::Strip),
+            _ => Err(format!(
+                "Unknown -bitcode_process_mode {s}, expected data, marker or strip"
+            )),
+        }
+    }
+}
+
+/// What machop was invoked as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persona {
+    /// Installed or symlinked as `ld`, standing in for the system
+    /// linker: ld64 options and only errors are reported.
+    Ld,
+    Machop,
+}
+
+impl Persona {
+    /// Pick the persona from argv[0].
+    pub fn from_argv0(argv0: &std::ffi::OsStr) -> Self {
+        let name = std::path::Path::new(argv0)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        // Toolchains also install prefixed names, like
+        // arm64-apple-darwin-ld.
+        if name == "ld" || name.ends_with("-ld") || name == "ld64" {
+            Persona::Ld
+        } else {
+            Persona::Machop
+        }
+    }
+
+    /// The log level used when RUST_LOG isn't set.
+    pub fn default_log_level(self) -> &'static str {
+        match self {
+            Persona::Ld => "error",
+            Persona::Machop => "warn",
+        }
+    }
+
+    pub fn default_dialect(self) -> Option<Dialect> {
+        match self {
+            Persona::Ld => Some(Dialect::Ld64),
+            Persona::Machop => None,
+        }
+    }
+}
+
 impl Args {
-    pub fn from_env() -> Result<Self, String> {
-        let options = llvm_command_parser::llvm_13_options("lld-macho").unwrap();
-        let mut args = std::env::args_os();
-        // Fist arg is the name of the executable.
-        args.next();
-        let lld_args: ParsedArguments = options
-            .parse_arguments(args)
-            .map_err(|e| e.to_string())?
-            .resolve_aliases(&options)
-            .unwrap();
-        log::trace!("parsed args: {lld_args:#?}");
+    /// Parse the linker arguments, not including argv[0]. Without an
+    /// explicit --dialect, `default_dialect` is used or, failing that,
+    /// the dialect is detected from the arguments.
+    pub fn parse(args: Vec<OsString>, default_dialect: Option<Dialect>) -> Result<Self, String> {
+        Self::parse_with_config(args, default_dialect, &Config::default())
+    }
+
+    /// Like [Args::parse], with the defaults from `config` where the
+    /// arguments don't say.
+    pub fn parse_with_config(
+        args: Vec<OsString>,
+        default_dialect: Option<Dialect>,
+        config: &Config,
+    ) -> Result<Self, String> {
+        Self::parse_in(args, default_dialect, config, &Environment::current())
+    }
+
+    /// Like [Args::parse_with_config], for a link in `env` rather than
+    /// this process's working directory and environment.
+    pub fn parse_in(
+        args: Vec<OsString>,
+        default_dialect: Option<Dialect>,
+        config: &Config,
+        env: &Environment,
+    ) -> Result<Self, String> {
+        warnings::start();
+        // --dialect isn't part of either option table, it picks the
+        // table. Nor is --spec, which stands for the arguments in the
+        // spec, so they're part of picking it.
+        let mut dialect = None;
+        let mut rest = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let spec = match arg.to_str() {
+                Some("--spec") => Some(
+                    args.next()
+                        .ok_or_else(|| "--spec expects a file".to_string())?,
+                ),
+                Some(arg) => arg.strip_prefix("--spec=").map(OsString::from),
+                None => None,
+            };
+            if let Some(spec) = spec {
+                rest.extend(
+                    spec::from_file(&env.resolve(&spec))?
+                        .into_iter()
+                        .map(OsString::from),
+                );
+                continue;
+            }
+            match arg.to_str().and_then(|arg| arg.strip_prefix("--dialect=")) {
+                Some(name) => dialect = Some(name.parse::<Dialect>()?),
+                None => rest.push(arg),
+            }
+        }
+        let dialect = dialect
+            .or(default_dialect)
+            .unwrap_or_else(|| Dialect::detect(&rest));
+        log::debug!("Using the {dialect:?} option dialect");
+        let argv = rest.clone();
+        let parsed = dialect.table().parse(rest).map_err(|e| e.to_string())?;
+        log::trace!("parsed args: {parsed:#?}");
 
         let mut object_files: Vec<PathBuf> = vec![];
         let mut libraries: Vec<String> = vec![];
-        let mut sys_lib_root: Option<PathBuf> = None;
+        let mut sys_lib_roots: Vec<PathBuf> = vec![];
         let mut dynamic = false;
+        let mut static_link = false;
+        let mut preload = false;
+        let mut dylinker: Option<String> = None;
+        let mut segment_addresses: Vec<(String, u64)> = vec![];
+        let mut segment_order: Vec<String> = vec![];
+        let mut section_order: Vec<(String, Vec<String>)> = vec![];
+        let mut entry = "_main".to_string();
+        let mut rpaths: Vec<String> = vec![];
+        // Build systems set SOURCE_DATE_EPOCH to ask for reproducible
+        // outputs without knowing the linker's flags.
+        let mut reproducible = env.var("SOURCE_DATE_EPOCH").is_some();
+        let mut no_uuid = false;
+        let mut force_cpusubtype_all = false;
         let mut no_deduplicate = false;
         let mut demangle = false;
+        let mut encryptable = false;
+        let mut application_extension = false;
+        let mut export_dynamic = false;
+        let mut bitcode_bundle = false;
+        let mut bitcode_mode: Option<BitcodeMode> = None;
+        let mut no_data_const = false;
+        let mut no_implicit_dylibs = false;
+        let mut ignore_auto_link = false;
+        let mut no_objc_category_merging = false;
         let mut output_file = None;
+        let mut final_output: Option<PathBuf> = None;
+        let mut umbrella: Option<String> = None;
+        let mut client_name: Option<String> = None;
+        let mut create_dirs = false;
+        let mut fatal_warnings = config.fatal_warnings.unwrap_or(false);
         let mut platform_version: Option<PlatformVersion> = None;
+        // Legacy -<platform>_version_min flags. These only provide the
+        // minimum version so the SDK version defaults to the same value.
+        let mut legacy_version_min: Option<(Platform, Version)> = None;
+        let mut move_to_ro_segment: Vec<(String, PathBuf)> = vec![];
+        let mut move_to_rw_segment: Vec<(String, PathBuf)> = vec![];
+        let mut trace_symbols: Vec<String> = vec![];
+        let mut undefined = UndefinedTreatment::default();
+        let mut reproduce: Option<PathBuf> = None;
+        let mut print_memory_usage = false;
+        let mut dry_run = false;
+        let mut query = None;
+        let mut print_statistics = false;
+        let mut profile_order: Option<PathBuf> = None;
+        let mut wraps: Vec<Wrap> = vec![];
+        let mut defsyms: Vec<(String, u64)> = vec![];
+        let mut size_report: Option<usize> = None;
+        let mut max_sizes: Vec<SizeBudget> = vec![];
+        let mut threads = config.threads.unwrap_or(0);
+        let mut error_limit = 20;
+        let mut dirty_data_list: Option<PathBuf> = None;
+        let mut non_global_symbols_strip_list: Option<PathBuf> = None;
+        let mut non_global_symbols_no_strip_list: Option<PathBuf> = None;
+        let mut map: Option<PathBuf> = None;
+        let mut time_trace: Option<PathBuf> = None;
+        let mut cross_reference = false;
+        let mut dylib_files: Vec<(String, PathBuf)> = vec![];
+        let mut ast_paths: Vec<PathBuf> = vec![];
         let mut library_search_paths: Vec<PathBuf> = vec![];
+        let mut framework_search_paths: Vec<PathBuf> = vec![];
+        let mut upward_libraries: Vec<String> = vec![];
+        let mut upward_library_paths: Vec<PathBuf> = vec![];
+        let mut hidden_libraries: Vec<String> = vec![];
+        let mut hidden_library_paths: Vec<PathBuf> = vec![];
+        let mut upward_frameworks: Vec<String> = vec![];
         let mut arch: Option<Architecture> = None;
-        for lld_arg in lld_args.parsed() {
-            use llvm_option_parser::ParsedArgument::*;
-            match lld_arg {
-                Unknown(flag) => {
-                    log::warn!("Unknown flag {}", flag.to_string_lossy())
-                }
-                Positional(value) => object_files.push(value.into()),
-
-                Flag(option) => {
-                    if option.matches_exact(OsStr::new("-help")) {
-                        usage();
-                        std::process::exit(1)
-                    } else if option.matches_exact(OsStr::new("-dynamic")) {
-                        dynamic = true;
-                    } else if option.matches_exact(OsStr::new("-no_deduplicate")) {
-                        no_deduplicate = true;
-                    } else if option.matches_exact(OsStr::new("-demangle")) {
-                        demangle = true;
-                    } else {
-                        log::warn!("Flag {} not handled", option.name)
-                    }
+        for arg in parsed {
+            let (name, values) = match arg {
+                Arg::Unknown(flag) => {
+                    warning!("Unknown flag {}", flag.to_string_lossy());
+                    continue;
                 }
-                SingleValue(option, value) => {
-                    if option.matches_exact(OsStr::new("-o")) {
-                        output_file = Some(PathBuf::from(value));
-                    } else if option.matches_exact(OsStr::new("-arch")) {
-                        arch = Some(value.to_str().unwrap().parse()?);
-                    } else if option.matches_exact(OsStr::new("-lto_library")) {
-                    } else if option.matches_exact(OsStr::new("-syslibroot")) {
-                        sys_lib_root = Some(value.into());
-                    } else if option.matches_exact(OsStr::new("-L")) {
-                        library_search_paths.push(value.into());
-                    } else if option.matches_exact(OsStr::new("-l")) {
-                        libraries.push(value.to_os_string().into_string().unwrap());
-                    } else {
-                        log::warn!(
-                            "Flag {} with value {} not handled",
-                            option.name,
-                            value.to_string_lossy(),
-                        )
-                    }
+                Arg::Positional(value) => {
+                    object_files.push(env.resolve(value));
+                    continue;
                 }
-                SingleValueKeyed(option, key, value) => {
-                    log::warn!(
-                        "Single keyed value flag {} with {}={} not handled",
-                        option.name,
-                        key.to_string_lossy(),
-                        value.to_string_lossy()
+                Arg::Option { name, values, .. } => (name, values),
+            };
+            let values = values
+                .into_iter()
+                .map(|value| {
+                    value
+                        .into_string()
+                        .map_err(|value| format!("Invalid value {value:?} for {name}"))
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            match (name, &values[..]) {
+                ("-help", _) => query = Some(Query::Help),
+                ("-dynamic", _) => dynamic = true,
+                ("-static", _) => static_link = true,
+                ("-preload", _) => preload = true,
+                ("-dynamic-linker", [value]) => dylinker = Some(value.clone()),
+                ("-dylinker", _) => {
+                    return Err(
+                        "-dylinker isn't supported, machop can't write a dynamic linker".into(),
                     )
                 }
-                CommaValues(option, comma_separated_values) => {
-                    log::warn!(
-                        "Comma separated flag {} with value {} not handled",
-                        option.name,
-                        comma_separated_values.to_string_lossy()
-                    );
-                }
-                MultipleValues(option, values) => {
-                    if option.matches_exact(OsStr::new("-platform_version")) {
-                        let s: Vec<String> = values
-                            .iter()
-                            .map(|os| os.to_os_string().into_string())
-                            .collect::<Result<Vec<String>, OsString>>()
-                            .unwrap();
-                        platform_version = Some(s.join(" ").parse()?);
-                    } else {
-                        log::warn!(
-                            "Multi value flag {} with value {:?} not handled",
-                            option.name,
-                            values
-                        )
+                ("-segaddr", [segment, address]) => {
+                    // Like ld64, the address is hex with or without 0x.
+                    let digits = address.trim_start_matches("0x");
+                    let address = u64::from_str_radix(digits, 16)
+                        .map_err(|_| format!("Invalid address {address} for -segaddr"))?;
+                    segment_addresses.push((segment.clone(), address));
+                }
+                ("-segment_order", [value]) => {
+                    segment_order = value.split(':').map(str::to_string).collect();
+                    output::check_segment_order(&segment_order)?;
+                }
+                ("-section_order", [segment, value]) => {
+                    let sections: Vec<String> = value.split(':').map(str::to_string).collect();
+                    output::check_section_order(segment, &sections)?;
+                    section_order.retain(|(name, _)| name != segment);
+                    section_order.push((segment.clone(), sections));
+                }
+                ("-e", [value]) => entry = value.clone(),
+                ("-rpath", [value]) => rpaths.push(value.clone()),
+                ("-reproducible", _) => reproducible = true,
+                ("-fatal_warnings", _) => fatal_warnings = true,
+                ("-no_fatal_warnings", _) => fatal_warnings = false,
+                ("-no_uuid", _) => no_uuid = true,
+                ("-force_cpusubtype_ALL", _) => force_cpusubtype_all = true,
+                ("-no_deduplicate", _) => no_deduplicate = true,
+                ("-demangle", _) => demangle = true,
+                ("-encryptable", _) => encryptable = true,
+                ("-export_dynamic", _) => export_dynamic = true,
+                ("-application_extension", _) => application_extension = true,
+                ("-no_application_extension", _) => application_extension = false,
+                ("-bitcode_bundle", _) => bitcode_bundle = true,
+                ("-bitcode_process_mode", [value]) => bitcode_mode = Some(value.parse()?),
+                ("-bitcode_verify", _) => {
+                    return Err(
+                        "-bitcode_verify isn't supported, machop can't compile bitcode to check it"
+                            .into(),
+                    )
+                }
+                ("-no_data_const", _) => no_data_const = true,
+                ("-no_implicit_dylibs", _) => no_implicit_dylibs = true,
+                ("-ignore_auto_link", _) => ignore_auto_link = true,
+                ("-objc_category_merging", _) => no_objc_category_merging = false,
+                ("-no_objc_category_merging", _) => no_objc_category_merging = true,
+                ("-o", [value]) => output_file = Some(env.resolve(value)),
+                ("-final_output", [value]) => final_output = Some(value.into()),
+                ("-umbrella", [value]) => umbrella = Some(value.clone()),
+                ("-client_name", [value]) => client_name = Some(value.clone()),
+                ("--create-dirs", _) => create_dirs = true,
+                ("-arch", [value]) => arch = Some(value.parse()?),
+                ("-lto_library", _) => {}
+                ("-syslibroot", [value]) => sys_lib_roots.push(env.resolve(value)),
+                ("-L", [value]) => library_search_paths.push(env.resolve(value)),
+                ("-l", [value]) => libraries.push(value.clone()),
+                ("-F", [value]) => framework_search_paths.push(env.resolve(value)),
+                ("-upward-l", [value]) => upward_libraries.push(value.clone()),
+                ("-upward_library", [value]) => upward_library_paths.push(env.resolve(value)),
+                ("-hidden-l", [value]) => hidden_libraries.push(value.clone()),
+                ("-load_hidden", [value]) => hidden_library_paths.push(env.resolve(value)),
+                ("-upward_framework", [value]) => upward_frameworks.push(value.clone()),
+                ("-dirty_data_list", [value]) => dirty_data_list = Some(env.resolve(value)),
+                ("-non_global_symbols_strip_list", [value]) => {
+                    non_global_symbols_strip_list = Some(env.resolve(value))
+                }
+                ("-non_global_symbols_no_strip_list", [value]) => {
+                    non_global_symbols_no_strip_list = Some(env.resolve(value))
+                }
+                ("-map", [value]) => map = Some(env.resolve(value)),
+                ("-cross_reference", _) => cross_reference = true,
+                ("-dylib_file", [value]) => {
+                    let (install_name, file) = value.split_once(':').ok_or_else(|| {
+                        format!("-dylib_file {value} should be <INSTALL_NAME>:<FILE>")
+                    })?;
+                    dylib_files.push((install_name.to_string(), env.resolve(file)));
+                }
+                ("-add_ast_path", [value]) => ast_paths.push(env.resolve(value)),
+                ("-trace_symbol", [value]) => trace_symbols.push(value.clone()),
+                ("-undefined", [value]) => undefined = value.parse()?,
+                ("--reproduce", [value]) => reproduce = Some(env.resolve(value)),
+                ("--print-memory-usage", _) => print_memory_usage = true,
+                ("--dry-run", _) => dry_run = true,
+                ("-print-search-dirs", _) => query = Some(Query::SearchDirs),
+                ("-print-prog-name=", [name]) => query = Some(Query::ProgName(name.clone())),
+                ("-print_statistics", _) => print_statistics = true,
+                ("-time_trace", [value]) => time_trace = Some(env.resolve(value)),
+                ("--profile-order", [value]) => profile_order = Some(env.resolve(value)),
+                ("--wrap", [value]) => wraps.push(Wrap::new(value)),
+                ("--defsym", [value]) => {
+                    let invalid = || format!("--defsym {value} should be <SYMBOL>=<VALUE>");
+                    let (name, number) = value.split_once('=').ok_or_else(invalid)?;
+                    let number = match number.strip_prefix("0x") {
+                        Some(digits) => u64::from_str_radix(digits, 16),
+                        None => number.parse(),
                     }
+                    .map_err(|_| invalid())?;
+                    defsyms.push((name.to_string(), number));
                 }
-                MultipleValuesKeyed(option, key, comma_separated_values) => {
-                    log::warn!(
-                        "Multi value keyed flag {} with value {}={:?} not handled",
-                        option.name,
-                        key.to_string_lossy(),
-                        comma_separated_values
+                ("--size-report", []) => size_report = Some(10),
+                ("--size-report", [value]) => {
+                    size_report = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("Invalid count {value} for --size-report"))?,
                     )
                 }
+                ("--max-size", [value]) => max_sizes.push(value.parse()?),
+                ("-error-limit", [value]) => {
+                    error_limit = value
+                        .parse()
+                        .map_err(|_| format!("Invalid error limit {value}"))?
+                }
+                ("-threads", [value]) => {
+                    threads = value
+                        .parse()
+                        .map_err(|_| format!("Invalid thread count {value}"))?
+                }
+                ("-macosx_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::MacOS, value.parse()?))
+                }
+                ("-ios_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::IOS, value.parse()?))
+                }
+                ("-ios_simulator_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::IOSSimulator, value.parse()?))
+                }
+                ("-tvos_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::TvOS, value.parse()?))
+                }
+                ("-tvos_simulator_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::TvOSSimulator, value.parse()?))
+                }
+                ("-watchos_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::WatchOS, value.parse()?))
+                }
+                ("-watchos_simulator_version_min", [value]) => {
+                    legacy_version_min = Some((Platform::WatchOSSimulator, value.parse()?))
+                }
+                ("-platform_version", values) => {
+                    // ld64 takes it twice for a zippered dylib, loadable
+                    // by both macOS and Mac Catalyst processes, which
+                    // waits on machop writing dylibs.
+                    if platform_version.is_some() {
+                        return Err("-platform_version can only be given once, machop can't \
+                                    write the zippered dylibs that take two"
+                            .to_string());
+                    }
+                    platform_version = Some(values.join(" ").parse()?)
+                }
+                ("-move_to_ro_segment" | "-move_to_rw_segment", [segment, file]) => {
+                    if segment.len() > 16 {
+                        return Err(format!("Segment name {segment} is too long"));
+                    }
+                    let moved = (segment.clone(), env.resolve(file));
+                    if name == "-move_to_ro_segment" {
+                        move_to_ro_segment.push(moved);
+                    } else {
+                        move_to_rw_segment.push(moved);
+                    }
+                }
+                (name, values) => warning!("Flag {name} with values {values:?} not handled"),
             }
         }
 
+        library_search_paths.extend(config.library_paths.iter().cloned());
+        framework_search_paths.extend(config.framework_paths.iter().cloned());
+
+        if query.is_some() {
+            arch = arch.or(Some(Architecture::ARM64));
+            output_file = output_file.or_else(|| Some("a.out".into()));
+        }
         if arch.is_none() {
             return Err("-arch must be provided".into());
         }
@@ -194,41 +1087,359 @@ impl Args {
         }
         let output_file = output_file.unwrap();
 
+        if non_global_symbols_strip_list.is_some() && non_global_symbols_no_strip_list.is_some() {
+            return Err(
+                "-non_global_symbols_strip_list and -non_global_symbols_no_strip_list can't be used together"
+                    .into(),
+            );
+        }
+
+        if bitcode_mode.is_some() && !bitcode_bundle {
+            warning!("-bitcode_process_mode is ignored without -bitcode_bundle");
+        }
+        let bitcode_bundle = bitcode_bundle.then(|| bitcode_mode.unwrap_or_default());
+
+        // -platform_version takes precedence over the legacy flags.
+        let mut platform_version_defaulted = false;
+        let platform_version = match platform_version {
+            Some(platform_version) => platform_version,
+            None => legacy_version_min
+                .map(|(platform, min_version)| PlatformVersion {
+                    platform,
+                    min_version,
+                    sdk_version: min_version,
+                })
+                .map_or_else(
+                    || PlatformVersion::from_deployment_target_env(env),
+                    |v| Ok(Some(v)),
+                )?
+                .or_else(|| config.platform_version.clone())
+                .unwrap_or_else(|| {
+                    platform_version_defaulted = true;
+                    // Fall back to the first macOS release with arm64
+                    // support.
+                    PlatformVersion {
+                        platform: Platform::MacOS,
+                        min_version: Version::new(11, 0, 0),
+                        sdk_version: Version::new(11, 0, 0),
+                    }
+                }),
+        };
+
         Ok(Args {
             arch,
             library_search_paths,
             libraries,
+            upward_libraries,
+            upward_library_paths,
+            hidden_libraries,
+            hidden_library_paths,
+            upward_frameworks,
+            framework_search_paths,
             output_file,
+            final_output,
+            umbrella,
+            client_name,
+            create_dirs,
             object_files,
-            sys_lib_root,
+            sys_lib_roots,
             demangle,
             deduplicate: !no_deduplicate,
             dynamic,
+            static_link,
+            preload,
+            dylinker,
+            segment_addresses,
+            segment_order,
+            section_order,
+            reproducible,
+            no_uuid,
+            force_cpusubtype_all,
+            rpaths,
+            entry,
             platform_version,
+            platform_version_defaulted,
+            fatal_warnings,
+            encryptable,
+            export_dynamic,
+            application_extension,
+            bitcode_bundle,
+            data_const: !no_data_const,
+            implicit_dylibs: !no_implicit_dylibs,
+            auto_link: !ignore_auto_link,
+            objc_category_merging: !no_objc_category_merging,
+            move_to_ro_segment,
+            move_to_rw_segment,
+            dirty_data_list,
+            non_global_symbols_strip_list,
+            non_global_symbols_no_strip_list,
+            map,
+            cross_reference,
+            dylib_files,
+            ast_paths,
+            undefined,
+            reproduce,
+            wraps,
+            defsyms,
+            profile_order,
+            print_memory_usage,
+            dry_run,
+            query,
+            print_statistics,
+            time_trace,
+            size_report,
+            max_sizes,
+            argv,
+            dialect,
+            environment: env.clone(),
+            trace_symbols,
+            threads,
+            error_limit,
         })
     }
+
+    /// Where the output is finally installed, the default install name
+    /// ld64 gives it: -final_output, or -o without it.
+    pub fn install_path(&self) -> &Path {
+        self.final_output.as_deref().unwrap_or(&self.output_file)
+    }
+}
+
+/// Extra arguments for every link, for when the build system running
+/// machop doesn't let its flags be changed.
+pub const FLAGS_ENV: &str = "MACHOP_FLAGS";
+
+/// The arguments in MACHOP_FLAGS, to go before those on the command
+/// line.
+pub fn injected_args() -> Result<Vec<OsString>, String> {
+    match std::env::var(FLAGS_ENV) {
+        Ok(flags) => Ok(split_shell_words(&flags)
+            .map_err(|e| format!("Invalid {FLAGS_ENV}: {e}"))?
+            .into_iter()
+            .map(OsString::from)
+            .collect()),
+        Err(std::env::VarError::NotPresent) => Ok(vec![]),
+        Err(e) => Err(format!("Invalid {FLAGS_ENV}: {e}")),
+    }
+}
+
+/// Split `s` into words the way a POSIX shell does, without expanding
+/// anything: words are separated by whitespace, which can be quoted
+/// with '' or "", or escaped with a backslash.
+pub fn split_shell_words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    word.get_or_insert_with(String::new).push(c);
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated '".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        // Only these are escaped in double quotes.
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("unterminated \"".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated \"".to_string()),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
 }
 
-fn usage() {
-    eprintln!(
-        r#"
+/// What -help prints.
+pub const USAGE: &str = r#"
 machop
 
+Usage: machop [link] <OPTIONS> <INPUTS>
+       machop version
+       machop options
+       machop daemon <SOCKET>
+       machop completions <bash|zsh|fish>
+       machop verify [-syslibroot <DIR>] [-L <DIR>] [-dylib_file <INSTALL_NAME>:<FILE>] <BINARY>
+
+When invoked as ld, machop only links and accepts ld64's options.
+
+machop completions prints a script completing machop's options in the
+given shell, e.g. for bash: source <(machop completions bash)
+
+machop daemon links on behalf of other invocations, keeping the inputs
+it has read, parsed TBDs in particular, between links. Invocations with
+MACHOP_DAEMON set to its socket link through it, falling back to linking
+themselves if it isn't running. Diagnostics are reported by the daemon.
+
+machop verify checks that every library an already linked binary loads
+can be found, is at least the version it was linked against, and exports
+each symbol the binary imports from it, which dyld would otherwise fail
+to launch it over. Libraries are looked for in each -syslibroot, or with
+-dylib_file, and @rpath ones under the binary's run paths and in each -L
+directory.
+
 Options:
 
 -help                         Print this message
+--dialect=<lld|ld64>          Accept lld-macho's or ld64's spelling of options. By default
+                              ld64 is used if any option is only spelt that way by ld64
+--spec <FILE>, --spec=<FILE>  Link as described by the JSON spec in FILE, in place of the
+                              arguments it stands for
 -arch <ARCH>                  Specify the target architecture
 -L <DIR>                      Add directory to library search path
 -l <LIB>                      Search for library
+-l:<FILE>                     Search for a library named exactly FILE, like libfoo.a
+-F <DIR>                      Add directory to framework search path
+-upward-l <LIB>               Search for library and link it as an upward dependency
+-upward_library <FILE>        Link library as an upward dependency
+-upward_framework <NAME>      Search for framework and link it as an upward dependency
+-hidden-l<LIB>                Search for the archive libLIB.a and link it without exporting
+                              its definitions
+-load_hidden <FILE>           Link the archive FILE without exporting its definitions
 -o <FILE>                     Set the output file
+-final_output <FILE>          Where the output ends up, when -o is a temporary path
+-umbrella <NAME>              The umbrella framework the output is part of
+-client_name <NAME>           The name to check against libraries' allowable clients
+--create-dirs                 Create the output file's directory if it doesn't exist
+-static                       Link an executable that isn't loaded by dyld
+-preload                      Output an MH_PRELOAD image, for firmware and the like
+--dynamic-linker <PATH>, --dynamic-linker=<PATH>
+                              Start the executable with the dynamic linker at PATH rather
+                              than /usr/lib/dyld
+-segaddr <SEGMENT> <ADDRESS>  Place SEGMENT at ADDRESS, given in hex
+-segment_order <SEGMENT>:<SEGMENT>...
+                              Lay out the listed segments first, in that order
+-section_order <SEGMENT> <SECTION>:<SECTION>...
+                              Lay out the listed sections first in SEGMENT, in that order
+-e <SYMBOL>                   Start the executable at SYMBOL rather than _main
+-rpath <PATH>                 Search PATH for dylibs installed in @rpath
+-reproducible                 Make the output depend only on the inputs and arguments
+-no_uuid                      Don't record a UUID for the output
+-force_cpusubtype_ALL         Record the cpusubtype for any arm64 CPU rather than merging
+                              the objects'
+-fatal_warnings               Fail the link, without writing the output, if there are warnings
+-no_fatal_warnings            Only warn, whatever the config says
 -lto_library <FILE>
--syslibroot <DIR>
+-syslibroot <DIR>             Search for libraries and frameworks in the SDK DIR, can be
+                              repeated to search several in order
 -platform_version <PLATFORM> <MIN_VERSION> <SDK_VERSION>
+                              PLATFORM is a name (macos, ios, mac-catalyst, ...) or number
+-macosx_version_min <VERSION>
+-ios_version_min <VERSION>, -iphoneos_version_min <VERSION>
+-ios_simulator_version_min <VERSION>
+-tvos_version_min <VERSION>, -tvos_simulator_version_min <VERSION>
+-watchos_version_min <VERSION>, -watchos_simulator_version_min <VERSION>
+-encryptable                  Reserve space for and describe App Store encryption
+-export_dynamic               Export the external definitions so that plugins the
+                              executable loads can find them with dlsym
+-application_extension        Mark the output as safe for app extensions, warning about
+                              libraries that aren't
+-bitcode_bundle               Bundle the inputs' embedded bitcode in __LLVM,__bundle
+-bitcode_process_mode <data|marker|strip>
+                              Bundle the bitcode, only a marker or nothing
+-no_data_const                Keep write-once data in __DATA instead of __DATA_CONST
+-no_implicit_dylibs           Don't link directly against public re-exported libraries
+-ignore_auto_link             Ignore the libraries and frameworks objects ask for with
+                              LC_LINKER_OPTION
+-no_objc_category_merging     Leave Objective-C categories for the runtime to attach
+-move_to_ro_segment <SEGMENT> <FILE>
+                              Move the read-only symbols listed in FILE to SEGMENT
+-move_to_rw_segment <SEGMENT> <FILE>
+                              Move the writable symbols listed in FILE to SEGMENT
+-dirty_data_list <FILE>       Move the data symbols listed in FILE to __DATA_DIRTY
+-non_global_symbols_strip_list <FILE>
+                              Leave the local symbols listed in FILE out of the symbol table
+-non_global_symbols_no_strip_list <FILE>
+                              Only keep the local symbols listed in FILE in the symbol table
+-map <FILE>                   Write a map of the output's sections and symbols to FILE
+-cross_reference              List the objects referencing each symbol in the -map file
+-dylib_file <INSTALL_NAME>:<FILE>
+                              Read FILE for the library installed as INSTALL_NAME
+-add_ast_path <FILE>          Point the debugger at the Swift module AST in FILE
+-threads <N>, --threads=<N>   Use N threads, or one per CPU when N is 0 (the default)
+-error-limit <N>, --error-limit=<N>
+                              Report at most N errors (20 by default), or all of them
+                              when N is 0
+-undefined <error|warning|suppress|dynamic_lookup>
+                              Anything but error also lets libraries that can't be found
+                              be ignored with a warning
+--reproduce <FILE>, --reproduce=<FILE>
+                              Write a tar of the inputs and arguments to FILE, to reproduce
+                              the link elsewhere
+--defsym <SYMBOL>=<VALUE>, --defsym=<SYMBOL>=<VALUE>
+                              Define SYMBOL as the absolute VALUE, given in decimal or
+                              0x prefixed hex, in place of any definition in the inputs
+--wrap <SYMBOL>, --wrap=<SYMBOL>
+                              Resolve references to SYMBOL, like _foo, to ___wrap_foo, and
+                              references to ___real_foo to SYMBOL
+--profile-order <FILE>, --profile-order=<FILE>
+                              Put the functions listed in FILE, one per line and hottest
+                              first, at the start of __text. Those followed by a sample
+                              count of 0 are cold and go at the end
+--print-memory-usage          Print the file and VM size of each segment and section
+-print-search-dirs, --print-search-dirs
+                              Print the library and framework directories searched, in
+                              GCC's format, instead of linking
+-print-prog-name=<NAME>, --print-prog-name=<NAME>
+                              Print the program run for NAME, which is machop itself for
+                              ld, instead of linking
+--dry-run                     Find the inputs and resolve symbols without writing the
+                              output, printing the inputs and libraries that would be
+                              loaded and the library each imported symbol is from
+-print_statistics             Print the time, peak memory and (with the alloc-stats
+                              feature) allocations of each phase of the link
+-time_trace <FILE>, --time-trace=<FILE>
+                              Write a Chrome trace of the link's phases and the time
+                              spent on each input to FILE
+--size-report, --size-report=<N>
+                              Print the N (by default 10) largest atoms in the output
+--max-size [<SEGMENT>=]<SIZE>, --max-size=[<SEGMENT>=]<SIZE>
+                              Fail the link if the output file, or SEGMENT of it, is larger
+                              than SIZE bytes, which can have a K, M or G suffix
+-trace_symbol <SYMBOL>, -y<SYMBOL>
+                              Report where SYMBOL is defined, referenced and resolved
 
 
 
-Any other arguments are treated as the input object files. Those that
-don't end in the extension .rlib or .o will be ignored.
-"#
-    )
-}
+Without -platform_version or a -<platform>_version_min flag, the
+platform is taken from MACOSX_DEPLOYMENT_TARGET, IPHONEOS_DEPLOYMENT_TARGET,
+TVOS_DEPLOYMENT_TARGET or WATCHOS_DEPLOYMENT_TARGET, then machop.toml.
+Failing those, it's inferred from the first object file's LC_BUILD_VERSION,
+with a warning, and defaults to macOS 11.0 for objects without one.
+
+Defaults for search paths, the platform, -threads, -fatal_warnings and the
+log level can be kept in a machop.toml in the current directory or one of
+its parents, or in ~/.config/machop/machop.toml. Flags override them.
+
+Setting SOURCE_DATE_EPOCH has the same effect as -reproducible.
+
+MACHOP_FLAGS is split like a shell command line and put before the
+arguments, so flags can be added when a build system hides them.
+
+Any other arguments are inputs: object files, archives, dylibs and TBDs,
+told apart by their contents rather than their extensions.
+"#;
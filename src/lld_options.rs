@@ -0,0 +1,232 @@
+/// The options machop accepts, following lld-macho's spelling of them,
+/// and the ld64 dialect on top of them.
+///
+/// Options that machop doesn't implement yet are still listed so their
+/// values are consumed correctly instead of being mistaken for inputs.
+use crate::arg_parser::{Kind::*, OptTable, OptionSpec, PathValue::*};
+
+const fn flag(name: &'static str) -> OptionSpec {
+    OptionSpec::new(name, Flag)
+}
+
+const fn joined(name: &'static str) -> OptionSpec {
+    OptionSpec::new(name, Joined)
+}
+
+const fn separate(name: &'static str) -> OptionSpec {
+    OptionSpec::new(name, Separate)
+}
+
+const fn joined_or_separate(name: &'static str) -> OptionSpec {
+    OptionSpec::new(name, JoinedOrSeparate)
+}
+
+const fn multi(name: &'static str, count: usize) -> OptionSpec {
+    OptionSpec::new(name, MultiArg(count))
+}
+
+pub static OPTIONS: &[OptionSpec] = &[
+    // General
+    flag("-help"),
+    flag("--help").alias("-help"),
+    flag("-v"),
+    flag("--version"),
+    flag("-t"),
+    flag("-w"),
+    flag("-fatal_warnings"),
+    // machop's own, to override a config's fatal-warnings.
+    flag("-no_fatal_warnings"),
+    flag("-demangle"),
+    separate("-o"),
+    // machop's own, not an lld-macho option.
+    flag("--create-dirs"),
+    separate("--reproduce"),
+    joined("--reproduce=").alias("--reproduce"),
+    flag("--print-memory-usage"),
+    flag("--dry-run"),
+    flag("-print-search-dirs"),
+    flag("--print-search-dirs").alias("-print-search-dirs"),
+    joined("-print-prog-name="),
+    joined("--print-prog-name=").alias("-print-prog-name="),
+    flag("-print_statistics"),
+    separate("-time_trace"),
+    joined("--time-trace=").alias("-time_trace"),
+    separate("--profile-order").path(At(0)),
+    joined("--profile-order=").alias("--profile-order"),
+    flag("--size-report"),
+    joined("--size-report=").alias("--size-report"),
+    separate("--max-size"),
+    joined("--max-size=").alias("--max-size"),
+    separate("--defsym"),
+    joined("--defsym=").alias("--defsym"),
+    separate("--wrap"),
+    joined("--wrap=").alias("--wrap"),
+    separate("-arch"),
+    separate("-lto_library"),
+    separate("-object_path_lto"),
+    separate("-cache_path_lto"),
+    separate("-mllvm"),
+    separate("-threads"),
+    joined("--threads=").alias("-threads"),
+    separate("-error-limit"),
+    separate("--error-limit").alias("-error-limit"),
+    joined("--error-limit=").alias("-error-limit"),
+    // Output kind
+    flag("-execute").group("output"),
+    flag("-dylib").group("output"),
+    flag("-bundle").group("output"),
+    flag("-dynamic"),
+    flag("-static"),
+    flag("-pie"),
+    flag("-no_pie"),
+    // ld64's, for writing dyld itself.
+    flag("-dylinker"),
+    // machop's own, spelt like ld.lld's. Not an lld-macho option.
+    separate("-dynamic-linker"),
+    separate("--dynamic-linker").alias("-dynamic-linker"),
+    joined("--dynamic-linker=").alias("-dynamic-linker"),
+    // Libraries and search paths
+    joined_or_separate("-L").group("search").path(At(0)),
+    joined_or_separate("-F").group("search").path(At(0)),
+    separate("-syslibroot").group("search").path(At(0)),
+    flag("-Z").group("search"),
+    flag("-search_paths_first").group("search"),
+    flag("-search_dylibs_first").group("search"),
+    joined("-l").group("libraries"),
+    joined("-needed-l").group("libraries"),
+    joined("-weak-l").group("libraries"),
+    joined("-reexport-l").group("libraries"),
+    joined("-upward-l").group("libraries"),
+    joined("-hidden-l").group("libraries"),
+    joined("-lazy-l").group("libraries"),
+    separate("-framework").group("libraries"),
+    separate("-needed_framework").group("libraries"),
+    separate("-weak_framework").group("libraries"),
+    separate("-reexport_framework").group("libraries"),
+    separate("-upward_framework").group("libraries"),
+    separate("-lazy_framework").group("libraries"),
+    separate("-needed_library").group("libraries").path(At(0)),
+    separate("-weak_library").group("libraries").path(At(0)),
+    separate("-reexport_library").group("libraries").path(At(0)),
+    separate("-upward_library").group("libraries").path(At(0)),
+    separate("-load_hidden").group("libraries").path(At(0)),
+    separate("-lazy_library").group("libraries").path(At(0)),
+    separate("-force_load").group("libraries").path(At(0)),
+    flag("-all_load").group("libraries"),
+    flag("-ObjC").group("libraries"),
+    flag("-no_implicit_dylibs").group("libraries"),
+    flag("-ignore_auto_link").group("libraries"),
+    separate("-filelist"),
+    // Platform
+    multi("-platform_version", 3).group("platform"),
+    separate("-macosx_version_min").group("platform"),
+    separate("-macos_version_min")
+        .alias("-macosx_version_min")
+        .group("platform"),
+    separate("-ios_version_min").group("platform"),
+    separate("-ios_simulator_version_min").group("platform"),
+    separate("-tvos_version_min").group("platform"),
+    separate("-watchos_version_min").group("platform"),
+    separate("-sdk_version").group("platform"),
+    // Dylib identity
+    separate("-install_name"),
+    separate("-dylib_install_name").alias("-install_name"),
+    separate("-current_version"),
+    separate("-dylib_current_version").alias("-current_version"),
+    separate("-compatibility_version"),
+    separate("-dylib_compatibility_version").alias("-compatibility_version"),
+    separate("-umbrella"),
+    separate("-client_name"),
+    separate("-rpath"),
+    // Symbols
+    separate("-e"),
+    separate("-u"),
+    flag("-export_dynamic"),
+    separate("-U"),
+    separate("-undefined"),
+    separate("-exported_symbol"),
+    separate("-exported_symbols_list").path(At(0)),
+    separate("-unexported_symbol"),
+    separate("-unexported_symbols_list").path(At(0)),
+    separate("-alias"),
+    separate("-alias_list").path(At(0)),
+    separate("-order_file").path(At(0)),
+    separate("-trace_symbol"),
+    joined("-y").alias("-trace_symbol"),
+    separate("-why_live"),
+    flag("-flat_namespace"),
+    flag("-twolevel_namespace"),
+    separate("-bundle_loader").path(At(0)),
+    flag("-no_deduplicate"),
+    flag("-dead_strip"),
+    flag("-objc_category_merging"),
+    flag("-no_objc_category_merging"),
+    flag("-S"),
+    flag("-x"),
+    separate("-non_global_symbols_strip_list").path(At(0)),
+    separate("-non_global_symbols_no_strip_list").path(At(0)),
+    // Layout
+    separate("-map"),
+    separate("-segment_order"),
+    multi("-section_order", 2),
+    flag("-cross_reference"),
+    multi("-sectcreate", 3).path(At(2)),
+    multi("-segcreate", 3).alias("-sectcreate"),
+    multi("-sectalign", 3),
+    multi("-segaddr", 2),
+    multi("-segprot", 3),
+    separate("-pagezero_size"),
+    separate("-stack_size"),
+    separate("-headerpad"),
+    flag("-headerpad_max_install_names"),
+    multi("-move_to_ro_segment", 2).path(At(1)),
+    multi("-move_to_rw_segment", 2).path(At(1)),
+    separate("-dirty_data_list").path(At(0)),
+    flag("-no_data_const"),
+    flag("-encryptable"),
+    flag("-application_extension"),
+    flag("-no_application_extension"),
+    // Signing and identification
+    flag("-adhoc_codesign"),
+    flag("-no_adhoc_codesign"),
+    flag("-no_uuid"),
+    separate("-objc_abi_version"),
+];
+
+/// Spellings ld64 accepts, as passed by Xcode, that lld-macho doesn't
+/// alias the same way or at all.
+pub static LD64_OPTIONS: &[OptionSpec] = &[
+    separate("-iphoneos_version_min")
+        .alias("-ios_version_min")
+        .group("platform"),
+    separate("-tvos_simulator_version_min").group("platform"),
+    separate("-watchos_simulator_version_min").group("platform"),
+    separate("-dylib_file").path(AfterColon),
+    separate("-final_output"),
+    separate("-add_ast_path"),
+    separate("-seg1addr"),
+    separate("-image_base").alias("-seg1addr"),
+    separate("-multiply_defined"),
+    multi("-sectorder", 3).path(At(2)),
+    flag("-single_module"),
+    flag("-prebind"),
+    flag("-force_cpusubtype_ALL"),
+    flag("-noprebind"),
+    flag("-no_compact_unwind"),
+    flag("-bitcode_bundle"),
+    flag("-bitcode_verify"),
+    separate("-bitcode_process_mode"),
+    flag("-bitcode_hide_symbols"),
+    separate("-bitcode_symbol_map"),
+    flag("-ignore_optimization_hints"),
+    flag("-preload").group("output"),
+    flag("-reproducible"),
+];
+
+/// Other linkers' options that machop doesn't implement, which would
+/// otherwise be parsed as -l with the rest of the name as the library.
+static UNKNOWN: &[&str] = &["-ld_classic", "-ld_new"];
+
+pub static TABLE: OptTable = OptTable::new(&[OPTIONS]).unknown(UNKNOWN);
+
+pub static LD64_TABLE: OptTable = OptTable::new(&[LD64_OPTIONS, OPTIONS]).unknown(UNKNOWN);
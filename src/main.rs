@@ -1,438 +1,231 @@
 use std::{
-    collections::{HashMap, HashSet},
-    error::Error,
-    fmt::{Debug, Display},
-    fs::Permissions,
-    os::unix::prelude::PermissionsExt,
-    path::PathBuf,
+    ffi::OsString,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
 };
 
-use goblin::mach::{
-    cputype::CPU_TYPE_ARM64,
-    header::{filetype_to_str, MH_DYLIB, MH_EXECUTE},
-    symbols::Nlist,
-    MachO, SingleArch,
-};
 use machop::{
-    linker_args::{Architecture, Args},
-    tbd::{self, TbdDylib},
+    completions,
+    config::Config,
+    daemon::{self, Request},
+    environment::Environment,
+    input_cache::InputCache,
+    link::link,
+    linker_args::{self, Args, Persona, Query},
+    lld_options,
+    observer::Observer,
+    panic_context,
+    verify::{self, Options as VerifyOptions},
 };
 
-#[derive(Debug)]
-#[allow(clippy::large_enum_variant)]
-pub enum Object<'a> {
-    /// An ELF32/ELF64!
-    Elf(goblin::elf::Elf<'a>),
-    /// A PE32/PE32+!
-    PE(goblin::pe::PE<'a>),
-    /// A 32/64-bit Mach-o binary _OR_ it is a multi-architecture binary container!
-    Mach(goblin::mach::Mach<'a>),
-    /// A Unix archive
-    Archive(goblin::archive::Archive<'a>),
-    /// A text stub file
-    Tbd(tbd::TbdDylib),
-}
-
-impl<'a> From<TbdDylib> for Object<'a> {
-    fn from(tbd: TbdDylib) -> Self {
-        Object::Tbd(tbd)
-    }
-}
-
-impl<'a> Object<'a> {
-    pub fn parse(s: &'a [u8]) -> Result<Self, Box<dyn Error>> {
-        let goblin_obj = goblin::Object::parse(s)?;
-        if let goblin::Object::Unknown(_) = goblin_obj {
-            Ok(tbd::TbdDylib::parse(Architecture::ARM64, s).unwrap().into())
-        } else {
-            Ok(goblin_obj.try_into().unwrap())
-        }
-    }
-}
-
-#[derive(Debug)]
-pub struct ObjectConversionError(());
-impl Error for ObjectConversionError {}
-impl Display for ObjectConversionError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "failed to convert object")
-    }
-}
-
-impl<'a> TryFrom<goblin::Object<'a>> for Object<'a> {
-    type Error = ObjectConversionError;
-
-    fn try_from(o: goblin::Object<'a>) -> Result<Self, Self::Error> {
-        match o {
-            goblin::Object::Elf(elf) => Ok(Object::Elf(elf)),
-            goblin::Object::PE(pe) => Ok(Object::PE(pe)),
-            goblin::Object::Mach(mach) => Ok(Object::Mach(mach)),
-            goblin::Object::Archive(archive) => Ok(Object::Archive(archive)),
-            goblin::Object::Unknown(_) => Err(ObjectConversionError(())),
-        }
-    }
-}
-
-enum Dylib<'a> {
-    MachO(&'a MachO<'a>),
-    Tbd(&'a tbd::TbdDylib),
-}
-
-struct Symbol<'a> {
-    name: &'a str,
-    nlist: Nlist,
-    object: Dylib<'a>,
-}
-
-impl<'a> Debug for Symbol<'a> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Symbol")
-            // .field("name", &self.name)
-            .field("nlist", &self.nlist)
-            .field("type_str()", &self.nlist.type_str())
-            .field("is_global()", &self.nlist.is_global())
-            .field("is_weak()", &self.nlist.is_weak())
-            .field("is_undefined()", &self.nlist.is_undefined())
-            .field("is_stab()", &self.nlist.is_stab())
-            .finish()
-    }
-}
-
 fn main() {
-    env_logger::init();
-    let mut args = Args::from_env().unwrap();
-    args.library_search_paths
-        .append(&mut vec!["/usr/lib".into(), "/usr/local/lib".into()]);
-    // Dedupe only removes consecutive duplicates so we need to sort
-    // it first. Maybe it'd be better to just use a set?
-    args.library_search_paths.sort();
-    args.library_search_paths.dedup();
-    log::debug!("Arg: {:#?}", args);
-    // args.object_files = vec![args.object_files.first().unwrap().to_owned()];
-    // args.libraries = vec![];
-    let mut object_files = vec![];
-    // let (cpu_type, cpu_subtype) = get_arch_from_flag(&args.arch.to_string())
-    //     .unwrap_or_else(|| panic!("no arch found for {}", args.arch));
-    object_files.append(&mut args.object_files.clone());
-    let library_search_paths = if let Some(ref root) = args.sys_lib_root {
-        args.library_search_paths
-            .iter()
-            .map(|path| {
-                let non_abs_path = if path.starts_with("/") {
-                    path.strip_prefix("/").unwrap()
-                } else {
-                    path
-                };
-                root.join(non_abs_path)
-            })
-            .collect()
-    } else {
-        args.library_search_paths.clone()
+    let mut argv = std::env::args_os();
+    let persona = argv
+        .next()
+        .map_or(Persona::Machop, |argv0| Persona::from_argv0(&argv0));
+    // The config can set the log level, so is read before there's a
+    // logger to report problems with it.
+    let config = std::env::current_dir()
+        .map_err(|e| format!("Unable to find the current directory: {e}"))
+        .and_then(|dir| Config::find(&dir, &Environment::current()));
+    let log_level = match &config {
+        Ok(Config {
+            log_level: Some(level),
+            ..
+        }) => level.as_str(),
+        _ => persona.default_log_level(),
     };
-    log::trace!("Using library search paths: {:?}", library_search_paths);
-    for library in &args.libraries {
-        let maybe_path = discover_library_path(&library_search_paths, library);
-        if let Some(path) = maybe_path {
-            object_files.push(path);
-        } else {
-            log::warn!("Unable to find libary {}", library);
-        }
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level));
+    // A daemon links with each request's config, log level included, so
+    // without RUST_LOG its logger lets everything through and the max
+    // level does the filtering. See serve.
+    let serving = persona == Persona::Machop
+        && std::env::args_os()
+            .nth(1)
+            .is_some_and(|arg| arg == "daemon" || arg == "--daemon");
+    let per_request_level = serving && std::env::var_os("RUST_LOG").is_none();
+    if per_request_level {
+        logger.filter_level(log::LevelFilter::Trace);
     }
-    log::trace!("Object files: {:?}", object_files);
-    let object_contents = object_files
-        .iter()
-        .map(|object_file_path| std::fs::read(&object_file_path).map_err(|e| e.to_string()))
-        .collect::<Result<Vec<Vec<u8>>, String>>()
-        .unwrap();
-    let objects = object_contents
-        .iter()
-        .enumerate()
-        .map(|(i, object_content)| {
-            log::debug!("Parsing {}", object_files[i].display());
-            Object::parse(object_content.as_slice())
-                .map_err(|e| e.to_string() + &format!(" xxx {}", i))
-                .unwrap()
-        })
-        .collect::<Vec<Object>>();
-    log::debug!("Linking {} objects", objects.len());
-    // log::debug!("Objects: {objects:#?}");
-
-    let mut dylibs = vec![];
-    let mut objs: Vec<MachO> = vec![];
-    let mut unowned_objs: Vec<&MachO> = vec![];
-
-    for (i, object) in objects.iter().enumerate() {
-        match object {
-            Object::Elf(_) => todo!(),
-            Object::PE(_) => todo!(),
-            Object::Mach(mach) => match mach {
-                goblin::mach::Mach::Fat(fat) => {
-                    let arch_position = fat
-                        .iter_arches()
-                        .position(|arch| {
-                            let arch = arch.unwrap();
-                            arch.cputype() & CPU_TYPE_ARM64 != 0
-                        })
-                        .unwrap();
-                    match fat.get(arch_position) {
-                        Ok(entry) => match entry {
-                            SingleArch::MachO(macho) => {
-                                if macho.is_object_file() {
-                                    objs.push(macho);
-                                }
-                            }
-                            SingleArch::Archive(archive) => {
-                                let content = &object_contents[i];
-                                let arch = fat.iter_arches().nth(arch_position).unwrap().unwrap();
-                                let start = arch.offset as usize;
-                                let end = (arch.offset + arch.size) as usize;
-                                let bytes = &content[start..end];
-                                for member_name in archive.members() {
-                                    let member_bytes = archive.extract(member_name, bytes).unwrap();
-                                    let macho = MachO::parse(member_bytes, 0).unwrap();
-                                    if macho.is_object_file() {
-                                        objs.push(macho);
-                                    }
-                                }
-                            }
-                        },
-                        Err(e) => panic!("{}", e),
+    logger.init();
+    let default_level = log_level.parse().unwrap_or(log::LevelFilter::Warn);
+    if per_request_level {
+        log::set_max_level(default_level);
+    }
+    let config = config.unwrap_or_else(|e| {
+        log::error!("{e}");
+        std::process::exit(1)
+    });
+    panic_context::install();
+    let mut argv: Vec<OsString> = argv.collect();
+    if persona == Persona::Machop {
+        match argv.first().and_then(|arg| arg.to_str()) {
+            Some("link") => {
+                argv.remove(0);
+            }
+            Some("version") => {
+                println!("machop {}", env!("CARGO_PKG_VERSION"));
+                return;
+            }
+            Some("options") => {
+                for option in lld_options::LD64_TABLE.options() {
+                    match option.alias {
+                        Some(alias) => println!("{} (alias of {alias})", option.name),
+                        None => println!("{}", option.name),
                     }
                 }
-                goblin::mach::Mach::Binary(macho) => {
-                    if macho.is_object_file() {
-                        unowned_objs.push(macho);
-                    } else {
-                        match macho.header.filetype {
-                            MH_EXECUTE | MH_DYLIB => dylibs.push(Dylib::MachO(macho)),
-                            _ => panic!(
-                                "unhandled macho filetype {}",
-                                filetype_to_str(macho.header.filetype)
-                            ),
-                        }
+                return;
+            }
+            Some("completions") => {
+                let shell = argv
+                    .get(1)
+                    .and_then(|shell| shell.to_str())
+                    .ok_or_else(|| "completions expects a shell: bash, zsh or fish".to_string())
+                    .and_then(|shell| shell.parse());
+                match shell {
+                    Ok(shell) => {
+                        print!("{}", completions::generate(shell, &lld_options::LD64_TABLE))
                     }
-                }
-            },
-            Object::Archive(archive) => {
-                let bytes = &object_contents[i];
-                for member_name in archive.members() {
-                    let member_bytes = archive.extract(member_name, bytes).unwrap();
-                    let macho = MachO::parse(member_bytes, 0).unwrap();
-                    if macho.is_object_file() {
-                        objs.push(macho);
+                    Err(e) => {
+                        log::error!("{e}");
+                        std::process::exit(1)
                     }
                 }
+                return;
             }
-            Object::Tbd(tbd) => dylibs.push(Dylib::Tbd(tbd)),
-        }
-    }
-
-    // let mut executable = ArtifactBuilder::new(target_lexicon::Triple {
-    //     architecture: target_lexicon::Architecture::Arm(ArmArchitecture::Arm),
-    //     vendor: target_lexicon::Vendor::Unknown,
-    //     operating_system: target_lexicon::OperatingSystem::Unknown,
-    //     environment: target_lexicon::Environment::Unknown,
-    //     binary_format: target_lexicon::BinaryFormat::Macho,
-    // })
-    // .name(
-    //     args.output_file
-    //         .file_name()
-    //         .unwrap()
-    //         .to_str()
-    //         .unwrap()
-    //         .to_string(),
-    // )
-    // .finish();
-
-    let mut symbols: HashMap<String, Symbol> = HashMap::new();
-    let mut undefined_symbols: HashSet<String> = HashSet::new();
-
-    for obj in &objs {
-        for symbol in obj.symbols() {
-            let (name, nlist) = symbol.unwrap();
-            // println!(
-            //     "{}:\t{:?}, type={}, global={}, weak={}, undefined={}, stab={}",
-            //     name,
-            //     Nlist64::from(nlist.clone()),
-            //     nlist.type_str(),
-            //     nlist.is_global(),
-            //     nlist.is_weak(),
-            //     nlist.is_undefined(),
-            //     nlist.is_stab(),
-            // );
-            let symbol = Symbol {
-                nlist,
-                object: Dylib::MachO(obj),
-                name,
-            };
-
-            // Keep track of undefined symbols so that we can check
-            // them at the end. If we encounter a definition of the
-            // symbol it'll be removed from the set.
-            if symbol.nlist.is_undefined() {
-                undefined_symbols.insert(name.to_string());
-                continue;
-            }
-
-            // Insert the symbol, whatever is, if we've never seen it
-            // before. Otherwise, only insert it if the new symbol is
-            // not weak. If there are only weak symbols then we just
-            // take the first one.
-            //
-            // Having two "strong" symbols is not allowed (through we
-            // don't return an error - maybe we should?).
-            if let Some(existing_symbol) = symbols.get(name) {
-                if existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
-                    // The old symbol was weak but this one isn't - replace it.
-                    symbols.insert(name.to_string(), symbol);
-                    undefined_symbols.remove(name);
-                } else if !existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
-                    log::warn!(
-                        "Non-weak symbol {} already exists. Ignoring it but this is malformed.\nHave={:?}\ngot={:?}",
-                        name,
-                        existing_symbol,
-                        symbol
-                    )
-                } else {
-                    log::trace!("Weak symbol {} already seen, ignoring it", name)
+            Some("verify") => {
+                let report = VerifyOptions::parse(argv.drain(1..))
+                    .and_then(|options| verify::verify(&options));
+                match report {
+                    Ok(report) => {
+                        for problem in &report.problems {
+                            if problem.is_error() {
+                                log::error!("{problem}");
+                            } else {
+                                log::warn!("{problem}");
+                            }
+                        }
+                        if report.problems.iter().any(|problem| problem.is_error()) {
+                            std::process::exit(1)
+                        }
+                        println!(
+                            "Checked {} imports from {} libraries",
+                            report.imports, report.libraries
+                        );
+                    }
+                    Err(e) => {
+                        log::error!("{e}");
+                        std::process::exit(1)
+                    }
                 }
-            } else {
-                symbols.insert(name.to_string(), symbol);
-                undefined_symbols.remove(name);
+                return;
             }
-        }
-    }
-
-    for obj in unowned_objs {
-        for symbol in obj.symbols() {
-            let (name, nlist) = symbol.unwrap();
-            let symbol = Symbol {
-                name,
-                nlist,
-                object: Dylib::MachO(obj),
-            };
-            if let Some(existing_symbol) = symbols.get(name) {
-                if existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
-                    // The old symbol was weak but this one isn't - replace it.
-                    symbols.insert(name.to_string(), symbol);
-                    undefined_symbols.remove(name);
-                } else if !existing_symbol.nlist.is_weak() && !symbol.nlist.is_weak() {
-                    log::warn!(
-                        "Non-weak symbol {} already exists. Ignoring it but this is malformed.\nHave={:?}\ngot={:?}",
-                        name,
-                        existing_symbol,
-                        symbol
-                    )
-                } else {
-                    log::trace!("Weak symbol {} already seen, ignoring it", name)
+            Some("daemon" | "--daemon") => {
+                let Some(socket) = argv.get(1) else {
+                    log::error!("daemon expects the path of the socket to listen on");
+                    std::process::exit(1)
+                };
+                let level = per_request_level.then_some(default_level);
+                if let Err(e) = serve(Path::new(socket), level) {
+                    log::error!("{e}");
+                    std::process::exit(1)
                 }
-            } else {
-                symbols.insert(name.to_string(), symbol);
-                undefined_symbols.remove(name);
+                return;
             }
+            _ => {}
         }
     }
-
-    for dylib in dylibs {
-        match dylib {
-            Dylib::MachO(_) => todo!(),
-            Dylib::Tbd(tbd) => {
-                for export in &tbd.exports {
-                    if undefined_symbols.contains(export) {
-                        log::trace!("{export} will be defined by {}", tbd.install_name.display());
-                        undefined_symbols.remove(export);
-                    }
-                }
-            }
+    match linker_args::injected_args() {
+        Ok(injected) if !injected.is_empty() => {
+            log::debug!("Adding {injected:?} from {}", linker_args::FLAGS_ENV);
+            argv.splice(0..0, injected);
         }
-    }
-
-    let mut segments: HashMap<String, HashMap<String, HashMap<String, &Symbol>>> = HashMap::new();
-    for symbol in symbols.values() {
-        let section_number = symbol.nlist.n_sect;
-        if section_number != 0 {
-            // Sections numbers, as given in the Mach-O binary, are
-            // 1-based.
-            let section_index = section_number - 1;
-            match &symbol.object {
-                Dylib::MachO(macho) => {
-                    let (section, _section_data) = macho
-                        .segments
-                        .sections()
-                        .flatten()
-                        .nth(section_index)
-                        .unwrap()
-                        .unwrap();
-                    let section_name = section.name().unwrap();
-                    let segment_name = section.segname().unwrap();
-                    if let Some(sections) = segments.get_mut(segment_name) {
-                        if let Some(section) = sections.get_mut(section_name) {
-                            section.insert(symbol.name.to_string(), symbol);
-                        } else {
-                            let mut section = HashMap::new();
-                            section.insert(symbol.name.to_string(), symbol);
-                            sections.insert(section_name.to_string(), section);
-                        };
-                    } else {
-                        let mut sections = HashMap::new();
-                        let mut section = HashMap::new();
-                        section.insert(symbol.name.to_string(), symbol);
-                        sections.insert(section_name.to_string(), section);
-                        segments.insert(segment_name.to_string(), sections);
-                    }
-                }
-                Dylib::Tbd(_) => todo!(),
-            }
+        Ok(_) => {}
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1)
         }
     }
-
-    for (segment_name, sections) in segments {
-        println!("{}", segment_name);
-        for (section_name, symbols) in sections {
-            println!("\t{}", section_name);
-            for (symbol_name, _) in symbols {
-                println!("\t\t{}", symbol_name);
-            }
-        }
+    log::debug!("Running as {persona:?}");
+    // The arguments are parsed here even when linking with a daemon so
+    // that mistakes in them are reported by the client.
+    let args = Args::parse_with_config(argv.clone(), persona.default_dialect(), &config)
+        .unwrap_or_else(|e| {
+            log::error!("{e}");
+            std::process::exit(1)
+        });
+    if args.query == Some(Query::Help) {
+        eprintln!("{}", linker_args::USAGE);
+        std::process::exit(1)
     }
-
-    if !undefined_symbols.is_empty() {
-        for symbol in undefined_symbols {
-            log::error!("{symbol} is undefined")
+    // Queries are answered and symbols traced on stdout, which the
+    // daemon doesn't have.
+    let socket = std::env::var_os(daemon::SOCKET_ENV)
+        .filter(|_| args.query.is_none() && args.trace_symbols.is_empty());
+    if let Some(socket) = socket {
+        // The daemon doesn't know how it was invoked, so pass on the
+        // dialect the persona implies.
+        let dialect = persona
+            .default_dialect()
+            .map(|dialect| OsString::from(format!("--dialect={dialect}")));
+        let request = Request::from_current(dialect.into_iter().chain(argv).collect());
+        match request.and_then(|request| daemon::forward(Path::new(&socket), &request)) {
+            Ok(status) => std::process::exit(status.into()),
+            Err(e) => log::debug!(
+                "Unable to link with the daemon at {}, linking in-process: {e}",
+                Path::new(&socket).display()
+            ),
         }
+    }
+    if link(args, &InputCache::default(), &mut TraceSymbols).is_err() {
         std::process::exit(1)
     }
+}
 
-    let fh = std::fs::File::create(&args.output_file).unwrap();
-    // Make rwx by all.
-    fh.set_permissions(Permissions::from_mode(0o777)).unwrap();
-    // executable.write(fh).unwrap();
+/// Prints what -trace_symbol asks about, like ld64.
+struct TraceSymbols;
+
+impl Observer for TraceSymbols {
+    fn on_symbol_traced(&mut self, name: &str, event: fmt::Arguments) {
+        println!("{name}: {event}");
+    }
 }
 
-fn discover_library_path(locations: &[PathBuf], library_name: &str) -> Option<PathBuf> {
-    log::trace!("Discovering library {library_name}");
-    let extensions = ["tbd", "dylib", "a"];
-    for prefix in locations {
-        for extension in extensions {
-            log::trace!(
-                "Looking for library {library_name} with extension {extension} in {}",
-                prefix.display()
-            );
-            let candidate = prefix
-                .join(&format!("lib{}", library_name))
-                .with_extension(extension);
-            log::trace!(
-                "Trying candidate {} for library {library_name}",
-                candidate.display()
-            );
-            if candidate.exists() {
-                log::trace!(
-                    "Using candidate {} for library {library_name}",
-                    candidate.display()
-                );
-                return Some(candidate);
+/// Link requests from clients until killed, sharing one cache between
+/// them. With a `default_level`, the log level is set for each request,
+/// from its config or failing that `default_level`.
+fn serve(socket: &Path, default_level: Option<log::LevelFilter>) -> std::io::Result<()> {
+    let cache = InputCache::default();
+    daemon::serve(socket, |request| {
+        // Requests come from different directories, with their own
+        // environment and config.
+        let env = request.environment();
+        let args = Config::find(&request.cwd, &env).and_then(|config| {
+            if let Some(default_level) = default_level {
+                let level = config
+                    .log_level
+                    .as_deref()
+                    .and_then(|level| level.parse().ok());
+                log::set_max_level(level.unwrap_or(default_level));
             }
-        }
-    }
-    None
+            Args::parse_in(request.args, None, &config, &env)
+        });
+        let status = match args {
+            Ok(args) => match panic::catch_unwind(AssertUnwindSafe(|| link(args, &cache, &mut ())))
+            {
+                Ok(Ok(())) => 0,
+                Ok(Err(())) => 1,
+                // Like the status of a Rust program that panicked.
+                Err(_) => 101,
+            },
+            Err(e) => {
+                log::error!("{e}");
+                1
+            }
+        };
+        cache.prune();
+        status
+    })
 }
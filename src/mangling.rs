@@ -0,0 +1,83 @@
+/// C and C++ symbol names.
+///
+/// Mach-O prefixes C names with an underscore, so `foo` is `_foo` and
+/// the Itanium mangled `_Z3foov` is `__Z3foov`.
+use std::collections::HashMap;
+
+/// The demangled form of a C++ symbol, like `foo(int)` for `__Z3fooi`.
+pub fn demangle(symbol: &str) -> Option<String> {
+    let mangled = symbol.strip_prefix('_')?;
+    if !mangled.starts_with("_Z") {
+        return None;
+    }
+    let symbol = cpp_demangle::Symbol::new(mangled).ok()?;
+    symbol.demangle(&Default::default()).ok()
+}
+
+/// What a C++ symbol is named in source, without its parameters, like
+/// `foo` for `__Z3fooi`.
+fn cxx_name(symbol: &str) -> Option<String> {
+    let demangled = demangle(symbol)?;
+    let name = demangled.split('(').next().unwrap_or_default();
+    Some(name.to_string())
+}
+
+/// Defined symbols indexed so a reference with the wrong linkage can be
+/// matched to the definition it probably meant.
+#[derive(Debug, Default)]
+pub struct LinkageIndex {
+    /// C++ definitions, and where they're defined, by their source name.
+    cxx: HashMap<String, (String, String)>,
+    /// C definitions, and where they're defined, by their source name.
+    c: HashMap<String, (String, String)>,
+}
+
+#[derive(Debug)]
+pub enum LinkageHint {
+    /// Referenced as a C symbol, but defined as C++.
+    DefinedAsCxx { symbol: String, demangled: String },
+    /// Referenced as a C++ symbol, but defined as C.
+    DefinedAsC { symbol: String },
+}
+
+impl LinkageIndex {
+    /// Add `symbol`, defined in `input`.
+    pub fn insert(&mut self, symbol: &str, input: &str) {
+        let definition = || (symbol.to_string(), input.to_string());
+        if let Some(name) = cxx_name(symbol) {
+            self.cxx.entry(name).or_insert_with(definition);
+        } else if let Some(name) = symbol.strip_prefix('_') {
+            self.c.entry(name.to_string()).or_insert_with(definition);
+        }
+    }
+
+    /// A definition of `undefined` with the other linkage, and where it's
+    /// defined.
+    pub fn hint(&self, undefined: &str) -> Option<(LinkageHint, &str)> {
+        if let Some(name) = cxx_name(undefined) {
+            let (symbol, input) = self.c.get(&name)?;
+            let symbol = symbol.clone();
+            Some((LinkageHint::DefinedAsC { symbol }, input))
+        } else {
+            let (symbol, input) = self.cxx.get(undefined.strip_prefix('_')?)?;
+            let hint = LinkageHint::DefinedAsCxx {
+                symbol: symbol.clone(),
+                demangled: demangle(symbol).unwrap_or_default(),
+            };
+            Some((hint, input))
+        }
+    }
+}
+
+impl std::fmt::Display for LinkageHint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkageHint::DefinedAsCxx { symbol, demangled } => {
+                write!(f, "{demangled} is defined with C++ linkage as {symbol}")
+            }
+            LinkageHint::DefinedAsC { symbol } => {
+                write!(f, "{symbol} is defined with C linkage")
+            }
+        }
+    }
+}
@@ -0,0 +1,498 @@
+/// Objective-C and Swift metadata that's merged rather than
+/// concatenated.
+///
+/// Every object compiled from Objective-C or Swift has an
+/// __objc_imageinfo section, a version and flags describing how it was
+/// compiled, but the runtime only reads one per image. The output gets
+/// a single record merged from the inputs'.
+///
+/// Like ld64, categories on a class are also folded into the class when
+/// both are linked into the image, so the runtime doesn't have to
+/// attach them at launch. Only categories in the same object as their
+/// class are merged here, as the metadata of each object is rewritten
+/// on its own.
+use std::collections::HashSet;
+
+use crate::{
+    layout::{Atom, InputObject},
+    reloc::{self, Kind, Target},
+};
+
+pub const IMAGE_INFO_SECTION: &str = "__objc_imageinfo";
+const CATEGORY_LIST_SECTION: &str = "__objc_catlist";
+/// Categories with a +load method, which the runtime has to call.
+const NON_LAZY_CATEGORY_LIST_SECTION: &str = "__objc_nlcatlist";
+
+// Offsets of the fields of category_t.
+const CATEGORY_CLASS: u64 = 8;
+const CATEGORY_INSTANCE_METHODS: u64 = 16;
+const CATEGORY_CLASS_METHODS: u64 = 24;
+const CATEGORY_PROTOCOLS: u64 = 32;
+const CATEGORY_INSTANCE_PROPERTIES: u64 = 40;
+/// Only present in categories compiled by newer compilers.
+const CATEGORY_CLASS_PROPERTIES: u64 = 48;
+
+// Offsets of the fields of class_t and class_ro_t.
+const CLASS_ISA: u64 = 0;
+const CLASS_RO: u64 = 32;
+const RO_METHODS: u64 = 32;
+const RO_PROTOCOLS: u64 = 40;
+const RO_PROPERTIES: u64 = 64;
+
+const POINTER_SIZE: u64 = 8;
+/// Method, property and protocol lists all start with 8 bytes giving
+/// the size and number of entries.
+const LIST_HEADER_SIZE: u64 = 8;
+/// Set in a method list's entry size when the entries are relative
+/// offsets, which can't be concatenated with absolute ones.
+const RELATIVE_METHOD_LIST: u32 = 0x8000_0000;
+
+// Flags of __objc_imageinfo.
+const SUPPORTS_GC: u32 = 1 << 1;
+const REQUIRES_GC: u32 = 1 << 2;
+const IS_SIMULATED: u32 = 1 << 5;
+const HAS_CATEGORY_CLASS_PROPERTIES: u32 = 1 << 6;
+/// Bits of the flags holding the Swift versions: the ABI version in
+/// bits 8 to 15 and the compiler's version in the rest above it.
+const SWIFT_VERSION_MASK: u32 = 0xffff_ff00;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An input compiled for garbage collection, which the runtime
+    /// hasn't supported since macOS 10.8.
+    GarbageCollection { input: String },
+    /// Inputs compiled for both a simulator and a device.
+    MixedSimulator { simulator: String, device: String },
+    /// Inputs compiled with Swift versions with different ABIs.
+    SwiftAbiMismatch {
+        first: (String, u32),
+        second: (String, u32),
+    },
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::GarbageCollection { input } => write!(
+                f,
+                "{input} was compiled for Objective-C garbage collection, which isn't supported"
+            ),
+            Error::MixedSimulator { simulator, device } => write!(
+                f,
+                "{simulator} was compiled for a simulator but {device} wasn't"
+            ),
+            Error::SwiftAbiMismatch {
+                first: (first, first_abi),
+                second: (second, second_abi),
+            } => write!(
+                f,
+                "{first} was compiled with Swift ABI version {first_abi} but {second} with \
+                 {second_abi}, they have to be compiled with compatible versions of Swift"
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub version: u32,
+    pub flags: u32,
+}
+
+impl ImageInfo {
+    pub const SIZE: u64 = 8;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let word = |at: usize| Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?));
+        Some(Self {
+            version: word(0)?,
+            flags: word(4)?,
+        })
+    }
+
+    pub fn to_bytes(self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&self.version.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.flags.to_le_bytes());
+        bytes
+    }
+
+    /// The Swift versions the input was compiled with, 0 if it has no
+    /// Swift.
+    pub fn swift_version(self) -> u32 {
+        self.flags & SWIFT_VERSION_MASK
+    }
+
+    fn swift_abi_version(self) -> u32 {
+        (self.flags >> 8) & 0xff
+    }
+
+    /// Merge the image infos of the inputs, with the name of the input
+    /// each is from, into one for the output.
+    ///
+    /// Category class properties are only supported by the image if
+    /// every input supports them. The Swift versions are those of the
+    /// first input with Swift, objects compiled from Objective-C alone
+    /// having none, and every input with Swift has to use the same ABI.
+    /// Flags dyld sets, like whether it has optimized the image, are
+    /// left clear.
+    pub fn merge(infos: &[(&str, ImageInfo)]) -> Result<Option<ImageInfo>, Error> {
+        if infos.is_empty() {
+            return Ok(None);
+        }
+        if let Some((input, _)) = infos
+            .iter()
+            .find(|(_, info)| info.flags & (SUPPORTS_GC | REQUIRES_GC) != 0)
+        {
+            return Err(Error::GarbageCollection {
+                input: input.to_string(),
+            });
+        }
+        let mut flags = 0;
+        let simulated = infos
+            .iter()
+            .find(|(_, info)| info.flags & IS_SIMULATED != 0);
+        let device = infos
+            .iter()
+            .find(|(_, info)| info.flags & IS_SIMULATED == 0);
+        match (simulated, device) {
+            (Some((simulator, _)), Some((device, _))) => {
+                return Err(Error::MixedSimulator {
+                    simulator: simulator.to_string(),
+                    device: device.to_string(),
+                })
+            }
+            (Some(_), None) => flags |= IS_SIMULATED,
+            _ => {}
+        }
+        if infos
+            .iter()
+            .all(|(_, info)| info.flags & HAS_CATEGORY_CLASS_PROPERTIES != 0)
+        {
+            flags |= HAS_CATEGORY_CLASS_PROPERTIES;
+        }
+        let mut swift = infos.iter().filter(|(_, info)| info.swift_version() != 0);
+        if let Some((first, first_info)) = swift.next() {
+            if let Some((second, second_info)) =
+                swift.find(|(_, info)| info.swift_abi_version() != first_info.swift_abi_version())
+            {
+                return Err(Error::SwiftAbiMismatch {
+                    first: (first.to_string(), first_info.swift_abi_version()),
+                    second: (second.to_string(), second_info.swift_abi_version()),
+                });
+            }
+            flags |= first_info.swift_version();
+        }
+        Ok(Some(ImageInfo { version: 0, flags }))
+    }
+}
+
+/// An atom, by section and atom index in its object.
+type AtomRef = (usize, usize);
+
+/// Where a pointer in an object points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Pointer {
+    Null,
+    /// A section index and offset in it.
+    To(usize, u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum List {
+    Methods,
+    Properties,
+    Protocols,
+}
+
+impl List {
+    /// The entry size and count from a list's header.
+    fn header(self, data: &[u8]) -> Option<(u64, u64)> {
+        let word = |at: usize| Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?));
+        match self {
+            List::Protocols => {
+                let count = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+                Some((POINTER_SIZE, count))
+            }
+            List::Methods | List::Properties => {
+                let entry_size = word(0)?;
+                if self == List::Methods && entry_size & RELATIVE_METHOD_LIST != 0 {
+                    return None;
+                }
+                // The low bits of the entry size are flags.
+                Some(((entry_size & !3) as u64, word(4)? as u64))
+            }
+        }
+    }
+
+    fn set_count(self, data: &mut [u8], count: u64) {
+        match self {
+            List::Protocols => data[..8].copy_from_slice(&count.to_le_bytes()),
+            List::Methods | List::Properties => {
+                data[4..8].copy_from_slice(&(count as u32).to_le_bytes())
+            }
+        }
+    }
+}
+
+/// A change to a class's metadata for one of a category's lists.
+enum Merge {
+    /// The class has no list of its own, so takes the category's.
+    Adopt {
+        category: (AtomRef, u64),
+        ro: (AtomRef, u64),
+    },
+    /// The category's entries are put in front of the class's, so they
+    /// take precedence like a category attached by the runtime.
+    Concat {
+        list: List,
+        category: AtomRef,
+        class: AtomRef,
+    },
+}
+
+/// Fold the categories in `object` on classes it defines into the
+/// classes, returning how many were merged.
+pub fn merge_categories(object: &mut InputObject) -> usize {
+    let non_lazy: HashSet<Pointer> = list_entries(object, NON_LAZY_CATEGORY_LIST_SECTION)
+        .into_iter()
+        .filter_map(|(atom, field)| pointer(object, atom, field))
+        .collect();
+    let mut merged = vec![];
+    for (atom, field) in list_entries(object, CATEGORY_LIST_SECTION) {
+        let Some(category) = pointer(object, atom, field) else {
+            continue;
+        };
+        if category == Pointer::Null || non_lazy.contains(&category) {
+            continue;
+        }
+        if merge_category(object, category).is_some() {
+            merged.push((atom, field));
+        }
+    }
+    // Later entries are removed first so the earlier ones don't move.
+    for &((section, atom), field) in merged.iter().rev() {
+        let atom = &mut object.sections[section].atoms[atom];
+        let address = field as i32;
+        atom.relocations
+            .retain(|relocation| relocation.address != address);
+        for relocation in &mut atom.relocations {
+            if relocation.address > address {
+                relocation.address -= POINTER_SIZE as i32;
+            }
+        }
+        atom.data
+            .drain(field as usize..(field + POINTER_SIZE) as usize);
+        atom.size -= POINTER_SIZE;
+    }
+    merged.len()
+}
+
+/// The pointer fields of the sections named `sectname`, which are
+/// arrays of pointers.
+fn list_entries(object: &InputObject, sectname: &str) -> Vec<(AtomRef, u64)> {
+    let mut entries = vec![];
+    for (section_index, section) in object.sections.iter().enumerate() {
+        if section.sectname != sectname {
+            continue;
+        }
+        for (atom_index, atom) in section.atoms.iter().enumerate() {
+            let fields = (0..atom.size / POINTER_SIZE).map(|i| i * POINTER_SIZE);
+            entries.extend(fields.map(|field| ((section_index, atom_index), field)));
+        }
+    }
+    entries
+}
+
+/// Merge the category at `category` into its class, if the class is
+/// defined in the same object and the metadata is laid out as the
+/// compiler does. Nothing is changed if it can't be merged.
+fn merge_category(object: &mut InputObject, category: Pointer) -> Option<()> {
+    let category = atom_starting_at(object, category, CATEGORY_CLASS_PROPERTIES)?;
+    let class = atom_starting_at(
+        object,
+        pointer(object, category, CATEGORY_CLASS)?,
+        CLASS_RO + 8,
+    )?;
+    let ro = atom_starting_at(object, pointer(object, class, CLASS_RO)?, RO_PROPERTIES + 8)?;
+    let mut lists = vec![
+        (List::Methods, CATEGORY_INSTANCE_METHODS, ro, RO_METHODS),
+        (List::Protocols, CATEGORY_PROTOCOLS, ro, RO_PROTOCOLS),
+        (
+            List::Properties,
+            CATEGORY_INSTANCE_PROPERTIES,
+            ro,
+            RO_PROPERTIES,
+        ),
+    ];
+    let has_class_properties = atom(object, category).size > CATEGORY_CLASS_PROPERTIES;
+    let class_side = [
+        (List::Methods, CATEGORY_CLASS_METHODS, RO_METHODS),
+        (List::Properties, CATEGORY_CLASS_PROPERTIES, RO_PROPERTIES),
+    ];
+    let class_side = &class_side[..if has_class_properties { 2 } else { 1 }];
+    if class_side
+        .iter()
+        .any(|(_, field, _)| pointer(object, category, *field) != Some(Pointer::Null))
+    {
+        let metaclass = atom_starting_at(object, pointer(object, class, CLASS_ISA)?, CLASS_RO + 8)?;
+        let meta_ro = atom_starting_at(
+            object,
+            pointer(object, metaclass, CLASS_RO)?,
+            RO_PROPERTIES + 8,
+        )?;
+        lists.extend(
+            class_side
+                .iter()
+                .map(|(list, field, ro_field)| (*list, *field, meta_ro, *ro_field)),
+        );
+    }
+
+    let mut merges = vec![];
+    for (list, field, ro, ro_field) in lists {
+        let category_list = pointer(object, category, field)?;
+        if category_list == Pointer::Null {
+            continue;
+        }
+        let class_list = pointer(object, ro, ro_field)?;
+        if class_list == Pointer::Null {
+            merges.push(Merge::Adopt {
+                category: (category, field),
+                ro: (ro, ro_field),
+            });
+            continue;
+        }
+        let category_list = atom_starting_at(object, category_list, LIST_HEADER_SIZE)?;
+        let class_list = atom_starting_at(object, class_list, LIST_HEADER_SIZE)?;
+        let (category_size, category_count) = list.header(&atom(object, category_list).data)?;
+        let (class_size, class_count) = list.header(&atom(object, class_list).data)?;
+        if category_list == class_list
+            || category_size != class_size
+            || atom(object, category_list).size != LIST_HEADER_SIZE + category_count * category_size
+            || atom(object, class_list).size != LIST_HEADER_SIZE + class_count * class_size
+        {
+            return None;
+        }
+        merges.push(Merge::Concat {
+            list,
+            category: category_list,
+            class: class_list,
+        });
+    }
+
+    for merge in merges {
+        match merge {
+            Merge::Adopt {
+                category: (category, field),
+                ro: ((section, index), ro_field),
+            } => {
+                let category = atom(object, category);
+                let address = field as i32;
+                let relocation = *category
+                    .relocations
+                    .iter()
+                    .find(|relocation| relocation.address == address)?;
+                let bytes = category.data[field as usize..(field + POINTER_SIZE) as usize].to_vec();
+                let ro = &mut object.sections[section].atoms[index];
+                ro.data[ro_field as usize..(ro_field + POINTER_SIZE) as usize]
+                    .copy_from_slice(&bytes);
+                ro.relocations.push(reloc::Relocation {
+                    address: ro_field as i32,
+                    ..relocation
+                });
+            }
+            Merge::Concat {
+                list,
+                category,
+                class: (section, index),
+            } => {
+                let category = clear(object, category);
+                let entries = category.data.len() as u64 - LIST_HEADER_SIZE;
+                let class = &mut object.sections[section].atoms[index];
+                let (_, class_count) = list.header(&class.data)?;
+                let (_, category_count) = list.header(&category.data)?;
+                let mut data = class.data[..LIST_HEADER_SIZE as usize].to_vec();
+                list.set_count(&mut data, class_count + category_count);
+                data.extend_from_slice(&category.data[LIST_HEADER_SIZE as usize..]);
+                data.extend_from_slice(&class.data[LIST_HEADER_SIZE as usize..]);
+                for relocation in &mut class.relocations {
+                    relocation.address += entries as i32;
+                }
+                class.relocations.extend(category.relocations);
+                class.size = data.len() as u64;
+                class.data = data;
+            }
+        }
+    }
+    clear(object, category);
+    Some(())
+}
+
+fn atom<'o>(object: &'o InputObject, (section, index): AtomRef) -> &'o Atom {
+    &object.sections[section].atoms[index]
+}
+
+/// Empty an atom whose contents have been merged into another,
+/// returning what it held.
+fn clear(object: &mut InputObject, (section, index): AtomRef) -> Atom {
+    let atom = &mut object.sections[section].atoms[index];
+    Atom {
+        offset: atom.offset,
+        size: std::mem::take(&mut atom.size),
+        align: atom.align,
+        symbol: atom.symbol,
+        data: std::mem::take(&mut atom.data),
+        relocations: std::mem::take(&mut atom.relocations),
+        output: None,
+    }
+}
+
+/// The atom starting exactly where `pointer` points that's at least
+/// `size` bytes long.
+fn atom_starting_at(object: &InputObject, pointer: Pointer, size: u64) -> Option<AtomRef> {
+    let Pointer::To(section, offset) = pointer else {
+        return None;
+    };
+    let index = object.sections.get(section)?.atom_index(offset)?;
+    let atom = atom(object, (section, index));
+    (atom.offset == offset && atom.size >= size && atom.data.len() as u64 == atom.size)
+        .then_some((section, index))
+}
+
+/// Where the pointer at `field` in `atom` points, or None if that can't
+/// be told from its relocation.
+fn pointer(object: &InputObject, atom_ref: AtomRef, field: u64) -> Option<Pointer> {
+    let atom = atom(object, atom_ref);
+    if field + POINTER_SIZE > atom.size {
+        return None;
+    }
+    let address = field as i32;
+    let Some(relocation) = atom
+        .relocations
+        .iter()
+        .find(|relocation| relocation.address == address)
+    else {
+        let inline = reloc::read_field(&atom.data, field as usize, 3);
+        return (inline == 0).then_some(Pointer::Null);
+    };
+    if relocation.kind != Kind::Unsigned || relocation.length != 3 {
+        return None;
+    }
+    let inline = reloc::read_field(&atom.data, field as usize, 3);
+    match relocation.target {
+        Target::Symbol(index) => {
+            // Undefined for a class defined elsewhere.
+            let (_, nlist) = object.symbols.get(index)?;
+            let offset = nlist
+                .n_value
+                .checked_sub(object.symbol_section(nlist)?.addr)?;
+            Some(Pointer::To(nlist.n_sect - 1, offset.wrapping_add(inline)))
+        }
+        Target::Section(ordinal) => {
+            let offset = inline.checked_sub(object.section(ordinal)?.addr)?;
+            Some(Pointer::To(ordinal - 1, offset))
+        }
+    }
+}
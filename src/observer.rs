@@ -0,0 +1,42 @@
+/// Hooks into symbol resolution, for tools built on top of the linker
+/// such as dependency analyzers, which would otherwise have to parse its
+/// logs.
+use std::{fmt, path::Path};
+
+/// What a symbol was resolved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Definition<'a> {
+    /// A definition in the object with this name. Archive members are
+    /// named `archive(member)`.
+    Object(&'a str),
+    /// An export of the dylib with this install name.
+    Dylib(&'a str),
+    /// Space the linker allocated for a common symbol.
+    Common,
+    /// An absolute value given with --defsym.
+    Absolute(u64),
+}
+
+/// Told about each event while linking. Every method does nothing by
+/// default, so only the events of interest need implementing.
+pub trait Observer {
+    /// `path` was read as an input.
+    fn on_file_loaded(&mut self, _path: &Path) {}
+
+    /// The object `member` of `archive` was loaded into the link.
+    fn on_member_pulled(&mut self, _archive: &Path, _member: &str) {}
+
+    /// `name` was resolved to `definition`. Called once for each symbol,
+    /// in no particular order, once every input has been read.
+    fn on_symbol_resolved(&mut self, _name: &str, _definition: Definition) {}
+
+    /// Nothing defines `name`, which fails the link.
+    fn on_symbol_undefined(&mut self, _name: &str) {}
+
+    /// Something happened to `name`, which -trace_symbol asked about,
+    /// such as a reference or definition in an input.
+    fn on_symbol_traced(&mut self, _name: &str, _event: fmt::Arguments) {}
+}
+
+/// Observes nothing.
+impl Observer for () {}
@@ -0,0 +1,1188 @@
+/// Serialisation of the output Mach-O image.
+use std::{fs::File, io, os::unix::fs::FileExt};
+
+use goblin::mach::{
+    constants::{SECTION_TYPE, S_ATTR_PURE_INSTRUCTIONS, S_ATTR_SOME_INSTRUCTIONS},
+    constants::{S_GB_ZEROFILL, S_THREAD_LOCAL_VARIABLES, S_THREAD_LOCAL_ZEROFILL, S_ZEROFILL},
+    constants::{VM_PROT_EXECUTE, VM_PROT_READ, VM_PROT_WRITE},
+    header::{MH_EXECUTE, MH_MAGIC_64, MH_PRELOAD, SIZEOF_HEADER_64},
+    load_command::{
+        LC_BUILD_VERSION, LC_DYLD_INFO_ONLY, LC_DYSYMTAB, LC_ENCRYPTION_INFO_64, LC_LOAD_DYLINKER,
+        LC_RPATH, LC_SEGMENT_64, LC_SYMTAB, LC_UNIXTHREAD, LC_UUID, LC_VERSION_MIN_IPHONEOS,
+        LC_VERSION_MIN_MACOSX, LC_VERSION_MIN_TVOS, LC_VERSION_MIN_WATCHOS, TOOL_LD,
+    },
+    symbols::{
+        N_ABS, N_AST, N_EXT, N_NO_DEAD_STRIP, N_TYPE, N_UNDF, N_WEAK_DEF, N_WEAK_REF,
+        REFERENCED_DYNAMICALLY,
+    },
+};
+
+use crate::{
+    export_trie::{self, Export},
+    linker_args::{Architecture, Platform, PlatformVersion, Version},
+    strtab::StringTable,
+    threads::ThreadPool,
+};
+
+/// Load commands are padded to a multiple of 8 bytes in 64-bit images.
+const LOAD_COMMAND_ALIGNMENT: usize = 8;
+
+/// Size of the __PAGEZERO segment mapped at address 0 in executables.
+const PAGEZERO_SIZE: u64 = 0x1_0000_0000;
+
+/// Preload images aren't mapped a page at a time, so their segments are
+/// only aligned enough for the sections in them.
+const PRELOAD_SEGMENT_ALIGNMENT: u64 = 16;
+
+/// Size of dylib_command, which is followed by the library's name.
+const DYLIB_COMMAND_SIZE: u32 = 24;
+
+/// ld64 always records this timestamp for dylibs.
+const DYLIB_TIMESTAMP: u32 = 2;
+
+/// Size of an nlist_64 entry.
+const NLIST_64_SIZE: usize = 16;
+
+/// ARM_THREAD_STATE64, and its size in u32s: x0-x28, fp, lr, sp and pc,
+/// then cpsr and padding.
+const ARM_THREAD_STATE64: u32 = 6;
+const ARM_THREAD_STATE64_COUNT: u32 = 68;
+
+/// dyld makes the segment read-only once fixups have been applied.
+pub const SG_READ_ONLY: u32 = 0x10;
+
+/// The order segments are laid out in. Anything else goes after
+/// __DATA.
+const SEGMENT_ORDER: &[&str] = &[
+    "__PAGEZERO",
+    "__TEXT",
+    "__DATA_CONST",
+    "__DATA_DIRTY",
+    "__DATA",
+];
+
+/// Sections that are zerofill wherever they're created, so have to come
+/// after the others in their segment.
+const ZEROFILL_SECTIONS: &[&str] = &["__bss", "__common", "__thread_bss"];
+
+/// Check the segments of a -segment_order can be laid out in that order.
+/// __TEXT holds the header so has to start the image, after __PAGEZERO,
+/// and __LINKEDIT has to end it.
+pub fn check_segment_order(segments: &[String]) -> Result<(), String> {
+    let text = usize::from(segments.first().is_some_and(|name| name == "__PAGEZERO"));
+    for (index, name) in segments.iter().enumerate() {
+        let problem = match &name[..] {
+            "__PAGEZERO" if index != 0 => "has to be the first segment",
+            "__TEXT" if index != text => "has to be the first segment after __PAGEZERO",
+            "__LINKEDIT" if index != segments.len() - 1 => "has to be the last segment",
+            _ => continue,
+        };
+        return Err(format!("Invalid -segment_order, {name} {problem}"));
+    }
+    Ok(())
+}
+
+/// Check the sections of a -section_order for `segment` can be laid out
+/// in that order. __text has to start __TEXT, and zerofill sections come
+/// after the rest so they take no space in the file.
+pub fn check_section_order(segment: &str, sections: &[String]) -> Result<(), String> {
+    if segment == "__TEXT" && sections.iter().position(|name| name == "__text") > Some(0) {
+        return Err("Invalid -section_order, __text has to be first in __TEXT".to_string());
+    }
+    let zerofill = |name: &String| ZEROFILL_SECTIONS.contains(&&name[..]);
+    if let Some(first) = sections.iter().position(zerofill) {
+        if let Some(name) = sections[first..].iter().find(|name| !zerofill(name)) {
+            return Err(format!(
+                "Invalid -section_order, {} is zerofill so has to come after {name} in {segment}",
+                sections[first]
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub sectname: String,
+    pub segname: String,
+    pub addr: u64,
+    pub size: u64,
+    pub offset: u32,
+    /// Alignment as a power of two.
+    pub align: u32,
+    pub flags: u32,
+    pub data: Vec<u8>,
+}
+
+impl Section {
+    pub fn new(segname: &str, sectname: &str, align: u32, flags: u32) -> Self {
+        Self {
+            sectname: sectname.to_string(),
+            segname: segname.to_string(),
+            addr: 0,
+            size: 0,
+            offset: 0,
+            align,
+            flags,
+            data: vec![],
+        }
+    }
+
+    pub fn is_zerofill(&self) -> bool {
+        is_zerofill(self.flags)
+    }
+
+    /// Append `size` bytes of `data` (nothing for zerofill sections)
+    /// aligned to `1 << align`, returning the offset it was placed at.
+    pub fn append(&mut self, data: &[u8], size: u64, align: u32) -> u64 {
+        let offset = self.reserve(size, align);
+        if !self.is_zerofill() {
+            self.data.resize(offset as usize, 0);
+            self.data.extend_from_slice(data);
+            self.data.resize((offset + size) as usize, 0);
+        }
+        offset
+    }
+
+    /// Make room for `size` bytes aligned to `1 << align` without
+    /// filling them in, returning the offset they're at. The data is
+    /// only as long as the section once resized to its size.
+    pub fn reserve(&mut self, size: u64, align: u32) -> u64 {
+        self.align = self.align.max(align);
+        let offset = self.size.next_multiple_of(1 << align);
+        self.size = offset + size;
+        offset
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub name: String,
+    pub vmaddr: u64,
+    pub vmsize: u64,
+    pub fileoff: u64,
+    pub filesize: u64,
+    pub maxprot: u32,
+    pub initprot: u32,
+    pub flags: u32,
+    pub sections: Vec<Section>,
+}
+
+impl Segment {
+    pub fn new(name: &str, prot: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            vmaddr: 0,
+            vmsize: 0,
+            fileoff: 0,
+            filesize: 0,
+            maxprot: prot,
+            initprot: prot,
+            flags: 0,
+            sections: vec![],
+        }
+    }
+
+    pub fn section_mut(&mut self, sectname: &str) -> Option<&mut Section> {
+        self.sections.iter_mut().find(|s| s.sectname == sectname)
+    }
+}
+
+/// An entry in the output symbol table.
+#[derive(Debug, Clone)]
+pub struct OutputSymbol {
+    pub name: String,
+    pub n_type: u8,
+    /// The output section (segment and section index) the symbol is
+    /// defined in, if any.
+    pub section: Option<(usize, usize)>,
+    pub n_desc: u16,
+    pub n_value: u64,
+}
+
+impl OutputSymbol {
+    /// A stab pointing the debugger at a Swift module's AST, from
+    /// -add_ast_path.
+    pub fn ast(path: &str) -> Self {
+        Self {
+            name: path.to_string(),
+            n_type: N_AST,
+            section: None,
+            n_desc: 0,
+            n_value: 0,
+        }
+    }
+
+    /// The n_desc of a definition, keeping the flags of the input's
+    /// n_desc that still mean something once linked.
+    pub fn defined_desc(n_desc: u16) -> u16 {
+        n_desc & (N_NO_DEAD_STRIP | N_WEAK_DEF | REFERENCED_DYNAMICALLY)
+    }
+
+    /// The n_desc of an import from the dylib with (1-based) `ordinal`.
+    pub fn undefined_desc(ordinal: u16, weak: bool) -> u16 {
+        let weak = if weak { N_WEAK_REF } else { 0 };
+        (ordinal << 8) | weak
+    }
+
+    fn is_local(&self) -> bool {
+        self.n_type & N_EXT == 0
+    }
+
+    fn is_undefined(&self) -> bool {
+        self.n_type & N_TYPE == N_UNDF
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LoadCommand {
+    BuildVersion {
+        platform: Platform,
+        min_version: Version,
+        sdk_version: Version,
+    },
+    VersionMin {
+        cmd: u32,
+        version: Version,
+        sdk_version: Version,
+    },
+    Segment64(Segment),
+    EncryptionInfo64 {
+        cryptoff: u32,
+        cryptsize: u32,
+        cryptid: u32,
+    },
+    /// LC_LOAD_DYLIB and friends.
+    Dylib {
+        cmd: u32,
+        name: String,
+        current_version: Version,
+        compatibility_version: Version,
+    },
+    /// LC_DYLD_INFO_ONLY, only locating the export trie.
+    DyldInfoOnly {
+        export_off: u32,
+        export_size: u32,
+    },
+    /// LC_RPATH, a directory searched for @rpath install names.
+    Rpath {
+        path: String,
+    },
+    /// LC_LOAD_DYLINKER, the dynamic linker the kernel loads to start an
+    /// executable.
+    Dylinker {
+        path: String,
+    },
+    Symtab {
+        symoff: u32,
+        nsyms: u32,
+        stroff: u32,
+        strsize: u32,
+    },
+    /// LC_DYSYMTAB, only describing how the symbol table is grouped.
+    Dysymtab {
+        nlocalsym: u32,
+        nextdefsym: u32,
+        nundefsym: u32,
+    },
+    /// LC_UUID. The UUID is a hash of the output, filled in by
+    /// [MachOWriter::write].
+    Uuid([u8; 16]),
+    /// LC_UNIXTHREAD with an arm64 thread state that only sets the pc.
+    UnixThread {
+        pc: u64,
+    },
+}
+
+impl LoadCommand {
+    /// Pick the version command dyld on the target will understand.
+    pub fn for_platform_version(platform_version: &PlatformVersion) -> Self {
+        if platform_version.supports_build_version() {
+            return LoadCommand::BuildVersion {
+                platform: platform_version.platform,
+                min_version: platform_version.min_version,
+                sdk_version: platform_version.sdk_version,
+            };
+        }
+        // Simulators share the device's load command, dyld tells them
+        // apart by the architecture.
+        let cmd = match platform_version.platform.device() {
+            Platform::MacOS => LC_VERSION_MIN_MACOSX,
+            Platform::IOS => LC_VERSION_MIN_IPHONEOS,
+            Platform::TvOS => LC_VERSION_MIN_TVOS,
+            Platform::WatchOS => LC_VERSION_MIN_WATCHOS,
+            // These only exist in a post LC_BUILD_VERSION world.
+            _ => unreachable!(),
+        };
+        LoadCommand::VersionMin {
+            cmd,
+            version: platform_version.min_version,
+            sdk_version: platform_version.sdk_version,
+        }
+    }
+
+    pub fn cmd(&self) -> u32 {
+        match self {
+            LoadCommand::BuildVersion { .. } => LC_BUILD_VERSION,
+            LoadCommand::VersionMin { cmd, .. } => *cmd,
+            LoadCommand::Segment64(_) => LC_SEGMENT_64,
+            LoadCommand::EncryptionInfo64 { .. } => LC_ENCRYPTION_INFO_64,
+            LoadCommand::Dylib { cmd, .. } => *cmd,
+            LoadCommand::DyldInfoOnly { .. } => LC_DYLD_INFO_ONLY,
+            LoadCommand::Rpath { .. } => LC_RPATH,
+            LoadCommand::Dylinker { .. } => LC_LOAD_DYLINKER,
+            LoadCommand::Symtab { .. } => LC_SYMTAB,
+            LoadCommand::Dysymtab { .. } => LC_DYSYMTAB,
+            LoadCommand::Uuid(_) => LC_UUID,
+            LoadCommand::UnixThread { .. } => LC_UNIXTHREAD,
+        }
+    }
+
+    /// The body of the command, i.e. everything after `cmd` and
+    /// `cmdsize`.
+    fn payload(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        match self {
+            LoadCommand::BuildVersion {
+                platform,
+                min_version,
+                sdk_version,
+            } => {
+                push_u32(&mut buf, platform.to_u32());
+                push_u32(&mut buf, min_version.to_u32());
+                push_u32(&mut buf, sdk_version.to_u32());
+                // ntools, followed by a single entry describing us.
+                push_u32(&mut buf, 1);
+                push_u32(&mut buf, TOOL_LD);
+                push_u32(&mut buf, linker_version().to_u32());
+            }
+            LoadCommand::VersionMin {
+                version,
+                sdk_version,
+                ..
+            } => {
+                push_u32(&mut buf, version.to_u32());
+                push_u32(&mut buf, sdk_version.to_u32());
+            }
+            LoadCommand::Segment64(segment) => {
+                push_name(&mut buf, &segment.name);
+                push_u64(&mut buf, segment.vmaddr);
+                push_u64(&mut buf, segment.vmsize);
+                push_u64(&mut buf, segment.fileoff);
+                push_u64(&mut buf, segment.filesize);
+                push_u32(&mut buf, segment.maxprot);
+                push_u32(&mut buf, segment.initprot);
+                push_u32(&mut buf, segment.sections.len() as u32);
+                push_u32(&mut buf, segment.flags);
+                for section in &segment.sections {
+                    push_name(&mut buf, &section.sectname);
+                    push_name(&mut buf, &section.segname);
+                    push_u64(&mut buf, section.addr);
+                    push_u64(&mut buf, section.size);
+                    push_u32(&mut buf, section.offset);
+                    push_u32(&mut buf, section.align);
+                    // reloff and nreloc, final images have no
+                    // section relocations.
+                    push_u32(&mut buf, 0);
+                    push_u32(&mut buf, 0);
+                    push_u32(&mut buf, section.flags);
+                    // reserved1, reserved2 and reserved3
+                    push_u32(&mut buf, 0);
+                    push_u32(&mut buf, 0);
+                    push_u32(&mut buf, 0);
+                }
+            }
+            LoadCommand::EncryptionInfo64 {
+                cryptoff,
+                cryptsize,
+                cryptid,
+            } => {
+                push_u32(&mut buf, *cryptoff);
+                push_u32(&mut buf, *cryptsize);
+                push_u32(&mut buf, *cryptid);
+                // pad
+                push_u32(&mut buf, 0);
+            }
+            LoadCommand::Dylib {
+                name,
+                current_version,
+                compatibility_version,
+                ..
+            } => {
+                // The name is stored after the fixed part of the
+                // command, with the offset from the start of the
+                // command.
+                push_u32(&mut buf, DYLIB_COMMAND_SIZE);
+                // timestamp, nothing checks it.
+                push_u32(&mut buf, DYLIB_TIMESTAMP);
+                push_u32(&mut buf, current_version.to_u32());
+                push_u32(&mut buf, compatibility_version.to_u32());
+                buf.extend_from_slice(name.as_bytes());
+                buf.push(0);
+            }
+            LoadCommand::DyldInfoOnly {
+                export_off,
+                export_size,
+            } => {
+                // Rebase, bind, weak bind and lazy bind information.
+                for _ in 0..8 {
+                    push_u32(&mut buf, 0);
+                }
+                push_u32(&mut buf, *export_off);
+                push_u32(&mut buf, *export_size);
+            }
+            LoadCommand::Rpath { path } | LoadCommand::Dylinker { path } => {
+                // Offset of the path from the start of the command.
+                push_u32(&mut buf, 12);
+                buf.extend_from_slice(path.as_bytes());
+                buf.push(0);
+            }
+            LoadCommand::Symtab {
+                symoff,
+                nsyms,
+                stroff,
+                strsize,
+            } => {
+                push_u32(&mut buf, *symoff);
+                push_u32(&mut buf, *nsyms);
+                push_u32(&mut buf, *stroff);
+                push_u32(&mut buf, *strsize);
+            }
+            LoadCommand::Dysymtab {
+                nlocalsym,
+                nextdefsym,
+                nundefsym,
+            } => {
+                // The symbol table is sorted into locals, then external
+                // definitions, then undefined symbols.
+                push_u32(&mut buf, 0);
+                push_u32(&mut buf, *nlocalsym);
+                push_u32(&mut buf, *nlocalsym);
+                push_u32(&mut buf, *nextdefsym);
+                push_u32(&mut buf, nlocalsym + nextdefsym);
+                push_u32(&mut buf, *nundefsym);
+                // The table of contents, module table, referenced
+                // symbols, indirect symbols and relocations, none of
+                // which are used in final images.
+                for _ in 0..12 {
+                    push_u32(&mut buf, 0);
+                }
+            }
+            LoadCommand::Uuid(uuid) => buf.extend_from_slice(uuid),
+            LoadCommand::UnixThread { pc } => {
+                push_u32(&mut buf, ARM_THREAD_STATE64);
+                push_u32(&mut buf, ARM_THREAD_STATE64_COUNT);
+                // x0-x28, fp, lr and sp
+                for _ in 0..32 {
+                    push_u64(&mut buf, 0);
+                }
+                push_u64(&mut buf, *pc);
+                // cpsr and padding
+                push_u32(&mut buf, 0);
+                push_u32(&mut buf, 0);
+            }
+        }
+        buf
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let payload = self.payload();
+        let size = (8 + payload.len()).next_multiple_of(LOAD_COMMAND_ALIGNMENT);
+        let mut buf = Vec::with_capacity(size);
+        push_u32(&mut buf, self.cmd());
+        push_u32(&mut buf, size as u32);
+        buf.extend_from_slice(&payload);
+        buf.resize(size, 0);
+        buf
+    }
+}
+
+#[derive(Debug)]
+pub struct MachOWriter {
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    pub filetype: u32,
+    pub flags: u32,
+    pub page_size: u64,
+    pub segments: Vec<Segment>,
+    pub load_commands: Vec<LoadCommand>,
+    /// Reserve page aligned padding after the load commands and
+    /// describe the rest of __TEXT with LC_ENCRYPTION_INFO_64 so it
+    /// can be encrypted after the fact (App Store processing).
+    pub encryptable: bool,
+    /// Start the executable at this address with LC_UNIXTHREAD, for
+    /// executables that aren't loaded by dyld. The command's size
+    /// doesn't depend on the address, so it can be filled in after
+    /// laying out.
+    pub unix_thread: Option<u64>,
+    /// Segments placed at a given address rather than straight after
+    /// the previous segment, from -segaddr.
+    pub segment_addresses: Vec<(String, u64)>,
+    /// Segments to place in this order, from -segment_order. Those not
+    /// listed follow them, in [SEGMENT_ORDER].
+    pub segment_order: Vec<String>,
+    /// Sections of a segment to place in this order, from
+    /// -section_order. Those not listed follow them, in the order
+    /// they're created.
+    pub section_order: Vec<(String, Vec<String>)>,
+    /// Record a UUID for debuggers and crash reporters to match the
+    /// output with its symbols.
+    pub uuid: bool,
+    /// Make the UUID depend only on the output's contents, rather than
+    /// also on when it was linked, so linking the same inputs twice
+    /// gives byte identical outputs.
+    pub reproducible: bool,
+    /// Write an export trie of the external definitions so they can be
+    /// found with dlsym, from -export_dynamic.
+    pub export_trie: bool,
+    pub symbols: Vec<OutputSymbol>,
+    /// Built from `symbols` by [MachOWriter::layout].
+    strtab: StringTable,
+    /// The export trie, built from `symbols` by [MachOWriter::layout]
+    /// once they have their addresses.
+    exports: Vec<u8>,
+}
+
+impl MachOWriter {
+    pub fn new(arch: &Architecture, filetype: u32) -> Self {
+        let mut segments = vec![];
+        if filetype == MH_EXECUTE {
+            let mut pagezero = Segment::new("__PAGEZERO", 0);
+            pagezero.vmsize = PAGEZERO_SIZE;
+            segments.push(pagezero);
+        }
+        let mut text = Segment::new("__TEXT", VM_PROT_READ | VM_PROT_EXECUTE);
+        text.sections.push(Section::new(
+            "__TEXT",
+            "__text",
+            2,
+            S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS,
+        ));
+        segments.push(text);
+        segments.push(Segment::new("__LINKEDIT", VM_PROT_READ));
+        Self {
+            cputype: arch.cpu_type(),
+            cpusubtype: arch.cpu_subtype(),
+            filetype,
+            flags: 0,
+            page_size: if filetype == MH_PRELOAD {
+                PRELOAD_SEGMENT_ALIGNMENT
+            } else {
+                arch.page_size()
+            },
+            segments,
+            load_commands: vec![],
+            encryptable: false,
+            unix_thread: None,
+            segment_addresses: vec![],
+            segment_order: vec![],
+            section_order: vec![],
+            uuid: true,
+            reproducible: false,
+            export_trie: false,
+            exports: vec![],
+            symbols: vec![],
+            strtab: StringTable::default(),
+        }
+    }
+
+    pub fn push_load_command(&mut self, command: LoadCommand) {
+        self.load_commands.push(command);
+    }
+
+    pub fn segment_mut(&mut self, name: &str) -> Option<&mut Segment> {
+        self.segments.iter_mut().find(|s| s.name == name)
+    }
+
+    /// Find or create a segment with the given protection, returning
+    /// its index. New segments are inserted following `segment_order`,
+    /// then [SEGMENT_ORDER].
+    pub fn add_segment(&mut self, segname: &str, prot: u32) -> usize {
+        if let Some(index) = self.segments.iter().position(|s| s.name == segname) {
+            return index;
+        }
+        let rank = |name: &str| {
+            let fixed = match name {
+                "__PAGEZERO" => 0,
+                "__TEXT" => 1,
+                "__LINKEDIT" => 3,
+                _ => 2,
+            };
+            let ordered = self
+                .segment_order
+                .iter()
+                .position(|ordered| ordered == name)
+                .unwrap_or(self.segment_order.len());
+            let known = SEGMENT_ORDER
+                .iter()
+                .position(|known| *known == name)
+                .unwrap_or(SEGMENT_ORDER.len());
+            (fixed, ordered, known)
+        };
+        let index = self
+            .segments
+            .iter()
+            .position(|s| rank(&s.name) > rank(segname))
+            .unwrap_or(self.segments.len());
+        let mut segment = Segment::new(segname, prot);
+        if segname == "__DATA_CONST" {
+            segment.flags |= SG_READ_ONLY;
+        }
+        self.segments.insert(index, segment);
+        index
+    }
+
+    /// Find or create the output section, returning its segment and
+    /// section indexes.
+    ///
+    /// Zerofill sections are kept at the end of their segment so they
+    /// take no space in the file, otherwise sections are placed
+    /// following `section_order`. That means creating a section can move
+    /// the zerofill sections after it, so all the sections should be
+    /// created before holding on to any indexes.
+    pub fn section_index(&mut self, segname: &str, sectname: &str, flags: u32) -> (usize, usize) {
+        let segment_index = self.add_segment(segname, segment_protection(segname));
+        let order: &[String] = self
+            .section_order
+            .iter()
+            .find(|(name, _)| name == segname)
+            .map_or(&[], |(_, order)| order);
+        let rank = |name: &str, zerofill: bool| {
+            let text = segname == "__TEXT" && name == "__text";
+            let ordered = order
+                .iter()
+                .position(|ordered| ordered == name)
+                .unwrap_or(order.len());
+            (zerofill, !text, ordered)
+        };
+        let segment = &mut self.segments[segment_index];
+        let section_index = match segment.sections.iter().position(|s| s.sectname == sectname) {
+            Some(index) => index,
+            None => {
+                let new = rank(sectname, is_zerofill(flags));
+                let index = segment
+                    .sections
+                    .iter()
+                    .position(|s| rank(&s.sectname, s.is_zerofill()) > new)
+                    .unwrap_or(segment.sections.len());
+                segment
+                    .sections
+                    .insert(index, Section::new(segname, sectname, 0, flags));
+                index
+            }
+        };
+        (segment_index, section_index)
+    }
+
+    pub fn section(&self, (segment, section): (usize, usize)) -> &Section {
+        &self.segments[segment].sections[section]
+    }
+
+    pub fn section_at_mut(&mut self, (segment, section): (usize, usize)) -> &mut Section {
+        &mut self.segments[segment].sections[section]
+    }
+
+    /// All the load commands in the order they'll be written. Segments
+    /// always come first.
+    fn commands(&self) -> Vec<LoadCommand> {
+        let mut commands: Vec<LoadCommand> = self
+            .segments
+            .iter()
+            .cloned()
+            .map(LoadCommand::Segment64)
+            .collect();
+        let (locals, extdefs, undefs) = self.symbol_groups();
+        // The export trie comes first in __LINKEDIT.
+        if self.export_trie {
+            commands.push(LoadCommand::DyldInfoOnly {
+                export_off: self.linkedit_offset() as u32,
+                export_size: self.exports.len() as u32,
+            });
+        }
+        let symoff = self.linkedit_offset() + self.exports.len();
+        let nsyms = self.symbols.len();
+        commands.push(LoadCommand::Symtab {
+            symoff: symoff as u32,
+            nsyms: nsyms as u32,
+            stroff: (symoff + nsyms * NLIST_64_SIZE) as u32,
+            strsize: self.strtab.data().len() as u32,
+        });
+        if self.uuid {
+            commands.push(LoadCommand::Uuid([0; 16]));
+        }
+        // Preload images have no dynamic linking information.
+        if self.filetype != MH_PRELOAD {
+            commands.push(LoadCommand::Dysymtab {
+                nlocalsym: locals.len() as u32,
+                nextdefsym: extdefs.len() as u32,
+                nundefsym: undefs.len() as u32,
+            });
+        }
+        commands.extend(self.load_commands.iter().cloned());
+        if let Some(pc) = self.unix_thread {
+            commands.push(LoadCommand::UnixThread { pc });
+        }
+        if self.encryptable {
+            let text = self.segments.iter().find(|s| s.name == "__TEXT");
+            // The encrypted range starts after the (page aligned) load
+            // commands and covers the remainder of __TEXT. cryptid 0
+            // means it isn't encrypted yet.
+            let cryptoff = text
+                .and_then(|text| text.sections.first())
+                .map_or(0, |section| section.offset);
+            let cryptsize = text
+                .map_or(0, |text| text.filesize as u32)
+                .saturating_sub(cryptoff);
+            commands.push(LoadCommand::EncryptionInfo64 {
+                cryptoff,
+                cryptsize,
+                cryptid: 0,
+            });
+        }
+        commands
+    }
+
+    /// The symbols split into locals, external definitions and
+    /// undefined symbols, the order they're written in. Externals are
+    /// sorted by name, locals keep the order they were given in.
+    fn symbol_groups(&self) -> (Vec<&OutputSymbol>, Vec<&OutputSymbol>, Vec<&OutputSymbol>) {
+        let mut locals = vec![];
+        let mut extdefs = vec![];
+        let mut undefs = vec![];
+        for symbol in &self.symbols {
+            if symbol.is_local() {
+                locals.push(symbol);
+            } else if symbol.is_undefined() {
+                undefs.push(symbol);
+            } else {
+                extdefs.push(symbol);
+            }
+        }
+        extdefs.sort_by(|a, b| a.name.cmp(&b.name));
+        undefs.sort_by(|a, b| a.name.cmp(&b.name));
+        (locals, extdefs, undefs)
+    }
+
+    fn linkedit_offset(&self) -> usize {
+        self.segments
+            .iter()
+            .find(|segment| segment.name == "__LINKEDIT")
+            .map_or(0, |segment| segment.fileoff as usize)
+    }
+
+    fn linkedit_size(&self) -> u64 {
+        (self.exports.len() + self.symbols.len() * NLIST_64_SIZE + self.strtab.data().len()) as u64
+    }
+
+    /// The export trie of the external definitions, with addresses
+    /// relative to the header at the start of __TEXT.
+    fn export_trie(&self) -> Vec<u8> {
+        let base = self
+            .segments
+            .iter()
+            .find(|segment| segment.name == "__TEXT")
+            .map_or(0, |segment| segment.vmaddr);
+        let (_, extdefs, _) = self.symbol_groups();
+        let exports: Vec<Export> = extdefs
+            .into_iter()
+            .map(|symbol| {
+                let weak = if symbol.n_desc & N_WEAK_DEF != 0 {
+                    export_trie::EXPORT_SYMBOL_FLAGS_WEAK_DEFINITION
+                } else {
+                    0
+                };
+                let thread_local = symbol.section.is_some_and(|section| {
+                    self.section(section).flags & SECTION_TYPE == S_THREAD_LOCAL_VARIABLES
+                });
+                let (kind, address) = if symbol.n_type & N_TYPE == N_ABS {
+                    (
+                        export_trie::EXPORT_SYMBOL_FLAGS_KIND_ABSOLUTE,
+                        symbol.n_value,
+                    )
+                } else if thread_local {
+                    (
+                        export_trie::EXPORT_SYMBOL_FLAGS_KIND_THREAD_LOCAL,
+                        symbol.n_value - base,
+                    )
+                } else {
+                    (
+                        export_trie::EXPORT_SYMBOL_FLAGS_KIND_REGULAR,
+                        symbol.n_value - base,
+                    )
+                };
+                Export {
+                    name: &symbol.name,
+                    flags: kind | weak,
+                    address,
+                }
+            })
+            .collect();
+        export_trie::build(&exports)
+    }
+
+    /// The export trie, symbol table and string table. It's an error
+    /// for a symbol to be in a section past the 255th, which n_sect
+    /// can't number.
+    fn linkedit(&self) -> Result<Vec<u8>, String> {
+        // Section ordinals count sections across all segments, from 1.
+        let mut ordinals = std::collections::HashMap::new();
+        for (segment_index, segment) in self.segments.iter().enumerate() {
+            for section_index in 0..segment.sections.len() {
+                let ordinal = ordinals.len() + 1;
+                ordinals.insert((segment_index, section_index), ordinal);
+            }
+        }
+        let n_sect = |symbol: &OutputSymbol| {
+            let Some(section) = symbol.section else {
+                return Ok(0);
+            };
+            let ordinal = ordinals[&section];
+            u8::try_from(ordinal).map_err(|_| {
+                let section = self.section(section);
+                format!(
+                    "{} is in {},{}, section {ordinal}, but a symbol can only be in one of the first 255 sections",
+                    symbol.name, section.segname, section.sectname
+                )
+            })
+        };
+        let (locals, extdefs, undefs) = self.symbol_groups();
+        let mut buf = self.exports.clone();
+        for symbol in locals.into_iter().chain(extdefs).chain(undefs) {
+            push_u32(&mut buf, self.strtab.offset(&symbol.name));
+            buf.push(symbol.n_type);
+            buf.push(n_sect(symbol)?);
+            buf.extend_from_slice(&symbol.n_desc.to_le_bytes());
+            push_u64(&mut buf, symbol.n_value);
+        }
+        buf.extend_from_slice(self.strtab.data());
+        Ok(buf)
+    }
+
+    fn sizeofcmds(&self) -> usize {
+        // Command sizes don't depend on the layout so we can work out
+        // how big the header will be before assigning addresses.
+        self.commands()
+            .iter()
+            .map(|command| command.to_bytes().len())
+            .sum()
+    }
+
+    /// Assign addresses and file offsets to all segments and
+    /// sections. __TEXT starts at file offset 0 so it contains the
+    /// header and load commands.
+    ///
+    /// This needs to be called again if any sections or load commands
+    /// are added.
+    pub fn layout(&mut self) {
+        self.strtab = StringTable::new(self.symbols.iter().map(|symbol| &symbol.name[..]));
+        let linkedit_size = self.linkedit_size();
+        let header_size = (SIZEOF_HEADER_64 + self.sizeofcmds()) as u64;
+        let page_size = self.page_size;
+        let mut vmaddr = 0;
+        let mut fileoff = 0;
+        for segment in &mut self.segments {
+            if segment.name == "__PAGEZERO" {
+                vmaddr = segment.vmsize;
+                continue;
+            }
+            if let Some((_, address)) = self
+                .segment_addresses
+                .iter()
+                .find(|(name, _)| *name == segment.name)
+            {
+                vmaddr = *address;
+            }
+            segment.vmaddr = vmaddr;
+            segment.fileoff = fileoff;
+            let mut offset = if segment.name == "__TEXT" {
+                header_size
+            } else {
+                0
+            };
+            if segment.name == "__TEXT" && self.encryptable {
+                offset = offset.next_multiple_of(page_size);
+            }
+            let mut file_end = offset;
+            if segment.name == "__LINKEDIT" {
+                offset = linkedit_size;
+                file_end = linkedit_size;
+            }
+            for section in &mut segment.sections {
+                offset = offset.next_multiple_of(1 << section.align);
+                section.addr = segment.vmaddr + offset;
+                if section.is_zerofill() {
+                    section.offset = 0;
+                } else {
+                    // Past 4 GiB this doesn't fit, which
+                    // offset_overflows reports.
+                    section.offset = (segment.fileoff + offset) as u32;
+                    file_end = offset + section.size;
+                }
+                offset += section.size;
+            }
+            // Nothing follows __LINKEDIT, so it isn't padded in the
+            // file.
+            segment.filesize = if segment.name == "__LINKEDIT" {
+                file_end
+            } else {
+                file_end.next_multiple_of(page_size)
+            };
+            segment.vmsize = offset.next_multiple_of(page_size);
+            vmaddr += segment.vmsize;
+            fileoff += segment.filesize;
+        }
+        // The trie holds addresses, so it's only built once they're
+        // assigned. Nothing follows __LINKEDIT for it to move.
+        if self.export_trie {
+            self.exports = self.export_trie();
+            let linkedit_size = self.linkedit_size();
+            if let Some(linkedit) = self
+                .segments
+                .iter_mut()
+                .find(|segment| segment.name == "__LINKEDIT")
+            {
+                linkedit.filesize = linkedit_size;
+                linkedit.vmsize = linkedit_size.next_multiple_of(page_size);
+            }
+        }
+    }
+
+    /// The header and load commands, then the contents of each section
+    /// and __LINKEDIT, each at its file offset.
+    fn image(&mut self) -> Result<Image<'_>, String> {
+        self.layout();
+        let sizeofcmds = self.sizeofcmds();
+        let commands = self.commands();
+
+        let mut header = vec![];
+        push_u32(&mut header, MH_MAGIC_64);
+        push_u32(&mut header, self.cputype);
+        push_u32(&mut header, self.cpusubtype);
+        push_u32(&mut header, self.filetype);
+        push_u32(&mut header, commands.len() as u32);
+        push_u32(&mut header, sizeofcmds as u32);
+        push_u32(&mut header, self.flags);
+        // reserved
+        push_u32(&mut header, 0);
+        let mut uuid_offset = None;
+        for command in &commands {
+            if let LoadCommand::Uuid(_) = command {
+                uuid_offset = Some(header.len() + 8);
+            }
+            header.extend_from_slice(&command.to_bytes());
+        }
+        let mut sections = vec![];
+        let mut linkedit = (0, vec![]);
+        let mut len = 0;
+        for segment in &self.segments {
+            for section in &segment.sections {
+                if section.is_zerofill() || section.data.is_empty() {
+                    continue;
+                }
+                sections.push((file_offset(segment, section), &section.data[..]));
+            }
+            if segment.name == "__LINKEDIT" {
+                linkedit = (segment.fileoff, self.linkedit()?);
+            }
+            len = len.max(segment.fileoff + segment.filesize);
+        }
+        len = len.max(header.len() as u64);
+        Ok(Image {
+            header,
+            sections,
+            linkedit,
+            len,
+            uuid_offset,
+            reproducible: self.reproducible,
+        })
+    }
+
+    /// The size of the output file, as of the last [MachOWriter::layout].
+    pub fn file_size(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| segment.fileoff + segment.filesize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The offsets and sizes in the file that are too big for the load
+    /// commands to describe, as of the last [MachOWriter::layout].
+    /// Segments have 64-bit file offsets and sizes, but a section's file
+    /// offset, the symbol and string tables, the export trie and the
+    /// encrypted range are given in 32 bits, so none of them can reach
+    /// past 4 GiB.
+    pub fn offset_overflows(&self) -> Vec<String> {
+        let limit = u64::from(u32::MAX);
+        let mut overflows = vec![];
+        for segment in &self.segments {
+            for section in &segment.sections {
+                let offset = file_offset(segment, section);
+                if !section.is_zerofill() && offset > limit {
+                    overflows.push(format!(
+                        "{},{} starts at file offset {offset:#x}, past the 4 GiB a section's offset can reach",
+                        segment.name, section.sectname
+                    ));
+                }
+            }
+        }
+        let linkedit_end = self.linkedit_offset() as u64 + self.linkedit_size();
+        if linkedit_end > limit {
+            overflows.push(format!(
+                "__LINKEDIT ends at file offset {linkedit_end:#x}, past the 4 GiB the symbol table's offsets can reach"
+            ));
+        }
+        if self.encryptable {
+            if let Some(text) = self.segments.iter().find(|s| s.name == "__TEXT") {
+                if text.filesize > limit {
+                    overflows.push(format!(
+                        "__TEXT is {:#x} bytes, more than the 4 GiB an encrypted range can cover",
+                        text.filesize
+                    ));
+                }
+            }
+        }
+        overflows
+    }
+
+    pub fn write(&mut self) -> Result<Vec<u8>, String> {
+        let image = self.image()?;
+        let mut buf = image.header.clone();
+        for (offset, bytes) in image.chunks().skip(1) {
+            buf.resize(offset as usize, 0);
+            buf.extend_from_slice(bytes);
+        }
+        buf.resize(image.len as usize, 0);
+        if let Some(offset) = image.uuid_offset {
+            buf[offset..offset + 16].copy_from_slice(&image.uuid());
+        }
+        Ok(buf)
+    }
+
+    /// Write the image straight into `file`, with the sections written
+    /// in parallel, rather than putting it together in memory first as
+    /// [MachOWriter::write] does.
+    pub fn write_to(&mut self, file: &File, threads: &ThreadPool) -> io::Result<()> {
+        let image = self.image().map_err(io::Error::other)?;
+        // Sizing the file first leaves the gaps as zeros without
+        // writing them.
+        file.set_len(image.len)?;
+        file.write_all_at(&image.header, 0)?;
+        threads
+            .map(&image.sections, |(offset, bytes)| {
+                file.write_all_at(bytes, *offset)
+            })
+            .into_iter()
+            .collect::<io::Result<()>>()?;
+        file.write_all_at(&image.linkedit.1, image.linkedit.0)?;
+        if let Some(offset) = image.uuid_offset {
+            file.write_all_at(&image.uuid(), offset as u64)?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of an output file, from [MachOWriter::image]. Anything
+/// between the pieces is zero.
+struct Image<'a> {
+    /// The header and load commands, at offset 0.
+    header: Vec<u8>,
+    sections: Vec<(u64, &'a [u8])>,
+    linkedit: (u64, Vec<u8>),
+    len: u64,
+    /// Where LC_UUID's UUID is, zero until [Image::uuid] fills it in.
+    uuid_offset: Option<usize>,
+    reproducible: bool,
+}
+
+impl Image<'_> {
+    /// The pieces in file order, with their offsets.
+    fn chunks(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        std::iter::once((0, &self.header[..]))
+            .chain(self.sections.iter().copied())
+            .chain(std::iter::once((self.linkedit.0, &self.linkedit.1[..])))
+    }
+
+    /// A UUID derived from the contents of the file, with the UUID
+    /// itself zeroed, and when it's not reproducible the time.
+    fn uuid(&self) -> [u8; 16] {
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut end = 0;
+        for (offset, bytes) in self.chunks() {
+            hash = fnv1a_128_zeros(hash, offset.saturating_sub(end));
+            hash = fnv1a_128(hash, bytes);
+            end = end.max(offset + bytes.len() as u64);
+        }
+        hash = fnv1a_128_zeros(hash, self.len.saturating_sub(end));
+        if !self.reproducible {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            hash ^= fnv1a_128(FNV_OFFSET_BASIS, &now.as_nanos().to_le_bytes());
+        }
+        let mut uuid = hash.to_be_bytes();
+        // Mark it as a name based UUID, like ld64's.
+        uuid[6] = (uuid[6] & 0x0f) | 0x30;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        uuid
+    }
+}
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+/// 128-bit FNV-1a, which is stable from one machop build to the next
+/// unlike std's hashers, continuing from `hash`.
+fn fnv1a_128(hash: u128, bytes: &[u8]) -> u128 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ *byte as u128).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// [fnv1a_128] of `count` zero bytes.
+fn fnv1a_128_zeros(hash: u128, count: u64) -> u128 {
+    (0..count).fold(hash, |hash, _| hash.wrapping_mul(FNV_PRIME))
+}
+
+/// Whether sections with `flags` take space in memory but not the file.
+fn is_zerofill(flags: u32) -> bool {
+    matches!(
+        flags & SECTION_TYPE,
+        S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL
+    )
+}
+
+/// Where `section`, laid out in `segment`, is in the file. Unlike
+/// [Section::offset] it isn't cut down to 32 bits.
+fn file_offset(segment: &Segment, section: &Section) -> u64 {
+    segment.fileoff + (section.addr - segment.vmaddr)
+}
+
+fn segment_protection(segname: &str) -> u32 {
+    match segname {
+        "__TEXT" => VM_PROT_READ | VM_PROT_EXECUTE,
+        "__LINKEDIT" | "__LLVM" => VM_PROT_READ,
+        _ => VM_PROT_READ | VM_PROT_WRITE,
+    }
+}
+
+/// The version recorded for machop in LC_BUILD_VERSION's tool list.
+fn linker_version() -> Version {
+    env!("CARGO_PKG_VERSION").parse().unwrap_or_default()
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Segment and section names are fixed 16 byte, NUL padded fields.
+fn push_name(buf: &mut Vec<u8>, name: &str) {
+    let mut field = [0u8; 16];
+    let len = name.len().min(field.len());
+    field[..len].copy_from_slice(&name.as_bytes()[..len]);
+    buf.extend_from_slice(&field);
+}
@@ -0,0 +1,62 @@
+/// What a link was doing when it hit an internal error.
+///
+/// Plenty of the link still unwraps, so the panic hook installed by
+/// [install] reports the phase of the link, the input being processed
+/// and the command line along with the panic, which is something a bug
+/// report can be made from. The phase and command line are shared by
+/// all threads, the input is per thread since inputs are processed in
+/// parallel.
+use std::{cell::RefCell, ffi::OsString, sync::Mutex};
+
+static PHASE: Mutex<&str> = Mutex::new("starting");
+static COMMAND_LINE: Mutex<Vec<OsString>> = Mutex::new(vec![]);
+
+thread_local! {
+    static INPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Report the context of any panic before the usual message.
+pub fn install() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let phase = *PHASE.lock().unwrap_or_else(|e| e.into_inner());
+        let command_line = COMMAND_LINE
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        eprintln!("machop hit an internal error while {phase}");
+        if let Some(input) = INPUT.with(|input| input.borrow().clone()) {
+            eprintln!("  processing {input}");
+        }
+        eprintln!("  linking with: {command_line}");
+        eprintln!(
+            "Please report this as a bug, attaching the bundle written by \
+             --reproduce=<FILE> if the inputs can be shared."
+        );
+        default(info);
+    }));
+}
+
+/// Start a link of `argv`.
+pub fn start(argv: &[OsString]) {
+    *COMMAND_LINE.lock().unwrap_or_else(|e| e.into_inner()) = argv.to_vec();
+    set_phase("starting");
+    set_input(None);
+}
+
+/// Record what the link is doing now, like "resolving symbols". The
+/// phase is also where -print_statistics starts timing afresh, and
+/// where -time_trace starts a new span.
+pub fn set_phase(phase: &'static str) {
+    *PHASE.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+    crate::stats::phase(phase);
+    crate::time_trace::phase(phase);
+}
+
+/// Record the input being processed on this thread, if any.
+pub fn set_input(input: Option<String>) {
+    INPUT.with(|current| *current.borrow_mut() = input);
+}
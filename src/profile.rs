@@ -0,0 +1,73 @@
+/// Profiles giving the order functions should be laid out in, as taken by
+/// --profile-order.
+///
+/// Each line holds a symbol name, optionally followed by a sample count,
+/// hottest first, as written from a list of hot functions or from an
+/// ordering derived from branch records. `#` starts a comment. Symbols
+/// with a count of 0 are cold.
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+/// Where a function goes in __text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Placement {
+    /// At the front, in the profile's order.
+    Hot(usize),
+    /// After the hot functions, in input order.
+    Unlisted,
+    /// At the end, in input order.
+    Cold,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    hot: HashMap<String, usize>,
+    cold: HashSet<String>,
+}
+
+impl Profile {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut profile = Self::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = match line.find('#') {
+                Some(start) => &line[..start],
+                None => line,
+            };
+            let mut fields = line.split_whitespace();
+            let Some(name) = fields.next() else {
+                continue;
+            };
+            let samples: Option<u64> =
+                match fields.next() {
+                    Some(count) => Some(count.parse().map_err(|_| {
+                        format!("Invalid sample count {count} on line {}", number + 1)
+                    })?),
+                    None => None,
+                };
+            if samples == Some(0) {
+                profile.cold.insert(name.to_string());
+            } else {
+                // The first mention of a function decides where it goes.
+                let rank = profile.hot.len();
+                profile.hot.entry(name.to_string()).or_insert(rank);
+            }
+        }
+        Ok(profile)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read profile {}: {e}", path.display()))?;
+        Self::parse(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn placement(&self, name: &str) -> Placement {
+        match self.hot.get(name) {
+            Some(rank) => Placement::Hot(*rank),
+            None if self.cold.contains(name) => Placement::Cold,
+            None => Placement::Unlisted,
+        }
+    }
+}
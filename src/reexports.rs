@@ -0,0 +1,42 @@
+/// Walking the graph of re-exported libraries.
+///
+/// A library's re-exports can re-export others in turn. A malformed SDK
+/// or hand written TBD can make that a cycle, which is reported and cut
+/// rather than followed forever.
+use std::collections::HashSet;
+
+use crate::warning;
+
+/// Call `reexports` once for `root` and once for each library it
+/// re-exports, directly or not, depth first. Given a library's install
+/// name, `reexports` returns the install names of the libraries it
+/// re-exports.
+pub fn walk(root: &str, mut reexports: impl FnMut(&str) -> Vec<String>) {
+    let mut visited = HashSet::new();
+    let mut path = vec![];
+    visit(root, &mut reexports, &mut visited, &mut path);
+}
+
+fn visit(
+    name: &str,
+    reexports: &mut impl FnMut(&str) -> Vec<String>,
+    visited: &mut HashSet<String>,
+    path: &mut Vec<String>,
+) {
+    if let Some(start) = path.iter().position(|seen| seen == name) {
+        warning!(
+            "Ignoring cyclic re-export {} -> {name}",
+            path[start..].join(" -> ")
+        );
+        return;
+    }
+    // A library re-exported along two paths is only visited once.
+    if !visited.insert(name.to_string()) {
+        return;
+    }
+    path.push(name.to_string());
+    for child in reexports(name) {
+        visit(&child, reexports, visited, path);
+    }
+    path.pop();
+}
@@ -0,0 +1,425 @@
+/// ARM64 relocation decoding and application.
+use std::collections::HashSet;
+
+use goblin::mach::relocation::{
+    RelocationInfo, ARM64_RELOC_ADDEND, ARM64_RELOC_BRANCH26, ARM64_RELOC_GOT_LOAD_PAGE21,
+    ARM64_RELOC_GOT_LOAD_PAGEOFF12, ARM64_RELOC_PAGE21, ARM64_RELOC_PAGEOFF12,
+    ARM64_RELOC_POINTER_TO_GOT, ARM64_RELOC_SUBTRACTOR, ARM64_RELOC_TLVP_LOAD_PAGE21,
+    ARM64_RELOC_TLVP_LOAD_PAGEOFF12, ARM64_RELOC_UNSIGNED,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    /// An ARM64_RELOC_ADDEND that isn't followed by a BRANCH26, PAGE21
+    /// or PAGEOFF12 at the same address.
+    UnpairedAddend { address: i32 },
+    /// An ARM64_RELOC_SUBTRACTOR that isn't followed by an
+    /// ARM64_RELOC_UNSIGNED of the same length at the same address.
+    UnpairedSubtractor { address: i32 },
+    /// A relocation type that's not valid for ARM64.
+    UnknownType { address: i32, r_type: u8 },
+    /// The relocation's value doesn't fit in the instruction or
+    /// pointer that it's being written to.
+    OutOfRange { address: i32, value: i64 },
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::UnpairedAddend { address } => write!(
+                f,
+                "ARM64_RELOC_ADDEND at {address:#x} is not followed by a BRANCH26, PAGE21 or PAGEOFF12"
+            ),
+            Error::UnpairedSubtractor { address } => write!(
+                f,
+                "ARM64_RELOC_SUBTRACTOR at {address:#x} is not followed by a matching ARM64_RELOC_UNSIGNED"
+            ),
+            Error::UnknownType { address, r_type } => {
+                write!(f, "Unknown relocation type {r_type} at {address:#x}")
+            }
+            Error::OutOfRange { address, value } => {
+                write!(f, "Relocation at {address:#x} is out of range ({value:#x})")
+            }
+        }
+    }
+}
+
+/// What a relocation refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// Index into the object's symbol table (r_extern == 1).
+    Symbol(usize),
+    /// 1-based section ordinal in the object (r_extern == 0).
+    Section(usize),
+}
+
+impl From<RelocationInfo> for Target {
+    fn from(info: RelocationInfo) -> Self {
+        if info.is_extern() {
+            Target::Symbol(info.r_symbolnum())
+        } else {
+            Target::Section(info.r_symbolnum())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Unsigned,
+    /// `target - subtrahend + inline addend`, from a
+    /// SUBTRACTOR/UNSIGNED pair.
+    Subtractor {
+        subtrahend: Target,
+    },
+    Branch26,
+    Page21,
+    PageOff12,
+    GotLoadPage21,
+    GotLoadPageOff12,
+    PointerToGot,
+    TlvpLoadPage21,
+    TlvpLoadPageOff12,
+}
+
+/// A relocation with any pairs already folded in.
+#[derive(Debug, Clone, Copy)]
+pub struct Relocation {
+    /// Offset from the start of the section.
+    pub address: i32,
+    pub kind: Kind,
+    pub target: Target,
+    /// Explicit addend from a preceding ARM64_RELOC_ADDEND. Inline
+    /// addends (UNSIGNED, SUBTRACTOR) are read from the section data
+    /// when applying.
+    pub addend: i64,
+    pub pcrel: bool,
+    /// log2 of the size of the relocated field.
+    pub length: u8,
+}
+
+/// ADDEND stores a signed 24 bit value in r_symbolnum.
+fn addend_value(info: RelocationInfo) -> i64 {
+    ((info.r_symbolnum() as i64) << 40) >> 40
+}
+
+/// Decode a section's raw relocation entries, folding ADDEND and
+/// SUBTRACTOR entries into the relocation they modify.
+pub fn decode(infos: &[RelocationInfo]) -> Result<Vec<Relocation>, Error> {
+    let mut relocations = Vec::with_capacity(infos.len());
+    let mut iter = infos.iter().copied();
+    while let Some(info) = iter.next() {
+        let address = info.r_address;
+        let relocation = match info.r_type() {
+            ARM64_RELOC_ADDEND => {
+                let next = iter
+                    .next()
+                    .filter(|next| {
+                        next.r_address == address
+                            && matches!(
+                                next.r_type(),
+                                ARM64_RELOC_BRANCH26 | ARM64_RELOC_PAGE21 | ARM64_RELOC_PAGEOFF12
+                            )
+                    })
+                    .ok_or(Error::UnpairedAddend { address })?;
+                Relocation {
+                    addend: addend_value(info),
+                    ..simple(next)?
+                }
+            }
+            ARM64_RELOC_SUBTRACTOR => {
+                let next = iter
+                    .next()
+                    .filter(|next| {
+                        next.r_address == address
+                            && next.r_type() == ARM64_RELOC_UNSIGNED
+                            && next.r_length() == info.r_length()
+                    })
+                    .ok_or(Error::UnpairedSubtractor { address })?;
+                Relocation {
+                    address,
+                    kind: Kind::Subtractor {
+                        subtrahend: info.into(),
+                    },
+                    target: next.into(),
+                    addend: 0,
+                    pcrel: false,
+                    length: next.r_length(),
+                }
+            }
+            _ => simple(info)?,
+        };
+        relocations.push(relocation);
+    }
+    Ok(relocations)
+}
+
+/// Decode a relocation that doesn't take part in a pair.
+fn simple(info: RelocationInfo) -> Result<Relocation, Error> {
+    let kind = match info.r_type() {
+        ARM64_RELOC_UNSIGNED => Kind::Unsigned,
+        ARM64_RELOC_BRANCH26 => Kind::Branch26,
+        ARM64_RELOC_PAGE21 => Kind::Page21,
+        ARM64_RELOC_PAGEOFF12 => Kind::PageOff12,
+        ARM64_RELOC_GOT_LOAD_PAGE21 => Kind::GotLoadPage21,
+        ARM64_RELOC_GOT_LOAD_PAGEOFF12 => Kind::GotLoadPageOff12,
+        ARM64_RELOC_POINTER_TO_GOT => Kind::PointerToGot,
+        ARM64_RELOC_TLVP_LOAD_PAGE21 => Kind::TlvpLoadPage21,
+        ARM64_RELOC_TLVP_LOAD_PAGEOFF12 => Kind::TlvpLoadPageOff12,
+        // A pair's second half on its own means the first half was
+        // missing.
+        ARM64_RELOC_ADDEND => {
+            return Err(Error::UnpairedAddend {
+                address: info.r_address,
+            })
+        }
+        ARM64_RELOC_SUBTRACTOR => {
+            return Err(Error::UnpairedSubtractor {
+                address: info.r_address,
+            })
+        }
+        r_type => {
+            return Err(Error::UnknownType {
+                address: info.r_address,
+                r_type,
+            })
+        }
+    };
+    Ok(Relocation {
+        address: info.r_address,
+        kind,
+        target: info.into(),
+        addend: 0,
+        pcrel: info.is_pic(),
+        length: info.r_length(),
+    })
+}
+
+impl Relocation {
+    /// Write the relocated value into `data` (the section contents).
+    /// `section_addr` is the final address of the section, `target` the final
+    /// address of the relocation's target (or its GOT/TLV slot for the
+    /// indirect kinds) and `subtrahend` the address of the SUBTRACTOR
+    /// symbol, if there is one.
+    pub fn apply(
+        &self,
+        data: &mut [u8],
+        section_addr: u64,
+        target: u64,
+        subtrahend: Option<u64>,
+    ) -> Result<(), Error> {
+        let offset = self.address as usize;
+        let place = section_addr + offset as u64;
+        let target = target.wrapping_add(self.addend as u64);
+        match self.kind {
+            Kind::Unsigned | Kind::Subtractor { .. } => {
+                let inline = read_field(data, offset, self.length);
+                let value = match self.kind {
+                    Kind::Subtractor { .. } => target
+                        .wrapping_sub(subtrahend.unwrap_or(0))
+                        .wrapping_add(inline),
+                    _ => target.wrapping_add(inline),
+                };
+                write_field(data, offset, self.length, value);
+            }
+            Kind::PointerToGot => {
+                let delta = target.wrapping_sub(place) as i64;
+                if i32::try_from(delta).is_err() {
+                    return Err(self.out_of_range(delta));
+                }
+                write_field(data, offset, self.length, delta as u64);
+            }
+            Kind::Branch26 => {
+                let delta = target.wrapping_sub(place) as i64;
+                if !(-(1 << 27)..(1 << 27)).contains(&delta) {
+                    return Err(self.out_of_range(delta));
+                }
+                let insn = read_insn(data, offset);
+                let imm = ((delta >> 2) as u32) & 0x03ff_ffff;
+                write_insn(data, offset, (insn & 0xfc00_0000) | imm);
+            }
+            Kind::Page21 | Kind::GotLoadPage21 | Kind::TlvpLoadPage21 => {
+                let delta = ((target & !0xfff) as i64).wrapping_sub((place & !0xfff) as i64) >> 12;
+                if !(-(1 << 20)..(1 << 20)).contains(&delta) {
+                    return Err(self.out_of_range(delta));
+                }
+                let insn = read_insn(data, offset);
+                write_insn(data, offset, encode_adrp(insn, delta));
+            }
+            Kind::PageOff12 | Kind::GotLoadPageOff12 | Kind::TlvpLoadPageOff12 => {
+                let insn = read_insn(data, offset);
+                let pageoff = (target & 0xfff) as u32;
+                let scale = load_store_scale(insn);
+                write_insn(
+                    data,
+                    offset,
+                    (insn & !(0xfff << 10)) | (((pageoff >> scale) & 0xfff) << 10),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn out_of_range(&self, value: i64) -> Error {
+        Error::OutOfRange {
+            address: self.address,
+            value,
+        }
+    }
+}
+
+const NOP: u32 = 0xd503_201f;
+
+fn is_ldr_x_imm(insn: u32) -> bool {
+    insn & 0xffc0_0000 == 0xf940_0000
+}
+
+fn is_add_x_imm(insn: u32) -> bool {
+    insn & 0xffc0_0000 == 0x9100_0000
+}
+
+/// Relax GOT loads of symbols that are defined in the image being
+/// linked so they don't need a GOT entry: `ldr xD, [xN, _foo@GOTPAGEOFF]`
+/// becomes `add xD, xN, _foo@PAGEOFF` and the ADRP points at the
+/// symbol's page instead of the GOT's. This has to happen before
+/// layout so the GOT isn't sized for them.
+///
+/// All the PAGEOFF12 loads for a target in the section must be
+/// relaxable, otherwise the ADRP they share has to stay pointing at
+/// the GOT. Returns the number of loads relaxed.
+pub fn relax_got_loads(
+    relocations: &mut [Relocation],
+    data: &mut [u8],
+    is_local: impl Fn(Target) -> bool,
+) -> usize {
+    let blocked: HashSet<Target> = relocations
+        .iter()
+        .filter(|relocation| relocation.kind == Kind::GotLoadPageOff12)
+        .filter(|relocation| {
+            !is_local(relocation.target)
+                || !is_ldr_x_imm(read_insn(data, relocation.address as usize))
+        })
+        .map(|relocation| relocation.target)
+        .collect();
+    let mut relaxed = 0;
+    for relocation in relocations.iter_mut() {
+        if blocked.contains(&relocation.target) || !is_local(relocation.target) {
+            continue;
+        }
+        match relocation.kind {
+            Kind::GotLoadPage21 => relocation.kind = Kind::Page21,
+            Kind::GotLoadPageOff12 => {
+                let offset = relocation.address as usize;
+                // Keep Rn and Rt, the immediate is filled in when the
+                // relocation is applied.
+                let insn = read_insn(data, offset);
+                write_insn(data, offset, 0x9100_0000 | (insn & 0x3ff));
+                relocation.kind = Kind::PageOff12;
+                relaxed += 1;
+            }
+            _ => {}
+        }
+    }
+    relaxed
+}
+
+/// Fold `adrp xD, _foo@PAGE; add xD, xD, _foo@PAGEOFF` into
+/// `adr xD, _foo; nop` when the target is within ADR's +/-1MiB range.
+/// Must run after the relocations have been applied, `resolve` gives
+/// the final target address of a relocation, if it has one. Returns
+/// the number of pairs folded.
+pub fn fold_adrp_add(
+    relocations: &[Relocation],
+    data: &mut [u8],
+    section_addr: u64,
+    resolve: impl Fn(&Relocation) -> Option<u64>,
+) -> usize {
+    let mut folded = 0;
+    for adrp in relocations.iter().filter(|r| r.kind == Kind::Page21) {
+        let Some(add) = relocations.iter().find(|r| {
+            r.kind == Kind::PageOff12
+                && r.address == adrp.address + 4
+                && r.target == adrp.target
+                && r.addend == adrp.addend
+        }) else {
+            continue;
+        };
+        let adrp_offset = adrp.address as usize;
+        let adrp_insn = read_insn(data, adrp_offset);
+        let add_insn = read_insn(data, adrp_offset + 4);
+        let rd = adrp_insn & 0x1f;
+        // The ADD must consume and overwrite the ADRP's register,
+        // otherwise something else may still depend on the page
+        // address.
+        if !is_add_x_imm(add_insn) || (add_insn >> 5) & 0x1f != rd || add_insn & 0x1f != rd {
+            continue;
+        }
+        let Some(target) = resolve(add) else {
+            continue;
+        };
+        let place = section_addr + adrp_offset as u64;
+        let delta = target.wrapping_add(add.addend as u64).wrapping_sub(place) as i64;
+        if !(-(1 << 20)..(1 << 20)).contains(&delta) {
+            continue;
+        }
+        let imm = delta as u32 & 0x1f_ffff;
+        let adr = 0x1000_0000 | ((imm & 0x3) << 29) | ((imm >> 2) << 5) | rd;
+        write_insn(data, adrp_offset, adr);
+        write_insn(data, adrp_offset + 4, NOP);
+        folded += 1;
+    }
+    folded
+}
+
+/// Put a page delta into an ADRP's immlo/immhi fields.
+pub(crate) fn encode_adrp(insn: u32, pages: i64) -> u32 {
+    let imm = pages as u32 & 0x1f_ffff;
+    let immlo = (imm & 0x3) << 29;
+    let immhi = (imm >> 2) << 5;
+    (insn & 0x9f00_001f) | immlo | immhi
+}
+
+/// Load/store immediates are scaled by the access size, all other
+/// PAGEOFF12 users (ADD) take the raw byte offset.
+pub(crate) fn load_store_scale(insn: u32) -> u32 {
+    if insn & 0x3b00_0000 == 0x3900_0000 {
+        let size = insn >> 30;
+        // 128-bit SIMD loads and stores use opc to extend the size.
+        if size == 0 && insn & 0x0480_0000 == 0x0480_0000 {
+            4
+        } else {
+            size
+        }
+    } else {
+        0
+    }
+}
+
+pub(crate) fn read_insn(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub(crate) fn write_insn(data: &mut [u8], offset: usize, insn: u32) {
+    data[offset..offset + 4].copy_from_slice(&insn.to_le_bytes());
+}
+
+pub(crate) fn read_field(data: &[u8], offset: usize, length: u8) -> u64 {
+    match length {
+        3 => u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap()),
+        // Narrower fields hold signed deltas, sign extend them so the
+        // arithmetic wraps correctly.
+        2 => i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as i64 as u64,
+        1 => i16::from_le_bytes(data[offset..offset + 2].try_into().unwrap()) as i64 as u64,
+        _ => data[offset] as i8 as i64 as u64,
+    }
+}
+
+fn write_field(data: &mut [u8], offset: usize, length: u8, value: u64) {
+    match length {
+        3 => data[offset..offset + 8].copy_from_slice(&value.to_le_bytes()),
+        2 => data[offset..offset + 4].copy_from_slice(&(value as u32).to_le_bytes()),
+        1 => data[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+        _ => data[offset] = value as u8,
+    }
+}
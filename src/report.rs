@@ -0,0 +1,250 @@
+/// Reports on what went into the output and how big it is, printed
+/// after linking, or in place of it with --dry-run.
+use std::{collections::BTreeMap, fmt::Write, path::Path};
+
+use goblin::mach::symbols::N_EXT;
+
+use crate::{
+    layout::{is_common, Layout},
+    linker_args::{Architecture, SizeBudget},
+    output::MachOWriter,
+    warning,
+};
+
+/// A map of the output in ld64's format, for -map: the objects linked,
+/// the output's sections and the address and size of each symbol placed
+/// in them. Objects are referred to by their index in the list of
+/// objects, 0 being the linker itself, which is where commons are
+/// allocated. Symbols are sized by the distance to the next one.
+///
+/// With `cross_reference`, it ends with the external symbols each object
+/// references, so it's possible to find out what keeps a symbol alive.
+pub fn map(
+    layout: &Layout,
+    writer: &MachOWriter,
+    output: &Path,
+    arch: &Architecture,
+    inputs: &[String],
+    cross_reference: bool,
+) -> String {
+    let mut map = String::new();
+    writeln!(map, "# Path: {}", output.display()).unwrap();
+    writeln!(map, "# Arch: {arch}").unwrap();
+    writeln!(map, "# Object files:").unwrap();
+    writeln!(map, "[{:>3}] linker synthesized", 0).unwrap();
+    for (index, input) in inputs.iter().enumerate() {
+        writeln!(map, "[{:>3}] {input}", index + 1).unwrap();
+    }
+
+    writeln!(map, "# Sections:").unwrap();
+    writeln!(map, "# Address\tSize    \tSegment\tSection").unwrap();
+    for section in writer.segments.iter().flat_map(|segment| &segment.sections) {
+        writeln!(
+            map,
+            "{:#010X}\t{:#010X}\t{}\t{}",
+            section.addr, section.size, section.segname, section.sectname
+        )
+        .unwrap();
+    }
+
+    writeln!(map, "# Symbols:").unwrap();
+    writeln!(map, "# Address\tSize    \tFile  Name").unwrap();
+    let mut symbols = vec![];
+    for (index, object) in layout.objects.iter().enumerate() {
+        for (name, nlist) in &object.symbols {
+            // Assembler temporaries are left out, as in the symbol table.
+            if nlist.is_stab() || name.is_empty() || name.starts_with(['l', 'L']) {
+                continue;
+            }
+            let Some(section) = layout.symbol_section(index, nlist) else {
+                continue;
+            };
+            let Some(address) = layout.symbol_address(writer, index, nlist) else {
+                continue;
+            };
+            symbols.push((address, section, index + 1, *name));
+        }
+    }
+    if let Some(section) = layout.common_section() {
+        symbols.extend(
+            layout
+                .common_addresses(writer)
+                .map(|(name, address)| (address, section, 0, name)),
+        );
+    }
+    symbols.sort();
+    // A symbol runs up to the next one in its section.
+    for (index, &(address, section, object, name)) in symbols.iter().enumerate() {
+        let end = match symbols.get(index + 1) {
+            Some(&(next, next_section, ..)) if next_section == section => next,
+            _ => {
+                let section = writer.section(section);
+                section.addr + section.size
+            }
+        };
+        writeln!(
+            map,
+            "{address:#010X}\t{:#010X}\t[{object:>3}] {name}",
+            end - address
+        )
+        .unwrap();
+    }
+
+    if cross_reference {
+        let mut references: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+        for (index, object) in layout.objects.iter().enumerate() {
+            for (name, nlist) in &object.symbols {
+                if nlist.is_undefined() && nlist.n_type & N_EXT != 0 && !is_common(nlist) {
+                    let objects = references.entry(name).or_default();
+                    if objects.last() != Some(&(index + 1)) {
+                        objects.push(index + 1);
+                    }
+                }
+            }
+        }
+        writeln!(map, "# Cross references:").unwrap();
+        writeln!(map, "# Symbol\tReferenced by").unwrap();
+        for (name, objects) in references {
+            let objects: Vec<String> = objects
+                .iter()
+                .map(|object| format!("[{object:>3}]"))
+                .collect();
+            writeln!(map, "{name}\t{}", objects.join(" ")).unwrap();
+        }
+    }
+    map
+}
+
+/// The file and VM size of each segment and section of the written
+/// output, with their share of the total VM size. __PAGEZERO reserves
+/// address space but has no contents, so it's left out.
+pub fn memory_usage(writer: &MachOWriter) -> String {
+    let segments: Vec<_> = writer
+        .segments
+        .iter()
+        .filter(|segment| segment.name != "__PAGEZERO")
+        .collect();
+    let total_filesize: u64 = segments.iter().map(|segment| segment.filesize).sum();
+    let total_vmsize: u64 = segments.iter().map(|segment| segment.vmsize).sum();
+    let percent = |vmsize: u64| 100.0 * vmsize as f64 / total_vmsize.max(1) as f64;
+    let mut report = String::new();
+    writeln!(
+        report,
+        "{:<32}  {:>12}  {:>12}  {:>6}",
+        "SEGMENT/SECTION", "FILE SIZE", "VM SIZE", "%"
+    )
+    .unwrap();
+    for segment in segments {
+        writeln!(
+            report,
+            "{:<32}  {:>12}  {:>12}  {:>5.1}%",
+            segment.name,
+            segment.filesize,
+            segment.vmsize,
+            percent(segment.vmsize)
+        )
+        .unwrap();
+        for section in &segment.sections {
+            let filesize = if section.is_zerofill() {
+                0
+            } else {
+                section.size
+            };
+            writeln!(
+                report,
+                "  {:<30}  {:>12}  {:>12}  {:>5.1}%",
+                section.sectname,
+                filesize,
+                section.size,
+                percent(section.size)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(
+        report,
+        "{:<32}  {:>12}  {:>12}  {:>5.1}%",
+        "TOTAL", total_filesize, total_vmsize, 100.0
+    )
+    .unwrap();
+    report
+}
+
+/// How the output, laid out by `writer`, goes over each of `budgets`,
+/// one message per budget that it's over. Segments are measured by
+/// their size in the file.
+pub fn budget_overruns(writer: &MachOWriter, budgets: &[SizeBudget]) -> Vec<String> {
+    let mut overruns = vec![];
+    for budget in budgets {
+        let (name, size) = match &budget.segment {
+            Some(name) => {
+                let Some(segment) = writer.segments.iter().find(|segment| segment.name == *name)
+                else {
+                    warning!("Ignoring --max-size for {name}, the output has no such segment");
+                    continue;
+                };
+                (name.clone(), segment.filesize)
+            }
+            None => ("The output".to_string(), writer.file_size()),
+        };
+        if size > budget.bytes {
+            overruns.push(format!(
+                "{name} is {size} bytes, {} over its --max-size budget of {}",
+                size - budget.bytes,
+                budget.bytes
+            ));
+        }
+    }
+    overruns
+}
+
+/// The `count` largest atoms in the output, with the section they were
+/// placed in and the input they're from. `inputs` are the names of the
+/// layout's objects.
+pub fn largest_atoms(
+    layout: &Layout,
+    writer: &MachOWriter,
+    inputs: &[String],
+    count: usize,
+) -> String {
+    let mut atoms: Vec<_> = layout.placed_atoms().collect();
+    // The sort is stable, so atoms of the same size stay in input order.
+    atoms.sort_by_key(|atom| std::cmp::Reverse(atom.size));
+    atoms.truncate(count);
+    let mut report = String::new();
+    writeln!(report, "Largest {} atoms:", atoms.len()).unwrap();
+    writeln!(report, "{:>10}  {:<32}  SYMBOL (INPUT)", "SIZE", "SECTION").unwrap();
+    for atom in atoms {
+        let section = writer.section(atom.section);
+        writeln!(
+            report,
+            "{:>10}  {:<32}  {} ({})",
+            atom.size,
+            format!("{},{}", section.segname, section.sectname),
+            atom.symbol.unwrap_or("<anonymous>"),
+            inputs[atom.object]
+        )
+        .unwrap();
+    }
+    report
+}
+
+/// What a link would do, for --dry-run: the inputs loaded, including
+/// each archive member, the libraries linked against with their dyld
+/// ordinals, and the library each imported symbol is bound to.
+pub fn link_plan(inputs: &[String], dylibs: &[String], imports: &[(&str, usize)]) -> String {
+    let mut plan = String::new();
+    writeln!(plan, "Inputs:").unwrap();
+    for input in inputs {
+        writeln!(plan, "  {input}").unwrap();
+    }
+    writeln!(plan, "Libraries:").unwrap();
+    for (index, dylib) in dylibs.iter().enumerate() {
+        writeln!(plan, "  {:>3}  {dylib}", index + 1).unwrap();
+    }
+    writeln!(plan, "Imports:").unwrap();
+    for (name, dylib) in imports {
+        writeln!(plan, "  {name} from {}", dylibs[*dylib]).unwrap();
+    }
+    plan
+}
@@ -0,0 +1,283 @@
+/// Bundles of a link's inputs and arguments, from --reproduce.
+///
+/// A bundle is a tar file with everything in a directory named after
+/// it: `response.txt` holds the arguments, one per line, and each input
+/// is stored under its absolute path. Paths in the arguments are
+/// rewritten to point into the bundle, so running
+/// `xargs machop < response.txt` in the extracted directory repeats the
+/// link. Which values are paths comes from the option table. Libraries
+/// only read to follow re-exports aren't included, like the rest of the
+/// SDK they're found through -syslibroot.
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    arg_parser::{OptTable, OptionSpec, PathValue},
+    environment::Environment,
+    linker_args::Dialect,
+};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Write a bundle of `args`, which are in `dialect` and were given in
+/// `env`, and `inputs` (paths and contents) to `path`. The files the
+/// arguments name, like symbol lists, are bundled along with the inputs.
+pub fn write(
+    path: &Path,
+    args: &[OsString],
+    dialect: Dialect,
+    env: &Environment,
+    inputs: &[(PathBuf, &[u8])],
+) -> io::Result<()> {
+    let root = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let table = dialect.table();
+    let mut tar = vec![];
+    append(
+        &mut tar,
+        &format!("{root}/response.txt"),
+        response(&args, dialect, env).as_bytes(),
+    );
+    let mut bundled = HashSet::new();
+    for (input, contents) in inputs {
+        let name = in_bundle(input);
+        append(&mut tar, &format!("{root}/{name}"), contents);
+        bundled.insert(name);
+    }
+    // Directories, like those from -L, aren't bundled. What was found in
+    // them is among the inputs.
+    for file in spellings(&args, table)
+        .flat_map(|spelling| spelling.paths())
+        .map(|path| env.resolve(path))
+        .filter(|path| path.is_file())
+    {
+        let name = in_bundle(&file);
+        if bundled.insert(name.clone()) {
+            append(&mut tar, &format!("{root}/{name}"), &std::fs::read(file)?);
+        }
+    }
+    // A tar file ends with two empty blocks.
+    tar.resize(tar.len() + 2 * BLOCK_SIZE, 0);
+    std::fs::write(path, tar)
+}
+
+/// An argument and the values that follow it.
+struct Spelling<'a> {
+    arg: &'a str,
+    option: Option<&'static OptionSpec>,
+    /// The value joined to the option's name, if it was given that way.
+    joined: Option<&'a str>,
+    separate: &'a [String],
+}
+
+impl<'a> Spelling<'a> {
+    /// The values, joined or separate, that the option's table entry
+    /// says are paths.
+    fn paths(&self) -> Vec<&'a str> {
+        let values: Vec<&'a str> = self
+            .joined
+            .into_iter()
+            .chain(self.separate.iter().map(String::as_str))
+            .collect();
+        match self.option.and_then(|option| option.path) {
+            Some(PathValue::At(index)) => values.get(index).copied().into_iter().collect(),
+            Some(PathValue::AfterColon) => values
+                .first()
+                .and_then(|value| value.split_once(':'))
+                .map(|(_, path)| path)
+                .into_iter()
+                .collect(),
+            None if self.option.is_none() && !self.arg.starts_with('-') => vec![self.arg],
+            None => vec![],
+        }
+    }
+}
+
+/// `args` split into options with their values, by `table`.
+fn spellings<'a>(
+    args: &'a [String],
+    table: &'static OptTable,
+) -> impl Iterator<Item = Spelling<'a>> {
+    let mut rest = args;
+    std::iter::from_fn(move || {
+        let (arg, after) = rest.split_first()?;
+        let found = Some(arg)
+            .filter(|arg| arg.starts_with('-') && arg.len() > 1)
+            .and_then(|arg| table.lookup(arg));
+        let Some((option, joined)) = found else {
+            rest = after;
+            return Some(Spelling {
+                arg,
+                option: None,
+                joined: None,
+                separate: &[],
+            });
+        };
+        let count = option.separate_values(joined).min(after.len());
+        let (separate, after) = after.split_at(count);
+        rest = after;
+        Some(Spelling {
+            arg,
+            option: Some(table.canonical(option)),
+            joined,
+            separate,
+        })
+    })
+}
+
+/// Where `path` is stored, relative to the bundle's directory.
+fn in_bundle(path: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    absolute
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .to_string()
+}
+
+/// The arguments, with paths pointing into the bundle and without
+/// --reproduce itself, one per line and quoted for xargs. The dialect
+/// is given first, so they're read the same way again.
+fn response(args: &[String], dialect: Dialect, env: &Environment) -> String {
+    let mut lines = vec![format!("--dialect={dialect}")];
+    for spelling in spellings(args, dialect.table()) {
+        let Some(option) = spelling.option else {
+            lines.push(match spelling.arg.starts_with('-') {
+                true => spelling.arg.to_string(),
+                false => rewrite(spelling.arg, env),
+            });
+            continue;
+        };
+        let mut values: Vec<String> = spelling
+            .joined
+            .into_iter()
+            .chain(spelling.separate.iter().map(String::as_str))
+            .map(str::to_string)
+            .collect();
+        match (option.name, option.path) {
+            ("--reproduce", _) => continue,
+            // The output goes next to response.txt.
+            ("-o", _) => {
+                for value in &mut values {
+                    let name = Path::new(value).file_name().unwrap_or_default();
+                    *value = name.to_string_lossy().into_owned();
+                }
+            }
+            (_, Some(PathValue::At(index))) => {
+                if let Some(value) = values.get_mut(index) {
+                    *value = rewrite(value, env);
+                }
+            }
+            (_, Some(PathValue::AfterColon)) => {
+                if let Some((name, path)) = values.first().and_then(|value| value.split_once(':')) {
+                    values[0] = format!("{name}:{}", rewrite(path, env));
+                }
+            }
+            (_, None) => {}
+        }
+        // A joined value is given back joined, after the name it was
+        // spelled with, which may be an alias.
+        match spelling.joined {
+            Some(joined) => {
+                let name = &spelling.arg[..spelling.arg.len() - joined.len()];
+                lines.push(format!("{name}{}", values.remove(0)));
+            }
+            None => lines.push(spelling.arg.to_string()),
+        }
+        lines.extend(values);
+    }
+    lines.iter().map(|line| quote(line) + "\n").collect()
+}
+
+/// `path`, given in `env`, relative to the bundle's directory if it's an
+/// existing path.
+fn rewrite(path: &str, env: &Environment) -> String {
+    let resolved = env.resolve(path);
+    if resolved.exists() {
+        in_bundle(&resolved)
+    } else {
+        path.to_string()
+    }
+}
+
+/// `arg` quoted for xargs, which takes single quotes but no escapes
+/// inside them, and not across lines. Quotes and newlines are escaped
+/// with a backslash between quoted runs instead.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || "'\"\\".contains(c)) {
+        return arg.to_string();
+    }
+    let mut quoted = String::from("'");
+    for c in arg.chars() {
+        if c == '\'' || c == '\n' {
+            quoted.push('\'');
+            quoted.push('\\');
+            quoted.push(c);
+            quoted.push('\'');
+        } else {
+            quoted.push(c);
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Append a file to the tar. Names that don't fit the ustar header get
+/// a PAX extended header.
+fn append(tar: &mut Vec<u8>, name: &str, contents: &[u8]) {
+    if name.len() >= 100 {
+        let record = pax_record("path", name);
+        tar.extend_from_slice(&header("pax_header", record.len(), b'x'));
+        append_data(tar, record.as_bytes());
+    }
+    tar.extend_from_slice(&header(name, contents.len(), b'0'));
+    append_data(tar, contents);
+}
+
+fn append_data(tar: &mut Vec<u8>, data: &[u8]) {
+    tar.extend_from_slice(data);
+    tar.resize(tar.len().next_multiple_of(BLOCK_SIZE), 0);
+}
+
+/// A PAX record, which starts with its own length in decimal.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {key}={value}\n");
+    let mut length = rest.len();
+    while (length.to_string().len() + rest.len()) != length {
+        length = length.to_string().len() + rest.len();
+    }
+    format!("{length}{rest}")
+}
+
+fn header(name: &str, size: usize, typeflag: u8) -> [u8; BLOCK_SIZE] {
+    let mut header = [0u8; BLOCK_SIZE];
+    let mut field = |offset: usize, width: usize, value: &[u8]| {
+        let len = value.len().min(width);
+        header[offset..offset + len].copy_from_slice(&value[..len]);
+    };
+    // Long names are truncated here, the PAX header has the full one.
+    field(0, 100, name.as_bytes());
+    field(100, 8, b"0000644\0");
+    field(108, 8, b"0000000\0");
+    field(116, 8, b"0000000\0");
+    field(124, 12, format!("{size:011o}\0").as_bytes());
+    // The modification time is left at 0 so bundles are reproducible.
+    field(136, 12, b"00000000000\0");
+    field(156, 1, &[typeflag]);
+    field(257, 8, b"ustar\x0000");
+    // The checksum is computed with its own field as spaces.
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|byte| *byte as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
@@ -0,0 +1,120 @@
+/// Link specs for --spec: a JSON description of a link, so build systems
+/// can drive machop without minding command line length limits or
+/// quoting. For example:
+///
+/// ```json
+/// {
+///   "output": "build/app",
+///   "arch": "arm64",
+///   "platform-version": "macos 12.0 13.0",
+///   "inputs": ["build/main.o", "build/libutil.a"],
+///   "library-paths": ["vendor/lib"],
+///   "libraries": ["z"],
+///   "frameworks": ["Foundation"],
+///   "options": ["-fatal_warnings"]
+/// }
+/// ```
+///
+/// A spec is turned into the arguments it stands for, in place of
+/// --spec, so it's checked like any other command line and flags before
+/// or after it can add to it. Paths are relative to the current
+/// directory, like on the command line. Anything without a key of its
+/// own goes in "options", which are passed on as they are. The
+/// arguments come in the order of the fields of [Spec], whatever order
+/// the keys are in, with "inputs" ahead of "libraries" and
+/// "frameworks".
+use std::path::Path;
+
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Spec {
+    output: Option<String>,
+    output_type: Option<OutputType>,
+    arch: Option<String>,
+    /// PLATFORM MIN_VERSION SDK_VERSION, like -platform_version.
+    platform_version: Option<String>,
+    entry: Option<String>,
+    syslibroot: Option<String>,
+    threads: Option<u32>,
+    #[serde(default)]
+    library_paths: Vec<String>,
+    #[serde(default)]
+    framework_paths: Vec<String>,
+    #[serde(default)]
+    rpaths: Vec<String>,
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    libraries: Vec<String>,
+    #[serde(default)]
+    frameworks: Vec<String>,
+    #[serde(default)]
+    options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputType {
+    Execute,
+    Preload,
+}
+
+/// The arguments the spec at `path` stands for.
+pub fn from_file(path: &Path) -> Result<Vec<String>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    parse(&content).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+/// The arguments the spec `content` stands for.
+pub fn parse(content: &str) -> Result<Vec<String>, String> {
+    let spec: Spec = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let mut args = vec![];
+    if let Some(output) = spec.output {
+        args.extend(["-o".to_string(), output]);
+    }
+    if let Some(arch) = spec.arch {
+        args.extend(["-arch".to_string(), arch]);
+    }
+    if let Some(version) = spec.platform_version {
+        let words: Vec<&str> = version.split_whitespace().collect();
+        let [platform, min_version, sdk_version] = words[..] else {
+            return Err(format!(
+                "Expected platform-version to be PLATFORM MIN_VERSION SDK_VERSION, found {version}"
+            ));
+        };
+        args.push("-platform_version".to_string());
+        args.extend([platform, min_version, sdk_version].map(str::to_string));
+    }
+    if let Some(entry) = spec.entry {
+        args.extend(["-e".to_string(), entry]);
+    }
+    if let Some(root) = spec.syslibroot {
+        args.extend(["-syslibroot".to_string(), root]);
+    }
+    for path in spec.library_paths {
+        args.extend(["-L".to_string(), path]);
+    }
+    for path in spec.framework_paths {
+        args.extend(["-F".to_string(), path]);
+    }
+    for path in spec.rpaths {
+        args.extend(["-rpath".to_string(), path]);
+    }
+    match spec.output_type {
+        Some(OutputType::Execute) | None => {}
+        Some(OutputType::Preload) => args.push("-preload".to_string()),
+    }
+    if let Some(threads) = spec.threads {
+        args.push(format!("--threads={threads}"));
+    }
+    args.extend(spec.inputs);
+    args.extend(spec.libraries.into_iter().map(|name| format!("-l{name}")));
+    for name in spec.frameworks {
+        args.extend(["-framework".to_string(), name]);
+    }
+    args.extend(spec.options);
+    Ok(args)
+}
@@ -0,0 +1,259 @@
+/// Statistics about a link, from -print_statistics: how long each phase
+/// took and how much memory the link used, to tell whether it's CPU or
+/// memory that's holding it up.
+///
+/// Phases are the ones [panic_context::set_phase] is told about. Peak
+/// resident memory and CPU time come from getrusage. Allocations are
+/// only counted when built with the alloc-stats feature, which installs
+/// a counting global allocator.
+///
+/// [panic_context::set_phase]: crate::panic_context::set_phase
+use std::{
+    fmt::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+struct Phase {
+    name: &'static str,
+    time: Duration,
+    /// The peak resident memory of the process by the end of the phase.
+    peak_rss: Option<u64>,
+    allocations: Option<Allocations>,
+}
+
+/// Allocation counts over a phase.
+struct Allocations {
+    allocated: u64,
+    /// The change in the bytes allocated but not yet freed.
+    live: i64,
+}
+
+struct Recorder {
+    phases: Vec<Phase>,
+    current: &'static str,
+    started: Instant,
+    counters: Option<(u64, u64)>,
+}
+
+impl Recorder {
+    fn new(phase: &'static str) -> Self {
+        Self {
+            phases: vec![],
+            current: phase,
+            started: Instant::now(),
+            counters: allocation_counters(),
+        }
+    }
+
+    /// Finish the current phase.
+    fn end_phase(&mut self) {
+        let counters = allocation_counters();
+        let allocations =
+            self.counters
+                .zip(counters)
+                .map(
+                    |((allocated, live), (now_allocated, now_live))| Allocations {
+                        allocated: now_allocated - allocated,
+                        live: now_live as i64 - live as i64,
+                    },
+                );
+        self.phases.push(Phase {
+            name: self.current,
+            time: self.started.elapsed(),
+            peak_rss: usage().map(|usage| usage.peak_rss),
+            allocations,
+        });
+        self.started = Instant::now();
+        self.counters = counters;
+    }
+}
+
+/// Start or stop recording statistics for the link that's starting.
+pub fn start(enabled: bool) {
+    *RECORDER.lock().unwrap_or_else(|e| e.into_inner()) =
+        enabled.then(|| Recorder::new("starting"));
+}
+
+/// Record that the link has moved on to `phase`.
+pub fn phase(phase: &'static str) {
+    if let Some(recorder) = RECORDER.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        recorder.end_phase();
+        recorder.current = phase;
+    }
+}
+
+/// Stop recording, returning the report if statistics were being
+/// recorded.
+pub fn finish() -> Option<String> {
+    let mut recorder = RECORDER.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+    recorder.end_phase();
+    Some(report(&recorder.phases))
+}
+
+fn report(phases: &[Phase]) -> String {
+    let mut report = String::new();
+    let counted = phases.iter().any(|phase| phase.allocations.is_some());
+    write!(
+        report,
+        "{:<28}  {:>10}  {:>10}",
+        "PHASE", "TIME", "PEAK RSS"
+    )
+    .unwrap();
+    if counted {
+        write!(report, "  {:>10}  {:>11}", "ALLOCATED", "LIVE CHANGE").unwrap();
+    }
+    writeln!(report).unwrap();
+    let rss = |rss: Option<u64>| rss.map_or("-".to_string(), |rss| bytes(rss as i64));
+    for phase in phases {
+        write!(
+            report,
+            "{:<28}  {:>10}  {:>10}",
+            phase.name,
+            format!("{:.1?}", phase.time),
+            rss(phase.peak_rss)
+        )
+        .unwrap();
+        if let Some(allocations) = &phase.allocations {
+            write!(
+                report,
+                "  {:>10}  {:>11}",
+                bytes(allocations.allocated as i64),
+                bytes(allocations.live)
+            )
+            .unwrap();
+        }
+        writeln!(report).unwrap();
+    }
+    let total: Duration = phases.iter().map(|phase| phase.time).sum();
+    writeln!(
+        report,
+        "{:<28}  {:>10}  {:>10}",
+        "total",
+        format!("{total:.1?}"),
+        rss(phases.last().and_then(|phase| phase.peak_rss))
+    )
+    .unwrap();
+    if let Some(usage) = usage() {
+        writeln!(
+            report,
+            "CPU time: {:.1?} user, {:.1?} system",
+            usage.user, usage.system
+        )
+        .unwrap();
+    }
+    if !counted {
+        writeln!(
+            report,
+            "Build machop with the alloc-stats feature to count allocations."
+        )
+        .unwrap();
+    }
+    report
+}
+
+/// `count` bytes with a binary unit.
+fn bytes(count: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = count as f64;
+    let mut unit = 0;
+    while value.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{count}B")
+    } else {
+        format!("{value:.1}{}", UNITS[unit])
+    }
+}
+
+struct Usage {
+    user: Duration,
+    system: Duration,
+    peak_rss: u64,
+}
+
+/// The process's resource usage so far.
+fn usage() -> Option<Usage> {
+    // SAFETY: struct rusage is plain integers, all zero is valid.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    // SAFETY: usage is a struct rusage for getrusage to fill in.
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let duration = |time: &libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    // ru_maxrss is in bytes on Darwin, KiB elsewhere.
+    let unit = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    Some(Usage {
+        user: duration(&usage.ru_utime),
+        system: duration(&usage.ru_stime),
+        peak_rss: usage.ru_maxrss as u64 * unit,
+    })
+}
+
+/// The bytes allocated so far and those still live, if they're counted.
+fn allocation_counters() -> Option<(u64, u64)> {
+    #[cfg(feature = "alloc-stats")]
+    {
+        Some(counting::counters())
+    }
+    #[cfg(not(feature = "alloc-stats"))]
+    {
+        None
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+mod counting {
+    use std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicU64, Ordering},
+    };
+
+    static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+    static LIVE: AtomicU64 = AtomicU64::new(0);
+
+    struct Counting;
+
+    // SAFETY: everything is passed on to the system allocator.
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let ptr = System.alloc(layout);
+            if !ptr.is_null() {
+                ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed);
+                LIVE.fetch_add(layout.size() as u64, Ordering::Relaxed);
+            }
+            ptr
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout);
+            LIVE.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            let new = System.realloc(ptr, layout, new_size);
+            if !new.is_null() {
+                ALLOCATED.fetch_add(new_size as u64, Ordering::Relaxed);
+                LIVE.fetch_add(new_size as u64, Ordering::Relaxed);
+                LIVE.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+            }
+            new
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: Counting = Counting;
+
+    pub fn counters() -> (u64, u64) {
+        (
+            ALLOCATED.load(Ordering::Relaxed),
+            LIVE.load(Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,59 @@
+/// The output string table.
+///
+/// Each name is stored once, and a name that's the tail of another, like
+/// `_init` of `__init` or `_end` of `_section_end`, points into it rather
+/// than being stored again. The layout only depends on the set of names, so
+/// it's the same from one run to the next.
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct StringTable {
+    data: Vec<u8>,
+    offsets: HashMap<String, u32>,
+}
+
+impl StringTable {
+    pub fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut names: Vec<&str> = names.into_iter().filter(|name| !name.is_empty()).collect();
+        // Sorting by the reversed names puts a name straight after the
+        // longer names it's a suffix of.
+        names.sort_unstable_by(|a, b| b.bytes().rev().cmp(a.bytes().rev()));
+        names.dedup();
+        // Like ld64, offset 0 is a space so that the empty name, at
+        // offset 1, can't be confused with n_strx 0.
+        let mut data = b" \0".to_vec();
+        let mut offsets = HashMap::new();
+        let mut previous: Option<(&str, u32)> = None;
+        for name in names {
+            let offset = match previous {
+                Some((longer, offset)) if longer.ends_with(name) => {
+                    offset + (longer.len() - name.len()) as u32
+                }
+                _ => {
+                    let offset = data.len() as u32;
+                    data.extend_from_slice(name.as_bytes());
+                    data.push(0);
+                    previous = Some((name, offset));
+                    offset
+                }
+            };
+            offsets.insert(name.to_string(), offset);
+        }
+        // ld64 pads the table to a multiple of the pointer size.
+        data.resize(data.len().next_multiple_of(8), 0);
+        Self { data, offsets }
+    }
+
+    /// The offset of `name`, which must have been given to
+    /// [StringTable::new]. The empty name is at offset 1.
+    pub fn offset(&self, name: &str) -> u32 {
+        if name.is_empty() {
+            return 1;
+        }
+        self.offsets[name]
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,79 @@
+/// Symbol list files as taken by flags like -move_to_rw_segment.
+///
+/// Each line holds one symbol name, `#` starts a comment and names may
+/// contain `*` and `?` wildcards.
+use std::{collections::HashSet, path::Path};
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolList {
+    names: HashSet<String>,
+    patterns: Vec<String>,
+}
+
+impl SymbolList {
+    pub fn parse(content: &str) -> Self {
+        let mut list = Self::default();
+        for line in content.lines() {
+            let line = match line.find('#') {
+                Some(start) => &line[..start],
+                None => line,
+            }
+            .trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.contains(['*', '?']) {
+                list.patterns.push(line.to_string());
+            } else {
+                list.names.insert(line.to_string());
+            }
+        }
+        list
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read symbol list {}: {e}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains(name)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| wildcard_match(pattern.as_bytes(), name.as_bytes()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.patterns.is_empty()
+    }
+}
+
+fn wildcard_match(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` seen and the name position it's
+    // currently matched up to, for backtracking.
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
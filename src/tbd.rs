@@ -1,7 +1,19 @@
-use std::{collections::HashMap, path::PathBuf};
-
 /// Parse .tbd files.
-use crate::linker_args::Architecture;
+///
+/// [TbdLibrary] is a document as written, for every target it lists,
+/// for tools that want more than the linker uses. The resolver works on
+/// a [TbdDylib], its view for the target being linked.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    linker_args::{Architecture, Platform, Version},
+    reexports,
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -34,30 +46,245 @@ impl std::fmt::Display for Error {
     }
 }
 
+/// Something that only applies to some of a library's targets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Targeted<T> {
+    /// Target triples, like `arm64-macos`.
+    pub targets: Vec<String>,
+    pub value: T,
+}
+
+/// The symbols exported to some of a library's targets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportSet {
+    pub targets: Vec<String>,
+    /// Whether the symbols are re-exported from another library.
+    pub reexported: bool,
+    pub symbols: Vec<String>,
+    pub weak_symbols: Vec<String>,
+    pub thread_local_symbols: Vec<String>,
+    pub objc_classes: Vec<String>,
+    pub objc_eh_types: Vec<String>,
+    pub objc_ivars: Vec<String>,
+}
+
+impl ExportSet {
+    fn new(section: text_stub_library::yaml::TbdVersion4ExportSection, reexported: bool) -> Self {
+        Self {
+            targets: section.targets,
+            reexported,
+            symbols: section.symbols,
+            weak_symbols: section.weak_symbols,
+            thread_local_symbols: section.thread_local_symbols,
+            objc_classes: section.objc_classes,
+            objc_eh_types: section.objc_eh_types,
+            objc_ivars: section.objc_ivars,
+        }
+    }
+}
+
+/// A library as described by one TBD document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TbdLibrary {
+    pub install_name: PathBuf,
+    /// Target triples, like `arm64-macos`.
+    pub targets: Vec<String>,
+    pub uuids: Vec<Targeted<String>>,
+    /// Flags like `not_app_extension_safe`.
+    pub flags: Vec<String>,
+    pub current_version: Version,
+    pub compatibility_version: Version,
+    /// Versions that couldn't be parsed, which are taken as 1.0.
+    pub malformed_versions: Vec<String>,
+    pub swift_abi_version: Option<String>,
+    pub parent_umbrellas: Vec<Targeted<String>>,
+    /// Libraries re-exported directly.
+    pub reexported_libraries: Vec<Targeted<Vec<PathBuf>>>,
+    pub exports: Vec<ExportSet>,
+}
+
+impl TbdLibrary {
+    /// Parse every document in a .tbd file. Only version 4 documents
+    /// are understood, earlier ones are skipped.
+    pub fn parse_documents(content: &[u8]) -> Result<Vec<Self>, Error> {
+        let text = std::str::from_utf8(content)?;
+        Ok(text_stub_library::parse_str(text)?
+            .into_iter()
+            .filter_map(|tbd| match tbd {
+                text_stub_library::TbdVersionedRecord::V4(v4) => Some(Self::from_v4(v4)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn from_v4(tbd: text_stub_library::yaml::TbdVersion4) -> Self {
+        // Both versions default to 1.0 when they're not given.
+        let mut malformed_versions = vec![];
+        let mut version = |field: &str, version: Option<String>| match version
+            .map(|version| version.parse::<Version>())
+        {
+            Some(Ok(version)) => version,
+            Some(Err(e)) => {
+                malformed_versions.push(format!("has a malformed {field}: {e}"));
+                Version::new(1, 0, 0)
+            }
+            None => Version::new(1, 0, 0),
+        };
+        let current_version = version("current-version", tbd.current_version);
+        let compatibility_version = version("compatibility-version", tbd.compatibility_version);
+        let exports = tbd.exports.into_iter().map(|section| (section, false));
+        let reexports = tbd.re_exports.into_iter().map(|section| (section, true));
+        Self {
+            install_name: PathBuf::from(tbd.install_name),
+            targets: tbd.targets,
+            uuids: tbd
+                .uuids
+                .into_iter()
+                .map(|uuid| Targeted {
+                    targets: vec![uuid.target],
+                    value: uuid.value,
+                })
+                .collect(),
+            flags: tbd.flags,
+            current_version,
+            compatibility_version,
+            malformed_versions,
+            swift_abi_version: tbd.swift_abi_version,
+            parent_umbrellas: tbd
+                .parent_umbrella
+                .into_iter()
+                .map(|umbrella| Targeted {
+                    targets: umbrella.targets,
+                    value: umbrella.umbrella,
+                })
+                .collect(),
+            reexported_libraries: tbd
+                .reexported_libraries
+                .into_iter()
+                .map(|reexport| Targeted {
+                    targets: reexport.targets,
+                    value: reexport.libraries.into_iter().map(PathBuf::from).collect(),
+                })
+                .collect(),
+            exports: exports
+                .chain(reexports)
+                .map(|(section, reexported)| ExportSet::new(section, reexported))
+                .collect(),
+        }
+    }
+
+    /// The library as linked for `arch` and one of `platforms`, or None
+    /// if it isn't built for any of them.
+    pub fn view(&self, arch: &Architecture, platforms: &[Platform]) -> Option<TbdDylib> {
+        let matches = |targets: &[String]| {
+            targets
+                .iter()
+                .any(|triple| match_target(arch, platforms, triple))
+        };
+        if !matches(&self.targets) {
+            return None;
+        }
+        let mut exports = vec![];
+        let mut weak_exports = vec![];
+        // TODO: ObjC symbols
+        for set in self.exports.iter().filter(|set| matches(&set.targets)) {
+            exports.extend_from_slice(&set.symbols);
+            weak_exports.extend_from_slice(&set.weak_symbols);
+        }
+        Some(TbdDylib {
+            install_name: self.install_name.clone(),
+            current_version: self.current_version,
+            compatibility_version: self.compatibility_version,
+            malformed_versions: self.malformed_versions.clone(),
+            reexported_libraries: self
+                .reexported_libraries
+                .iter()
+                .filter(|reexport| matches(&reexport.targets))
+                .flat_map(|reexport| reexport.value.iter().cloned())
+                .collect(),
+            exports,
+            weak_exports,
+            app_extension_safe: !self
+                .flags
+                .iter()
+                .any(|flag| flag == "not_app_extension_safe"),
+            reexports: vec![],
+        })
+    }
+}
+
+/// A library as linked for one target.
 #[derive(Debug)]
 pub struct TbdDylib {
     pub install_name: PathBuf,
+    pub current_version: Version,
+    pub compatibility_version: Version,
+    /// Versions that couldn't be parsed, which are taken as 1.0.
+    pub malformed_versions: Vec<String>,
+    /// Every library re-exported, directly or through another
+    /// re-exported library.
     pub reexported_libraries: Vec<PathBuf>,
     pub exports: Vec<String>,
     pub weak_exports: Vec<String>,
+    /// Whether the library is safe to use in app extensions, which it
+    /// is unless flagged `not_app_extension_safe`.
+    pub app_extension_safe: bool,
+    /// Re-exported libraries described in the same file. Their exports
+    /// are included in `exports`.
+    pub reexports: Vec<TbdDylib>,
 }
 
-fn match_arch(arch: &Architecture, triple: &str) -> bool {
+/// Whether a library sits where ld64 considers it public, in which case
+/// it can be linked against directly when it's re-exported by another
+/// library (an implicit dylib).
+pub fn is_public_install_name(install_name: &Path) -> bool {
+    let Some(parent) = install_name.parent() else {
+        return false;
+    };
+    if parent == Path::new("/usr/lib") {
+        return true;
+    }
+    // Top level frameworks, i.e. /System/Library/Frameworks/Foo.framework/Foo
+    // or the versioned Foo.framework/Versions/A/Foo.
+    let framework = parent
+        .ancestors()
+        .find(|ancestor| ancestor.extension().is_some_and(|ext| ext == "framework"));
+    match (framework, install_name.file_name()) {
+        (Some(framework), Some(name)) => {
+            framework.parent() == Some(Path::new("/System/Library/Frameworks"))
+                && framework.file_stem() == Some(name)
+        }
+        _ => false,
+    }
+}
+
+/// Whether a TBD target like `arm64-macos` is for `arch` and one of
+/// `platforms`. An empty list of platforms matches any platform.
+fn match_target(arch: &Architecture, platforms: &[Platform], triple: &str) -> bool {
     let arch = arch.to_string();
-    arch == triple || triple.starts_with(&format!("{arch}-"))
+    if triple == arch {
+        return true;
+    }
+    let Some(target_platform) = triple.strip_prefix(&format!("{arch}-")) else {
+        return false;
+    };
+    platforms.is_empty()
+        || Platform::from_target(target_platform).is_some_and(|p| platforms.contains(&p))
 }
 
 impl TbdDylib {
-    pub fn parse(arch: Architecture, content: &[u8]) -> Result<Self, Error> {
-        let text = std::str::from_utf8(content)?;
-        let mut tbds: Vec<TbdDylib> = text_stub_library::parse_str(text)?
-            .into_iter()
-            .filter_map(|tbd| match Self::parse_one(&arch, tbd) {
-                Ok(Some(v)) => Some(Ok(v)),
-                Ok(None) => None,
-                Err(e) => Some(Err(e)),
-            })
-            .collect::<Result<Vec<TbdDylib>, Error>>()?;
+    /// Parse the documents in a .tbd file, keeping the parts that apply
+    /// to `arch` and any of `platforms`. Without platforms, any platform
+    /// for the architecture matches.
+    pub fn parse(
+        arch: Architecture,
+        platforms: &[Platform],
+        content: &[u8],
+    ) -> Result<Self, Error> {
+        let mut tbds: Vec<TbdDylib> = TbdLibrary::parse_documents(content)?
+            .iter()
+            .filter_map(|library| library.view(&arch, platforms))
+            .collect();
         if tbds.is_empty() {
             return Err(Error::InvalidDocument);
         };
@@ -66,89 +293,79 @@ impl TbdDylib {
         let mut main = tbds.remove(0);
         let tbds_by_install_name: HashMap<&PathBuf, &TbdDylib> =
             tbds.iter().map(|tbd| (&tbd.install_name, tbd)).collect();
+        // Re-exports described in the same file contribute their exports,
+        // and any libraries they re-export in turn are re-exported too.
         let mut exports = vec![];
         let mut weak_exports = vec![];
-        let mut reexported_libs = vec![];
-        let mut visit = |tbd: &TbdDylib| {
-            for lib in &tbd.reexported_libraries {
-                if let Some(child) = tbds_by_install_name.get(&lib) {
-                    let mut child_exports = child.exports.clone();
-                    exports.append(&mut child_exports);
-                    let mut child_weak_exports = child.weak_exports.clone();
-                    weak_exports.append(&mut child_weak_exports);
-                } else {
-                    reexported_libs.push(lib.to_owned())
+        let mut reexported_libs: Vec<PathBuf> = vec![];
+        let root = main.install_name.display().to_string();
+        reexports::walk(&root, |name| {
+            if name == root {
+                return main
+                    .reexported_libraries
+                    .iter()
+                    .map(|lib| lib.display().to_string())
+                    .collect();
+            }
+            let lib = PathBuf::from(name);
+            reexported_libs.push(lib.clone());
+            match tbds_by_install_name.get(&lib) {
+                Some(child) => {
+                    exports.extend_from_slice(&child.exports);
+                    weak_exports.extend_from_slice(&child.weak_exports);
+                    child
+                        .reexported_libraries
+                        .iter()
+                        .map(|lib| lib.display().to_string())
+                        .collect()
                 }
+                None => vec![],
             }
-        };
-        visit(&main);
+        });
         main.exports.append(&mut exports);
         main.weak_exports.append(&mut weak_exports);
-        main.reexported_libraries.append(&mut reexported_libs);
+        main.reexported_libraries = reexported_libs;
+        main.reexports = tbds
+            .into_iter()
+            .filter(|tbd| main.reexported_libraries.contains(&tbd.install_name))
+            .collect();
         Ok(main)
     }
+}
 
-    fn parse_one(
-        arch: &Architecture,
-        tbd: text_stub_library::TbdVersionedRecord,
-    ) -> Result<Option<Self>, Error> {
-        let tbd = match tbd {
-            text_stub_library::TbdVersionedRecord::V1(_)
-            | text_stub_library::TbdVersionedRecord::V2(_)
-            | text_stub_library::TbdVersionedRecord::V3(_) => return Ok(None),
-            text_stub_library::TbdVersionedRecord::V4(v4) => {
-                if v4.targets.iter().any(|triple| match_arch(arch, triple)) {
-                    v4
-                } else {
-                    return Ok(None);
-                }
-            }
+/// The symbols a .tbd file only exports for architectures other than
+/// `arch`, each with one of those architectures. This is only used to
+/// explain undefined symbols, so it isn't cached.
+pub fn foreign_exports(
+    arch: &Architecture,
+    content: &[u8],
+) -> Result<Vec<(String, String)>, Error> {
+    let arch = arch.to_string();
+    let text = std::str::from_utf8(content)?;
+    let mut foreign = vec![];
+    for tbd in text_stub_library::parse_str(text)? {
+        let text_stub_library::TbdVersionedRecord::V4(tbd) = tbd else {
+            continue;
         };
-        let reexported_libraries = tbd
-            .reexported_libraries
-            .into_iter()
-            .flat_map(|reexport| {
-                if reexport
-                    .targets
-                    .iter()
-                    .any(|triple| match_arch(arch, triple))
-                {
-                    reexport.libraries.iter().map(PathBuf::from).collect()
-                } else {
-                    vec![]
-                }
-            })
-            .collect();
-        let mut all_exports = vec![];
-        let mut all_weak_exports = vec![];
-        for exports in tbd.exports {
-            if exports
+        for exports in tbd.exports.iter().chain(&tbd.re_exports) {
+            let mut arches = exports
                 .targets
                 .iter()
-                .any(|triple| match_arch(arch, triple))
-            {
-                all_exports.append(&mut exports.symbols.clone());
-                all_weak_exports.append(&mut exports.weak_symbols.clone());
-            }
-        }
-        for reexport in tbd.re_exports {
-            if reexport
-                .targets
-                .iter()
-                .any(|triple| match_arch(arch, triple))
-            {
-                all_exports.append(&mut reexport.symbols.clone());
-                all_weak_exports.append(&mut reexport.weak_symbols.clone());
+                .filter_map(|triple| triple.split('-').next());
+            if arches.clone().any(|target_arch| target_arch == arch) {
+                continue;
             }
+            let Some(target_arch) = arches.next() else {
+                continue;
+            };
+            foreign.extend(
+                exports
+                    .symbols
+                    .iter()
+                    .chain(&exports.weak_symbols)
+                    .map(|symbol| (symbol.clone(), target_arch.to_string())),
+            );
         }
-
-        // TODO: ObjC symbols
-
-        Ok(Some(TbdDylib {
-            install_name: PathBuf::from(tbd.install_name),
-            reexported_libraries,
-            exports: all_exports,
-            weak_exports: all_weak_exports,
-        }))
     }
+    Ok(foreign)
 }
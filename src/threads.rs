@@ -0,0 +1,81 @@
+/// Work splitting across threads.
+///
+/// All the parallel parts of a link go through a [ThreadPool] so the
+/// number of threads is controlled in one place with -threads. With one
+/// thread everything runs on the calling thread, in order.
+use std::num::NonZeroUsize;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThreadPool {
+    threads: usize,
+}
+
+impl Default for ThreadPool {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl ThreadPool {
+    /// A pool of `threads` threads, or one per CPU when `threads` is 0.
+    pub fn new(threads: usize) -> Self {
+        let threads = match threads {
+            0 => std::thread::available_parallelism()
+                .map(NonZeroUsize::get)
+                .unwrap_or(1),
+            n => n,
+        };
+        Self { threads }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Apply `f` to every item, keeping the results in the same order
+    /// as the items.
+    pub fn map<T, R, F>(&self, items: &[T], f: F) -> Vec<R>
+    where
+        T: Sync,
+        R: Send,
+        F: Fn(&T) -> R + Sync,
+    {
+        if self.threads == 1 || items.len() < 2 {
+            return items.iter().map(f).collect();
+        }
+        let chunk_size = items.len().div_ceil(self.threads);
+        std::thread::scope(|scope| {
+            let f = &f;
+            let handles: Vec<_> = items
+                .chunks(chunk_size)
+                .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<R>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| match handle.join() {
+                    Ok(results) => results,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        })
+    }
+
+    /// Like [ThreadPool::map] but each item is given mutably.
+    pub fn for_each_mut<T, F>(&self, items: &mut [T], f: F)
+    where
+        T: Send,
+        F: Fn(&mut T) + Sync,
+    {
+        if self.threads == 1 || items.len() < 2 {
+            items.iter_mut().for_each(f);
+            return;
+        }
+        let chunk_size = items.len().div_ceil(self.threads);
+        std::thread::scope(|scope| {
+            let f = &f;
+            for chunk in items.chunks_mut(chunk_size) {
+                scope.spawn(move || chunk.iter_mut().for_each(f));
+            }
+        });
+    }
+}
@@ -0,0 +1,161 @@
+/// A Chrome trace of the link, from -time_trace, for chrome://tracing or
+/// Perfetto to show where a slow link spends its time, alongside clang's
+/// -ftime-trace output.
+///
+/// Like -print_statistics, the phases are the ones
+/// [panic_context::set_phase] is told about. Within them, reading,
+/// parsing and relocating each input are spans of their own, on the
+/// thread that did the work. Recording is off unless a trace was asked
+/// for, when a span is only a check of a flag.
+///
+/// [panic_context::set_phase]: crate::panic_context::set_phase
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+static TRACE: Mutex<Option<Trace>> = Mutex::new(None);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+struct Event {
+    name: &'static str,
+    /// What the span was working on, like the input's path.
+    detail: Option<String>,
+    thread: u64,
+    start: Duration,
+    duration: Duration,
+}
+
+struct Trace {
+    started: Instant,
+    events: Vec<Event>,
+    /// The phase being recorded and when it started.
+    phase: (&'static str, Duration),
+}
+
+impl Trace {
+    fn end_phase(&mut self) {
+        let (name, start) = self.phase;
+        let end = self.started.elapsed();
+        self.events.push(Event {
+            name,
+            detail: None,
+            thread: THREAD.with(|thread| *thread),
+            start,
+            duration: end - start,
+        });
+        self.phase.1 = end;
+    }
+}
+
+/// A span of work, recorded when it's dropped.
+pub struct Span {
+    started: Option<(Instant, &'static str, String)>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some((started, name, detail)) = self.started.take() else {
+            return;
+        };
+        let duration = started.elapsed();
+        if let Some(trace) = TRACE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            trace.events.push(Event {
+                name,
+                detail: Some(detail),
+                thread: THREAD.with(|thread| *thread),
+                start: started.saturating_duration_since(trace.started),
+                duration,
+            });
+        }
+    }
+}
+
+/// Start or stop tracing the link that's starting.
+pub fn start(enabled: bool) {
+    let mut trace = TRACE.lock().unwrap_or_else(|e| e.into_inner());
+    *trace = enabled.then(|| Trace {
+        started: Instant::now(),
+        events: vec![],
+        phase: ("starting", Duration::ZERO),
+    });
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Record that the link has moved on to `phase`.
+pub fn phase(phase: &'static str) {
+    if let Some(trace) = TRACE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        trace.end_phase();
+        trace.phase.0 = phase;
+    }
+}
+
+/// Start a span called `name`, working on whatever `detail` describes,
+/// which is only asked for when tracing.
+pub fn span(name: &'static str, detail: impl FnOnce() -> String) -> Span {
+    Span {
+        started: ENABLED
+            .load(Ordering::Relaxed)
+            .then(|| (Instant::now(), name, detail())),
+    }
+}
+
+/// Stop tracing, returning the trace's JSON if the link was being
+/// traced.
+pub fn finish() -> Option<String> {
+    let mut trace = TRACE.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+    ENABLED.store(false, Ordering::Relaxed);
+    trace.end_phase();
+    Some(json(&trace.events))
+}
+
+fn json(events: &[Event]) -> String {
+    let mut json = String::new();
+    json.push_str("{\"traceEvents\":[\n");
+    write!(
+        json,
+        r#"{{"ph":"M","pid":1,"tid":0,"name":"process_name","args":{{"name":"machop"}}}}"#
+    )
+    .unwrap();
+    for event in events {
+        write!(
+            json,
+            ",\n{{\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{},\"dur\":{},\"name\":\"{}\"",
+            event.thread,
+            event.start.as_micros(),
+            event.duration.as_micros(),
+            escape(event.name)
+        )
+        .unwrap();
+        if let Some(detail) = &event.detail {
+            write!(json, ",\"args\":{{\"detail\":\"{}\"}}", escape(detail)).unwrap();
+        }
+        json.push('}');
+    }
+    json.push_str("\n]}\n");
+    json
+}
+
+/// `text` escaped for a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,492 @@
+/// Checking a linked binary against the libraries it will run with, for
+/// `machop verify`, so a binary that dyld would refuse to launch is
+/// caught before it's deployed.
+///
+/// Every library the binary loads has to be found, be at least the
+/// compatibility version recorded when the binary was linked, and export
+/// each symbol the binary imports from it. Imports are read both from
+/// the bind opcodes ld64 writes and from the two-level namespace
+/// ordinals of undefined symbols, which is all machop writes. Weakly
+/// linked libraries and weak imports may be missing at runtime, so
+/// they're only warnings.
+///
+/// Libraries are found like re-exports are when linking: from
+/// -dylib_file, then in each -syslibroot, and for @rpath install names
+/// under the binary's run paths and the -L directories, preferring a
+/// .tbd next to where the library would be.
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ffi::OsString,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use goblin::mach::{
+    load_command::CommandVariant,
+    symbols::{N_EXT, N_WEAK_REF},
+    Mach, MachO, MultiArch,
+};
+
+use crate::{
+    link::{self, Dylib},
+    linker_args::{Architecture, Platform, PlatformVersion, Version},
+    reexports,
+    tbd::TbdDylib,
+};
+
+/// Library ordinals that don't name a library: the image itself, flat
+/// namespace lookups and the main executable.
+const SELF_LIBRARY_ORDINAL: u16 = 0;
+const DYNAMIC_LOOKUP_ORDINAL: u16 = 0xfe;
+const EXECUTABLE_ORDINAL: u16 = 0xff;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub binary: PathBuf,
+    pub sys_lib_roots: Vec<PathBuf>,
+    /// Directories to look for @rpath libraries in, from -L.
+    pub search_paths: Vec<PathBuf>,
+    /// Install names and the files to read for them, from -dylib_file.
+    pub dylib_files: Vec<(String, PathBuf)>,
+}
+
+impl Options {
+    /// The options following `machop verify`.
+    pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Self, String> {
+        let mut options = Options::default();
+        let mut binary = None;
+        let mut args = args.into_iter().map(|arg| {
+            arg.into_string()
+                .map_err(|arg| format!("Invalid argument {}", arg.to_string_lossy()))
+        });
+        while let Some(arg) = args.next() {
+            let arg = arg?;
+            let mut value = || {
+                args.next()
+                    .unwrap_or_else(|| Err(format!("{arg} expects a value")))
+            };
+            match &arg[..] {
+                "-syslibroot" => options.sys_lib_roots.push(value()?.into()),
+                "-L" => options.search_paths.push(value()?.into()),
+                "-dylib_file" => {
+                    let value = value()?;
+                    let (install_name, file) = value.split_once(':').ok_or_else(|| {
+                        format!("-dylib_file {value} should be <INSTALL_NAME>:<FILE>")
+                    })?;
+                    options
+                        .dylib_files
+                        .push((install_name.to_string(), file.into()));
+                }
+                _ => match arg.strip_prefix("-L") {
+                    Some(path) => options.search_paths.push(path.into()),
+                    None if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+                    None if binary.is_some() => {
+                        return Err("verify expects a single binary".to_string())
+                    }
+                    None => binary = Some(PathBuf::from(arg)),
+                },
+            }
+        }
+        options.binary = binary.ok_or_else(|| "verify expects the binary to check".to_string())?;
+        Ok(options)
+    }
+}
+
+#[derive(Debug)]
+pub enum Problem {
+    /// A library that can't be found or read.
+    MissingLibrary {
+        install_name: String,
+        /// Why it couldn't be read, if it was found.
+        error: Option<String>,
+        weak: bool,
+    },
+    /// A library older than the binary was linked against.
+    IncompatibleVersion {
+        path: PathBuf,
+        current: Version,
+        required: Version,
+    },
+    /// A symbol the library it's bound to doesn't export.
+    MissingSymbol {
+        symbol: String,
+        install_name: String,
+        weak: bool,
+    },
+}
+
+impl Problem {
+    /// Whether dyld would fail to launch the binary because of it.
+    pub fn is_error(&self) -> bool {
+        match self {
+            Problem::MissingLibrary { weak, .. } | Problem::MissingSymbol { weak, .. } => !weak,
+            Problem::IncompatibleVersion { .. } => true,
+        }
+    }
+}
+
+impl std::error::Error for Problem {}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingLibrary {
+                install_name,
+                error,
+                weak,
+            } => {
+                match error {
+                    Some(error) => write!(f, "{error}")?,
+                    None => write!(f, "Unable to find {install_name}")?,
+                }
+                if *weak {
+                    write!(f, ", it's weakly linked so dyld will skip it")?;
+                }
+                Ok(())
+            }
+            Problem::IncompatibleVersion {
+                path,
+                current,
+                required,
+            } => write!(
+                f,
+                "{} has current version {current}, but the binary needs at least {required}",
+                path.display()
+            ),
+            Problem::MissingSymbol {
+                symbol,
+                install_name,
+                weak,
+            } => {
+                write!(f, "{symbol} isn't exported by {install_name}")?;
+                if *weak {
+                    write!(f, ", it's weakly imported so it will be NULL")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// What was checked, and what's wrong.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub libraries: usize,
+    pub imports: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Check the binary `options` names, each of its slices if it's fat.
+pub fn verify(options: &Options) -> Result<Report, String> {
+    let bytes = std::fs::read(&options.binary)
+        .map_err(|e| format!("Unable to read {}: {e}", options.binary.display()))?;
+    let parse_error = |e| format!("Unable to parse {}: {e}", options.binary.display());
+    let mut report = Report::default();
+    for slice in link::slices(&bytes).map_err(parse_error)? {
+        let macho = MachO::parse(slice, 0).map_err(parse_error)?;
+        verify_image(options, &macho, &mut report)?;
+    }
+    Ok(report)
+}
+
+/// A library the binary loads.
+struct Library {
+    install_name: String,
+    weak: bool,
+    compatibility_version: Version,
+    /// Everything it exports, including through the libraries it
+    /// re-exports. None if it couldn't be read.
+    exports: Option<HashSet<String>>,
+}
+
+fn verify_image(options: &Options, macho: &MachO, report: &mut Report) -> Result<(), String> {
+    let Some(arch) = Architecture::ALL
+        .iter()
+        .find(|arch| arch.cpu_type() == macho.header.cputype())
+    else {
+        log::warn!(
+            "Skipping the {} slice of {}, machop doesn't support it",
+            goblin::mach::cputype::get_arch_name_from_types(
+                macho.header.cputype(),
+                macho.header.cpusubtype()
+            )
+            .unwrap_or("unknown"),
+            options.binary.display()
+        );
+        return Ok(());
+    };
+    let platforms: Vec<Platform> = PlatformVersion::from_load_commands(macho)
+        .map(|platform_version| vec![platform_version.platform])
+        .unwrap_or_default();
+    let finder = Finder {
+        options,
+        rpaths: &macho.rpaths,
+    };
+
+    // libs has the names of the library load commands, in order, after
+    // "self", so a library ordinal indexes both.
+    let mut libraries = vec![];
+    let commands = macho
+        .load_commands
+        .iter()
+        .filter_map(|command| match &command.command {
+            CommandVariant::LoadDylib(dylib)
+            | CommandVariant::LoadUpwardDylib(dylib)
+            | CommandVariant::ReexportDylib(dylib)
+            | CommandVariant::LazyLoadDylib(dylib) => Some((dylib, false)),
+            CommandVariant::LoadWeakDylib(dylib) => Some((dylib, true)),
+            _ => None,
+        });
+    for ((dylib, weak), install_name) in commands.zip(macho.libs.iter().skip(1)) {
+        let required = Version::from_u32(dylib.dylib.compatibility_version);
+        let exports = match finder.find(install_name) {
+            Some(path) => match finder.exports(install_name, &path, arch, &platforms) {
+                Ok((exports, current)) => {
+                    if current < required {
+                        report.problems.push(Problem::IncompatibleVersion {
+                            path,
+                            current,
+                            required,
+                        });
+                    }
+                    Some(exports)
+                }
+                Err(error) => {
+                    report.problems.push(Problem::MissingLibrary {
+                        install_name: install_name.to_string(),
+                        error: Some(error),
+                        weak,
+                    });
+                    None
+                }
+            },
+            None => {
+                report.problems.push(Problem::MissingLibrary {
+                    install_name: install_name.to_string(),
+                    error: None,
+                    weak,
+                });
+                None
+            }
+        };
+        libraries.push(Library {
+            install_name: install_name.to_string(),
+            weak,
+            compatibility_version: required,
+            exports,
+        });
+    }
+    for library in &libraries {
+        log::debug!(
+            "{} needs {} at compatibility version {}",
+            options.binary.display(),
+            library.install_name,
+            library.compatibility_version
+        );
+    }
+
+    // Each is a symbol, the index of the library it's bound to and
+    // whether it's a weak import.
+    let mut imports: Vec<(&str, usize, bool)> = macho
+        .symbols()
+        .filter_map(Result::ok)
+        .filter(|(_, nlist)| nlist.is_undefined() && nlist.n_type & N_EXT != 0)
+        .filter_map(|(name, nlist)| {
+            let ordinal = nlist.n_desc >> 8;
+            if matches!(
+                ordinal,
+                SELF_LIBRARY_ORDINAL | DYNAMIC_LOOKUP_ORDINAL | EXECUTABLE_ORDINAL
+            ) || ordinal as usize > libraries.len()
+            {
+                return None;
+            }
+            let weak = nlist.n_desc & N_WEAK_REF != 0;
+            Some((name, ordinal as usize - 1, weak))
+        })
+        .collect();
+    let binds = macho.imports().map_err(|e| {
+        format!(
+            "Unable to read the bind information of {}: {e}",
+            options.binary.display()
+        )
+    })?;
+    for import in binds {
+        // Binds to the image itself or looked up in the flat namespace
+        // are reported as binds to "self".
+        if import.dylib == macho.libs[0] {
+            continue;
+        }
+        if let Some(index) = macho
+            .libs
+            .iter()
+            .skip(1)
+            .position(|lib| *lib == import.dylib)
+        {
+            imports.push((import.name, index, import.is_weak));
+        }
+    }
+    imports.sort_unstable();
+    // A symbol imported both weakly and not is only weak if every
+    // import of it is.
+    imports.dedup_by(|next, kept| {
+        let same = next.0 == kept.0 && next.1 == kept.1;
+        if same {
+            kept.2 &= next.2;
+        }
+        same
+    });
+
+    for &(symbol, index, weak) in &imports {
+        let library = &libraries[index];
+        let Some(exports) = &library.exports else {
+            continue;
+        };
+        if !exports.contains(symbol) {
+            report.problems.push(Problem::MissingSymbol {
+                symbol: symbol.to_string(),
+                install_name: library.install_name.clone(),
+                weak: weak || library.weak,
+            });
+        }
+    }
+    report.libraries += libraries.len();
+    report.imports += imports.len();
+    Ok(())
+}
+
+struct Finder<'a> {
+    options: &'a Options,
+    /// The binary's LC_RPATHs.
+    rpaths: &'a [&'a str],
+}
+
+impl Finder<'_> {
+    /// Where the library installed as `install_name` is.
+    fn find(&self, install_name: &str) -> Option<PathBuf> {
+        if let Some((_, file)) = self
+            .options
+            .dylib_files
+            .iter()
+            .find(|(name, _)| name == install_name)
+        {
+            return Some(file.clone());
+        }
+        self.candidates(install_name)
+            .into_iter()
+            .flat_map(|path| [path.with_extension("tbd"), path])
+            .find(|path| path.exists())
+    }
+
+    fn candidates(&self, install_name: &str) -> Vec<PathBuf> {
+        let binary_dir = self.options.binary.parent().unwrap_or(Path::new("."));
+        // The binary is both the executable and, since re-exported
+        // libraries aren't told apart, the loader.
+        let expand = |path: &str| -> Vec<PathBuf> {
+            if let Some(rest) = path
+                .strip_prefix("@executable_path/")
+                .or_else(|| path.strip_prefix("@loader_path/"))
+            {
+                return vec![binary_dir.join(rest)];
+            }
+            let path = Path::new(path);
+            let rooted = self
+                .options
+                .sys_lib_roots
+                .iter()
+                .map(|root| root.join(path.strip_prefix("/").unwrap_or(path)));
+            rooted.chain([path.to_path_buf()]).collect()
+        };
+        match install_name.strip_prefix("@rpath/") {
+            Some(rest) => self
+                .rpaths
+                .iter()
+                .flat_map(|rpath| expand(rpath))
+                .chain(self.options.search_paths.iter().cloned())
+                .map(|dir| dir.join(rest))
+                .collect(),
+            None => expand(install_name),
+        }
+    }
+
+    /// The exports and current version of the library installed as
+    /// `install_name` at `path`, following its re-exports.
+    fn exports(
+        &self,
+        install_name: &str,
+        path: &Path,
+        arch: &Architecture,
+        platforms: &[Platform],
+    ) -> Result<(HashSet<String>, Version), String> {
+        let (mut exports, current, root_reexports) = read_library(path, arch, platforms)?;
+        reexports::walk(install_name, |name| {
+            if name == install_name {
+                return root_reexports.clone();
+            }
+            let Some(path) = self.find(name) else {
+                log::warn!("Unable to find {name}, re-exported by {install_name}");
+                return vec![];
+            };
+            match read_library(&path, arch, platforms) {
+                Ok((child, _, reexports)) => {
+                    exports.extend(child);
+                    reexports
+                }
+                Err(e) => {
+                    log::warn!("{e}");
+                    vec![]
+                }
+            }
+        });
+        Ok((exports, current))
+    }
+}
+
+/// The exports and current version of the dylib or TBD at `path`, and
+/// the install names of the libraries it re-exports that aren't
+/// described along with it.
+fn read_library(
+    path: &Path,
+    arch: &Architecture,
+    platforms: &[Platform],
+) -> Result<(HashSet<String>, Version, Vec<String>), String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Unable to read {}: {e}", path.display()))?;
+    let bytes = link::thin_fat64(path, Arc::new(bytes), arch)?;
+    let parse_error = |e: goblin::error::Error| format!("Unable to parse {}: {e}", path.display());
+    let read = |dylib: Dylib| {
+        let exports = dylib.exports().into_iter().map(Cow::into_owned).collect();
+        (exports, dylib.versions().0, dylib.external_reexports())
+    };
+    match goblin::Object::parse(&bytes).map_err(parse_error)? {
+        goblin::Object::Unknown(_) => {
+            let tbd = TbdDylib::parse(arch.clone(), platforms, &bytes)
+                .map_err(|e| format!("Unable to parse {}: {e}", path.display()))?;
+            let (mut exports, current, reexports): (HashSet<String>, _, _) = read(Dylib::Tbd(&tbd));
+            // Linking only binds to strong definitions, but a weak one
+            // satisfies dyld.
+            exports.extend(tbd.weak_exports.iter().cloned());
+            Ok((exports, current, reexports))
+        }
+        goblin::Object::Mach(Mach::Binary(macho)) => Ok(read(Dylib::MachO(&macho))),
+        goblin::Object::Mach(Mach::Fat(fat)) => {
+            let slice = fat_slice(&fat, &bytes, arch)
+                .map_err(parse_error)?
+                .ok_or_else(|| format!("{} has no {arch} slice", path.display()))?;
+            let macho = MachO::parse(slice, 0).map_err(parse_error)?;
+            Ok(read(Dylib::MachO(&macho)))
+        }
+        _ => Err(format!("{} isn't a dylib or TBD", path.display())),
+    }
+}
+
+fn fat_slice<'a>(
+    fat: &MultiArch,
+    bytes: &'a [u8],
+    arch: &Architecture,
+) -> Result<Option<&'a [u8]>, goblin::error::Error> {
+    Ok(fat
+        .find_cputype(arch.cpu_type())?
+        .map(|fat_arch| fat_arch.slice(bytes)))
+}
@@ -0,0 +1,34 @@
+/// Warnings from the link in progress, for -fatal_warnings.
+///
+/// The link reports warnings with [warning!](crate::warning), which
+/// counts them before handing them to the logger, so they're counted
+/// whichever logger is installed. Like [panic_context](crate::panic_context),
+/// this assumes one link at a time. The count starts when the arguments
+/// are parsed, since their warnings are the link's too.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Log a warning for the link in progress, like `log::warn!`.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)+) => {{
+        $crate::warnings::count();
+        log::warn!($($arg)+)
+    }};
+}
+
+/// Start counting afresh, for a new link.
+pub fn start() {
+    WARNINGS.store(0, Ordering::Relaxed);
+}
+
+/// Count a warning, done by [warning!](crate::warning).
+pub fn count() {
+    WARNINGS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The number of warnings since [start].
+pub fn counted() -> usize {
+    WARNINGS.load(Ordering::Relaxed)
+}
@@ -0,0 +1,50 @@
+/// GNU style symbol wrapping, from --wrap.
+///
+/// Wrapping `_foo` sends undefined references to `_foo` to `___wrap_foo`
+/// and references to `___real_foo` to the original `_foo`. Definitions
+/// keep their names, so the wrapper can still call the real function.
+/// Like --defsym and the other symbol options, the symbol is named as
+/// it is in the symbol table, with C's `_` prefix.
+use goblin::mach::symbols::Nlist;
+
+use crate::layout::is_common;
+
+#[derive(Debug, Clone)]
+pub struct Wrap {
+    symbol: String,
+    wrapper: String,
+    real: String,
+}
+
+impl Wrap {
+    /// Wrap the function `symbol`. The wrapper and the real function
+    /// are named after it the way C's `__wrap_foo` and `__real_foo` are
+    /// after `foo`, behind the same `_` prefix.
+    pub fn new(symbol: &str) -> Self {
+        let (prefix, name) = match symbol.strip_prefix('_') {
+            Some(name) => ("_", name),
+            None => ("", symbol),
+        };
+        Self {
+            symbol: symbol.to_string(),
+            wrapper: format!("{prefix}__wrap_{name}"),
+            real: format!("{prefix}__real_{name}"),
+        }
+    }
+}
+
+/// The name the symbol table entry `name` is resolved by.
+pub fn resolved_name<'n>(wraps: &'n [Wrap], name: &'n str, nlist: &Nlist) -> &'n str {
+    if !nlist.is_undefined() || is_common(nlist) {
+        return name;
+    }
+    for wrap in wraps {
+        if name == wrap.symbol {
+            return &wrap.wrapper;
+        }
+        if name == wrap.real {
+            return &wrap.symbol;
+        }
+    }
+    name
+}
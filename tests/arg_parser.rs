@@ -0,0 +1,71 @@
+/// Option table parsing, for the cases where a spelling could be read
+/// more than one way.
+use machop::{arg_parser::Arg, lld_options::LD64_TABLE};
+
+fn parse(args: &[&str]) -> Vec<Arg> {
+    LD64_TABLE.parse(args.iter().map(Into::into)).unwrap()
+}
+
+fn option(name: &'static str, group: Option<&'static str>, values: &[&str]) -> Arg {
+    Arg::Option {
+        name,
+        group,
+        values: values.iter().map(Into::into).collect(),
+    }
+}
+
+#[test]
+fn joined_values() {
+    assert_eq!(
+        parse(&["-lz", "-L/opt/lib", "-L", "lib", "-ytrace", "-lazy-lfoo"]),
+        [
+            option("-l", Some("libraries"), &["z"]),
+            option("-L", Some("search"), &["/opt/lib"]),
+            option("-L", Some("search"), &["lib"]),
+            option("-trace_symbol", None, &["trace"]),
+            option("-lazy-l", Some("libraries"), &["foo"]),
+        ]
+    );
+}
+
+/// Options that start like a joined option are that option, not a value
+/// for the joined one.
+#[test]
+fn exact_spellings_win() {
+    assert_eq!(
+        parse(&["-lazy_library", "libfoo.dylib", "-load_hidden", "libbar.a"]),
+        [
+            option("-lazy_library", Some("libraries"), &["libfoo.dylib"]),
+            option("-load_hidden", Some("libraries"), &["libbar.a"]),
+        ]
+    );
+    assert_eq!(
+        parse(&["-ld_classic", "-ld_new"]),
+        [
+            Arg::Unknown("-ld_classic".into()),
+            Arg::Unknown("-ld_new".into())
+        ]
+    );
+    assert!(!LD64_TABLE.recognises("-ld_classic"));
+}
+
+/// ld64's -dylinker takes no value, so machop's option for the dynamic
+/// linker to use is spelt like ld.lld's instead.
+#[test]
+fn dynamic_linker() {
+    assert_eq!(
+        parse(&[
+            "--dynamic-linker=/a",
+            "-dynamic-linker",
+            "/b",
+            "-dylinker",
+            "main.o"
+        ]),
+        [
+            option("-dynamic-linker", None, &["/a"]),
+            option("-dynamic-linker", None, &["/b"]),
+            option("-dylinker", None, &[]),
+            Arg::Positional("main.o".into()),
+        ]
+    );
+}
@@ -0,0 +1,38 @@
+/// Libraries objects ask for with LC_LINKER_OPTION.
+///
+/// The fixtures are assembled from the .s files next to them with
+/// `clang -target arm64-apple-macos11 -c <NAME>.s -o <NAME>.o`, and the
+/// archives made with `ar rcs lib<NAME>.a <NAME>.o`.
+use std::{path::PathBuf, process::Command};
+
+/// main.o asks for libfirst.a, whose member asks for libsecond.a.
+#[test]
+fn archives_ask_for_more() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/auto_link");
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("auto_link"))
+        .arg(format!("-L{}", fixtures.display()))
+        .arg(fixtures.join("main.o"))
+        .args(["-trace_symbol", "_second"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+    assert!(
+        stdout.contains("_second: resolved to definition in") && stdout.contains("libsecond.a"),
+        "{stdout}"
+    );
+}
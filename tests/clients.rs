@@ -0,0 +1,19 @@
+/// Allowable clients and umbrellas of the libraries linked against.
+use machop::clients::Restrictions;
+
+#[test]
+fn clients_match_exactly() {
+    let restrictions = Restrictions {
+        umbrella: None,
+        allowable_clients: vec!["Foundation".to_string()],
+    };
+    let check = |install_path, client_name| {
+        restrictions.check("libobjc.dylib", install_path, None, client_name)
+    };
+    assert!(check("/usr/bin/Foundation", None).is_ok());
+    assert!(check("a.out", Some("Foundation")).is_ok());
+    // A prefix of an allowable client isn't one.
+    assert!(check("/usr/lib/libFound.dylib", None).is_err());
+    assert!(check("a.out", Some("F")).is_err());
+    assert!(check("a.out", Some("FoundationKit")).is_err());
+}
@@ -0,0 +1,35 @@
+/// Common symbols, from `.comm`.
+///
+/// The fixtures are assembled from the .s files next to them with
+/// `clang -target arm64-apple-macos11 -c <NAME>.s -o <NAME>.o`.
+use std::{path::PathBuf, process::Command};
+
+/// A reference to a common symbol is satisfied whichever comes first.
+#[test]
+fn reference_before_common() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/common");
+    for (name, inputs) in [
+        ("reference_first", ["reference.o", "common.o"]),
+        ("common_first", ["common.o", "reference.o"]),
+    ] {
+        let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+            .args([
+                "-arch",
+                "arm64",
+                "-platform_version",
+                "macos",
+                "11.0",
+                "11.0",
+            ])
+            .arg("-o")
+            .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name))
+            .args(inputs.map(|input| fixtures.join(input)))
+            .output()
+            .unwrap();
+        assert!(
+            result.status.success(),
+            "{name}: {}",
+            String::from_utf8_lossy(&result.stderr)
+        );
+    }
+}
@@ -0,0 +1,28 @@
+/// DTrace USDT probes, which machop can't describe in DOF.
+///
+/// The fixtures are assembled from the .s files next to them with
+/// `clang -target arm64-apple-macos11 -c <NAME>.s -o <NAME>.o`.
+use std::{path::PathBuf, process::Command};
+
+#[test]
+fn probes_fail_the_link() {
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/probe.o");
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("probe"))
+        .arg(&fixture)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(!result.status.success());
+    assert!(stderr.contains("USDT probes need DOF"), "{stderr}");
+    assert!(!stderr.contains("Undefined"), "{stderr}");
+}
@@ -0,0 +1,65 @@
+/// -error-limit, which caps every error of the link, not just the
+/// undefined symbols.
+///
+/// errors.o, assembled from errors.s with
+/// `clang -target arm64-apple-macos11 -c errors.s -o errors.o`, defines
+/// the same symbols as hello.o and references two that nothing does.
+use std::{path::PathBuf, process::Command};
+
+/// The errors of linking hello.o and errors.o with `extra_args`.
+fn errors(extra_args: &[&str]) -> Vec<String> {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("errors"))
+        .arg(fixtures.join("hello.o"))
+        .arg(fixtures.join("errors.o"))
+        .args(extra_args)
+        .output()
+        .unwrap();
+    assert!(!result.status.success());
+    String::from_utf8_lossy(&result.stderr)
+        .lines()
+        .filter(|line| line.starts_with("[ERROR "))
+        .map(|line| line.split_once("] ").unwrap().1.to_string())
+        .collect()
+}
+
+#[test]
+fn error_limit() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let duplicate = |name: &str| {
+        format!(
+            "duplicate symbol {name} in {} and {}",
+            fixtures.join("hello.o").display(),
+            fixtures.join("errors.o").display()
+        )
+    };
+    // The assembler writes the symbol table sorted by name.
+    let all = errors(&["-error-limit", "0"]);
+    assert_eq!(
+        all,
+        [
+            duplicate("_counter"),
+            duplicate("_main"),
+            "_undefined_a is undefined".to_string(),
+            "_undefined_b is undefined".to_string(),
+        ]
+    );
+
+    // Duplicates and undefined symbols count towards the same limit.
+    let limited = errors(&["-error-limit", "3"]);
+    assert_eq!(limited[..3], all[..3]);
+    assert_eq!(
+        limited[3..],
+        ["... and 1 more errors, use -error-limit 0 to see all"]
+    );
+}
@@ -0,0 +1,28 @@
+/// The export trie machop writes for dyld.
+use machop::export_trie::{self, Export, EXPORT_SYMBOL_FLAGS_KIND_REGULAR};
+
+/// A node with a child for every byte a name can start with.
+#[test]
+fn widest_node() {
+    let names: Vec<String> = (1..=char::MAX as u32)
+        .filter_map(char::from_u32)
+        .map(String::from)
+        .collect();
+    // One name per leading byte is enough, and UTF-8 keeps them in
+    // order.
+    let mut names: Vec<&str> = names.iter().map(|name| &name[..]).collect();
+    names.dedup_by_key(|name| name.as_bytes()[0]);
+    let exports: Vec<Export> = names
+        .iter()
+        .map(|name| Export {
+            name,
+            flags: EXPORT_SYMBOL_FLAGS_KIND_REGULAR,
+            address: 0x1000,
+        })
+        .collect();
+    let trie = export_trie::build(&exports);
+    // The root isn't an export, so its child count follows the zero
+    // length of its terminal information.
+    assert_eq!(trie[0], 0);
+    assert_eq!(usize::from(trie[1]), names.len());
+}
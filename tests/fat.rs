@@ -0,0 +1,35 @@
+/// Universal files given as inputs.
+use std::{path::PathBuf, process::Command};
+
+/// A slice that runs past the end of the file is reported, not sliced.
+#[test]
+fn truncated_slice() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("truncated_slice.o");
+    let mut bytes = vec![];
+    // One x86_64 slice at 0x20, 4 GiB long.
+    for word in [0xcafe_babe_u32, 1, 0x0100_0007, 3, 0x20, 0xffff_ffff, 0] {
+        bytes.extend(word.to_be_bytes());
+    }
+    bytes.resize(0x40, 0);
+    std::fs::write(&path, bytes).unwrap();
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("truncated_slice"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Unable to read {}", path.display())),
+        "{stderr}"
+    );
+}
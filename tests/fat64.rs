@@ -0,0 +1,106 @@
+/// Universal files with 64-bit headers, which goblin can't read, among
+/// the libraries a link or `machop verify` reads for their re-exports.
+///
+/// machop can't write dylibs, so a TBD stands in for the re-exported
+/// library's slice.
+use std::path::{Path, PathBuf};
+
+use machop::verify::{self, Options};
+
+/// `slice`, as the arm64 slice of a universal file with a 64-bit header.
+fn fat64(slice: &[u8]) -> Vec<u8> {
+    const OFFSET: u64 = 0x4000;
+    let mut bytes = vec![];
+    for word in [0xcafe_babf_u32, 1, 0x0100_000c, 0] {
+        bytes.extend(word.to_be_bytes());
+    }
+    bytes.extend(OFFSET.to_be_bytes());
+    bytes.extend((slice.len() as u64).to_be_bytes());
+    bytes.extend([14_u32, 0].map(u32::to_be_bytes).concat());
+    bytes.resize(OFFSET as usize, 0);
+    bytes.extend(slice);
+    bytes
+}
+
+fn tbd(name: &str, extra: &str) -> String {
+    format!(
+        "--- !tapi-tbd
+tbd-version: 4
+targets: [ arm64-macos ]
+install-name: '/usr/lib/lib{name}.dylib'
+{extra}...
+"
+    )
+}
+
+#[test]
+fn reexported_fat64_library() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fat64");
+    std::fs::create_dir_all(&dir).unwrap();
+    let parent = dir.join("libparent.tbd");
+    let child = dir.join("libchild");
+    std::fs::write(
+        &parent,
+        tbd(
+            "parent",
+            "reexported-libraries:
+  - targets: [ arm64-macos ]
+    libraries: [ '/usr/lib/libchild.dylib' ]
+",
+        ),
+    )
+    .unwrap();
+    let exports = "exports:
+  - targets: [ arm64-macos ]
+    symbols: [ _undefined_a, _undefined_b ]
+";
+    std::fs::write(&child, fat64(tbd("child", exports).as_bytes())).unwrap();
+    let dylib_files = [
+        format!("/usr/lib/libparent.dylib:{}", parent.display()),
+        format!("/usr/lib/libchild.dylib:{}", child.display()),
+    ];
+
+    // errors.o references what only the re-exported library defines.
+    let object = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/errors.o");
+    let output = dir.join("out");
+    let result = std::process::Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(&output)
+        .arg(object)
+        .arg(&parent)
+        .args(dylib_files.iter().flat_map(|file| ["-dylib_file", file]))
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    // Both the binary and the library are read through their headers.
+    let binary = dir.join("out.fat64");
+    std::fs::write(&binary, fat64(&std::fs::read(&output).unwrap())).unwrap();
+    let options = Options {
+        binary,
+        dylib_files: dylib_files
+            .iter()
+            .map(|file| {
+                let (name, path) = file.split_once(':').unwrap();
+                (name.to_string(), path.into())
+            })
+            .collect(),
+        ..Options::default()
+    };
+    let report = verify::verify(&options).unwrap();
+    assert_eq!(report.libraries, 1);
+    assert_eq!(report.imports, 2);
+    assert!(report.problems.is_empty(), "{:?}", report.problems);
+}
@@ -0,0 +1,33 @@
+/// The C interface, called the way a host toolchain would.
+use std::ffi::{c_char, CStr, CString};
+
+use machop::ffi::{machop_free_diagnostics, machop_link};
+
+/// Link with `args` through [machop_link], returning the status and the
+/// diagnostics.
+fn link(args: &[&str]) -> (i32, String) {
+    let args: Vec<CString> = args.iter().map(|arg| CString::new(*arg).unwrap()).collect();
+    let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+    let mut diagnostics = std::ptr::null_mut();
+    unsafe {
+        let status = machop_link(argv.len() as i32, argv.as_ptr(), &mut diagnostics);
+        let text = CStr::from_ptr(diagnostics).to_string_lossy().into_owned();
+        machop_free_diagnostics(diagnostics);
+        (status, text)
+    }
+}
+
+/// -help has to come back to the host rather than exit it.
+#[test]
+fn help() {
+    let (status, diagnostics) = link(&["machop", "-help"]);
+    assert_eq!(status, 1);
+    assert!(diagnostics.contains("Usage: machop"), "{diagnostics}");
+}
+
+#[test]
+fn bad_arguments() {
+    let (status, diagnostics) = link(&["machop", "-arch", "sparc", "-o", "out"]);
+    assert_eq!(status, 1);
+    assert!(diagnostics.starts_with("error: "), "{diagnostics}");
+}
@@ -0,0 +1,7 @@
+	.linker_option "-lsecond"
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_first
+	.p2align	2
+_first:
+	b	_second
+.subsections_via_symbols
@@ -0,0 +1,7 @@
+	.linker_option "-lfirst"
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	b	_first
+.subsections_via_symbols
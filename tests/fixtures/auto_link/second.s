@@ -0,0 +1,7 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_second
+	.p2align	2
+_second:
+	mov	w0, #0
+	ret
+.subsections_via_symbols
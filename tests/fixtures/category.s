@@ -0,0 +1,68 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.p2align	2
+	.globl	_main
+_main:
+	mov	w0, #0
+	ret
+"-[Foo foo]":
+	ret
+"-[Foo(Bar) bar]":
+	ret
+"+[Foo(Bar) cbar]":
+	ret
+
+	.section	__TEXT,__objc_methname,cstring_literals
+l_name_foo:  .asciz "foo"
+l_name_bar:  .asciz "bar"
+l_name_cbar: .asciz "cbar"
+	.section	__TEXT,__objc_methtype,cstring_literals
+l_type: .asciz "v16@0:8"
+	.section	__TEXT,__objc_classname,cstring_literals
+l_class_name: .asciz "Foo"
+l_cat_name: .asciz "Bar"
+
+	.section	__DATA,__objc_const
+	.p2align	3
+__OBJC_METACLASS_RO_$_Foo:
+	.long 1, 40, 40, 0
+	.quad 0, l_class_name, 0, 0, 0, 0, 0
+	.p2align	3
+__OBJC_$_INSTANCE_METHODS_Foo:
+	.long 24, 1
+	.quad l_name_foo, l_type, "-[Foo foo]"
+	.p2align	3
+__OBJC_CLASS_RO_$_Foo:
+	.long 0, 8, 8, 0
+	.quad 0, l_class_name, __OBJC_$_INSTANCE_METHODS_Foo, 0, 0, 0, 0
+	.p2align	3
+__OBJC_$_CATEGORY_INSTANCE_METHODS_Foo_$_Bar:
+	.long 24, 1
+	.quad l_name_bar, l_type, "-[Foo(Bar) bar]"
+	.p2align	3
+__OBJC_$_CATEGORY_CLASS_METHODS_Foo_$_Bar:
+	.long 24, 1
+	.quad l_name_cbar, l_type, "+[Foo(Bar) cbar]"
+	.p2align	3
+__OBJC_$_CATEGORY_Foo_$_Bar:
+	.quad l_cat_name, _OBJC_CLASS_$_Foo, __OBJC_$_CATEGORY_INSTANCE_METHODS_Foo_$_Bar, __OBJC_$_CATEGORY_CLASS_METHODS_Foo_$_Bar, 0, 0, 0
+	.long 64, 0
+
+	.section	__DATA,__objc_data
+	.globl	_OBJC_METACLASS_$_Foo
+	.p2align	3
+_OBJC_METACLASS_$_Foo:
+	.quad _OBJC_METACLASS_$_Foo, 0, 0, 0, __OBJC_METACLASS_RO_$_Foo
+	.globl	_OBJC_CLASS_$_Foo
+	.p2align	3
+_OBJC_CLASS_$_Foo:
+	.quad _OBJC_METACLASS_$_Foo, 0, 0, 0, __OBJC_CLASS_RO_$_Foo
+
+	.section	__DATA,__objc_catlist,regular,no_dead_strip
+	.p2align	3
+l_OBJC_LABEL_CATEGORY_$:
+	.quad __OBJC_$_CATEGORY_Foo_$_Bar
+
+	.section	__DATA,__objc_imageinfo,regular,no_dead_strip
+L_OBJC_IMAGE_INFO:
+	.long 0, 64
+.subsections_via_symbols
@@ -0,0 +1,2 @@
+	.comm	_x,4,2
+.subsections_via_symbols
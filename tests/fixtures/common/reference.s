@@ -0,0 +1,9 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	adrp	x8, _x@GOTPAGE
+	ldr	x8, [x8, _x@GOTPAGEOFF]
+	ldr	w0, [x8]
+	ret
+.subsections_via_symbols
@@ -0,0 +1,14 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	bl	_undefined_a
+	bl	_undefined_b
+	ret
+
+	.section	__DATA,__data
+	.globl	_counter
+	.p2align	2
+_counter:
+	.long	7
+.subsections_via_symbols
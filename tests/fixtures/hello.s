@@ -0,0 +1,14 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	adrp	x8, _counter@PAGE
+	ldr	w0, [x8, _counter@PAGEOFF]
+	ret
+
+	.section	__DATA,__data
+	.globl	_counter
+	.p2align	2
+_counter:
+	.long	42
+.subsections_via_symbols
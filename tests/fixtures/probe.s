@@ -0,0 +1,13 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	stp	x29, x30, [sp, #-16]!
+	bl	"___dtrace_isenabled$machop$start$v1"
+	cbz	x0, 1f
+	bl	"___dtrace_probe$machop$start$v1"
+1:
+	mov	w0, #0
+	ldp	x29, x30, [sp], #16
+	ret
+.subsections_via_symbols
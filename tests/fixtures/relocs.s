@@ -0,0 +1,38 @@
+	.section	__TEXT,__text,regular,pure_instructions
+	.globl	_main
+	.p2align	2
+_main:
+	bl	_callee
+	bl	_callee+8
+	adrp	x0, _data@PAGE
+	add	x0, x0, _data@PAGEOFF
+	adrp	x1, _data@PAGE+16
+	ldr	x1, [x1, _data@PAGEOFF+16]
+	adrp	x2, _external@GOTPAGE
+	ldr	x2, [x2, _external@GOTPAGEOFF]
+	adrp	x3, _tlv@TLVPPAGE
+	ldr	x3, [x3, _tlv@TLVPPAGEOFF]
+	ret
+
+	.section	__DATA,__data
+	.globl	_data
+	.p2align	3
+_data:
+	.quad	_callee
+	.quad	_callee+4
+	.quad	_data - _main
+	.long	_data - _main
+	.long	_external@GOT - .
+
+	.section	__DATA,__thread_vars,thread_local_variables
+	.globl	_tlv
+_tlv:
+	.quad	__tlv_bootstrap
+	.quad	0
+	.quad	_tlv$tlv$init
+
+	.section	__DATA,__thread_data,thread_local_regular
+_tlv$tlv$init:
+	.long	1
+
+.subsections_via_symbols
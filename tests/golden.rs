@@ -0,0 +1,153 @@
+/// Golden output tests: fixture objects are linked and the structure of
+/// the output (header, load commands and section layout) is compared
+/// with a dump checked in under tests/golden, so a change to the writer
+/// shows up as a diff rather than a binary that no longer runs.
+///
+/// The fixtures are assembled from the .s files next to them with
+/// `clang -target arm64-apple-macos11 -c <NAME>.s -o <NAME>.o`. After an
+/// intended change to the output, run the tests with MACHOP_BLESS=1 to
+/// update the dumps. With MACHOP_LD64 set to an ld64 binary, the
+/// fixtures are also linked by it, against libSystem from SDKROOT, and
+/// any differences printed, which are expected where machop doesn't do
+/// what ld64 does yet.
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use goblin::mach::{
+    header::filetype_to_str,
+    load_command::{cmd_to_str, CommandVariant},
+    MachO,
+};
+
+const ARGS: &[&str] = &[
+    "-arch",
+    "arm64",
+    "-platform_version",
+    "macos",
+    "11.0",
+    "11.0",
+];
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Link `inputs` with `linker` into a file in the target directory.
+fn link(linker: &Path, name: &str, inputs: &[&Path], extra_args: &[String]) -> Vec<u8> {
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!(
+        "{name}-{}",
+        linker.file_name().unwrap().to_string_lossy()
+    ));
+    let result = Command::new(linker)
+        .args(ARGS)
+        .args(extra_args)
+        .arg("-o")
+        .arg(&output)
+        .args(inputs)
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{} failed: {}",
+        linker.display(),
+        String::from_utf8_lossy(&result.stderr)
+    );
+    std::fs::read(&output).unwrap()
+}
+
+/// The structure of a linked image, one line per header field, load
+/// command and section.
+fn dump(bytes: &[u8]) -> String {
+    let macho = MachO::parse(bytes, 0).unwrap();
+    let mut dump = String::new();
+    writeln!(dump, "filetype {}", filetype_to_str(macho.header.filetype)).unwrap();
+    writeln!(dump, "flags {:#x}", macho.header.flags).unwrap();
+    for command in &macho.load_commands {
+        let name = cmd_to_str(command.command.cmd());
+        match &command.command {
+            CommandVariant::Segment64(segment) => {
+                let segname = std::str::from_utf8(&segment.segname)
+                    .unwrap()
+                    .trim_end_matches('\0');
+                writeln!(
+                    dump,
+                    "{name} {segname} vmaddr {:#x} vmsize {:#x} fileoff {:#x} filesize {:#x} \
+                     maxprot {} initprot {}",
+                    segment.vmaddr,
+                    segment.vmsize,
+                    segment.fileoff,
+                    segment.filesize,
+                    segment.maxprot,
+                    segment.initprot
+                )
+                .unwrap();
+            }
+            _ => writeln!(dump, "{name}").unwrap(),
+        }
+    }
+    for segment in macho.segments.iter() {
+        for (section, _) in segment.sections().unwrap() {
+            writeln!(
+                dump,
+                "section {},{} addr {:#x} size {:#x} align {} flags {:#x}",
+                section.segname().unwrap(),
+                section.name().unwrap(),
+                section.addr,
+                section.size,
+                section.align,
+                section.flags
+            )
+            .unwrap();
+        }
+    }
+    dump
+}
+
+/// Link the fixture `name` with machop and compare the output with its
+/// golden dump.
+fn check(name: &str) {
+    let fixture = manifest_dir().join(format!("tests/fixtures/{name}.o"));
+    let machop = PathBuf::from(env!("CARGO_BIN_EXE_machop"));
+    let actual = dump(&link(&machop, name, &[&fixture], &[]));
+    let golden = manifest_dir().join(format!("tests/golden/{name}.txt"));
+    if std::env::var_os("MACHOP_BLESS").is_some() {
+        std::fs::write(&golden, &actual).unwrap();
+    }
+    let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+    assert_eq!(
+        actual,
+        expected,
+        "the output for {name} doesn't match {}, run with MACHOP_BLESS=1 if the change is intended",
+        golden.display()
+    );
+
+    if let Some(ld64) = std::env::var_os("MACHOP_LD64") {
+        // ld64 won't link an executable without libSystem.
+        let mut extra_args = vec!["-lSystem".to_string()];
+        if let Ok(sdk) = std::env::var("SDKROOT") {
+            extra_args.extend(["-syslibroot".to_string(), sdk]);
+        }
+        let reference = dump(&link(Path::new(&ld64), name, &[&fixture], &extra_args));
+        for line in reference.lines().filter(|line| !actual.contains(line)) {
+            eprintln!("{name}: only ld64 has {line}");
+        }
+        for line in actual.lines().filter(|line| !reference.contains(line)) {
+            eprintln!("{name}: only machop has {line}");
+        }
+    }
+}
+
+#[test]
+fn hello() {
+    check("hello");
+}
+
+/// Objective-C metadata, with a category on a class in the same object
+/// which is merged into it.
+#[test]
+fn category() {
+    check("category");
+}
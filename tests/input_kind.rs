@@ -0,0 +1,66 @@
+/// Inputs of kinds machop can't link.
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+fn link(name: &str, inputs: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name))
+        .args(inputs)
+        .output()
+        .unwrap()
+}
+
+/// A bundle is reported by name and type, not linked against.
+#[test]
+fn bundle() {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("bundle");
+    let mut bytes = vec![];
+    // An arm64 MH_BUNDLE with no load commands.
+    for word in [0xfeed_facf_u32, 0x0100_000c, 0, 8, 0, 0, 0, 0] {
+        bytes.extend(word.to_le_bytes());
+    }
+    std::fs::write(&path, bytes).unwrap();
+    let result = link("bundle_out", &[path.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains(&format!("Unable to link {}: it's a bundle", path.display())),
+        "{stderr}"
+    );
+}
+
+/// An object given where a dylib goes is reported rather than linked
+/// in as an object.
+#[test]
+fn upward_object() {
+    let object = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/common/common.o");
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/common/reference.o");
+    let result = link(
+        "upward_object",
+        &[
+            reference.to_str().unwrap(),
+            "-upward_library",
+            object.to_str().unwrap(),
+        ],
+    );
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert_eq!(result.status.code(), Some(1), "{stderr}");
+    assert!(
+        stderr.contains(&format!(
+            "{} can't be linked upward, it's an object file",
+            object.display()
+        )),
+        "{stderr}"
+    );
+}
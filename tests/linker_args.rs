@@ -0,0 +1,115 @@
+/// Command lines read into [Args].
+use std::path::{Path, PathBuf};
+
+use machop::{config::Config, environment::Environment, linker_args::Args};
+
+fn parse(args: &[&str]) -> Result<Args, String> {
+    Args::parse(args.iter().map(Into::into).collect(), None)
+}
+
+/// Repeating -platform_version is for zippered dylibs, which machop
+/// can't write.
+#[test]
+fn one_platform_version() {
+    let platform_version = ["-platform_version", "macos", "11.0", "11.0"];
+    let args =
+        parse(&[&["-arch", "arm64", "-o", "a.out"], &platform_version[..]].concat()).unwrap();
+    assert_eq!(args.platform_version.platform.to_string(), "macos");
+    let zippered = [
+        &["-arch", "arm64", "-o", "a.out"],
+        &platform_version[..],
+        &["-platform_version", "mac-catalyst", "14.0", "14.0"],
+    ]
+    .concat();
+    let error = parse(&zippered).unwrap_err();
+    assert!(error.contains("can only be given once"), "{error}");
+}
+
+/// A spec stands for the arguments it describes, and flags around it
+/// add to them.
+#[test]
+fn spec() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("spec");
+    std::fs::create_dir_all(&dir).unwrap();
+    let spec = dir.join("link.json");
+    std::fs::write(
+        &spec,
+        r#"{
+            "output": "build/app",
+            "arch": "arm64",
+            "platform-version": "ios 14.0 15.0",
+            "inputs": ["build/main.o"],
+            "library-paths": ["vendor/lib"],
+            "libraries": ["z"],
+            "threads": 2
+        }"#,
+    )
+    .unwrap();
+    let spec = spec.to_str().unwrap();
+    let args = parse(&["--spec", spec, "-e", "_start"]).unwrap();
+    assert_eq!(args.output_file, Path::new("build/app"));
+    assert_eq!(args.object_files, [Path::new("build/main.o")]);
+    assert_eq!(args.libraries, ["z"]);
+    assert_eq!(args.entry, "_start");
+    assert_eq!(args.platform_version.platform.to_string(), "ios");
+
+    for (content, error) in [
+        (r#"{"output": "a", "inptus": []}"#, "unknown field `inptus`"),
+        (r#"{"threads": -1}"#, "invalid value"),
+        (r#"{"output-type": "dylib"}"#, "unknown variant `dylib`"),
+    ] {
+        let bad = dir.join("bad.json");
+        std::fs::write(&bad, content).unwrap();
+        let e = parse(&["--spec", bad.to_str().unwrap()]).unwrap_err();
+        assert!(e.contains(error), "{e}");
+    }
+}
+
+/// A config's fatal-warnings can be turned off for one link.
+#[test]
+fn no_fatal_warnings() {
+    let config = Config {
+        fatal_warnings: Some(true),
+        ..Config::default()
+    };
+    let parse = |extra: &[&str]| {
+        let args = [&["-arch", "arm64", "-o", "a.out"], extra].concat();
+        Args::parse_with_config(args.iter().map(Into::into).collect(), None, &config).unwrap()
+    };
+    assert!(parse(&[]).fatal_warnings);
+    assert!(!parse(&["-no_fatal_warnings"]).fatal_warnings);
+}
+
+/// A daemon's link is in the client's directory and environment, not
+/// its own.
+#[test]
+fn client_environment() {
+    let env = Environment::client(
+        PathBuf::from("/work"),
+        vec![("MACOSX_DEPLOYMENT_TARGET".into(), "12.0".into())],
+    );
+    let args = [
+        "-arch",
+        "arm64",
+        "-o",
+        "out/app",
+        "main.o",
+        "-L/opt/lib",
+        "-Llib",
+    ];
+    let args = Args::parse_in(
+        args.iter().map(Into::into).collect(),
+        None,
+        &Config::default(),
+        &env,
+    )
+    .unwrap();
+    assert_eq!(args.output_file, Path::new("/work/out/app"));
+    assert_eq!(args.object_files, [Path::new("/work/main.o")]);
+    assert_eq!(
+        args.library_search_paths,
+        [Path::new("/opt/lib"), Path::new("/work/lib")]
+    );
+    assert_eq!(args.platform_version.min_version.to_string(), "12.0");
+    assert!(!args.platform_version_defaulted);
+}
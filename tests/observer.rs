@@ -0,0 +1,131 @@
+/// What a link tells its observer.
+use std::{
+    ffi::OsString,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use machop::{
+    config::Config, input_cache::InputCache, link, linker_args::Args, observer::Observer,
+};
+
+#[derive(Default)]
+struct Loaded(Vec<PathBuf>);
+
+impl Observer for Loaded {
+    fn on_file_loaded(&mut self, path: &Path) {
+        self.0.push(path.to_path_buf());
+    }
+}
+
+/// A library read because another re-exports it is loaded too, from
+/// -dylib_file when it's given.
+#[test]
+fn reexported_libraries_are_loaded() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("observer");
+    std::fs::create_dir_all(&dir).unwrap();
+    let tbd = |name: &str, reexports: &str| {
+        format!(
+            "--- !tapi-tbd
+tbd-version: 4
+targets: [ arm64-macos ]
+install-name: '/usr/lib/lib{name}.dylib'
+{reexports}exports:
+  - targets: [ arm64-macos ]
+    symbols: [ _{name} ]
+...
+"
+        )
+    };
+    let parent = dir.join("libparent.tbd");
+    let child = dir.join("libchild.tbd");
+    std::fs::write(
+        &parent,
+        tbd(
+            "parent",
+            "reexported-libraries:
+  - targets: [ arm64-macos ]
+    libraries: [ '/usr/lib/libchild.dylib' ]
+",
+        ),
+    )
+    .unwrap();
+    std::fs::write(&child, tbd("child", "")).unwrap();
+    let hello = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hello.o");
+
+    let mut args: Vec<OsString> = [
+        "-arch",
+        "arm64",
+        "-platform_version",
+        "macos",
+        "11.0",
+        "11.0",
+        "-o",
+    ]
+    .map(OsString::from)
+    .into();
+    args.extend([dir.join("out"), hello.clone(), parent.clone()].map(OsString::from));
+    args.push("-dylib_file".into());
+    args.push(format!("/usr/lib/libchild.dylib:{}", child.display()).into());
+    let args = Args::parse_with_config(args, None, &Config::default()).unwrap();
+    let mut loaded = Loaded::default();
+    link::link(args, &InputCache::default(), &mut loaded).unwrap();
+    assert_eq!(loaded.0, [hello, parent, child]);
+}
+
+#[derive(Default)]
+struct Traced(Vec<String>);
+
+impl Observer for Traced {
+    fn on_symbol_traced(&mut self, name: &str, event: fmt::Arguments) {
+        self.0.push(format!("{name}: {event}"));
+    }
+}
+
+/// -trace_symbol is answered through the observer, and nothing is
+/// printed on the caller's stdout.
+#[test]
+fn traced_symbols() {
+    let hello = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hello.o");
+    let mut args: Vec<OsString> = [
+        "-arch",
+        "arm64",
+        "-platform_version",
+        "macos",
+        "11.0",
+        "11.0",
+        "-trace_symbol",
+        "_counter",
+        "-o",
+    ]
+    .map(OsString::from)
+    .into();
+    args.push(
+        PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+            .join("traced")
+            .into(),
+    );
+    args.push(hello.clone().into());
+    let args = Args::parse_with_config(args, None, &Config::default()).unwrap();
+    let mut traced = Traced::default();
+    link::link(args, &InputCache::default(), &mut traced).unwrap();
+    let definition = format!("_counter: definition in {}", hello.display());
+    assert!(traced.0.contains(&definition), "{:?}", traced.0);
+
+    let result = std::process::Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("untraced"))
+        .arg(&hello)
+        .output()
+        .unwrap();
+    assert!(result.status.success());
+    assert_eq!(String::from_utf8_lossy(&result.stdout), "");
+}
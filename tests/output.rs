@@ -0,0 +1,32 @@
+/// Writing Mach-O files with MachOWriter.
+use goblin::mach::{constants::S_REGULAR, header::MH_EXECUTE, symbols::N_SECT};
+use machop::{
+    linker_args::Architecture,
+    output::{MachOWriter, OutputSymbol},
+};
+
+/// A symbol's n_sect only has room for the first 255 sections.
+#[test]
+fn symbol_in_section_256() {
+    let writer_with_symbol_in = |sectname: &str| {
+        let mut writer = MachOWriter::new(&Architecture::ARM64, MH_EXECUTE);
+        for i in 0..300 {
+            writer.section_index("__DATA", &format!("__s{i:03}"), S_REGULAR);
+        }
+        let section = writer.section_index("__DATA", sectname, S_REGULAR);
+        writer.symbols.push(OutputSymbol {
+            name: "_data".to_string(),
+            n_type: N_SECT,
+            section: Some(section),
+            n_desc: 0,
+            n_value: 0,
+        });
+        writer
+    };
+    assert!(writer_with_symbol_in("__s000").write().is_ok());
+    let error = writer_with_symbol_in("__s299").write().unwrap_err();
+    assert!(
+        error.starts_with("_data is in __DATA,__s299, section "),
+        "{error}"
+    );
+}
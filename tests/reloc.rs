@@ -0,0 +1,120 @@
+/// Relocation decoding tests against objects written by the assembler,
+/// so pairs are folded the way compilers actually emit them.
+///
+/// The fixtures are assembled from the .s files next to them with
+/// `clang -target arm64-apple-macos11 -c <NAME>.s -o <NAME>.o`.
+use std::path::PathBuf;
+
+use goblin::mach::{relocation::RelocationInfo, MachO};
+use machop::reloc::{self, Error, Kind, Relocation, Target};
+
+/// The relocations of each section of the fixture `name`, decoded, one
+/// line each with the target's name.
+fn decoded(name: &str) -> Vec<String> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/{name}.o"));
+    let bytes = std::fs::read(path).unwrap();
+    let macho = MachO::parse(&bytes, 0).unwrap();
+    let symbols: Vec<String> = macho
+        .symbols()
+        .map(|symbol| symbol.unwrap().0.to_string())
+        .collect();
+    let target_name = |target: Target| match target {
+        Target::Symbol(index) => symbols[index].clone(),
+        Target::Section(ordinal) => format!("section {ordinal}"),
+    };
+    let mut lines = vec![];
+    for (_, infos, section) in macho.relocations().unwrap() {
+        let infos: Vec<RelocationInfo> = infos.map(Result::unwrap).collect();
+        let relocations = reloc::decode(&infos).unwrap();
+        for relocation in relocations {
+            let kind = match relocation.kind {
+                Kind::Subtractor { subtrahend } => {
+                    format!("Subtractor - {}", target_name(subtrahend))
+                }
+                kind => format!("{kind:?}"),
+            };
+            lines.push(format!(
+                "{} {:#x} {kind} {}{:+} length {}",
+                section.name().unwrap(),
+                relocation.address,
+                target_name(relocation.target),
+                relocation.addend,
+                relocation.length
+            ));
+        }
+    }
+    lines
+}
+
+/// Every ARM64 relocation type, with ADDEND on a branch and on both
+/// halves of a page reference, and SUBTRACTOR pairs of both lengths.
+#[test]
+fn every_kind() {
+    let expected = [
+        "__text 0x24 TlvpLoadPageOff12 _tlv+0 length 2",
+        "__text 0x20 TlvpLoadPage21 _tlv+0 length 2",
+        "__text 0x1c GotLoadPageOff12 _external+0 length 2",
+        "__text 0x18 GotLoadPage21 _external+0 length 2",
+        "__text 0x14 PageOff12 _data+16 length 2",
+        "__text 0x10 Page21 _data+16 length 2",
+        "__text 0xc PageOff12 _data+0 length 2",
+        "__text 0x8 Page21 _data+0 length 2",
+        "__text 0x4 Branch26 _callee+8 length 2",
+        "__text 0x0 Branch26 _callee+0 length 2",
+        "__data 0x1c PointerToGot _external+0 length 2",
+        "__data 0x18 Subtractor - _main _data+0 length 2",
+        "__data 0x10 Subtractor - _main _data+0 length 3",
+        "__data 0x8 Unsigned _callee+0 length 3",
+        "__data 0x0 Unsigned _callee+0 length 3",
+        "__thread_vars 0x10 Unsigned _tlv$tlv$init+0 length 3",
+        "__thread_vars 0x0 Unsigned __tlv_bootstrap+0 length 3",
+    ];
+    assert_eq!(decoded("relocs"), expected);
+}
+
+/// A relocation entry with the fields of `struct relocation_info`.
+fn info(address: i32, symbolnum: u32, pcrel: bool, length: u32, r_type: u8) -> RelocationInfo {
+    RelocationInfo {
+        r_address: address,
+        r_info: symbolnum | (pcrel as u32) << 24 | length << 25 | 1 << 27 | (r_type as u32) << 28,
+    }
+}
+
+#[test]
+fn addend_needs_a_pair() {
+    use goblin::mach::relocation::{
+        ARM64_RELOC_ADDEND, ARM64_RELOC_BRANCH26, ARM64_RELOC_GOT_LOAD_PAGE21,
+        ARM64_RELOC_SUBTRACTOR, ARM64_RELOC_UNSIGNED,
+    };
+    let addend = info(4, 8, false, 2, ARM64_RELOC_ADDEND);
+    // A negative addend is sign extended from 24 bits.
+    let negative = info(4, 0xff_fff8, false, 2, ARM64_RELOC_ADDEND);
+    let branch = info(4, 1, true, 2, ARM64_RELOC_BRANCH26);
+    let decoded: Vec<Relocation> = reloc::decode(&[negative, branch]).unwrap();
+    assert_eq!(decoded[0].kind, Kind::Branch26);
+    assert_eq!(decoded[0].addend, -8);
+
+    for next in [
+        info(4, 1, true, 2, ARM64_RELOC_GOT_LOAD_PAGE21),
+        info(4, 1, false, 3, ARM64_RELOC_UNSIGNED),
+        // The right type, but somewhere else.
+        info(8, 1, true, 2, ARM64_RELOC_BRANCH26),
+    ] {
+        assert!(matches!(
+            reloc::decode(&[addend, next]),
+            Err(Error::UnpairedAddend { address: 4 })
+        ));
+    }
+    assert!(matches!(
+        reloc::decode(&[addend]),
+        Err(Error::UnpairedAddend { address: 4 })
+    ));
+    // SUBTRACTOR needs an UNSIGNED of the same length.
+    assert!(matches!(
+        reloc::decode(&[
+            info(0, 1, false, 3, ARM64_RELOC_SUBTRACTOR),
+            info(0, 2, false, 2, ARM64_RELOC_UNSIGNED),
+        ]),
+        Err(Error::UnpairedSubtractor { address: 0 })
+    ));
+}
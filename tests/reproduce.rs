@@ -0,0 +1,87 @@
+/// --reproduce bundles: the files options name are bundled with the
+/// inputs, and response.txt points at them in a form xargs reads back.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The files in the tar `bytes`, by name.
+fn files(bytes: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut files = vec![];
+    let mut at = 0;
+    while at + 512 <= bytes.len() && bytes[at] != 0 {
+        let field = |from: usize, len: usize| {
+            let field = &bytes[at + from..at + from + len];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(len);
+            String::from_utf8_lossy(&field[..end]).trim().to_string()
+        };
+        let name = field(0, 100);
+        let size = usize::from_str_radix(&field(124, 12), 8).unwrap();
+        let kind = bytes[at + 156];
+        let contents = bytes[at + 512..at + 512 + size].to_vec();
+        // PAX headers, for long names, aren't files.
+        if kind != b'x' {
+            files.push((name, contents));
+        }
+        at += 512 + size.div_ceil(512) * 512;
+    }
+    files
+}
+
+#[test]
+fn option_files() {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("reproduce");
+    std::fs::create_dir_all(&dir).unwrap();
+    let list = dir.join("it's a list.txt");
+    std::fs::write(&list, "_main\n").unwrap();
+    let order = dir.join("order.txt");
+    std::fs::write(&order, "_main\n").unwrap();
+    let bundle = dir.join("bundle.tar");
+    let hello = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hello.o");
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(dir.join("out"))
+        .arg(&hello)
+        .arg("-unexported_symbols_list")
+        .arg(&list)
+        .arg(format!("--profile-order={}", order.display()))
+        .arg(format!("--reproduce={}", bundle.display()))
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+
+    let files = files(&std::fs::read(&bundle).unwrap());
+    let names: Vec<&str> = files.iter().map(|(name, _)| &name[..]).collect();
+    let in_bundle = |path: &Path| path.to_string_lossy().trim_start_matches('/').to_string();
+    for path in [&hello, &list, &order] {
+        let name = format!("bundle/{}", in_bundle(path));
+        assert!(names.contains(&&name[..]), "{name} not in {names:?}");
+    }
+    let response = files
+        .iter()
+        .find(|(name, _)| name == "bundle/response.txt")
+        .map(|(_, contents)| String::from_utf8(contents.clone()).unwrap())
+        .unwrap();
+    let lines: Vec<&str> = response.lines().collect();
+    assert_eq!(lines[0], "--dialect=lld");
+    assert!(lines.contains(&"out"), "{response}");
+    assert!(!response.contains("--reproduce"), "{response}");
+    let profile_order = format!("--profile-order={}", in_bundle(&order));
+    assert!(lines.contains(&&profile_order[..]), "{response}");
+    // A quote can't be escaped inside single quotes, so it's spelt
+    // between them.
+    let quoted = format!("'{}'", in_bundle(&list).replace('\'', r"'\''"));
+    assert!(lines.contains(&&quoted[..]), "{response}");
+}
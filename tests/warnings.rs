@@ -0,0 +1,73 @@
+/// -fatal_warnings, which turns every warning of the link into a failure.
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Link the hello fixture into `output` with `extra_args`.
+fn link(output: &Path, extra_args: &[&str]) -> Output {
+    let fixture = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/hello.o");
+    Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+        ])
+        .arg("-o")
+        .arg(output)
+        .arg(fixture)
+        .args(extra_args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn fatal_warnings() {
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fatal_warnings");
+    let _ = std::fs::remove_file(&output);
+    // -encryptable only means something for iOS.
+    let result = link(&output, &["-encryptable"]);
+    assert!(result.status.success());
+    std::fs::remove_file(&output).unwrap();
+
+    let result = link(&output, &["-encryptable", "-fatal_warnings"]);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(!result.status.success());
+    assert!(
+        stderr.contains("-encryptable is only meaningful"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("-fatal_warnings is set"), "{stderr}");
+    assert!(!output.exists());
+}
+
+/// Each object's non-external symbols, like the assembler's ltmp0, are
+/// its own, so objects sharing their names isn't a warning.
+#[test]
+fn fatal_warnings_with_many_objects() {
+    let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/common");
+    let result = Command::new(env!("CARGO_BIN_EXE_machop"))
+        .args([
+            "-arch",
+            "arm64",
+            "-platform_version",
+            "macos",
+            "11.0",
+            "11.0",
+            "-fatal_warnings",
+        ])
+        .arg("-o")
+        .arg(PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("fatal_warnings_with_many_objects"))
+        .arg(fixtures.join("reference.o"))
+        .arg(fixtures.join("common.o"))
+        .output()
+        .unwrap();
+    assert!(
+        result.status.success(),
+        "{}",
+        String::from_utf8_lossy(&result.stderr)
+    );
+}
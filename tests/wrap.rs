@@ -0,0 +1,30 @@
+/// --wrap, which takes the symbol's name as it's in the symbol table.
+use goblin::mach::symbols::{Nlist, N_EXT, N_SECT};
+use machop::wrap::{self, Wrap};
+
+fn nlist(n_type: u8, n_sect: usize) -> Nlist {
+    Nlist {
+        n_strx: 0,
+        n_type,
+        n_sect,
+        n_desc: 0,
+        n_value: 0,
+    }
+}
+
+#[test]
+fn raw_names() {
+    let wraps = [Wrap::new("_malloc"), Wrap::new("free")];
+    let undefined = nlist(N_EXT, 0);
+    let defined = nlist(N_EXT | N_SECT, 1);
+    let resolved = |name| wrap::resolved_name(&wraps, name, &undefined);
+    assert_eq!(resolved("_malloc"), "___wrap_malloc");
+    assert_eq!(resolved("___real_malloc"), "_malloc");
+    assert_eq!(resolved("free"), "__wrap_free");
+    assert_eq!(resolved("__real_free"), "free");
+    // Only the one that's named.
+    assert_eq!(resolved("malloc"), "malloc");
+    assert_eq!(resolved("_free"), "_free");
+    // Definitions keep their names.
+    assert_eq!(wrap::resolved_name(&wraps, "_malloc", &defined), "_malloc");
+}